@@ -1,14 +1,26 @@
-use std::sync::Arc;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
 
 use stores::{
     bucketstore::BucketStore,
-    config::{ConfigStore, CreateScript, UpdateScript},
+    config::{
+        AuditLogAction, AuditLogSource, ConfigStore, CreateAuditLogEntry, CreateScript,
+        GuildAccess, GuildAccessPermission, GuildAccessRule, UpdateScript,
+    },
     timers::TimerStore,
 };
 use tracing::{error, info, instrument};
+use tscompiler::typecheck::TypeChecker;
 use twilight_cache_inmemory::InMemoryCache;
 use twilight_gateway::Cluster;
-use twilight_model::{gateway::payload::incoming::MessageCreate, guild::Permissions, id::RoleId};
+use twilight_model::{
+    gateway::payload::incoming::MessageCreate,
+    guild::Permissions,
+    id::{GuildId, RoleId},
+};
 use twilight_util::permission_calculator::PermissionCalculator;
 use validation::{validate, ValidationError};
 
@@ -19,6 +31,52 @@ pub struct CommandContext<CT> {
     pub(crate) state: Arc<InMemoryCache>,
     pub(crate) config_store: CT,
     pub(crate) vm_manager: vm_manager::Manager<CT>,
+    pub(crate) type_checker: Option<Arc<TypeChecker>>,
+    pub(crate) access_rules_cache: AccessRulesCache,
+}
+
+/// How long the access rules fetched for a command are used to turn away members without access
+const ACCESS_RULES_CACHE_TTL: Duration = Duration::from_secs(60);
+
+/// The access rules of the guilds recently used in commands
+///
+/// This is only used to skip the database for members the rules don't give any access,
+/// access is always checked against the stored rules so removed rules take effect right away,
+/// while added rules can take up to [ACCESS_RULES_CACHE_TTL] to apply to chat commands.
+#[derive(Clone, Default)]
+pub struct AccessRulesCache {
+    inner: Arc<Mutex<HashMap<GuildId, CachedAccessRules>>>,
+}
+
+struct CachedAccessRules {
+    fetched_at: Instant,
+    rules: Vec<GuildAccessRule>,
+}
+
+impl AccessRulesCache {
+    fn set(&self, guild_id: GuildId, rules: Vec<GuildAccessRule>) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.retain(|_, cached| cached.fetched_at.elapsed() < ACCESS_RULES_CACHE_TTL);
+        inner.insert(
+            guild_id,
+            CachedAccessRules {
+                fetched_at: Instant::now(),
+                rules,
+            },
+        );
+    }
+
+    /// Returns true if the recently fetched rules of the guild gives the member no access
+    fn denies(&self, guild_id: GuildId, m: &MessageCreate) -> bool {
+        let inner = self.inner.lock().unwrap();
+        match inner.get(&guild_id) {
+            Some(cached) if cached.fetched_at.elapsed() < ACCESS_RULES_CACHE_TTL => {
+                let roles = m.member.as_ref().map(|m| m.roles.as_slice()).unwrap_or(&[]);
+                GuildAccess::from_rules(&cached.rules, m.author.id, roles).is_empty()
+            }
+            _ => false,
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -32,21 +90,38 @@ pub enum Command {
     EnabledScript(String),
     DisableScript(String),
 
+    ScriptHistory(String),
+    RollbackScript(String, u32),
+
     StartVM,
     SetErrorChannel(bool),
 }
 
+impl Command {
+    fn required_access(&self) -> GuildAccessPermission {
+        match self {
+            Self::GetScript(_) | Self::ListScripts | Self::ScriptHistory(_) => {
+                GuildAccessPermission::View
+            }
+            Self::SetErrorChannel(_) => GuildAccessPermission::ManageSettings,
+            _ => GuildAccessPermission::EditScripts,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct ParsedCommand {
     m: MessageCreate,
     command: Command,
+    /// Whether the author has MANAGE_GUILD, giving them full access regardless of the guild's access rules
+    is_manager: bool,
 }
 
 pub(crate) fn check_for_command<CT>(
     ctx: &CommandContext<CT>,
     m: MessageCreate,
 ) -> Option<ParsedCommand> {
-    if let Some(mem) = &m.member {
+    let is_manager = if let Some(mem) = &m.member {
         let roles: Vec<_> = mem
             .roles
             .iter()
@@ -72,11 +147,14 @@ pub(crate) fn check_for_command<CT>(
         // }
 
         let calculator = PermissionCalculator::new(m.guild_id?, m.author.id, everyone, &roles);
-        if !(calculator.root().intersects(Permissions::MANAGE_GUILD)) {
-            return None;
-        }
+        calculator.root().intersects(Permissions::MANAGE_GUILD)
     } else {
         return None;
+    };
+
+    // members without MANAGE_GUILD need an access rule, skip the ones we already know don't have one
+    if !is_manager && ctx.access_rules_cache.denies(m.guild_id?, &m) {
+        return None;
     }
 
     let mut split = m.content.split(' ');
@@ -86,7 +164,11 @@ pub(crate) fn check_for_command<CT>(
             match parse_command(&m, collected) {
                 Ok(Some(cmd)) => {
                     info!("Parsed command: {:?}", cmd);
-                    return Some(ParsedCommand { m, command: cmd });
+                    return Some(ParsedCommand {
+                        m,
+                        command: cmd,
+                        is_manager,
+                    });
                 }
                 Err(e) => {
                     info!("failed parsing command: {}", e);
@@ -131,6 +213,13 @@ fn parse_command(_m: &MessageCreate, split: Vec<String>) -> Result<Option<Comman
                     "disable" => Ok(Some(Command::DisableScript(cmd_parse_string_word(
                         &mut iter,
                     )?))),
+                    "history" => Ok(Some(Command::ScriptHistory(cmd_parse_string_word(
+                        &mut iter,
+                    )?))),
+                    "rollback" => Ok(Some(Command::RollbackScript(
+                        cmd_parse_string_word(&mut iter)?,
+                        cmd_parse_uint32(&mut iter)?,
+                    ))),
                     _ => Ok(None),
                 }
             } else {
@@ -144,8 +233,7 @@ fn parse_command(_m: &MessageCreate, split: Vec<String>) -> Result<Option<Comman
     }
 }
 
-#[allow(dead_code)]
-fn cmd_parse_uint64<T: Iterator<Item = String>>(iter: &mut T) -> Result<u64, String> {
+fn cmd_parse_uint32<T: Iterator<Item = String>>(iter: &mut T) -> Result<u32, String> {
     match iter.next() {
         None => Err("no more args".to_string()),
         Some(s) => Ok(s.parse().map_err(|e| format!("failed parsing: {}", e))?),
@@ -226,6 +314,20 @@ async fn run_command<CT: ConfigStore + BucketStore + TimerStore + Send + Sync +
     ctx: &CommandContext<CT>,
     cmd: &ParsedCommand,
 ) -> Result<Option<String>, String> {
+    let access = member_guild_access(ctx, cmd).await?;
+    if access.is_empty() {
+        // not someone that can manage botloader on this guild
+        return Ok(None);
+    }
+
+    let required = cmd.command.required_access();
+    if !access.has(required) {
+        return Ok(Some(format!(
+            "You need the `{}` permission for this",
+            required.as_str()
+        )));
+    }
+
     match &cmd.command {
         Command::AddScript(name, source) | Command::UpdateScript(name, source) => {
             match ctx
@@ -234,12 +336,14 @@ async fn run_command<CT: ConfigStore + BucketStore + TimerStore + Send + Sync +
                 .await
             {
                 Ok(existing) => {
+                    let before = existing.clone();
                     let script = UpdateScript {
                         original_source: source.clone(),
                         contributes: None,
                         enabled: true,
                         id: existing.id,
                         name: existing.name,
+                        author_id: Some(cmd.m.author.id),
                     };
                     if let Err(verr) = validate(&script) {
                         return Ok(Some(format!(
@@ -247,6 +351,9 @@ async fn run_command<CT: ConfigStore + BucketStore + TimerStore + Send + Sync +
                             format_validation_err(verr)
                         )));
                     }
+                    if let Some(msg) = type_check_source(ctx, &script.original_source).await {
+                        return Ok(Some(msg));
+                    }
 
                     let script = ctx
                         .config_store
@@ -254,6 +361,18 @@ async fn run_command<CT: ConfigStore + BucketStore + TimerStore + Send + Sync +
                         .await
                         .map_err(|e| format!("failed updating script :( {}", e))?;
 
+                    for action in AuditLogAction::for_script_update(&before, &script) {
+                        record_audit_log(
+                            ctx,
+                            cmd,
+                            action,
+                            Some(script.name.clone()),
+                            Some(before.audit_summary()),
+                            Some(script.audit_summary()),
+                        )
+                        .await;
+                    }
+
                     ctx.vm_manager
                         .update_script(cmd.m.guild_id.unwrap(), script)
                         .await?;
@@ -266,6 +385,7 @@ async fn run_command<CT: ConfigStore + BucketStore + TimerStore + Send + Sync +
                         name: name.clone(),
                         original_source: source.clone(),
                         enabled: true,
+                        author_id: Some(cmd.m.author.id),
                     };
 
                     if let Err(verr) = validate(&create) {
@@ -274,6 +394,9 @@ async fn run_command<CT: ConfigStore + BucketStore + TimerStore + Send + Sync +
                             format_validation_err(verr)
                         )));
                     }
+                    if let Some(msg) = type_check_source(ctx, &create.original_source).await {
+                        return Ok(Some(msg));
+                    }
 
                     let script = ctx
                         .config_store
@@ -281,6 +404,16 @@ async fn run_command<CT: ConfigStore + BucketStore + TimerStore + Send + Sync +
                         .await
                         .map_err(|e| format!("failed creating script :( {}", e))?;
 
+                    record_audit_log(
+                        ctx,
+                        cmd,
+                        AuditLogAction::CreateScript,
+                        Some(script.name.clone()),
+                        None,
+                        Some(script.audit_summary()),
+                    )
+                    .await;
+
                     ctx.vm_manager
                         .load_script(cmd.m.guild_id.unwrap(), script)
                         .await?;
@@ -300,6 +433,7 @@ async fn run_command<CT: ConfigStore + BucketStore + TimerStore + Send + Sync +
                 .await
                 .map_err(|e| format!("unknown script: {}", e))?;
 
+            let before = script.audit_summary();
             ctx.vm_manager
                 .unload_scripts(cmd.m.guild_id.unwrap(), vec![script])
                 .await
@@ -310,6 +444,16 @@ async fn run_command<CT: ConfigStore + BucketStore + TimerStore + Send + Sync +
                 .await
                 .map_err(|e| format!("failed deleting script: {}", e))?;
 
+            record_audit_log(
+                ctx,
+                cmd,
+                AuditLogAction::DeleteScript,
+                Some(name.clone()),
+                Some(before),
+                None,
+            )
+            .await;
+
             Ok(Some(format!("Script {} has been deleted!", name)))
         }
         Command::GetScript(name) => {
@@ -351,12 +495,14 @@ async fn run_command<CT: ConfigStore + BucketStore + TimerStore + Send + Sync +
             if script.enabled {
                 Ok(Some("Script already enabled".to_string()))
             } else {
+                let before = script.audit_summary();
                 let update = UpdateScript {
                     id: script.id,
                     name: script.name,
                     enabled: true,
                     original_source: script.original_source,
                     contributes: None,
+                    author_id: Some(cmd.m.author.id),
                 };
                 let script = ctx
                     .config_store
@@ -364,6 +510,16 @@ async fn run_command<CT: ConfigStore + BucketStore + TimerStore + Send + Sync +
                     .await
                     .map_err(|e| format!("failed updating script :( {}", e))?;
 
+                record_audit_log(
+                    ctx,
+                    cmd,
+                    AuditLogAction::EnableScript,
+                    Some(script.name.clone()),
+                    Some(before),
+                    Some(script.audit_summary()),
+                )
+                .await;
+
                 ctx.vm_manager
                     .load_script(cmd.m.guild_id.unwrap(), script)
                     .await
@@ -382,12 +538,14 @@ async fn run_command<CT: ConfigStore + BucketStore + TimerStore + Send + Sync +
             if !script.enabled {
                 Ok(Some("Script already disabled".to_string()))
             } else {
+                let before = script.audit_summary();
                 let update = UpdateScript {
                     id: script.id,
                     name: script.name,
-                    enabled: true,
+                    enabled: false,
                     original_source: script.original_source,
                     contributes: None,
+                    author_id: Some(cmd.m.author.id),
                 };
                 let script = ctx
                     .config_store
@@ -395,13 +553,98 @@ async fn run_command<CT: ConfigStore + BucketStore + TimerStore + Send + Sync +
                     .await
                     .map_err(|e| format!("failed updating script :( {}", e))?;
 
+                record_audit_log(
+                    ctx,
+                    cmd,
+                    AuditLogAction::DisableScript,
+                    Some(script.name.clone()),
+                    Some(before),
+                    Some(script.audit_summary()),
+                )
+                .await;
+
                 ctx.vm_manager
                     .unload_scripts(cmd.m.guild_id.unwrap(), vec![script])
                     .await
                     .ok();
 
-                Ok(Some("Enabled script".to_string()))
+                Ok(Some("Disabled script".to_string()))
+            }
+        }
+        Command::ScriptHistory(name) => {
+            let script = ctx
+                .config_store
+                .get_script(cmd.m.guild_id.unwrap(), name.clone())
+                .await
+                .map_err(|e| format!("unknown script: {}", e))?;
+
+            let revisions = ctx
+                .config_store
+                .list_script_revisions(cmd.m.guild_id.unwrap(), script.id)
+                .await
+                .map_err(|e| format!("failed fetching revisions: {}", e))?;
+
+            let summary = revisions
+                .into_iter()
+                .take(15)
+                .map(|rev| {
+                    format!(
+                        "#{}: {} by {}\n",
+                        rev.revision,
+                        rev.created_at.format("%Y-%m-%d %H:%M:%S UTC"),
+                        rev.author_id
+                            .map(|id| id.to_string())
+                            .unwrap_or_else(|| "unknown".to_string())
+                    )
+                })
+                .collect::<String>();
+
+            Ok(Some(format!(
+                "Latest revisions of {}: ```\n{}\n```",
+                script.name, summary
+            )))
+        }
+        Command::RollbackScript(name, revision) => {
+            let script = ctx
+                .config_store
+                .get_script(cmd.m.guild_id.unwrap(), name.clone())
+                .await
+                .map_err(|e| format!("unknown script: {}", e))?;
+
+            let script = ctx
+                .config_store
+                .rollback_script(
+                    cmd.m.guild_id.unwrap(),
+                    script.id,
+                    *revision,
+                    Some(cmd.m.author.id),
+                )
+                .await
+                .map_err(|e| format!("failed rolling back script: {}", e))?;
+
+            record_audit_log(
+                ctx,
+                cmd,
+                AuditLogAction::RollbackScript,
+                Some(script.name.clone()),
+                None,
+                Some(serde_json::json!({
+                    "revision": revision,
+                    "script": script.audit_summary(),
+                })),
+            )
+            .await;
+
+            if script.enabled {
+                ctx.vm_manager
+                    .update_script(cmd.m.guild_id.unwrap(), script)
+                    .await?;
             }
+
+            Ok(Some(format!(
+                "Script {} has been rolled back to revision #{}",
+                name, revision
+            )))
         }
         Command::StartVM => {
             ctx.vm_manager
@@ -409,6 +652,8 @@ async fn run_command<CT: ConfigStore + BucketStore + TimerStore + Send + Sync +
                 .await
                 .map_err(|e| format!("failed restarting guild vm: {}", e))?;
 
+            record_audit_log(ctx, cmd, AuditLogAction::ReloadVm, None, None, None).await;
+
             Ok(Some(
                 "Restarting your guild's vm... (note that if it keeps stopping, there might be a \
                  runaway script that contains something like a infinite loop, you should find and \
@@ -423,6 +668,7 @@ async fn run_command<CT: ConfigStore + BucketStore + TimerStore + Send + Sync +
                 .await
                 .map_err(|e| format!("failed fetching your guild config: {}", e))?;
 
+            let before = conf.error_channel_id;
             if *set {
                 conf.error_channel_id = Some(cmd.m.channel_id);
             } else {
@@ -434,6 +680,16 @@ async fn run_command<CT: ConfigStore + BucketStore + TimerStore + Send + Sync +
                 .await
                 .map_err(|e| format!("failed updating the config: {}", e))?;
 
+            record_audit_log(
+                ctx,
+                cmd,
+                AuditLogAction::SetErrorChannel,
+                None,
+                Some(serde_json::json!(before)),
+                Some(serde_json::json!(conf.error_channel_id)),
+            )
+            .await;
+
             Ok(Some(if *set {
                 "set the error channel to this channel".to_string()
             } else {
@@ -445,6 +701,83 @@ async fn run_command<CT: ConfigStore + BucketStore + TimerStore + Send + Sync +
     }
 }
 
+/// Records an action taken through a command in the guild's audit log, failing to do so is only logged
+async fn record_audit_log<CT: ConfigStore + Send + Sync + 'static>(
+    ctx: &CommandContext<CT>,
+    cmd: &ParsedCommand,
+    action: AuditLogAction,
+    target: Option<String>,
+    before: Option<serde_json::Value>,
+    after: Option<serde_json::Value>,
+) {
+    let entry = CreateAuditLogEntry {
+        actor_id: cmd.m.author.id,
+        source: AuditLogSource::Chat,
+        action,
+        target,
+        before,
+        after,
+    };
+
+    if let Err(err) = ctx
+        .config_store
+        .add_audit_log_entry(cmd.m.guild_id.unwrap(), entry)
+        .await
+    {
+        error!(%err, "failed adding audit log entry");
+    }
+}
+
+/// Returns the botloader permissions the author of the command has
+async fn member_guild_access<CT: ConfigStore + Send + Sync + 'static>(
+    ctx: &CommandContext<CT>,
+    cmd: &ParsedCommand,
+) -> Result<GuildAccess, String> {
+    if cmd.is_manager {
+        return Ok(GuildAccess::full());
+    }
+
+    let rules = ctx
+        .config_store
+        .get_guild_access_rules(cmd.m.guild_id.unwrap())
+        .await
+        .map_err(|e| format!("failed fetching access rules: {}", e))?;
+    ctx.access_rules_cache
+        .set(cmd.m.guild_id.unwrap(), rules.clone());
+
+    let roles = cmd
+        .m
+        .member
+        .as_ref()
+        .map(|m| m.roles.clone())
+        .unwrap_or_default();
+
+    Ok(GuildAccess::from_rules(&rules, cmd.m.author.id, &roles))
+}
+
+/// Runs the type checker on the source if one is configured, returning the message to respond with if it failed
+async fn type_check_source<CT>(ctx: &CommandContext<CT>, source: &str) -> Option<String> {
+    let type_checker = ctx.type_checker.clone()?;
+    let source = source.to_string();
+
+    match tokio::task::spawn_blocking(move || type_checker.check(&source)).await {
+        Ok(Ok(diagnostics)) if diagnostics.is_empty() => None,
+        Ok(Ok(diagnostics)) => Some(format!(
+            "failed type checking script: {}",
+            format_validation_err(diagnostics.into_iter().map(Into::into).collect())
+        )),
+        Ok(Err(err)) => {
+            // type checking is best effort, save the script anyway
+            error!(%err, "failed type checking script");
+            None
+        }
+        Err(err) => {
+            error!(%err, "type checking task failed");
+            None
+        }
+    }
+}
+
 fn format_validation_err(errs: Vec<ValidationError>) -> String {
     errs.iter()
         .map(|e| e.to_string())
@@ -3,18 +3,33 @@ use std::time::Duration;
 
 use futures::StreamExt;
 use futures_core::Stream;
+use lazy_static::lazy_static;
+use prometheus::{register_int_counter_vec, IntCounterVec};
 use stores::bucketstore::BucketStore;
 use stores::config::{ConfigStore, JoinedGuild};
+use stores::nodes::{BotNode, BotNodeStore, ShardRange};
 use stores::postgres::Postgres;
 use stores::timers::TimerStore;
 use tracing::{error, info};
 use twilight_cache_inmemory::InMemoryCacheBuilder;
-use twilight_gateway::{Cluster, Event, Intents};
+use twilight_gateway::{cluster::ShardScheme, Cluster, Event, Intents};
 use twilight_model::application::callback::{CallbackData, InteractionResponse};
 use vm::init_v8_flags;
 
 mod commands;
 
+lazy_static! {
+    static ref GATEWAY_EVENTS: IntCounterVec = register_int_counter_vec!(
+        "botloader_gateway_events_total",
+        "Number of events received from the discord gateway",
+        &["shard", "event"]
+    )
+    .unwrap();
+}
+
+/// Has to be well below [botrpc::client::NODE_MAX_AGE]
+const NODE_HEARTBEAT_INTERVAL: Duration = Duration::from_secs(10);
+
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let config = common::common_init();
@@ -31,7 +46,19 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     ]);
 
     let intents = Intents::GUILD_MESSAGES | Intents::GUILDS | Intents::GUILD_VOICE_STATES;
-    let (cluster, events) = Cluster::new(config.discord_token.clone(), intents).await?;
+    let shard_scheme = match (config.shard_range, config.total_shards) {
+        (Some(range), Some(total)) => ShardScheme::Range {
+            from: range.first,
+            to: range.last,
+            total,
+        },
+        _ => ShardScheme::Auto,
+    };
+
+    let (cluster, events) = Cluster::builder(config.discord_token.clone(), intents)
+        .shard_scheme(shard_scheme)
+        .build()
+        .await?;
     let cluster = Arc::new(cluster);
 
     let cluster_spawn = cluster.clone();
@@ -61,11 +88,14 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         discord_config.client.clone(),
         state.clone(),
         config_store.clone(),
+        config.persist_compile_cache,
     );
 
     let bot_rpc_server = botrpc::Server::new(
         guild_log_sub_backend,
         vm_manager.clone(),
+        discord_config.client.clone(),
+        state.clone(),
         config.bot_rpc_listen_addr.clone(),
     );
 
@@ -74,16 +104,39 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             http: discord_config.client.clone(),
             cluster: cluster.clone(),
             state,
-            config_store,
+            config_store: config_store.clone(),
             vm_manager: vm_manager.clone(),
+            type_checker: config.get_type_checker().map(Arc::new),
+            access_rules_cache: Default::default(),
         },
         events,
     ));
 
     tokio::spawn(bot_rpc_server.run());
+    tokio::spawn(common::metrics::run_metrics_server(
+        config.bot_metrics_listen_addr.clone(),
+    ));
+
+    let node = BotNode {
+        rpc_addr: config.get_bot_rpc_advertise_addr(),
+        shards: cluster_shard_range(&cluster),
+    };
+    info!(
+        addr = node.rpc_addr.as_str(),
+        first_shard = node.shards.start,
+        last_shard = node.shards.end,
+        total_shards = node.shards.total,
+        "registering bot node"
+    );
+    tokio::spawn(run_node_heartbeat(config_store.clone(), node.clone()));
 
     common::shutdown::wait_shutdown_signal().await;
 
+    // stop routing requests to us
+    if let Err(err) = config_store.del_bot_node(&node.rpc_addr).await {
+        error!(%err, "failed removing bot node");
+    }
+
     info!("cluster going down...");
     cluster.down();
 
@@ -103,13 +156,45 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
+/// The shards the cluster is running, with automatic sharding it's all of them
+fn cluster_shard_range(cluster: &Cluster) -> ShardRange {
+    let shards = cluster
+        .shards()
+        .map(|shard| shard.config().shard())
+        .collect::<Vec<_>>();
+
+    ShardRange {
+        start: shards.iter().map(|s| s[0]).min().unwrap_or_default(),
+        end: shards.iter().map(|s| s[0]).max().unwrap_or_default(),
+        total: shards.first().map(|s| s[1]).unwrap_or(1),
+    }
+}
+
+/// Keeps the node registered so the webapi can route requests for our guilds to us
+async fn run_node_heartbeat<ST: BotNodeStore + Send + Sync>(store: ST, node: BotNode) {
+    let mut ticker = tokio::time::interval(NODE_HEARTBEAT_INTERVAL);
+    loop {
+        ticker.tick().await;
+        if let Err(err) = store.heartbeat_bot_node(&node).await {
+            error!(%err, "failed sending bot node heartbeat");
+        }
+    }
+}
+
 async fn handle_events<
     CT: Clone + BucketStore + ConfigStore + TimerStore + Send + Sync + 'static,
 >(
     ctx: commands::CommandContext<CT>,
     mut stream: impl Stream<Item = (u64, Event)> + Unpin,
 ) {
-    while let Some((_, event)) = stream.next().await {
+    while let Some((shard_id, event)) = stream.next().await {
+        GATEWAY_EVENTS
+            .with_label_values(&[
+                &shard_id.to_string(),
+                event.kind().name().unwrap_or("NON_DISPATCH"),
+            ])
+            .inc();
+
         ctx.state.update(&event);
 
         match &event {
@@ -0,0 +1,120 @@
+use std::path::PathBuf;
+
+use guild_archive::{GuildArchive, ImportOptions};
+use stores::postgres::Postgres;
+use structopt::StructOpt;
+use twilight_model::id::GuildId;
+
+#[derive(StructOpt)]
+#[structopt(about = "botloader admin tools")]
+struct CliConfig {
+    #[structopt(long, env = "DATABASE_URL")]
+    database_url: String,
+
+    #[structopt(subcommand)]
+    cmd: Command,
+}
+
+#[derive(StructOpt)]
+enum Command {
+    /// Export the scripts, storage, timers and config of a guild to a file
+    ExportGuild {
+        guild_id: u64,
+
+        /// File to write the archive to, prints to stdout if not provided
+        #[structopt(short, long)]
+        out: Option<PathBuf>,
+    },
+
+    /// Import a guild archive, note that the guild vm has to be reloaded afterwards
+    ImportGuild {
+        guild_id: u64,
+
+        /// Path to the archive file
+        file: PathBuf,
+
+        /// Only report conflicts, don't write anything
+        #[structopt(long)]
+        dry_run: bool,
+
+        /// Overwrite existing scripts and storage entries
+        #[structopt(long)]
+        overwrite: bool,
+    },
+}
+
+#[tokio::main]
+async fn main() -> Result<(), anyhow::Error> {
+    match dotenv::dotenv() {
+        Ok(_) => {}
+        Err(dotenv::Error::Io(_)) => {} // ignore io errors
+        Err(e) => panic!("failed loading dotenv file: {}", e),
+    }
+
+    let config = CliConfig::from_args();
+    let store = Postgres::new_with_url(&config.database_url).await?;
+
+    match config.cmd {
+        Command::ExportGuild { guild_id, out } => {
+            let archive = guild_archive::export_guild(&store, parse_guild_id(guild_id)?).await?;
+            let serialized = archive.to_json()?;
+
+            match out {
+                Some(path) => {
+                    std::fs::write(&path, serialized)?;
+                    eprintln!(
+                        "exported {} scripts, {} storage entries and {} interval timers to {}",
+                        archive.scripts.len(),
+                        archive.storage.len(),
+                        archive.interval_timers.len(),
+                        path.display()
+                    );
+                }
+                None => println!("{}", serialized),
+            }
+        }
+        Command::ImportGuild {
+            guild_id,
+            file,
+            dry_run,
+            overwrite,
+        } => {
+            let archive = GuildArchive::from_json(&std::fs::read(file)?)?;
+            let options = ImportOptions { dry_run, overwrite };
+
+            let report =
+                guild_archive::import_guild(&store, parse_guild_id(guild_id)?, &archive, &options)
+                    .await?;
+
+            for conflict in &report.conflicts {
+                let prefix = if conflict.is_blocking(&options) {
+                    "conflict"
+                } else {
+                    "note"
+                };
+                println!("{}: {}", prefix, serde_json::to_string(conflict)?);
+            }
+
+            if report.applied {
+                println!(
+                    "imported {} scripts, {} storage entries and {} interval timers, reload the \
+                     guild vm for the changes to take effect",
+                    report.num_scripts, report.num_storage_entries, report.num_interval_timers
+                );
+            } else if dry_run {
+                println!(
+                    "dry run: would import {} scripts, {} storage entries and {} interval timers",
+                    report.num_scripts, report.num_storage_entries, report.num_interval_timers
+                );
+            } else {
+                anyhow::bail!("import has blocking conflicts, nothing was written");
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn parse_guild_id(id: u64) -> Result<GuildId, anyhow::Error> {
+    GuildId::new(id).ok_or_else(|| anyhow::anyhow!("invalid guild id"))
+}
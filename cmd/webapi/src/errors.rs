@@ -19,6 +19,27 @@ pub enum ApiErrorResponse {
 
     #[error("Internal server error")]
     InternalError,
+
+    #[error("Not found")]
+    NotFound,
+
+    #[error("Api key is missing the scope for this")]
+    MissingScope,
+
+    #[error("Bad signature or secret")]
+    Unauthorized,
+
+    #[error("Script took too long to respond")]
+    ScriptTimeout,
+
+    #[error("Script is not running")]
+    ScriptUnavailable,
+
+    #[error("Only superusers can do this")]
+    NotSuperuser,
+
+    #[error("This library is owned by someone else")]
+    NotLibraryOwner,
 }
 
 impl ApiErrorResponse {
@@ -32,6 +53,13 @@ impl ApiErrorResponse {
                 4,
                 serde_json::to_string(verr).unwrap_or_default(),
             ),
+            Self::NotFound => (StatusCode::NOT_FOUND, 5, self.to_string()),
+            Self::MissingScope => (StatusCode::FORBIDDEN, 6, self.to_string()),
+            Self::Unauthorized => (StatusCode::UNAUTHORIZED, 7, self.to_string()),
+            Self::ScriptTimeout => (StatusCode::GATEWAY_TIMEOUT, 8, self.to_string()),
+            Self::ScriptUnavailable => (StatusCode::SERVICE_UNAVAILABLE, 9, self.to_string()),
+            Self::NotSuperuser => (StatusCode::FORBIDDEN, 10, self.to_string()),
+            Self::NotLibraryOwner => (StatusCode::FORBIDDEN, 11, self.to_string()),
         }
     }
 }
@@ -3,7 +3,7 @@ use std::{convert::Infallible, sync::Arc};
 use axum::{
     error_handling::HandleErrorLayer,
     response::IntoResponse,
-    routing::{delete, get, patch, post},
+    routing::{delete, get, patch, post, put},
     AddExtensionLayer, BoxError, Router,
 };
 use common::config::RunConfig;
@@ -13,6 +13,7 @@ use stores::{inmemory::web::InMemoryCsrfStore, postgres::Postgres};
 use tower::ServiceBuilder;
 use tower_http::trace::TraceLayer;
 use tracing::{error, info};
+use twilight_model::id::UserId;
 
 mod errors;
 mod middlewares;
@@ -21,7 +22,9 @@ mod util;
 
 use crate::errors::ApiErrorResponse;
 use crate::middlewares::{
-    CorsLayer, CurrentGuildLayer, NoSession, RequireCurrentGuildAuthLayer, SessionLayer,
+    CorsLayer, CurrentGuildLayer, MissingScopeError, NoSession, NotSuperuserError,
+    RequestMetricsLayer, RequireCurrentGuildAuthLayer, RequireSuperuserLayer, RouteLabelLayer,
+    SessionLayer,
 };
 
 #[derive(Clone)]
@@ -32,6 +35,7 @@ pub struct ConfigData {
 
 type CurrentSessionStore = Postgres;
 type CurrentConfigStore = Postgres;
+type CurrentBucketStore = Postgres;
 type AuthHandlerData = AuthHandlers<InMemoryCsrfStore, CurrentSessionStore>;
 type ApiResult<T> = Result<T, ApiErrorResponse>;
 
@@ -46,9 +50,17 @@ async fn main() {
     let postgres_store = Postgres::new_with_url(&conf.database_url).await.unwrap();
     let config_store: CurrentConfigStore = postgres_store.clone();
     let session_store: CurrentSessionStore = postgres_store.clone();
-    let bot_rpc_client = botrpc::Client::new(conf.bot_rpc_connect_addr.clone())
-        .await
-        .expect("failed connecting to bot rpc");
+    let bucket_store: CurrentBucketStore = postgres_store.clone();
+    let bot_rpc_client = if conf.discover_bot_nodes {
+        botrpc::Client::new_discovered(postgres_store.clone())
+            .await
+            .expect("failed fetching bot nodes")
+    } else {
+        botrpc::Client::new(conf.bot_rpc_connect_addr.clone())
+            .await
+            .expect("failed connecting to bot rpc")
+    };
+    let type_checker = conf.get_type_checker().map(Arc::new);
 
     let auth_handler: AuthHandlerData =
         routes::auth::AuthHandlers::new(session_store.clone(), InMemoryCsrfStore::default());
@@ -65,8 +77,10 @@ async fn main() {
         }))
         .layer(TraceLayer::new_for_http())
         .layer(AddExtensionLayer::new(bot_rpc_client))
+        .layer(AddExtensionLayer::new(type_checker))
         .layer(AddExtensionLayer::new(Arc::new(auth_handler)))
         .layer(AddExtensionLayer::new(config_store))
+        .layer(AddExtensionLayer::new(bucket_store))
         .layer(AddExtensionLayer::new(session_store.clone()))
         .layer(AddExtensionLayer::new(client_cache))
         .layer(session_layer)
@@ -90,15 +104,108 @@ async fn main() {
             "/scripts",
             get(routes::scripts::get_all_guild_scripts).put(routes::scripts::create_guild_script),
         )
+        .route(
+            "/validate_script",
+            post(routes::scripts::validate_guild_script),
+        )
         .route(
             "/scripts/:script_id",
             patch(routes::scripts::update_guild_script)
                 .delete(routes::scripts::delete_guild_script),
         )
-        .layer(auth_guild_mw_stack);
+        .route(
+            "/scripts/:script_id/revisions",
+            get(routes::scripts::list_script_revisions),
+        )
+        .route(
+            "/scripts/:script_id/revisions/:revision",
+            get(routes::scripts::get_script_revision),
+        )
+        .route(
+            "/scripts/:script_id/revisions/:revision/diff",
+            get(routes::scripts::diff_script_revisions),
+        )
+        .route(
+            "/scripts/:script_id/revisions/:revision/rollback",
+            post(routes::scripts::rollback_script),
+        )
+        .route(
+            "/command_conflicts",
+            get(routes::scripts::get_command_conflicts),
+        )
+        .route(
+            "/command_sync_status",
+            get(routes::scripts::get_command_sync_status),
+        )
+        .route(
+            "/command_conflict_policy",
+            put(routes::scripts::set_command_conflict_policy),
+        )
+        .route("/access", get(routes::access::get_current_access))
+        .route(
+            "/access_rules",
+            get(routes::access::get_access_rules).put(routes::access::set_access_rules),
+        )
+        .route("/audit_log", get(routes::audit_log::list_audit_log))
+        .route(
+            "/settings",
+            get(routes::settings::get_guild_settings)
+                .patch(routes::settings::update_guild_settings),
+        )
+        .route("/webhooks", get(routes::webhooks::list_webhooks))
+        .route(
+            "/scripts/:script_id/webhooks/:name",
+            put(routes::webhooks::set_webhook).delete(routes::webhooks::delete_webhook),
+        )
+        .route("/export", get(routes::archive::export_guild))
+        .route("/import", post(routes::archive::import_guild))
+        .route("/storage", get(routes::storage::get_storage_overview))
+        .route(
+            "/storage/:bucket",
+            get(routes::storage::list_bucket_entries).patch(routes::storage::rename_bucket),
+        )
+        .route(
+            "/storage/:bucket/:key",
+            get(routes::storage::get_bucket_entry)
+                .put(routes::storage::set_bucket_entry)
+                .delete(routes::storage::delete_bucket_entry),
+        )
+        .layer(auth_guild_mw_stack)
+        .layer(RouteLabelLayer);
+
+    let admin_mw_stack = ServiceBuilder::new()
+        .layer(HandleErrorLayer::new(handle_mw_err_internal_err))
+        .layer(RequireSuperuserLayer {
+            superusers: Arc::new(
+                conf.superusers
+                    .iter()
+                    .copied()
+                    .filter_map(UserId::new)
+                    .collect(),
+            ),
+        });
+
+    let admin_api_routes = Router::new()
+        .route("/whitelist", get(routes::admin::list_whitelisted_guilds))
+        .route(
+            "/whitelist/:guild",
+            put(routes::admin::whitelist_guild).delete(routes::admin::unwhitelist_guild),
+        )
+        .route("/joined_guilds", get(routes::admin::list_joined_guilds))
+        .route(
+            "/guilds/:guild/reload_vm",
+            post(routes::admin::reload_guild_vm),
+        )
+        .route(
+            "/guilds/:guild/unload_vm",
+            post(routes::admin::unload_guild_vm),
+        )
+        .layer(admin_mw_stack)
+        .layer(RouteLabelLayer);
 
     let authorized_api_routes = Router::new()
         .nest("/guilds/:guild", authorized_api_guild_routes)
+        .nest("/admin", admin_api_routes)
         .route(
             "/guilds",
             get(routes::guilds::list_user_guilds_route::<CurrentSessionStore, CurrentConfigStore>),
@@ -113,11 +220,21 @@ async fn main() {
             "/sessions/all",
             delete(routes::sessions::del_all_sessions::<CurrentSessionStore>),
         )
+        .route("/libraries", put(routes::libraries::publish_library_module))
+        .route(
+            "/libraries/:name",
+            get(routes::libraries::list_library_module_versions),
+        )
+        .route(
+            "/libraries/:name/:version",
+            get(routes::libraries::get_library_module),
+        )
         .route(
             "/current_user",
             get(routes::general::get_current_user::<CurrentSessionStore>),
         )
-        .route("/logout", post(AuthHandlerData::handle_logout));
+        .route("/logout", post(AuthHandlerData::handle_logout))
+        .layer(RouteLabelLayer);
 
     let auth_routes_mw_stack = ServiceBuilder::new()
         .layer(HandleErrorLayer::new(handle_mw_err_no_auth))
@@ -130,6 +247,10 @@ async fn main() {
     let public_routes = Router::new()
         .route("/error", get(routes::errortest::handle_errortest))
         .route("/login", get(AuthHandlerData::handle_login))
+        .route(
+            "/hooks/:guild/:script/:name",
+            post(routes::webhooks::handle_webhook),
+        )
         .route(
             "/api/ws",
             get(routes::ws::ws_headler::<CurrentSessionStore>),
@@ -137,11 +258,17 @@ async fn main() {
         .route(
             "/api/confirm_login",
             post(AuthHandlerData::handle_confirm_login),
-        );
+        )
+        .layer(RouteLabelLayer);
 
     let app = public_routes
         .merge(authorized_routes)
-        .layer(common_middleware_stack.clone());
+        .layer(common_middleware_stack.clone())
+        .layer(RequestMetricsLayer);
+
+    tokio::spawn(common::metrics::run_metrics_server(
+        conf.webapi_metrics_listen_addr.clone(),
+    ));
 
     let make_service = app.into_make_service();
 
@@ -161,6 +288,14 @@ async fn todo_route() -> &'static str {
 }
 
 fn handle_mw_err_internal_err(err: BoxError) -> Result<impl IntoResponse, Infallible> {
+    if err.is::<MissingScopeError>() {
+        return Ok(ApiErrorResponse::MissingScope);
+    }
+
+    if err.is::<NotSuperuserError>() {
+        return Ok(ApiErrorResponse::NotSuperuser);
+    }
+
     error!("internal error occured: {}", err);
 
     Ok(ApiErrorResponse::InternalError)
@@ -1,6 +1,6 @@
 use axum::{
-    extract::{FromRequest, Path, RequestParts},
-    http::{Request, Response},
+    extract::{FromRequest, OriginalUri, Path, RequestParts},
+    http::{Method, Request, Response},
     BoxError,
 };
 use core::fmt;
@@ -11,11 +11,19 @@ use std::{
 };
 use tower::{Layer, Service};
 use tracing::Instrument;
-use twilight_model::{guild::Permissions, id::GuildId, user::CurrentUserGuild};
+use twilight_model::{
+    guild::Permissions,
+    id::{GuildId, UserId},
+    user::CurrentUserGuild,
+};
 
-use stores::web::SessionStore;
+use stores::{
+    config::{ConfigStore, GuildAccess, GuildAccessPermission, GuildAccessSubject},
+    web::SessionStore,
+};
 
-use super::LoggedInSession;
+use super::{LoggedInSession, MissingScopeError};
+use crate::CurrentConfigStore;
 
 #[derive(Clone)]
 pub struct CurrentGuildLayer<ST> {
@@ -78,11 +86,25 @@ where
 
             if let (Some(s), Ok(gp)) = (session, guild_path) {
                 if let Some(guild_id) = GuildId::new(gp.guild) {
+                    if let Some(scope) = &s.session.scope {
+                        if !scope.allows_guild(guild_id) {
+                            return Err(MissingScopeError.into());
+                        }
+                    }
+
                     if let Some(g) = fetch_guild(s, guild_id).await? {
                         span = Some(tracing::debug_span!("guild", guild_id=%g.id));
 
+                        let extensions = req_parts.extensions().unwrap();
+                        let config_store = extensions.get::<CurrentConfigStore>().unwrap();
+                        let bot_rpc = extensions.get::<botrpc::Client>().unwrap();
+                        let access =
+                            fetch_guild_access(config_store, bot_rpc, &g, s.session.user.id)
+                                .await?;
+
                         let extensions_mut = req_parts.extensions_mut().unwrap();
                         extensions_mut.insert(g);
+                        extensions_mut.insert(access);
                     }
                 }
             }
@@ -105,6 +127,102 @@ async fn fetch_guild<ST: SessionStore + Send + 'static>(
     Ok(user_guilds.into_iter().find(|e| e.id == guild_id))
 }
 
+/// Returns the botloader permissions the user has on the guild
+///
+/// The owner and members with ADMINISTRATOR or MANAGE_GUILD have full access, everyone else
+/// gets what the guild's access rules grants them.
+pub async fn fetch_guild_access(
+    config_store: &CurrentConfigStore,
+    bot_rpc: &botrpc::Client,
+    guild: &CurrentUserGuild,
+    user_id: UserId,
+) -> Result<GuildAccess, BoxError> {
+    if guild.owner
+        || guild
+            .permissions
+            .intersects(Permissions::ADMINISTRATOR | Permissions::MANAGE_GUILD)
+    {
+        return Ok(GuildAccess::full());
+    }
+
+    let rules = config_store.get_guild_access_rules(guild.id).await?;
+    if rules.is_empty() {
+        return Ok(GuildAccess::default());
+    }
+
+    // only ask the bot for the roles if we need them
+    let roles = if rules
+        .iter()
+        .any(|r| matches!(r.subject, GuildAccessSubject::Role(_)))
+    {
+        bot_rpc
+            .get_member_roles(guild.id, user_id)
+            .await?
+            .unwrap_or_default()
+    } else {
+        Vec::new()
+    };
+
+    Ok(GuildAccess::from_rules(&rules, user_id, &roles))
+}
+
+/// What a member needs to use a guild route
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RequiredGuildAccess {
+    /// The owner or a member with ADMINISTRATOR or MANAGE_GUILD
+    Manager,
+    /// All of these permissions
+    Permissions(&'static [GuildAccessPermission]),
+}
+
+impl RequiredGuildAccess {
+    fn allowed_by(&self, access: &GuildAccess) -> bool {
+        match self {
+            Self::Manager => access.manager,
+            Self::Permissions(perms) => perms.iter().all(|p| access.has(*p)),
+        }
+    }
+}
+
+/// Returns what's needed to use a guild route
+///
+/// Routes not listed here are only available to managers, so new routes need to be added
+/// to be usable by members given access through the access rules.
+fn required_guild_access(method: &Method, path: &str) -> RequiredGuildAccess {
+    use GuildAccessPermission::*;
+
+    let segments = path.trim_matches('/').split('/').collect::<Vec<_>>();
+    let rest = match segments.as_slice() {
+        ["api", "guilds", _, rest @ ..] => rest,
+        _ => return RequiredGuildAccess::Manager,
+    };
+
+    let read = method == Method::GET;
+
+    let perms: &'static [GuildAccessPermission] = match rest {
+        ["access"] if read => &[View],
+        ["settings"] if read => &[View],
+        ["settings"] => &[ManageSettings],
+        ["audit_log"] if read => &[ManageSettings],
+        // dry run, nothing is saved
+        ["validate_script"] => &[View],
+        ["scripts", ..] | ["command_conflicts"] | ["command_sync_status"] | ["webhooks"]
+            if read =>
+        {
+            &[View]
+        }
+        ["scripts", ..] | ["command_conflict_policy"] | ["reload_vm"] => &[EditScripts],
+        ["storage", ..] => &[ManageStorage],
+        ["export"] if read => &[ManageStorage],
+        // replaces scripts, storage and the guild settings
+        ["import"] => &[EditScripts, ManageStorage, ManageSettings],
+        // granting access is limited to managers so it can't be used to escalate
+        _ => return RequiredGuildAccess::Manager,
+    };
+
+    RequiredGuildAccess::Permissions(perms)
+}
+
 pub struct RequireCurrentGuildAuthLayer;
 
 impl<S> Layer<S> for RequireCurrentGuildAuthLayer {
@@ -143,15 +261,22 @@ where
         let mut inner = std::mem::replace(&mut self.inner, clone);
 
         Box::pin(async move {
-            let current_guild = req
-                .extensions()
+            req.extensions()
                 .get::<CurrentUserGuild>()
                 .ok_or(UnknownGuildError)?;
 
-            if !current_guild
-                .permissions
-                .intersects(Permissions::ADMINISTRATOR | Permissions::MANAGE_GUILD)
-            {
+            let access = req
+                .extensions()
+                .get::<GuildAccess>()
+                .ok_or(UnknownGuildError)?;
+
+            // the full path, the nested routers strips part of it
+            let path = match req.extensions().get::<OriginalUri>() {
+                Some(uri) => uri.0.path().to_string(),
+                None => req.uri().path().to_string(),
+            };
+
+            if !required_guild_access(req.method(), &path).allowed_by(access) {
                 return Err(MissingPermsError.into());
             }
 
@@ -176,8 +301,146 @@ pub struct MissingPermsError;
 
 impl Display for MissingPermsError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        f.write_str("missing permissions to manage this guild")
+        f.write_str("missing permissions for this on this guild")
     }
 }
 
 impl std::error::Error for MissingPermsError {}
+
+#[test]
+fn guild_route_access() {
+    use GuildAccessPermission::*;
+
+    let get = Method::GET;
+    let post = Method::POST;
+    let put = Method::PUT;
+    let patch = Method::PATCH;
+    let delete = Method::DELETE;
+
+    let cases: &[(&Method, &str, RequiredGuildAccess)] = &[
+        (&get, "access", RequiredGuildAccess::Permissions(&[View])),
+        (&get, "access_rules", RequiredGuildAccess::Manager),
+        (&put, "access_rules", RequiredGuildAccess::Manager),
+        (&get, "settings", RequiredGuildAccess::Permissions(&[View])),
+        (
+            &patch,
+            "settings",
+            RequiredGuildAccess::Permissions(&[ManageSettings]),
+        ),
+        (
+            &get,
+            "audit_log",
+            RequiredGuildAccess::Permissions(&[ManageSettings]),
+        ),
+        (&get, "scripts", RequiredGuildAccess::Permissions(&[View])),
+        (
+            &put,
+            "scripts",
+            RequiredGuildAccess::Permissions(&[EditScripts]),
+        ),
+        (
+            &patch,
+            "scripts/1",
+            RequiredGuildAccess::Permissions(&[EditScripts]),
+        ),
+        (
+            &delete,
+            "scripts/1",
+            RequiredGuildAccess::Permissions(&[EditScripts]),
+        ),
+        (
+            &get,
+            "scripts/1/revisions/2/diff",
+            RequiredGuildAccess::Permissions(&[View]),
+        ),
+        (
+            &post,
+            "scripts/1/revisions/2/rollback",
+            RequiredGuildAccess::Permissions(&[EditScripts]),
+        ),
+        (
+            &post,
+            "validate_script",
+            RequiredGuildAccess::Permissions(&[View]),
+        ),
+        (
+            &post,
+            "reload_vm",
+            RequiredGuildAccess::Permissions(&[EditScripts]),
+        ),
+        (
+            &get,
+            "command_conflicts",
+            RequiredGuildAccess::Permissions(&[View]),
+        ),
+        (
+            &get,
+            "command_sync_status",
+            RequiredGuildAccess::Permissions(&[View]),
+        ),
+        (
+            &put,
+            "command_conflict_policy",
+            RequiredGuildAccess::Permissions(&[EditScripts]),
+        ),
+        (&get, "webhooks", RequiredGuildAccess::Permissions(&[View])),
+        (
+            &put,
+            "scripts/1/webhooks/deploys",
+            RequiredGuildAccess::Permissions(&[EditScripts]),
+        ),
+        (
+            &delete,
+            "scripts/1/webhooks/deploys",
+            RequiredGuildAccess::Permissions(&[EditScripts]),
+        ),
+        (
+            &get,
+            "storage",
+            RequiredGuildAccess::Permissions(&[ManageStorage]),
+        ),
+        (
+            &patch,
+            "storage/bucket",
+            RequiredGuildAccess::Permissions(&[ManageStorage]),
+        ),
+        (
+            &delete,
+            "storage/bucket/key",
+            RequiredGuildAccess::Permissions(&[ManageStorage]),
+        ),
+        (
+            &get,
+            "export",
+            RequiredGuildAccess::Permissions(&[ManageStorage]),
+        ),
+        (
+            &post,
+            "import",
+            RequiredGuildAccess::Permissions(&[EditScripts, ManageStorage, ManageSettings]),
+        ),
+        (&post, "something_new", RequiredGuildAccess::Manager),
+    ];
+
+    for (method, route, expected) in cases {
+        let path = format!("/api/guilds/1/{}", route);
+        assert_eq!(
+            required_guild_access(method, &path),
+            *expected,
+            "{} {}",
+            method,
+            path
+        );
+    }
+
+    let settings_only = GuildAccess {
+        permissions: vec![View, ManageSettings],
+        manager: false,
+    };
+    assert!(!required_guild_access(&put, "/api/guilds/1/access_rules").allowed_by(&settings_only));
+    assert!(!required_guild_access(&post, "/api/guilds/1/import").allowed_by(&settings_only));
+    assert!(!required_guild_access(&get, "/api/guilds/1/export").allowed_by(&settings_only));
+    assert!(
+        required_guild_access(&put, "/api/guilds/1/access_rules").allowed_by(&GuildAccess::full())
+    );
+}
@@ -0,0 +1,142 @@
+use axum::{
+    body::BoxBody,
+    extract::MatchedPath,
+    http::{Request, Response},
+};
+use futures::future::BoxFuture;
+use lazy_static::lazy_static;
+use prometheus::{register_histogram_vec, HistogramVec};
+use std::{
+    task::{Context, Poll},
+    time::Instant,
+};
+use tower::{Layer, Service};
+
+lazy_static! {
+    static ref REQUEST_LATENCY: HistogramVec = register_histogram_vec!(
+        "botloader_webapi_request_duration_seconds",
+        "Time spent handling webapi requests",
+        &["method", "route", "status"]
+    )
+    .unwrap();
+}
+
+/// The route that handled the request, see [RouteLabelLayer]
+#[derive(Clone)]
+struct RouteLabel(String);
+
+/// Records the latency of requests by route, this has to be the outermost layer
+///
+/// The route is only known after routing, so [RouteLabelLayer] has to be added to the
+/// routers for the requests to be labeled with it.
+#[derive(Clone)]
+pub struct RequestMetricsLayer;
+
+impl<S> Layer<S> for RequestMetricsLayer {
+    type Service = RequestMetricsMiddleware<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RequestMetricsMiddleware { inner }
+    }
+}
+
+#[derive(Clone)]
+pub struct RequestMetricsMiddleware<S> {
+    inner: S,
+}
+
+impl<S, ReqBody> Service<Request<ReqBody>> for RequestMetricsMiddleware<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<BoxBody>> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+    ReqBody: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        // best practice is to clone the inner service like this
+        // see https://github.com/tower-rs/tower/issues/547 for details
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+
+        let started = Instant::now();
+        let method = req.method().clone();
+
+        Box::pin(async move {
+            let resp = inner.call(req).await?;
+
+            let route = resp
+                .extensions()
+                .get::<RouteLabel>()
+                .map(|r| r.0.as_str())
+                .unwrap_or("unmatched");
+
+            REQUEST_LATENCY
+                .with_label_values(&[method.as_str(), route, resp.status().as_str()])
+                .observe(started.elapsed().as_secs_f64());
+
+            Ok(resp)
+        })
+    }
+}
+
+/// Passes the matched route on to [RequestMetricsLayer] through the response
+#[derive(Clone)]
+pub struct RouteLabelLayer;
+
+impl<S> Layer<S> for RouteLabelLayer {
+    type Service = RouteLabelMiddleware<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RouteLabelMiddleware { inner }
+    }
+}
+
+#[derive(Clone)]
+pub struct RouteLabelMiddleware<S> {
+    inner: S,
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for RouteLabelMiddleware<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+    ReqBody: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+
+        let route = req
+            .extensions()
+            .get::<MatchedPath>()
+            .map(|p| p.as_str().to_string());
+
+        Box::pin(async move {
+            let mut resp = inner.call(req).await?;
+
+            // nested routers are routes themselves, the innermost route is the one we want
+            if let Some(route) = route {
+                if resp.extensions().get::<RouteLabel>().is_none() {
+                    resp.extensions_mut().insert(RouteLabel(route));
+                }
+            }
+
+            Ok(resp)
+        })
+    }
+}
@@ -1,7 +1,13 @@
 pub mod cors;
 pub mod guild;
+pub mod metrics;
 pub mod mw_session;
+pub mod scopes;
+pub mod superuser;
 
 pub use cors::*;
 pub use guild::*;
+pub use metrics::*;
 pub use mw_session::*;
+pub use scopes::*;
+pub use superuser::*;
@@ -14,7 +14,9 @@ use std::{
 use tower::{Layer, Service};
 use tracing::{error, Instrument};
 
-use stores::web::{Session, SessionStore};
+use stores::web::{Session, SessionStore, SessionType};
+
+use super::{required_scope, MissingScopeError};
 
 type OAuthApiClientWrapper<ST> =
     DiscordOauthApiClient<TwilightApiProvider, oauth2::basic::BasicClient, ST>;
@@ -114,6 +116,20 @@ where
             match auth_header.map(|e| e.to_str()) {
                 Some(Ok(t)) => {
                     if let Some(session) = store.get_session(t).await? {
+                        if let Some(scope) = &session.scope {
+                            if !required_scope(req.method(), req.uri().path()).allowed_by(scope) {
+                                return Err(MissingScopeError.into());
+                            }
+                        }
+
+                        if should_touch_session(&session) {
+                            store
+                                .touch_session(&session.token)
+                                .await
+                                .map_err(|err| error!(%err, "failed updating session last used"))
+                                .ok();
+                        }
+
                         let extensions = req.extensions_mut();
 
                         let span = tracing::debug_span!("session", user_id=%session.user.id);
@@ -162,6 +178,18 @@ where
     }
 }
 
+/// Api keys keep track of when they were last used, updated at most once a minute
+fn should_touch_session(session: &Session) -> bool {
+    if !matches!(session.kind, SessionType::ApiKey) {
+        return false;
+    }
+
+    match session.last_used_at {
+        Some(t) => chrono::Utc::now() - t > chrono::Duration::minutes(1),
+        None => true,
+    }
+}
+
 #[derive(Clone)]
 pub struct RequireAuthLayer<ST> {
     _phantom: PhantomData<ST>,
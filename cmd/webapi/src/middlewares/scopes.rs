@@ -0,0 +1,133 @@
+use core::fmt;
+use std::fmt::Display;
+
+use axum::http::Method;
+use stores::web::{ApiKeyPermission, ApiKeyScope};
+
+/// What a scoped api key needs to be allowed to use a route
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RequiredScope {
+    /// Any session can use the route
+    Any,
+    Permission(ApiKeyPermission),
+    /// Only sessions that aren't scoped can use the route
+    Unscoped,
+}
+
+impl RequiredScope {
+    pub fn allowed_by(&self, scope: &ApiKeyScope) -> bool {
+        match self {
+            Self::Any => true,
+            Self::Permission(p) => scope.allows(*p),
+            Self::Unscoped => false,
+        }
+    }
+}
+
+/// Returns the scope required for a route
+///
+/// Routes not listed here can't be used with scoped api keys, so new routes need to be added
+/// to be usable by them.
+pub fn required_scope(method: &Method, path: &str) -> RequiredScope {
+    let segments = path.trim_matches('/').split('/').collect::<Vec<_>>();
+
+    match segments.as_slice() {
+        ["api", "guilds"] | ["api", "current_user"] | ["api", "logout"] => RequiredScope::Any,
+        ["api", "libraries", ..] if method == Method::GET => RequiredScope::Any,
+        ["api", "guilds", _, rest @ ..] => guild_route_scope(method, rest),
+        _ => RequiredScope::Unscoped,
+    }
+}
+
+fn guild_route_scope(method: &Method, path: &[&str]) -> RequiredScope {
+    let read = method == Method::GET;
+
+    let permission = match path {
+        ["access"] | ["settings"] if read => return RequiredScope::Any,
+        ["reload_vm"] => ApiKeyPermission::ReloadVm,
+        // dry run, nothing is saved
+        ["validate_script"] => ApiKeyPermission::ReadScripts,
+        ["webhooks"] if read => ApiKeyPermission::ReadScripts,
+        // creating a webhook hands out its secret
+        ["scripts", _, "webhooks", ..] => return RequiredScope::Unscoped,
+        ["scripts", ..]
+        | ["command_conflicts"]
+        | ["command_conflict_policy"]
+        | ["command_sync_status"] => {
+            if read {
+                ApiKeyPermission::ReadScripts
+            } else {
+                ApiKeyPermission::WriteScripts
+            }
+        }
+        ["storage", ..] => {
+            if read {
+                ApiKeyPermission::ReadStorage
+            } else {
+                ApiKeyPermission::WriteStorage
+            }
+        }
+        _ => return RequiredScope::Unscoped,
+    };
+
+    RequiredScope::Permission(permission)
+}
+
+#[derive(Debug)]
+pub struct MissingScopeError;
+
+impl Display for MissingScopeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("api key is not allowed to use this route")
+    }
+}
+
+impl std::error::Error for MissingScopeError {}
+
+#[test]
+fn scoped_routes() {
+    let put = Method::PUT;
+    let get = Method::GET;
+
+    assert_eq!(
+        required_scope(&put, "/api/guilds/1/scripts"),
+        RequiredScope::Permission(ApiKeyPermission::WriteScripts)
+    );
+    assert_eq!(
+        required_scope(&get, "/api/guilds/1/scripts/2/revisions"),
+        RequiredScope::Permission(ApiKeyPermission::ReadScripts)
+    );
+    assert_eq!(
+        required_scope(&Method::DELETE, "/api/guilds/1/storage/bucket/key"),
+        RequiredScope::Permission(ApiKeyPermission::WriteStorage)
+    );
+    assert_eq!(required_scope(&get, "/api/guilds"), RequiredScope::Any);
+    assert_eq!(
+        required_scope(&get, "/api/guilds/1/access"),
+        RequiredScope::Any
+    );
+    assert_eq!(
+        required_scope(&Method::PATCH, "/api/guilds/1/settings"),
+        RequiredScope::Unscoped
+    );
+    assert_eq!(
+        required_scope(&put, "/api/guilds/1/scripts/2/webhooks/deploys"),
+        RequiredScope::Unscoped
+    );
+    assert_eq!(
+        required_scope(&put, "/api/sessions"),
+        RequiredScope::Unscoped
+    );
+    assert_eq!(
+        required_scope(&get, "/api/guilds/1/export"),
+        RequiredScope::Unscoped
+    );
+    assert_eq!(
+        required_scope(&put, "/api/libraries"),
+        RequiredScope::Unscoped
+    );
+    assert_eq!(
+        required_scope(&put, "/api/admin/whitelist/1"),
+        RequiredScope::Unscoped
+    );
+}
@@ -0,0 +1,89 @@
+use axum::{
+    http::{Request, Response},
+    BoxError,
+};
+use core::fmt;
+use futures::future::BoxFuture;
+use std::{
+    fmt::Display,
+    sync::Arc,
+    task::{Context, Poll},
+};
+use tower::{Layer, Service};
+use twilight_model::id::UserId;
+
+use super::LoggedInSession;
+use crate::CurrentSessionStore;
+
+/// Only lets the configured superusers through, used for the admin routes
+#[derive(Clone)]
+pub struct RequireSuperuserLayer {
+    pub superusers: Arc<Vec<UserId>>,
+}
+
+impl<S> Layer<S> for RequireSuperuserLayer {
+    type Service = RequireSuperuserMiddleware<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RequireSuperuserMiddleware {
+            superusers: self.superusers.clone(),
+            inner,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct RequireSuperuserMiddleware<S> {
+    inner: S,
+    superusers: Arc<Vec<UserId>>,
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for RequireSuperuserMiddleware<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+    S::Error: Into<BoxError>,
+    ReqBody: Send + 'static,
+    ResBody: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = BoxError;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(|e| e.into())
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        // best practice is to clone the inner service like this
+        // see https://github.com/tower-rs/tower/issues/547 for details
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let superusers = self.superusers.clone();
+
+        Box::pin(async move {
+            let is_superuser = req
+                .extensions()
+                .get::<LoggedInSession<CurrentSessionStore>>()
+                .map(|s| superusers.contains(&s.session.user.id))
+                .unwrap_or(false);
+
+            if !is_superuser {
+                return Err(NotSuperuserError.into());
+            }
+
+            inner.call(req).await.map_err(|e| e.into())
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct NotSuperuserError;
+
+impl Display for NotSuperuserError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("only superusers can use this route")
+    }
+}
+
+impl std::error::Error for NotSuperuserError {}
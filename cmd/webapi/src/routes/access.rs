@@ -0,0 +1,107 @@
+use axum::{extract::Extension, response::IntoResponse, Json};
+use serde::Deserialize;
+use stores::config::{AuditLogAction, ConfigStore, GuildAccess, GuildAccessRule};
+use tracing::error;
+use twilight_model::user::CurrentUserGuild;
+use validation::ValidationError;
+
+use crate::{
+    errors::ApiErrorResponse,
+    middlewares::LoggedInSession,
+    routes::audit_log::{audit_entry, record_audit_log},
+    ApiResult, CurrentConfigStore, CurrentSessionStore,
+};
+
+const MAX_ACCESS_RULES: usize = 100;
+
+/// Returns the botloader permissions the current user has on the guild
+pub async fn get_current_access(
+    Extension(access): Extension<GuildAccess>,
+) -> ApiResult<impl IntoResponse> {
+    Ok(Json(access))
+}
+
+pub async fn get_access_rules(
+    Extension(config_store): Extension<CurrentConfigStore>,
+    Extension(current_guild): Extension<CurrentUserGuild>,
+) -> ApiResult<impl IntoResponse> {
+    let rules = config_store
+        .get_guild_access_rules(current_guild.id)
+        .await
+        .map_err(|err| {
+            error!(%err, "failed fetching guild access rules");
+            ApiErrorResponse::InternalError
+        })?;
+
+    Ok(Json(rules))
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SetAccessRulesData {
+    pub rules: Vec<GuildAccessRule>,
+}
+
+/// Replaces all the guild's access rules
+pub async fn set_access_rules(
+    Extension(config_store): Extension<CurrentConfigStore>,
+    Extension(session): Extension<LoggedInSession<CurrentSessionStore>>,
+    Extension(current_guild): Extension<CurrentUserGuild>,
+    Json(payload): Json<SetAccessRulesData>,
+) -> ApiResult<impl IntoResponse> {
+    let errs = validate_access_rules(&payload.rules);
+    if !errs.is_empty() {
+        return Err(ApiErrorResponse::ValidationFailed(errs));
+    }
+
+    let before = config_store
+        .get_guild_access_rules(current_guild.id)
+        .await
+        .map_err(|err| {
+            error!(%err, "failed fetching guild access rules");
+            ApiErrorResponse::InternalError
+        })?;
+
+    let rules = config_store
+        .set_guild_access_rules(current_guild.id, payload.rules)
+        .await
+        .map_err(|err| {
+            error!(%err, "failed updating guild access rules");
+            ApiErrorResponse::InternalError
+        })?;
+
+    let mut entry = audit_entry(&session, AuditLogAction::SetAccessRules, None);
+    entry.before = Some(serde_json::json!(before));
+    entry.after = Some(serde_json::json!(rules));
+    record_audit_log(&config_store, current_guild.id, entry).await;
+
+    Ok(Json(rules))
+}
+
+fn validate_access_rules(rules: &[GuildAccessRule]) -> Vec<ValidationError> {
+    let mut errs = Vec::new();
+
+    if rules.len() > MAX_ACCESS_RULES {
+        errs.push(ValidationError {
+            field: "rules".to_string(),
+            msg: format!("no more than {} rules allowed", MAX_ACCESS_RULES),
+        });
+    }
+
+    for (i, rule) in rules.iter().enumerate() {
+        if rule.permissions.is_empty() {
+            errs.push(ValidationError {
+                field: format!("rules.{}.permissions", i),
+                msg: "rule has to grant at least one permission".to_string(),
+            });
+        }
+
+        if rules[..i].iter().any(|r| r.subject == rule.subject) {
+            errs.push(ValidationError {
+                field: format!("rules.{}.subject", i),
+                msg: "duplicate rule for this role or user".to_string(),
+            });
+        }
+    }
+
+    errs
+}
@@ -0,0 +1,160 @@
+use axum::{
+    extract::{Extension, Path, Query},
+    response::IntoResponse,
+    Json,
+};
+use serde::Deserialize;
+use stores::config::{AuditLogAction, ConfigStore};
+use tracing::error;
+use twilight_model::id::GuildId;
+
+use crate::{
+    errors::ApiErrorResponse,
+    middlewares::LoggedInSession,
+    routes::audit_log::{admin_audit_entry, record_audit_log},
+    util::EmptyResponse,
+    ApiResult, CurrentConfigStore, CurrentSessionStore,
+};
+
+fn parse_guild_id(guild: u64) -> ApiResult<GuildId> {
+    GuildId::new(guild).ok_or(ApiErrorResponse::NotFound)
+}
+
+pub async fn list_whitelisted_guilds(
+    Extension(config_store): Extension<CurrentConfigStore>,
+) -> ApiResult<impl IntoResponse> {
+    let guilds = config_store
+        .list_whitelisted_guilds()
+        .await
+        .map_err(|err| {
+            error!(%err, "failed fetching guild whitelist");
+            ApiErrorResponse::InternalError
+        })?;
+
+    Ok(Json(guilds))
+}
+
+pub async fn whitelist_guild(
+    Extension(config_store): Extension<CurrentConfigStore>,
+    Extension(session): Extension<LoggedInSession<CurrentSessionStore>>,
+    Path(guild): Path<u64>,
+) -> ApiResult<impl IntoResponse> {
+    let guild_id = parse_guild_id(guild)?;
+
+    let entry = config_store
+        .add_guild_whitelist(guild_id)
+        .await
+        .map_err(|err| {
+            error!(%err, "failed adding guild to whitelist");
+            ApiErrorResponse::InternalError
+        })?;
+
+    let mut audit = admin_audit_entry(&session, AuditLogAction::WhitelistGuild, None);
+    audit.after = Some(serde_json::json!(entry));
+    record_audit_log(&config_store, guild_id, audit).await;
+
+    Ok(Json(entry))
+}
+
+/// Removes the guild from the whitelist, the bot leaves it the next time the guild is received
+/// from the gateway
+pub async fn unwhitelist_guild(
+    Extension(config_store): Extension<CurrentConfigStore>,
+    Extension(session): Extension<LoggedInSession<CurrentSessionStore>>,
+    Path(guild): Path<u64>,
+) -> ApiResult<impl IntoResponse> {
+    let guild_id = parse_guild_id(guild)?;
+
+    let deleted = config_store
+        .del_guild_whitelist(guild_id)
+        .await
+        .map_err(|err| {
+            error!(%err, "failed removing guild from whitelist");
+            ApiErrorResponse::InternalError
+        })?;
+
+    if !deleted {
+        return Err(ApiErrorResponse::NotFound);
+    }
+
+    record_audit_log(
+        &config_store,
+        guild_id,
+        admin_audit_entry(&session, AuditLogAction::UnwhitelistGuild, None),
+    )
+    .await;
+
+    Ok(EmptyResponse)
+}
+
+#[derive(Deserialize)]
+pub struct ListJoinedGuildsQuery {
+    /// Only return guilds with a higher id than this
+    after: Option<u64>,
+    limit: Option<u32>,
+}
+
+/// Returns the guilds the bot is in, ordered by id
+pub async fn list_joined_guilds(
+    Extension(config_store): Extension<CurrentConfigStore>,
+    Query(query): Query<ListJoinedGuildsQuery>,
+) -> ApiResult<impl IntoResponse> {
+    let limit = query.limit.unwrap_or(100).min(1000);
+
+    let guilds = config_store
+        .list_joined_guilds(query.after.and_then(GuildId::new), limit)
+        .await
+        .map_err(|err| {
+            error!(%err, "failed fetching joined guilds");
+            ApiErrorResponse::InternalError
+        })?;
+
+    Ok(Json(guilds))
+}
+
+pub async fn reload_guild_vm(
+    Extension(bot_rpc): Extension<botrpc::Client>,
+    Extension(config_store): Extension<CurrentConfigStore>,
+    Extension(session): Extension<LoggedInSession<CurrentSessionStore>>,
+    Path(guild): Path<u64>,
+) -> ApiResult<impl IntoResponse> {
+    let guild_id = parse_guild_id(guild)?;
+
+    bot_rpc.restart_guild_vm(guild_id).await.map_err(|err| {
+        error!(%err, "failed reloading guild vm");
+        ApiErrorResponse::InternalError
+    })?;
+
+    record_audit_log(
+        &config_store,
+        guild_id,
+        admin_audit_entry(&session, AuditLogAction::ReloadVm, None),
+    )
+    .await;
+
+    Ok(EmptyResponse)
+}
+
+/// Shuts down the guild's vm until it's reloaded
+pub async fn unload_guild_vm(
+    Extension(bot_rpc): Extension<botrpc::Client>,
+    Extension(config_store): Extension<CurrentConfigStore>,
+    Extension(session): Extension<LoggedInSession<CurrentSessionStore>>,
+    Path(guild): Path<u64>,
+) -> ApiResult<impl IntoResponse> {
+    let guild_id = parse_guild_id(guild)?;
+
+    bot_rpc.unload_guild_vm(guild_id).await.map_err(|err| {
+        error!(%err, "failed unloading guild vm");
+        ApiErrorResponse::InternalError
+    })?;
+
+    record_audit_log(
+        &config_store,
+        guild_id,
+        admin_audit_entry(&session, AuditLogAction::UnloadVm, None),
+    )
+    .await;
+
+    Ok(EmptyResponse)
+}
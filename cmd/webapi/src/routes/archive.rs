@@ -0,0 +1,86 @@
+use axum::{extract::Extension, response::IntoResponse, Json};
+use guild_archive::{GuildArchive, ImportOptions};
+use serde::Deserialize;
+use stores::config::AuditLogAction;
+use tracing::error;
+use twilight_model::user::CurrentUserGuild;
+use validation::ValidationError;
+
+use crate::{
+    errors::ApiErrorResponse,
+    middlewares::LoggedInSession,
+    routes::audit_log::{audit_entry, record_audit_log},
+    ApiResult, CurrentConfigStore, CurrentSessionStore,
+};
+
+pub async fn export_guild(
+    Extension(config_store): Extension<CurrentConfigStore>,
+    Extension(current_guild): Extension<CurrentUserGuild>,
+) -> ApiResult<impl IntoResponse> {
+    let archive = guild_archive::export_guild(&config_store, current_guild.id)
+        .await
+        .map_err(|err| {
+            error!(%err, "failed exporting guild");
+            ApiErrorResponse::InternalError
+        })?;
+
+    Ok(Json(archive))
+}
+
+#[derive(Deserialize)]
+pub struct ImportRequestData {
+    archive: GuildArchive,
+    #[serde(flatten)]
+    options: ImportOptions,
+}
+
+pub async fn import_guild(
+    Extension(config_store): Extension<CurrentConfigStore>,
+    Extension(bot_rpc): Extension<botrpc::Client>,
+    Extension(session): Extension<LoggedInSession<CurrentSessionStore>>,
+    Extension(current_guild): Extension<CurrentUserGuild>,
+    Json(payload): Json<ImportRequestData>,
+) -> ApiResult<impl IntoResponse> {
+    let report = guild_archive::import_guild(
+        &config_store,
+        current_guild.id,
+        &payload.archive,
+        &payload.options,
+    )
+    .await
+    .map_err(|err| match err {
+        guild_archive::ArchiveError::UnsupportedVersion(_) => {
+            ApiErrorResponse::ValidationFailed(vec![ValidationError {
+                field: "archive.version".to_string(),
+                msg: err.to_string(),
+            }])
+        }
+        _ => {
+            error!(%err, "failed importing guild");
+            ApiErrorResponse::InternalError
+        }
+    })?;
+
+    if report.applied {
+        let mut entry = audit_entry(&session, AuditLogAction::ImportArchive, None);
+        entry.after = Some(serde_json::json!({
+            "archive_guild_id": payload.archive.guild_id,
+            "archive_created_at": payload.archive.created_at,
+            "overwrite": payload.options.overwrite,
+            "num_scripts": report.num_scripts,
+            "num_storage_entries": report.num_storage_entries,
+            "num_interval_timers": report.num_interval_timers,
+        }));
+        record_audit_log(&config_store, current_guild.id, entry).await;
+
+        bot_rpc
+            .restart_guild_vm(current_guild.id)
+            .await
+            .map_err(|err| {
+                error!(%err, "failed reloading guild vm after import");
+                ApiErrorResponse::InternalError
+            })?;
+    }
+
+    Ok(Json(report))
+}
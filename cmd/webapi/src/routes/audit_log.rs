@@ -0,0 +1,87 @@
+use axum::{
+    extract::{Extension, Query},
+    response::IntoResponse,
+    Json,
+};
+use serde::Deserialize;
+use stores::{
+    config::{AuditLogAction, AuditLogSource, ConfigStore, CreateAuditLogEntry},
+    web::SessionType,
+};
+use tracing::error;
+use twilight_model::{id::GuildId, user::CurrentUserGuild};
+
+use crate::{
+    errors::ApiErrorResponse, middlewares::LoggedInSession, ApiResult, CurrentConfigStore,
+};
+
+#[derive(Deserialize)]
+pub struct ListAuditLogQuery {
+    /// Only return entries older than this entry
+    before: Option<u64>,
+    limit: Option<u32>,
+}
+
+/// Returns the guild's audit log, newest first
+pub async fn list_audit_log(
+    Extension(config_store): Extension<CurrentConfigStore>,
+    Extension(current_guild): Extension<CurrentUserGuild>,
+    Query(query): Query<ListAuditLogQuery>,
+) -> ApiResult<impl IntoResponse> {
+    let limit = query.limit.unwrap_or(25).min(100);
+
+    let entries = config_store
+        .list_audit_log_entries(current_guild.id, query.before, limit)
+        .await
+        .map_err(|err| {
+            error!(%err, "failed fetching audit log");
+            ApiErrorResponse::InternalError
+        })?;
+
+    Ok(Json(entries))
+}
+
+/// Creates an audit log entry for an action taken by the session's user
+pub fn audit_entry<ST>(
+    session: &LoggedInSession<ST>,
+    action: AuditLogAction,
+    target: Option<String>,
+) -> CreateAuditLogEntry {
+    CreateAuditLogEntry {
+        actor_id: session.session.user.id,
+        source: match session.session.kind {
+            SessionType::User => AuditLogSource::Web,
+            SessionType::ApiKey => AuditLogSource::ApiKey,
+        },
+        action,
+        target,
+        before: None,
+        after: None,
+    }
+}
+
+/// Creates an audit log entry for an action taken by a superuser through the admin api
+pub fn admin_audit_entry<ST>(
+    session: &LoggedInSession<ST>,
+    action: AuditLogAction,
+    target: Option<String>,
+) -> CreateAuditLogEntry {
+    CreateAuditLogEntry {
+        source: AuditLogSource::Admin,
+        ..audit_entry(session, action, target)
+    }
+}
+
+/// Records an action in the audit log
+///
+/// This is done after the action was taken so failing here only gets logged, the request
+/// still succeeds.
+pub async fn record_audit_log(
+    config_store: &CurrentConfigStore,
+    guild_id: GuildId,
+    entry: CreateAuditLogEntry,
+) {
+    if let Err(err) = config_store.add_audit_log_entry(guild_id, entry).await {
+        error!(%err, "failed adding audit log entry");
+    }
+}
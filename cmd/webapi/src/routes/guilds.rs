@@ -22,7 +22,7 @@ pub async fn list_user_guilds_route<ST: SessionStore + 'static, CT: ConfigStore
     Extension(config_store): Extension<CT>,
     Extension(session): Extension<LoggedInSession<ST>>,
 ) -> ApiResult<impl IntoResponse> {
-    let user_guilds = session
+    let mut user_guilds = session
         .api_client
         .current_user_guilds()
         .await
@@ -31,6 +31,11 @@ pub async fn list_user_guilds_route<ST: SessionStore + 'static, CT: ConfigStore
             ApiErrorResponse::InternalError
         })?;
 
+    // scoped api keys only see the guilds they're allowed to use
+    if let Some(scope) = &session.session.scope {
+        user_guilds.retain(|g| scope.allows_guild(g.id));
+    }
+
     let guild_ids = user_guilds.iter().map(|g| g.id).collect::<Vec<_>>();
 
     let connected_guilds = config_store
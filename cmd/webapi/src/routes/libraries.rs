@@ -0,0 +1,139 @@
+use axum::{
+    extract::{Extension, Path},
+    response::IntoResponse,
+    Json,
+};
+use serde::Deserialize;
+use stores::config::{ConfigStore, ConfigStoreError, CreateLibraryModule};
+use tracing::error;
+use validation::{validate, ValidationError};
+
+use crate::{
+    errors::ApiErrorResponse, middlewares::LoggedInSession, ApiResult, CurrentConfigStore,
+    CurrentSessionStore,
+};
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct PublishLibraryRequestData {
+    pub name: String,
+    pub version: String,
+    pub original_source: String,
+}
+
+pub async fn publish_library_module(
+    Extension(config_store): Extension<CurrentConfigStore>,
+    Extension(session): Extension<LoggedInSession<CurrentSessionStore>>,
+    Json(payload): Json<PublishLibraryRequestData>,
+) -> ApiResult<impl IntoResponse> {
+    let mut module = CreateLibraryModule {
+        name: payload.name,
+        version: payload.version,
+        author_id: Some(session.session.user.id),
+        original_source: payload.original_source,
+        compiled_source: String::new(),
+    };
+
+    if let Err(verr) = validate(&module) {
+        return Err(ApiErrorResponse::ValidationFailed(verr));
+    }
+
+    module.compiled_source =
+        compile_library(&module.original_source).map_err(source_validation_err)?;
+
+    let module = config_store
+        .publish_library_module(module)
+        .await
+        .map_err(|err| match err {
+            ConfigStoreError::LibraryVersionExists => {
+                ApiErrorResponse::ValidationFailed(vec![ValidationError {
+                    field: "version".to_string(),
+                    msg: "this version has already been published".to_string(),
+                }])
+            }
+            ConfigStoreError::LibraryNotOwned => ApiErrorResponse::NotLibraryOwner,
+            _ => {
+                error!(%err, "failed publishing library module");
+                ApiErrorResponse::InternalError
+            }
+        })?;
+
+    Ok(Json(module))
+}
+
+/// Compiles the library, libraries run without a script header and can only import the built in modules
+fn compile_library(source: &str) -> Result<String, String> {
+    let compiled = tscompiler::compile_typescript(source)
+        .map_err(|err| format!("failed compiling library: {}", err))?;
+
+    if let Some(import) = compiled
+        .imports
+        .iter()
+        .find(|import| import.starts_with('.') || import.starts_with("lib:"))
+    {
+        return Err(format!(
+            "libraries can only import built in modules, found import of {}",
+            import
+        ));
+    }
+
+    if compiled.free_identifiers.contains("script") {
+        return Err(
+            "libraries can't use the script global, export functions that take the script as a \
+             parameter instead"
+                .to_string(),
+        );
+    }
+
+    Ok(compiled.output)
+}
+
+fn source_validation_err(msg: String) -> ApiErrorResponse {
+    ApiErrorResponse::ValidationFailed(vec![ValidationError {
+        field: "original_source".to_string(),
+        msg,
+    }])
+}
+
+#[derive(Deserialize)]
+pub struct LibraryPathParams {
+    name: String,
+}
+
+pub async fn list_library_module_versions(
+    Extension(config_store): Extension<CurrentConfigStore>,
+    Path(LibraryPathParams { name }): Path<LibraryPathParams>,
+) -> ApiResult<impl IntoResponse> {
+    let versions = config_store
+        .list_library_module_versions(&name)
+        .await
+        .map_err(|err| {
+            error!(%err, "failed fetching library module versions");
+            ApiErrorResponse::InternalError
+        })?;
+
+    Ok(Json(versions))
+}
+
+#[derive(Deserialize)]
+pub struct LibraryVersionPathParams {
+    name: String,
+    version: String,
+}
+
+pub async fn get_library_module(
+    Extension(config_store): Extension<CurrentConfigStore>,
+    Path(LibraryVersionPathParams { name, version }): Path<LibraryVersionPathParams>,
+) -> ApiResult<impl IntoResponse> {
+    let module = config_store
+        .get_library_module(&name, &version)
+        .await
+        .map_err(|err| match err {
+            ConfigStoreError::LibraryNotFound => ApiErrorResponse::NotFound,
+            _ => {
+                error!(%err, "failed fetching library module");
+                ApiErrorResponse::InternalError
+            }
+        })?;
+
+    Ok(Json(module))
+}
@@ -1,8 +1,16 @@
+pub mod access;
+pub mod admin;
+pub mod archive;
+pub mod audit_log;
 pub mod auth;
 pub mod errortest;
 pub mod general;
 pub mod guilds;
+pub mod libraries;
 pub mod scripts;
 pub mod sessions;
+pub mod settings;
+pub mod storage;
 pub mod vm;
+pub mod webhooks;
 pub mod ws;
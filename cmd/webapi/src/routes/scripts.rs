@@ -1,15 +1,27 @@
+use std::sync::Arc;
+
 use axum::{
-    extract::{Extension, Path},
+    extract::{Extension, Path, Query},
     response::IntoResponse,
     Json,
 };
-use serde::Deserialize;
-use stores::config::{ConfigStore, CreateScript, UpdateScript};
+use serde::{Deserialize, Serialize};
+use stores::config::{
+    find_command_conflicts, AuditLogAction, CommandConflict, CommandConflictPolicy,
+    CommandSyncState, ConfigStore, ConfigStoreError, CreateScript, GuildCommandSyncStatus,
+    UpdateScript,
+};
 use tracing::error;
+use tscompiler::typecheck::TypeChecker;
 use twilight_model::user::CurrentUserGuild;
 use validation::validate;
 
-use crate::{errors::ApiErrorResponse, ApiResult, CurrentConfigStore};
+use crate::{
+    errors::ApiErrorResponse,
+    middlewares::LoggedInSession,
+    routes::audit_log::{audit_entry, record_audit_log},
+    ApiResult, CurrentConfigStore, CurrentSessionStore,
+};
 
 pub async fn get_all_guild_scripts(
     Extension(config_store): Extension<CurrentConfigStore>,
@@ -40,6 +52,8 @@ pub struct CreateRequestData {
 
 pub async fn create_guild_script(
     Extension(config_store): Extension<CurrentConfigStore>,
+    Extension(type_checker): Extension<Option<Arc<TypeChecker>>>,
+    Extension(session): Extension<LoggedInSession<CurrentSessionStore>>,
     Extension(current_guild): Extension<CurrentUserGuild>,
     Json(payload): Json<CreateRequestData>,
 ) -> ApiResult<impl IntoResponse> {
@@ -47,11 +61,13 @@ pub async fn create_guild_script(
         enabled: payload.enabled,
         original_source: payload.original_source,
         name: payload.name,
+        author_id: Some(session.session.user.id),
     };
 
     if let Err(verr) = validate(&cs) {
         return Err(ApiErrorResponse::ValidationFailed(verr));
     }
+    type_check_source(type_checker, &cs.original_source).await?;
 
     let script = config_store
         .create_script(current_guild.id, cs)
@@ -61,6 +77,14 @@ pub async fn create_guild_script(
             ApiErrorResponse::InternalError
         })?;
 
+    let mut entry = audit_entry(
+        &session,
+        AuditLogAction::CreateScript,
+        Some(script.name.clone()),
+    );
+    entry.after = Some(script.audit_summary());
+    record_audit_log(&config_store, current_guild.id, entry).await;
+
     Ok(Json(script))
 }
 
@@ -73,6 +97,8 @@ pub struct UpdateRequestData {
 
 pub async fn update_guild_script(
     Extension(config_store): Extension<CurrentConfigStore>,
+    Extension(type_checker): Extension<Option<Arc<TypeChecker>>>,
+    Extension(session): Extension<LoggedInSession<CurrentSessionStore>>,
     Extension(current_guild): Extension<CurrentUserGuild>,
     Path(GuildScriptPathParams { script_id }): Path<GuildScriptPathParams>,
     Json(payload): Json<UpdateRequestData>,
@@ -83,11 +109,21 @@ pub async fn update_guild_script(
         original_source: payload.original_source,
         name: payload.name,
         contributes: None,
+        author_id: Some(session.session.user.id),
     };
 
     if let Err(verr) = validate(&sc) {
         return Err(ApiErrorResponse::ValidationFailed(verr));
     }
+    type_check_source(type_checker, &sc.original_source).await?;
+
+    let before = config_store
+        .get_script_by_id(current_guild.id, script_id)
+        .await
+        .map_err(|err| {
+            error!(%err, "failed fetching guild script");
+            ApiErrorResponse::InternalError
+        })?;
 
     let script = config_store
         .update_script(current_guild.id, sc)
@@ -97,11 +133,123 @@ pub async fn update_guild_script(
             ApiErrorResponse::InternalError
         })?;
 
+    for action in AuditLogAction::for_script_update(&before, &script) {
+        let mut entry = audit_entry(&session, action, Some(script.name.clone()));
+        entry.before = Some(before.audit_summary());
+        entry.after = Some(script.audit_summary());
+        record_audit_log(&config_store, current_guild.id, entry).await;
+    }
+
     Ok(Json(script))
 }
 
+/// Runs the type checker on the source if one is configured
+///
+/// Type checking is best effort, if the checker itself fails the script is saved anyway.
+async fn type_check_source(type_checker: Option<Arc<TypeChecker>>, source: &str) -> ApiResult<()> {
+    let type_checker = match type_checker {
+        Some(type_checker) => type_checker,
+        None => return Ok(()),
+    };
+
+    let source = source.to_string();
+    match tokio::task::spawn_blocking(move || type_checker.check(&source)).await {
+        Ok(Ok(diagnostics)) if diagnostics.is_empty() => Ok(()),
+        Ok(Ok(diagnostics)) => Err(ApiErrorResponse::ValidationFailed(
+            diagnostics.into_iter().map(Into::into).collect(),
+        )),
+        Ok(Err(err)) => {
+            error!(%err, "failed type checking script");
+            Ok(())
+        }
+        Err(err) => {
+            error!(%err, "type checking task failed");
+            Ok(())
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ValidateRequestData {
+    pub name: String,
+    pub original_source: String,
+    /// The script being updated, conflicts with its current version are ignored
+    pub script_id: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ValidateScriptResponse {
+    /// Set if the script failed compiling or running
+    pub error: Option<String>,
+    /// The ScriptMeta of the script, with the commands, command groups, interval timers
+    /// and storage buckets it would register
+    pub meta: Option<serde_json::Value>,
+    pub conflicts: Vec<CommandConflict>,
+}
+
+/// Dry runs a script without saving it
+pub async fn validate_guild_script(
+    Extension(bot_rpc): Extension<botrpc::Client>,
+    Extension(type_checker): Extension<Option<Arc<TypeChecker>>>,
+    Extension(current_guild): Extension<CurrentUserGuild>,
+    Json(payload): Json<ValidateRequestData>,
+) -> ApiResult<impl IntoResponse> {
+    let cs = CreateScript {
+        enabled: true,
+        original_source: payload.original_source,
+        name: payload.name,
+        author_id: None,
+    };
+
+    if let Err(verr) = validate(&cs) {
+        return Err(ApiErrorResponse::ValidationFailed(verr));
+    }
+    type_check_source(type_checker, &cs.original_source).await?;
+
+    let resp = bot_rpc
+        .validate_script(
+            current_guild.id,
+            payload.script_id,
+            cs.name,
+            cs.original_source,
+        )
+        .await
+        .map_err(|err| {
+            error!(%err, "failed validating script");
+            ApiErrorResponse::InternalError
+        })?;
+
+    if !resp.error.is_empty() {
+        return Ok(Json(ValidateScriptResponse {
+            error: Some(resp.error),
+            meta: None,
+            conflicts: Vec::new(),
+        }));
+    }
+
+    let meta = serde_json::from_str(&resp.script_meta_json).map_err(|err| {
+        error!(%err, "failed decoding script meta");
+        ApiErrorResponse::InternalError
+    })?;
+
+    Ok(Json(ValidateScriptResponse {
+        error: None,
+        meta: Some(meta),
+        conflicts: resp
+            .conflicts
+            .into_iter()
+            .map(|c| CommandConflict {
+                command: c.command,
+                other_script_id: c.other_script_id,
+                other_script_name: c.other_script_name,
+            })
+            .collect(),
+    }))
+}
+
 pub async fn delete_guild_script(
     Extension(config_store): Extension<CurrentConfigStore>,
+    Extension(session): Extension<LoggedInSession<CurrentSessionStore>>,
     Extension(current_guild): Extension<CurrentUserGuild>,
     Path(GuildScriptPathParams { script_id }): Path<GuildScriptPathParams>,
 ) -> ApiResult<impl IntoResponse> {
@@ -121,5 +269,299 @@ pub async fn delete_guild_script(
             ApiErrorResponse::InternalError
         })?;
 
+    let mut entry = audit_entry(
+        &session,
+        AuditLogAction::DeleteScript,
+        Some(script.name.clone()),
+    );
+    entry.before = Some(script.audit_summary());
+    record_audit_log(&config_store, current_guild.id, entry).await;
+
     Ok(Json(script))
 }
+
+pub async fn list_script_revisions(
+    Extension(config_store): Extension<CurrentConfigStore>,
+    Extension(current_guild): Extension<CurrentUserGuild>,
+    Path(GuildScriptPathParams { script_id }): Path<GuildScriptPathParams>,
+) -> ApiResult<impl IntoResponse> {
+    let revisions = config_store
+        .list_script_revisions(current_guild.id, script_id)
+        .await
+        .map_err(|err| {
+            error!(%err, "failed fetching script revisions");
+            ApiErrorResponse::InternalError
+        })?;
+
+    Ok(Json(revisions))
+}
+
+#[derive(Deserialize)]
+pub struct ScriptRevisionPathParams {
+    script_id: u64,
+    revision: u32,
+}
+
+pub async fn get_script_revision(
+    Extension(config_store): Extension<CurrentConfigStore>,
+    Extension(current_guild): Extension<CurrentUserGuild>,
+    Path(ScriptRevisionPathParams {
+        script_id,
+        revision,
+    }): Path<ScriptRevisionPathParams>,
+) -> ApiResult<impl IntoResponse> {
+    let revision = config_store
+        .get_script_revision(current_guild.id, script_id, revision)
+        .await
+        .map_err(revision_err_to_api_err)?;
+
+    Ok(Json(revision))
+}
+
+#[derive(Deserialize)]
+pub struct DiffQuery {
+    /// The revision to compare against, defaults to the current version of the script
+    to: Option<u32>,
+}
+
+#[derive(Serialize)]
+pub struct ScriptRevisionDiff {
+    from: u32,
+    to: Option<u32>,
+    /// unified diff of the sources
+    diff: String,
+}
+
+pub async fn diff_script_revisions(
+    Extension(config_store): Extension<CurrentConfigStore>,
+    Extension(current_guild): Extension<CurrentUserGuild>,
+    Path(ScriptRevisionPathParams {
+        script_id,
+        revision,
+    }): Path<ScriptRevisionPathParams>,
+    Query(query): Query<DiffQuery>,
+) -> ApiResult<impl IntoResponse> {
+    let from = config_store
+        .get_script_revision(current_guild.id, script_id, revision)
+        .await
+        .map_err(revision_err_to_api_err)?;
+
+    let (to_source, to_header) = match query.to {
+        Some(to) => {
+            let to_rev = config_store
+                .get_script_revision(current_guild.id, script_id, to)
+                .await
+                .map_err(revision_err_to_api_err)?;
+
+            (to_rev.original_source, format!("revision {}", to))
+        }
+        None => {
+            let script = config_store
+                .get_script_by_id(current_guild.id, script_id)
+                .await
+                .map_err(|err| {
+                    error!(%err, "failed fetching guild script");
+                    ApiErrorResponse::InternalError
+                })?;
+
+            (script.original_source, "current".to_string())
+        }
+    };
+
+    let diff = similar::TextDiff::from_lines(&from.original_source, &to_source)
+        .unified_diff()
+        .header(&format!("revision {}", revision), &to_header)
+        .to_string();
+
+    Ok(Json(ScriptRevisionDiff {
+        from: revision,
+        to: query.to,
+        diff,
+    }))
+}
+
+pub async fn rollback_script(
+    Extension(config_store): Extension<CurrentConfigStore>,
+    Extension(bot_rpc): Extension<botrpc::Client>,
+    Extension(session): Extension<LoggedInSession<CurrentSessionStore>>,
+    Extension(current_guild): Extension<CurrentUserGuild>,
+    Path(ScriptRevisionPathParams {
+        script_id,
+        revision,
+    }): Path<ScriptRevisionPathParams>,
+) -> ApiResult<impl IntoResponse> {
+    let script = config_store
+        .rollback_script(
+            current_guild.id,
+            script_id,
+            revision,
+            Some(session.session.user.id),
+        )
+        .await
+        .map_err(revision_err_to_api_err)?;
+
+    let mut entry = audit_entry(
+        &session,
+        AuditLogAction::RollbackScript,
+        Some(script.name.clone()),
+    );
+    entry.after = Some(serde_json::json!({
+        "revision": revision,
+        "script": script.audit_summary(),
+    }));
+    record_audit_log(&config_store, current_guild.id, entry).await;
+
+    bot_rpc
+        .reload_script(current_guild.id, script.id)
+        .await
+        .map_err(|err| {
+            error!(%err, "failed reloading script after rollback");
+            ApiErrorResponse::InternalError
+        })?;
+
+    Ok(Json(script))
+}
+
+fn revision_err_to_api_err(
+    err: ConfigStoreError<<CurrentConfigStore as ConfigStore>::Error>,
+) -> ApiErrorResponse {
+    match err {
+        ConfigStoreError::RevisionNotFound | ConfigStoreError::ScriptNotFound => {
+            ApiErrorResponse::NotFound
+        }
+        _ => {
+            error!(%err, "failed fetching script revision");
+            ApiErrorResponse::InternalError
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct ScriptCommandConflict {
+    pub script_id: u64,
+    pub script_name: String,
+    #[serde(flatten)]
+    pub conflict: CommandConflict,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CommandConflictsResponse {
+    pub policy: CommandConflictPolicy,
+    pub conflicts: Vec<ScriptCommandConflict>,
+}
+
+/// Lists the commands registered by more than one of the enabled scripts
+///
+/// Scripts are checked against the ones with a lower id, which are the ones that win
+/// with [CommandConflictPolicy::FirstWins].
+pub async fn get_command_conflicts(
+    Extension(config_store): Extension<CurrentConfigStore>,
+    Extension(current_guild): Extension<CurrentUserGuild>,
+) -> ApiResult<impl IntoResponse> {
+    let mut scripts = config_store
+        .list_scripts(current_guild.id)
+        .await
+        .map_err(|err| {
+            error!(%err, "failed fetching guild scripts");
+            ApiErrorResponse::InternalError
+        })?
+        .into_iter()
+        .filter(|s| s.enabled)
+        .collect::<Vec<_>>();
+    scripts.sort_by_key(|s| s.id);
+
+    let meta_config = config_store
+        .get_guild_meta_config_or_default(current_guild.id)
+        .await
+        .map_err(|err| {
+            error!(%err, "failed fetching guild meta config");
+            ApiErrorResponse::InternalError
+        })?;
+
+    let mut conflicts = Vec::new();
+    for (i, script) in scripts.iter().enumerate() {
+        conflicts.extend(
+            find_command_conflicts(&script.contributes.commands, &scripts[..i])
+                .into_iter()
+                .map(|conflict| ScriptCommandConflict {
+                    script_id: script.id,
+                    script_name: script.name.clone(),
+                    conflict,
+                }),
+        );
+    }
+
+    Ok(Json(CommandConflictsResponse {
+        policy: meta_config.command_conflict_policy,
+        conflicts,
+    }))
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SetCommandConflictPolicyData {
+    pub policy: CommandConflictPolicy,
+}
+
+pub async fn set_command_conflict_policy(
+    Extension(config_store): Extension<CurrentConfigStore>,
+    Extension(bot_rpc): Extension<botrpc::Client>,
+    Extension(session): Extension<LoggedInSession<CurrentSessionStore>>,
+    Extension(current_guild): Extension<CurrentUserGuild>,
+    Json(payload): Json<SetCommandConflictPolicyData>,
+) -> ApiResult<impl IntoResponse> {
+    let mut meta_config = config_store
+        .get_guild_meta_config_or_default(current_guild.id)
+        .await
+        .map_err(|err| {
+            error!(%err, "failed fetching guild meta config");
+            ApiErrorResponse::InternalError
+        })?;
+
+    let before = meta_config.command_conflict_policy;
+    meta_config.command_conflict_policy = payload.policy;
+    let updated = config_store
+        .update_guild_meta_config(&meta_config)
+        .await
+        .map_err(|err| {
+            error!(%err, "failed updating guild meta config");
+            ApiErrorResponse::InternalError
+        })?;
+
+    let mut entry = audit_entry(&session, AuditLogAction::SetCommandConflictPolicy, None);
+    entry.before = Some(serde_json::json!(before));
+    entry.after = Some(serde_json::json!(updated.command_conflict_policy));
+    record_audit_log(&config_store, current_guild.id, entry).await;
+
+    // commands are only synced when scripts are loaded
+    if let Err(err) = bot_rpc.restart_guild_vm(current_guild.id).await {
+        error!(%err, "failed reloading guild vm");
+    }
+
+    Ok(Json(updated))
+}
+
+/// Returns whether the guild's commands are live on discord
+pub async fn get_command_sync_status(
+    Extension(config_store): Extension<CurrentConfigStore>,
+    Extension(current_guild): Extension<CurrentUserGuild>,
+) -> ApiResult<impl IntoResponse> {
+    let status = config_store
+        .get_guild_command_sync_status(current_guild.id)
+        .await
+        .map_err(|err| {
+            error!(%err, "failed fetching guild command sync status");
+            ApiErrorResponse::InternalError
+        })?;
+
+    // guilds that haven't had their commands synced since this was added
+    let status = status.unwrap_or_else(|| GuildCommandSyncStatus {
+        guild_id: current_guild.id,
+        state: CommandSyncState::Pending,
+        failed_attempts: 0,
+        last_error: None,
+        last_synced_at: None,
+        updated_at: chrono::Utc::now(),
+    });
+
+    Ok(Json(status))
+}
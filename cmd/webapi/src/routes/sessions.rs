@@ -1,17 +1,21 @@
 use axum::{extract::Extension, response::IntoResponse, Json};
 use serde::{Deserialize, Serialize};
-use stores::web::{Session, SessionStore, SessionType};
+use stores::web::{ApiKeyScope, Session, SessionStore, SessionType};
 
 use crate::{
     errors::ApiErrorResponse, middlewares::LoggedInSession, util::EmptyResponse, ApiResult,
 };
 
 use tracing::error;
+use validation::ValidationError;
 
 #[derive(Serialize)]
 pub struct SessionMeta {
     kind: SessionType,
     created_at: chrono::DateTime<chrono::Utc>,
+    scope: Option<ApiKeyScope>,
+    expires_at: Option<chrono::DateTime<chrono::Utc>>,
+    last_used_at: Option<chrono::DateTime<chrono::Utc>>,
 }
 
 impl From<Session> for SessionMeta {
@@ -19,6 +23,9 @@ impl From<Session> for SessionMeta {
         Self {
             created_at: s.created_at,
             kind: s.kind,
+            scope: s.scope,
+            expires_at: s.expires_at,
+            last_used_at: s.last_used_at,
         }
     }
 }
@@ -28,6 +35,8 @@ pub struct SessionMetaWithKey {
     kind: SessionType,
     created_at: chrono::DateTime<chrono::Utc>,
     token: String,
+    scope: Option<ApiKeyScope>,
+    expires_at: Option<chrono::DateTime<chrono::Utc>>,
 }
 
 impl From<Session> for SessionMetaWithKey {
@@ -36,10 +45,19 @@ impl From<Session> for SessionMetaWithKey {
             created_at: s.created_at,
             kind: s.kind,
             token: s.token,
+            scope: s.scope,
+            expires_at: s.expires_at,
         }
     }
 }
 
+#[derive(Deserialize, Default)]
+pub struct CreateApiKeyPayload {
+    /// The key has the full authority of the user if not set
+    scope: Option<ApiKeyScope>,
+    expires_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
 pub async fn get_all_sessions<ST: SessionStore + 'static>(
     Extension(session): Extension<LoggedInSession<ST>>,
     Extension(session_store): Extension<ST>,
@@ -58,9 +76,23 @@ pub async fn get_all_sessions<ST: SessionStore + 'static>(
 pub async fn create_api_token<ST: SessionStore + 'static>(
     Extension(session): Extension<LoggedInSession<ST>>,
     Extension(session_store): Extension<ST>,
+    payload: Option<Json<CreateApiKeyPayload>>,
 ) -> ApiResult<Json<SessionMetaWithKey>> {
+    let payload = payload.map(|p| p.0).unwrap_or_default();
+
+    if matches!(payload.expires_at, Some(t) if t <= chrono::Utc::now()) {
+        return Err(ApiErrorResponse::ValidationFailed(vec![ValidationError {
+            field: "expires_at".to_string(),
+            msg: "expiry has to be in the future".to_string(),
+        }]));
+    }
+
     let session = session_store
-        .create_session(session.session.user.clone(), SessionType::ApiKey)
+        .create_api_key(
+            session.session.user.clone(),
+            payload.scope,
+            payload.expires_at,
+        )
         .await
         .map_err(|err| {
             error!(%err, "failed creating all api key");
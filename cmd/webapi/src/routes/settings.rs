@@ -0,0 +1,189 @@
+use axum::{extract::Extension, response::IntoResponse, Json};
+use stores::config::{AuditLogAction, ConfigStore, GuildMetaConfigPatch};
+use tracing::error;
+use twilight_model::{id::GuildId, user::CurrentUserGuild};
+use validation::ValidationError;
+
+use crate::{
+    errors::ApiErrorResponse,
+    middlewares::LoggedInSession,
+    routes::audit_log::{audit_entry, record_audit_log},
+    ApiResult, CurrentConfigStore, CurrentSessionStore,
+};
+
+pub async fn get_guild_settings(
+    Extension(config_store): Extension<CurrentConfigStore>,
+    Extension(current_guild): Extension<CurrentUserGuild>,
+) -> ApiResult<impl IntoResponse> {
+    let config = config_store
+        .get_guild_meta_config_or_default(current_guild.id)
+        .await
+        .map_err(|err| {
+            error!(%err, "failed fetching guild meta config");
+            ApiErrorResponse::InternalError
+        })?;
+
+    Ok(Json(config))
+}
+
+/// Updates the settings that are set in the payload, leaving the rest as is
+pub async fn update_guild_settings(
+    Extension(config_store): Extension<CurrentConfigStore>,
+    Extension(bot_rpc): Extension<botrpc::Client>,
+    Extension(session): Extension<LoggedInSession<CurrentSessionStore>>,
+    Extension(current_guild): Extension<CurrentUserGuild>,
+    Json(payload): Json<GuildMetaConfigPatch>,
+) -> ApiResult<impl IntoResponse> {
+    let errs = validate_settings(&bot_rpc, current_guild.id, &payload).await?;
+    if !errs.is_empty() {
+        return Err(ApiErrorResponse::ValidationFailed(errs));
+    }
+
+    let before = config_store
+        .get_guild_meta_config_or_default(current_guild.id)
+        .await
+        .map_err(|err| {
+            error!(%err, "failed fetching guild meta config");
+            ApiErrorResponse::InternalError
+        })?;
+
+    let mut config = before.clone();
+    payload.apply(&mut config);
+    if config == before {
+        return Ok(Json(before));
+    }
+
+    let updated = config_store
+        .update_guild_meta_config(&config)
+        .await
+        .map_err(|err| {
+            error!(%err, "failed updating guild meta config");
+            ApiErrorResponse::InternalError
+        })?;
+
+    let mut entry = audit_entry(&session, AuditLogAction::UpdateSettings, None);
+    entry.before = Some(serde_json::json!(before));
+    entry.after = Some(serde_json::json!(updated));
+    record_audit_log(&config_store, current_guild.id, entry).await;
+
+    // commands are only synced when scripts are loaded, and the vm only reads the allowed
+    // domains when it starts
+    if updated.command_conflict_policy != before.command_conflict_policy
+        || updated.fetch_allowed_domains != before.fetch_allowed_domains
+    {
+        if let Err(err) = bot_rpc.restart_guild_vm(current_guild.id).await {
+            error!(%err, "failed reloading guild vm");
+        }
+    }
+
+    Ok(Json(updated))
+}
+
+async fn validate_settings(
+    bot_rpc: &botrpc::Client,
+    guild_id: GuildId,
+    patch: &GuildMetaConfigPatch,
+) -> ApiResult<Vec<ValidationError>> {
+    let mut errs = Vec::new();
+
+    if let Some(Some(channel_id)) = patch.error_channel_id {
+        let access = bot_rpc
+            .check_channel_access(guild_id, channel_id)
+            .await
+            .map_err(|err| {
+                error!(%err, "failed checking channel access");
+                ApiErrorResponse::InternalError
+            })?;
+
+        if !access.exists {
+            errs.push(ValidationError {
+                field: "error_channel_id".to_string(),
+                msg: "unknown channel".to_string(),
+            });
+        } else if !access.can_send {
+            errs.push(ValidationError {
+                field: "error_channel_id".to_string(),
+                msg: "the bot can't send messages in this channel".to_string(),
+            });
+        }
+    }
+
+    if let Some(domains) = &patch.fetch_allowed_domains {
+        errs.extend(validate_fetch_allowed_domains(domains));
+    }
+
+    Ok(errs)
+}
+
+const MAX_FETCH_ALLOWED_DOMAINS: usize = 50;
+
+fn validate_fetch_allowed_domains(domains: &[String]) -> Vec<ValidationError> {
+    let mut errs = Vec::new();
+
+    if domains.len() > MAX_FETCH_ALLOWED_DOMAINS {
+        errs.push(ValidationError {
+            field: "fetch_allowed_domains".to_string(),
+            msg: format!("max {} domains", MAX_FETCH_ALLOWED_DOMAINS),
+        });
+    }
+
+    for (i, domain) in domains.iter().enumerate() {
+        let msg = if !valid_domain_pattern(domain) {
+            "not a valid domain, use for example example.com or *.example.com"
+        } else if domains[..i].contains(domain) {
+            "duplicate domain"
+        } else {
+            continue;
+        };
+
+        errs.push(ValidationError {
+            field: format!("fetch_allowed_domains.{}", i),
+            msg: msg.to_string(),
+        });
+    }
+
+    errs
+}
+
+/// A lowercase domain name, optionally starting with `*.` to also match subdomains
+fn valid_domain_pattern(pattern: &str) -> bool {
+    let domain = pattern.strip_prefix("*.").unwrap_or(pattern);
+    if domain.is_empty() || domain.len() > 253 {
+        return false;
+    }
+
+    domain.split('.').all(|label| {
+        !label.is_empty()
+            && label.len() <= 63
+            && !label.starts_with('-')
+            && !label.ends_with('-')
+            && label
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+    })
+}
+
+#[test]
+fn fetch_allowed_domains() {
+    let domains = |v: &[&str]| v.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+
+    assert!(
+        validate_fetch_allowed_domains(&domains(&["example.com", "*.botloader.io"])).is_empty()
+    );
+    assert_eq!(
+        validate_fetch_allowed_domains(&domains(&["example.com", "example.com"])).len(),
+        1
+    );
+
+    for invalid in [
+        "",
+        "*.",
+        "*",
+        "Example.com",
+        "a..com",
+        "-a.com",
+        "http://example.com",
+    ] {
+        assert!(!valid_domain_pattern(invalid), "{}", invalid);
+    }
+}
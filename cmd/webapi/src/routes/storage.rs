@@ -0,0 +1,348 @@
+use std::time::Duration;
+
+use axum::{
+    extract::{Extension, Path, Query},
+    response::IntoResponse,
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use stores::{
+    bucketstore::{
+        BucketStore, StoreError, StoreValue, GUILD_STORAGE_LIMIT_BYTES, MAX_JSON_VALUE_LEN_BYTES,
+        MAX_KEY_LEN_BYTES,
+    },
+    config::{ConfigStore, Script},
+};
+use tracing::error;
+use twilight_model::user::CurrentUserGuild;
+use validation::ValidationError;
+
+use crate::{errors::ApiErrorResponse, ApiResult, CurrentBucketStore, CurrentConfigStore};
+
+#[derive(Serialize)]
+pub struct StorageOverview {
+    usage_bytes: u64,
+    limit_bytes: u64,
+    buckets: Vec<BucketUsage>,
+}
+
+#[derive(Serialize)]
+pub struct BucketUsage {
+    name: String,
+    entries: u64,
+    size_bytes: u64,
+    quota_bytes: Option<u64>,
+    /// the scripts that has registered this bucket
+    scripts: Vec<String>,
+}
+
+pub async fn get_storage_overview(
+    Extension(bucket_store): Extension<CurrentBucketStore>,
+    Extension(config_store): Extension<CurrentConfigStore>,
+    Extension(current_guild): Extension<CurrentUserGuild>,
+) -> ApiResult<impl IntoResponse> {
+    let usage_bytes = bucket_store
+        .guild_storage_usage_bytes(current_guild.id)
+        .await
+        .map_err(|err| {
+            error!(%err, "failed fetching guild storage usage");
+            ApiErrorResponse::InternalError
+        })?;
+
+    let buckets = bucket_store
+        .list_buckets(current_guild.id)
+        .await
+        .map_err(|err| {
+            error!(%err, "failed fetching guild storage buckets");
+            ApiErrorResponse::InternalError
+        })?;
+
+    let scripts = fetch_scripts(&config_store, &current_guild).await?;
+
+    Ok(Json(StorageOverview {
+        usage_bytes,
+        limit_bytes: GUILD_STORAGE_LIMIT_BYTES,
+        buckets: buckets
+            .into_iter()
+            .map(|bucket| BucketUsage {
+                quota_bytes: bucket_quota(&scripts, &bucket.name),
+                scripts: scripts
+                    .iter()
+                    .filter(|s| {
+                        s.contributes
+                            .storage_buckets
+                            .iter()
+                            .any(|b| b.storage_name(&s.name) == bucket.name)
+                    })
+                    .map(|s| s.name.clone())
+                    .collect(),
+                name: bucket.name,
+                entries: bucket.entries,
+                size_bytes: bucket.size_bytes,
+            })
+            .collect(),
+    }))
+}
+
+#[derive(Deserialize)]
+pub struct BucketPathParams {
+    bucket: String,
+}
+
+#[derive(Deserialize)]
+pub struct BucketEntryPathParams {
+    bucket: String,
+    key: String,
+}
+
+#[derive(Deserialize)]
+pub struct ListEntriesQuery {
+    key_pattern: Option<String>,
+    after: Option<String>,
+    limit: Option<u32>,
+}
+
+pub async fn list_bucket_entries(
+    Extension(bucket_store): Extension<CurrentBucketStore>,
+    Extension(current_guild): Extension<CurrentUserGuild>,
+    Path(BucketPathParams { bucket }): Path<BucketPathParams>,
+    Query(query): Query<ListEntriesQuery>,
+) -> ApiResult<impl IntoResponse> {
+    let limit = query.limit.unwrap_or(25).min(100);
+
+    let entries = bucket_store
+        .get_many(
+            current_guild.id,
+            bucket,
+            query.key_pattern.unwrap_or_else(|| "%".to_string()),
+            query.after.unwrap_or_default(),
+            limit,
+        )
+        .await
+        .map_err(|err| {
+            error!(%err, "failed fetching bucket entries");
+            ApiErrorResponse::InternalError
+        })?;
+
+    Ok(Json(entries))
+}
+
+#[derive(Deserialize)]
+pub struct RenameBucketRequestData {
+    name: String,
+}
+
+#[derive(Serialize)]
+pub struct RenameBucketResponse {
+    moved_entries: u64,
+}
+
+/// Moves all the entries in a bucket to a new name
+///
+/// Buckets created before buckets were namespaced by script are moved into the namespace of the
+/// first script using them (`<script name>/<bucket name>`), use this to move them elsewhere.
+pub async fn rename_bucket(
+    Extension(bucket_store): Extension<CurrentBucketStore>,
+    Extension(current_guild): Extension<CurrentUserGuild>,
+    Path(BucketPathParams { bucket }): Path<BucketPathParams>,
+    Json(payload): Json<RenameBucketRequestData>,
+) -> ApiResult<impl IntoResponse> {
+    let name_error = if payload.name.is_empty() || payload.name.len() > 256 {
+        Some("name has to be between 1 and 256 bytes long")
+    } else if payload.name.matches('/').count() > 1 {
+        Some("name can contain at most 1 slash, separating the script name from the bucket name")
+    } else if payload.name == bucket {
+        Some("name is the same as the current name")
+    } else {
+        None
+    };
+
+    if let Some(msg) = name_error {
+        return Err(ApiErrorResponse::ValidationFailed(vec![ValidationError {
+            field: "name".to_string(),
+            msg: msg.to_string(),
+        }]));
+    }
+
+    let moved_entries = bucket_store
+        .rename_bucket(current_guild.id, bucket, payload.name)
+        .await
+        .map_err(|err| match err {
+            StoreError::BucketNotEmpty => {
+                ApiErrorResponse::ValidationFailed(vec![ValidationError {
+                    field: "name".to_string(),
+                    msg: "a bucket with this name already has entries in it".to_string(),
+                }])
+            }
+            _ => {
+                error!(%err, "failed renaming bucket");
+                ApiErrorResponse::InternalError
+            }
+        })?;
+
+    Ok(Json(RenameBucketResponse { moved_entries }))
+}
+
+pub async fn get_bucket_entry(
+    Extension(bucket_store): Extension<CurrentBucketStore>,
+    Extension(current_guild): Extension<CurrentUserGuild>,
+    Path(BucketEntryPathParams { bucket, key }): Path<BucketEntryPathParams>,
+) -> ApiResult<impl IntoResponse> {
+    let entry = bucket_store
+        .get(current_guild.id, bucket, key)
+        .await
+        .map_err(|err| {
+            error!(%err, "failed fetching bucket entry");
+            ApiErrorResponse::InternalError
+        })?;
+
+    match entry {
+        Some(entry) => Ok(Json(entry)),
+        None => Err(ApiErrorResponse::NotFound),
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SetEntryRequestData {
+    pub value: StoreValue,
+    /// Optional time to live in seconds
+    pub ttl: Option<u32>,
+}
+
+pub async fn set_bucket_entry(
+    Extension(bucket_store): Extension<CurrentBucketStore>,
+    Extension(config_store): Extension<CurrentConfigStore>,
+    Extension(current_guild): Extension<CurrentUserGuild>,
+    Path(BucketEntryPathParams { bucket, key }): Path<BucketEntryPathParams>,
+    Json(payload): Json<SetEntryRequestData>,
+) -> ApiResult<impl IntoResponse> {
+    let mut verrs = validate_set_entry(&key, &payload.value);
+
+    let usage_bytes = bucket_store
+        .guild_storage_usage_bytes(current_guild.id)
+        .await
+        .map_err(|err| {
+            error!(%err, "failed fetching guild storage usage");
+            ApiErrorResponse::InternalError
+        })?;
+
+    if usage_bytes > GUILD_STORAGE_LIMIT_BYTES {
+        verrs.push(ValidationError {
+            field: "value".to_string(),
+            msg: "guild storage limit reached, delete some entries".to_string(),
+        });
+    }
+
+    let scripts = fetch_scripts(&config_store, &current_guild).await?;
+    if let Some(quota) = bucket_quota(&scripts, &bucket) {
+        let bucket_usage = bucket_store
+            .bucket_usage(current_guild.id, bucket.clone())
+            .await
+            .map_err(|err| {
+                error!(%err, "failed fetching bucket storage usage");
+                ApiErrorResponse::InternalError
+            })?;
+
+        if bucket_usage.size_bytes > quota {
+            verrs.push(ValidationError {
+                field: "value".to_string(),
+                msg: format!(
+                    "bucket storage quota ({} bytes) reached, delete some entries",
+                    quota
+                ),
+            });
+        }
+    }
+
+    if !verrs.is_empty() {
+        return Err(ApiErrorResponse::ValidationFailed(verrs));
+    }
+
+    let entry = bucket_store
+        .set(
+            current_guild.id,
+            bucket,
+            key,
+            payload.value,
+            payload.ttl.map(|ttl| Duration::from_secs(ttl as u64)),
+        )
+        .await
+        .map_err(|err| {
+            error!(%err, "failed setting bucket entry");
+            ApiErrorResponse::InternalError
+        })?;
+
+    Ok(Json(entry))
+}
+
+pub async fn delete_bucket_entry(
+    Extension(bucket_store): Extension<CurrentBucketStore>,
+    Extension(current_guild): Extension<CurrentUserGuild>,
+    Path(BucketEntryPathParams { bucket, key }): Path<BucketEntryPathParams>,
+) -> ApiResult<impl IntoResponse> {
+    let entry = bucket_store
+        .del(current_guild.id, bucket, key)
+        .await
+        .map_err(|err| {
+            error!(%err, "failed deleting bucket entry");
+            ApiErrorResponse::InternalError
+        })?;
+
+    match entry {
+        Some(entry) => Ok(Json(entry)),
+        None => Err(ApiErrorResponse::NotFound),
+    }
+}
+
+async fn fetch_scripts(
+    config_store: &CurrentConfigStore,
+    current_guild: &CurrentUserGuild,
+) -> ApiResult<Vec<Script>> {
+    config_store
+        .list_scripts(current_guild.id)
+        .await
+        .map_err(|err| {
+            error!(%err, "failed fetching guild scripts");
+            ApiErrorResponse::InternalError
+        })
+}
+
+// the lowest quota is the effective one if multiple scripts declares one for the same bucket
+fn bucket_quota(scripts: &[Script], bucket: &str) -> Option<u64> {
+    scripts
+        .iter()
+        .flat_map(|s| {
+            s.contributes
+                .storage_buckets
+                .iter()
+                .filter(move |b| b.storage_name(&s.name) == bucket)
+        })
+        .filter_map(|b| b.quota_bytes)
+        .min()
+}
+
+fn validate_set_entry(key: &str, value: &StoreValue) -> Vec<ValidationError> {
+    let mut errs = Vec::new();
+
+    if key.len() > MAX_KEY_LEN_BYTES {
+        errs.push(ValidationError {
+            field: "key".to_string(),
+            msg: format!("key too long (max {} bytes)", MAX_KEY_LEN_BYTES),
+        });
+    }
+
+    if let StoreValue::Json(json) = value {
+        let serialized = serde_json::to_string(json).unwrap_or_default();
+        if serialized.len() > MAX_JSON_VALUE_LEN_BYTES {
+            errs.push(ValidationError {
+                field: "value".to_string(),
+                msg: format!(
+                    "value too big, max value size is {} bytes",
+                    MAX_JSON_VALUE_LEN_BYTES
+                ),
+            });
+        }
+    }
+
+    errs
+}
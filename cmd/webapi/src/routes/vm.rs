@@ -1,12 +1,20 @@
 use axum::{extract::Extension, response::IntoResponse};
-use stores::web::SessionStore;
+use stores::{config::AuditLogAction, web::SessionStore};
 use tracing::error;
 use twilight_model::user::CurrentUserGuild;
 
-use crate::{errors::ApiErrorResponse, util::EmptyResponse, ApiResult};
+use crate::{
+    errors::ApiErrorResponse,
+    middlewares::LoggedInSession,
+    routes::audit_log::{audit_entry, record_audit_log},
+    util::EmptyResponse,
+    ApiResult, CurrentConfigStore,
+};
 
 pub async fn reload_guild_vm<ST: SessionStore + Clone + Send + Sync + 'static>(
     Extension(bot_rpc): Extension<botrpc::Client>,
+    Extension(config_store): Extension<CurrentConfigStore>,
+    Extension(session): Extension<LoggedInSession<ST>>,
     Extension(current_guild): Extension<CurrentUserGuild>,
 ) -> ApiResult<impl IntoResponse> {
     bot_rpc
@@ -17,5 +25,12 @@ pub async fn reload_guild_vm<ST: SessionStore + Clone + Send + Sync + 'static>(
             ApiErrorResponse::InternalError
         })?;
 
+    record_audit_log(
+        &config_store,
+        current_guild.id,
+        audit_entry(&session, AuditLogAction::ReloadVm, None),
+    )
+    .await;
+
     Ok(EmptyResponse)
 }
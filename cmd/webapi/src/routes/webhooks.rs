@@ -0,0 +1,416 @@
+use std::{collections::HashMap, convert::TryFrom};
+
+use axum::{
+    body::Bytes,
+    extract::{ContentLengthLimit, Extension, Path},
+    http::{
+        header::{self, HeaderName},
+        HeaderMap, HeaderValue, Method, StatusCode,
+    },
+    response::IntoResponse,
+    Json,
+};
+use hmac::{Hmac, Mac, NewMac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use stores::config::{AuditLogAction, ConfigStore, ConfigStoreError, GuildWebhook};
+use subtle::ConstantTimeEq;
+use tracing::error;
+use twilight_model::{id::GuildId, user::CurrentUserGuild};
+use validation::ValidationError;
+
+use crate::{
+    errors::ApiErrorResponse,
+    middlewares::LoggedInSession,
+    routes::audit_log::{audit_entry, record_audit_log},
+    ApiResult, CurrentConfigStore, CurrentSessionStore,
+};
+
+const MAX_WEBHOOK_BODY_SIZE: u64 = 1_000_000;
+
+/// Header with a `sha256=<hex hmac of the body>` signature
+const SIGNATURE_HEADER: &str = "x-botloader-signature";
+/// Same as [`SIGNATURE_HEADER`], github signs its webhooks this way
+const GITHUB_SIGNATURE_HEADER: &str = "x-hub-signature-256";
+/// For senders that can't sign requests, the secret itself
+const SECRET_HEADER: &str = "x-botloader-secret";
+
+/// Headers that are never passed on to the script
+const STRIPPED_REQUEST_HEADERS: &[&str] = &["authorization", "cookie", SECRET_HEADER];
+
+/// Headers the script can't set on the response
+const STRIPPED_RESPONSE_HEADERS: &[&str] = &[
+    "connection",
+    "content-length",
+    "keep-alive",
+    "proxy-connection",
+    "set-cookie",
+    "te",
+    "trailer",
+    "transfer-encoding",
+    "upgrade",
+];
+
+/// Content types the script can respond with, anything else is served as text/plain
+///
+/// Responses are served from the api origin, so scripts must not be able to serve html or scripts
+/// that would run with the dashboard's session.
+const ALLOWED_RESPONSE_CONTENT_TYPES: &[&str] = &["text/plain", "application/json"];
+
+#[derive(Deserialize)]
+pub struct WebhookPathParams {
+    guild: u64,
+    script: u64,
+    name: String,
+}
+
+/// Forwards a request to the script that registered the webhook and responds with what the
+/// script returns
+pub async fn handle_webhook(
+    Extension(config_store): Extension<CurrentConfigStore>,
+    Extension(bot_rpc): Extension<botrpc::Client>,
+    Path(WebhookPathParams {
+        guild,
+        script,
+        name,
+    }): Path<WebhookPathParams>,
+    method: Method,
+    ContentLengthLimit(body): ContentLengthLimit<Bytes, MAX_WEBHOOK_BODY_SIZE>,
+    headers: HeaderMap,
+) -> ApiResult<impl IntoResponse> {
+    let guild_id = GuildId::new(guild).ok_or(ApiErrorResponse::NotFound)?;
+
+    let webhook = config_store
+        .get_guild_webhook(guild_id, script, &name)
+        .await
+        .map_err(|err| {
+            error!(%err, "failed fetching guild webhook");
+            ApiErrorResponse::InternalError
+        })?
+        .ok_or(ApiErrorResponse::NotFound)?;
+
+    if !verify_request(&webhook.secret, &headers, &body) {
+        return Err(ApiErrorResponse::Unauthorized);
+    }
+
+    let script = config_store
+        .get_script_by_id(guild_id, script)
+        .await
+        .map_err(|err| match err {
+            ConfigStoreError::ScriptNotFound => ApiErrorResponse::NotFound,
+            _ => {
+                error!(%err, "failed fetching guild script");
+                ApiErrorResponse::InternalError
+            }
+        })?;
+
+    if !script.enabled {
+        return Err(ApiErrorResponse::ScriptUnavailable);
+    }
+
+    let body = String::from_utf8(body.to_vec()).map_err(|_| {
+        ApiErrorResponse::ValidationFailed(vec![ValidationError {
+            field: "body".to_string(),
+            msg: "body has to be valid utf-8".to_string(),
+        }])
+    })?;
+
+    let resp = bot_rpc
+        .dispatch_webhook(botrpc::proto::WebhookRequest {
+            guild_id: guild_id.get(),
+            script_id: webhook.script_id,
+            name: webhook.name,
+            method: method.to_string(),
+            headers: forwarded_headers(&headers),
+            body,
+        })
+        .await
+        .map_err(|status| match status.code() {
+            tonic::Code::DeadlineExceeded => ApiErrorResponse::ScriptTimeout,
+            tonic::Code::Unavailable | tonic::Code::Aborted => ApiErrorResponse::ScriptUnavailable,
+            _ => {
+                error!(%status, "failed dispatching webhook");
+                ApiErrorResponse::InternalError
+            }
+        })?;
+
+    let status = u16::try_from(resp.status)
+        .ok()
+        .and_then(|status| StatusCode::from_u16(status).ok())
+        .unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+
+    Ok((status, response_headers(resp.headers), resp.body))
+}
+
+fn verify_request(secret: &str, headers: &HeaderMap, body: &[u8]) -> bool {
+    let signature = headers
+        .get(SIGNATURE_HEADER)
+        .or_else(|| headers.get(GITHUB_SIGNATURE_HEADER));
+
+    if let Some(signature) = signature {
+        return signature
+            .to_str()
+            .ok()
+            .and_then(|v| v.strip_prefix("sha256="))
+            .and_then(|v| hex::decode(v).ok())
+            .map(|sig| verify_signature(secret, body, &sig))
+            .unwrap_or(false);
+    }
+
+    match headers.get(SECRET_HEADER) {
+        Some(provided) => provided.as_bytes().ct_eq(secret.as_bytes()).into(),
+        None => false,
+    }
+}
+
+fn verify_signature(secret: &str, body: &[u8], signature: &[u8]) -> bool {
+    let mut mac = match Hmac::<Sha256>::new_varkey(secret.as_bytes()) {
+        Ok(mac) => mac,
+        Err(_) => return false,
+    };
+
+    mac.update(body);
+    mac.verify(signature).is_ok()
+}
+
+fn forwarded_headers(headers: &HeaderMap) -> HashMap<String, String> {
+    let mut result: HashMap<String, String> = HashMap::new();
+
+    for (name, value) in headers {
+        if STRIPPED_REQUEST_HEADERS.contains(&name.as_str()) {
+            continue;
+        }
+
+        let value = match value.to_str() {
+            Ok(v) => v,
+            Err(_) => continue,
+        };
+
+        result
+            .entry(name.as_str().to_string())
+            .and_modify(|existing| {
+                existing.push_str(", ");
+                existing.push_str(value);
+            })
+            .or_insert_with(|| value.to_string());
+    }
+
+    result
+}
+
+fn response_headers(headers: HashMap<String, String>) -> HeaderMap {
+    let mut result = HeaderMap::new();
+    let mut content_type = "text/plain";
+
+    for (name, value) in headers {
+        let name = match HeaderName::from_bytes(name.as_bytes()) {
+            Ok(name) => name,
+            Err(_) => continue,
+        };
+
+        if name == header::CONTENT_TYPE {
+            let essence = value.split(';').next().unwrap_or_default().trim();
+            if let Some(allowed) = ALLOWED_RESPONSE_CONTENT_TYPES
+                .iter()
+                .find(|allowed| allowed.eq_ignore_ascii_case(essence))
+            {
+                content_type = allowed;
+            }
+            continue;
+        }
+
+        if STRIPPED_RESPONSE_HEADERS.contains(&name.as_str()) {
+            continue;
+        }
+
+        if let Ok(value) = HeaderValue::from_str(&value) {
+            result.insert(name, value);
+        }
+    }
+
+    result.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_str(&format!("{}; charset=utf-8", content_type)).unwrap(),
+    );
+    result.insert(
+        header::X_CONTENT_TYPE_OPTIONS,
+        HeaderValue::from_static("nosniff"),
+    );
+    result.insert(
+        header::CONTENT_SECURITY_POLICY,
+        HeaderValue::from_static("sandbox"),
+    );
+
+    result
+}
+
+pub async fn list_webhooks(
+    Extension(config_store): Extension<CurrentConfigStore>,
+    Extension(current_guild): Extension<CurrentUserGuild>,
+) -> ApiResult<impl IntoResponse> {
+    let webhooks = config_store
+        .list_guild_webhooks(current_guild.id)
+        .await
+        .map_err(|err| {
+            error!(%err, "failed fetching guild webhooks");
+            ApiErrorResponse::InternalError
+        })?;
+
+    Ok(Json(webhooks))
+}
+
+#[derive(Deserialize)]
+pub struct ScriptWebhookPathParams {
+    script_id: u64,
+    name: String,
+}
+
+#[derive(Serialize)]
+pub struct CreatedWebhook {
+    #[serde(flatten)]
+    webhook: GuildWebhook,
+    /// Only returned here, a new one is generated if the webhook is set again
+    secret: String,
+}
+
+/// Creates the webhook, or generates a new secret for it if it already exists
+pub async fn set_webhook(
+    Extension(config_store): Extension<CurrentConfigStore>,
+    Extension(session): Extension<LoggedInSession<CurrentSessionStore>>,
+    Extension(current_guild): Extension<CurrentUserGuild>,
+    Path(ScriptWebhookPathParams { script_id, name }): Path<ScriptWebhookPathParams>,
+) -> ApiResult<impl IntoResponse> {
+    if !valid_webhook_name(&name) {
+        return Err(ApiErrorResponse::ValidationFailed(vec![ValidationError {
+            field: "name".to_string(),
+            msg: "name has to be 1-32 characters of a-z, 0-9, _ and -".to_string(),
+        }]));
+    }
+
+    let script = config_store
+        .get_script_by_id(current_guild.id, script_id)
+        .await
+        .map_err(|err| match err {
+            ConfigStoreError::ScriptNotFound => ApiErrorResponse::NotFound,
+            _ => {
+                error!(%err, "failed fetching guild script");
+                ApiErrorResponse::InternalError
+            }
+        })?;
+
+    let secret = stores::web::gen_token();
+    let webhook = config_store
+        .set_guild_webhook(current_guild.id, script_id, &name, &secret)
+        .await
+        .map_err(|err| {
+            error!(%err, "failed setting guild webhook");
+            ApiErrorResponse::InternalError
+        })?;
+
+    let mut entry = audit_entry(
+        &session,
+        AuditLogAction::SetWebhook,
+        Some(format!("{}/{}", script.name, name)),
+    );
+    entry.after = Some(serde_json::json!(webhook));
+    record_audit_log(&config_store, current_guild.id, entry).await;
+
+    Ok(Json(CreatedWebhook { webhook, secret }))
+}
+
+pub async fn delete_webhook(
+    Extension(config_store): Extension<CurrentConfigStore>,
+    Extension(session): Extension<LoggedInSession<CurrentSessionStore>>,
+    Extension(current_guild): Extension<CurrentUserGuild>,
+    Path(ScriptWebhookPathParams { script_id, name }): Path<ScriptWebhookPathParams>,
+) -> ApiResult<impl IntoResponse> {
+    let webhook = config_store
+        .get_guild_webhook(current_guild.id, script_id, &name)
+        .await
+        .map_err(|err| {
+            error!(%err, "failed fetching guild webhook");
+            ApiErrorResponse::InternalError
+        })?
+        .ok_or(ApiErrorResponse::NotFound)?;
+
+    config_store
+        .del_guild_webhook(current_guild.id, script_id, &name)
+        .await
+        .map_err(|err| {
+            error!(%err, "failed deleting guild webhook");
+            ApiErrorResponse::InternalError
+        })?;
+
+    let mut entry = audit_entry(
+        &session,
+        AuditLogAction::DeleteWebhook,
+        Some(format!("{}/{}", script_id, name)),
+    );
+    entry.before = Some(serde_json::json!(webhook));
+    record_audit_log(&config_store, current_guild.id, entry).await;
+
+    Ok(Json(webhook))
+}
+
+fn valid_webhook_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= 32
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+#[test]
+fn webhook_signatures() {
+    let body = b"{\"hello\":\"world\"}";
+
+    let mut mac = Hmac::<Sha256>::new_varkey(b"secret").unwrap();
+    mac.update(body);
+    let signature = format!("sha256={}", hex::encode(mac.finalize().into_bytes()));
+
+    let mut headers = HeaderMap::new();
+    headers.insert(SIGNATURE_HEADER, signature.parse().unwrap());
+    assert!(verify_request("secret", &headers, body));
+    assert!(!verify_request("other", &headers, body));
+    assert!(!verify_request("secret", &headers, b"{}"));
+
+    let mut headers = HeaderMap::new();
+    headers.insert(SECRET_HEADER, "secret".parse().unwrap());
+    assert!(verify_request("secret", &headers, body));
+    assert!(!verify_request("secre", &headers, body));
+
+    assert!(!verify_request("secret", &HeaderMap::new(), body));
+}
+
+#[test]
+fn webhook_response_headers() {
+    let headers = |pairs: &[(&str, &str)]| {
+        response_headers(
+            pairs
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+        )
+    };
+
+    let result = headers(&[("content-type", "text/html"), ("x-custom", "yes")]);
+    assert_eq!(result[header::CONTENT_TYPE], "text/plain; charset=utf-8");
+    assert_eq!(result["x-custom"], "yes");
+    assert_eq!(result[header::X_CONTENT_TYPE_OPTIONS], "nosniff");
+    assert_eq!(result[header::CONTENT_SECURITY_POLICY], "sandbox");
+
+    let result = headers(&[("Content-Type", "Application/JSON; charset=latin1")]);
+    assert_eq!(
+        result[header::CONTENT_TYPE],
+        "application/json; charset=utf-8"
+    );
+
+    let result = headers(&[
+        ("content-security-policy", "default-src *"),
+        ("x-content-type-options", "none"),
+        ("set-cookie", "session=1"),
+    ]);
+    assert_eq!(result[header::CONTENT_SECURITY_POLICY], "sandbox");
+    assert_eq!(result[header::X_CONTENT_TYPE_OPTIONS], "nosniff");
+    assert!(result.get(header::SET_COOKIE).is_none());
+}
@@ -11,17 +11,26 @@ use discordoauthwrapper::{ClientCache, DiscordOauthApiClient, TwilightApiProvide
 use futures::{stream::SelectAll, Stream, StreamExt};
 use guild_logger::LogEntry;
 use oauth2::basic::BasicClient;
+use runtime::guild_events::{GuildStatusEvent, ScriptEvent};
 use serde::{Deserialize, Serialize};
-use stores::web::SessionStore;
-use twilight_model::{guild::Permissions, id::GuildId, user::CurrentUser};
+use stores::{
+    config::GuildAccessPermission,
+    web::{ApiKeyPermission, SessionStore},
+};
+use tracing::warn;
+use twilight_model::{id::GuildId, user::CurrentUser};
 
-use crate::{middlewares::LoggedInSession, ConfigData};
+use crate::{
+    middlewares::{fetch_guild_access, LoggedInSession},
+    ConfigData, CurrentConfigStore,
+};
 
 pub async fn ws_headler<ST: SessionStore + Clone + Send + Sync + 'static>(
     ws: WebSocketUpgrade,
     Extension(session_store): Extension<ST>,
     Extension(config_data): Extension<ConfigData>,
     Extension(bot_rpc): Extension<botrpc::Client>,
+    Extension(config_store): Extension<CurrentConfigStore>,
     Extension(client_cache): Extension<
         ClientCache<TwilightApiProvider, oauth2::basic::BasicClient, ST>,
     >,
@@ -32,6 +41,7 @@ pub async fn ws_headler<ST: SessionStore + Clone + Send + Sync + 'static>(
             session_store,
             config_data.oauth_client,
             bot_rpc,
+            config_store,
             client_cache,
         )
     })
@@ -42,11 +52,19 @@ async fn handle_socket_upgrade<ST: SessionStore + Send + Clone + 'static>(
     session_store: ST,
     oauth_client: BasicClient,
     bot_rpc: botrpc::Client,
+    config_store: CurrentConfigStore,
     client_cache: ClientCache<TwilightApiProvider, oauth2::basic::BasicClient, ST>,
 ) {
-    WsConn::new(socket, session_store, oauth_client, bot_rpc, client_cache)
-        .run()
-        .await;
+    WsConn::new(
+        socket,
+        session_store,
+        oauth_client,
+        bot_rpc,
+        config_store,
+        client_cache,
+    )
+    .run()
+    .await;
 }
 
 struct WsConn<ST> {
@@ -54,9 +72,10 @@ struct WsConn<ST> {
     socket: WebSocket,
     oauth_client: BasicClient,
     bot_rpc: botrpc::Client,
+    config_store: CurrentConfigStore,
     client_cache: ClientCache<TwilightApiProvider, oauth2::basic::BasicClient, ST>,
 
-    active_log_streams: SelectAll<GuildLogStream>,
+    active_streams: SelectAll<GuildStream>,
 
     state: WsState<ST>,
 }
@@ -69,6 +88,7 @@ impl<ST: SessionStore + Clone + 'static> WsConn<ST> {
         session_store: ST,
         oauth_client: BasicClient,
         bot_rpc: botrpc::Client,
+        config_store: CurrentConfigStore,
         client_cache: ClientCache<TwilightApiProvider, oauth2::basic::BasicClient, ST>,
     ) -> Self {
         Self {
@@ -76,8 +96,9 @@ impl<ST: SessionStore + Clone + 'static> WsConn<ST> {
             session_store,
             oauth_client,
             bot_rpc,
+            config_store,
             client_cache,
-            active_log_streams: SelectAll::new(),
+            active_streams: SelectAll::new(),
             state: WsState::UnAuth,
         }
     }
@@ -89,10 +110,10 @@ impl<ST: SessionStore + Clone + 'static> WsConn<ST> {
             // SelectAll returns Ready(None) when empty
             // so if we didn't have this check this thread
             // would get pinned at 100%
-            if !self.active_log_streams.is_empty() {
+            if !self.active_streams.is_empty() {
                 tokio::select! {
-                    item = self.active_log_streams.next() => {
-                        if !self.handle_log_stream_item(item).await {
+                    item = self.active_streams.next() => {
+                        if !self.handle_stream_item(item).await {
                             return;
                         }
                     },
@@ -124,22 +145,28 @@ impl<ST: SessionStore + Clone + 'static> WsConn<ST> {
         }
     }
 
-    async fn handle_log_stream_item(
+    async fn handle_stream_item(
         &mut self,
-        item: Option<Result<LogEntry, tonic::Status>>,
+        item: Option<Result<WsEvent, (GuildId, WsTopic)>>,
     ) -> bool {
         match item {
-            Some(Ok(item)) => self.handle_inner_log_item(item).await,
-            Some(Err(_)) => {
-                self.close(WsCloseReason::BotRpcError).await;
-                false
+            Some(Ok(item)) => self.handle_inner_stream_item(item).await,
+            Some(Err((guild_id, topic))) => {
+                // the stream couldn't be re-opened, drop the subscription and let the client know
+                self.remove_stream(guild_id, topic);
+                if let Err(reason) = self.emit_subscriptions(topic).await {
+                    self.close(reason).await;
+                    false
+                } else {
+                    true
+                }
             }
             _ => true, // There can't be a none since we have the is_empty check in the caller
         }
     }
 
-    async fn handle_inner_log_item(&mut self, item: LogEntry) -> bool {
-        if let Err(reason) = self.send_event(WsEvent::ScriptLogMessage(item)).await {
+    async fn handle_inner_stream_item(&mut self, item: WsEvent) -> bool {
+        if let Err(reason) = self.send_event(item).await {
             self.close(reason).await;
             false
         } else {
@@ -247,18 +274,24 @@ impl<ST: SessionStore + Clone + 'static> WsConn<ST> {
     async fn handle_ws_command_auth(&mut self, cmd: WsCommand) -> WsResult {
         match cmd {
             // WsCommand::Authorize(_) => todo!(),
-            WsCommand::SubscribeLogs(g) => self.subscribe_logs(g).await,
-            WsCommand::UnSubscribeLogs(_) => todo!(),
+            WsCommand::SubscribeLogs(g) => self.subscribe(g, WsTopic::Logs).await,
+            WsCommand::UnSubscribeLogs(g) => self.unsubscribe(g, WsTopic::Logs).await,
+            WsCommand::SubscribeGuildStatus(g) => self.subscribe(g, WsTopic::GuildStatus).await,
+            WsCommand::UnSubscribeGuildStatus(g) => self.unsubscribe(g, WsTopic::GuildStatus).await,
+            WsCommand::SubscribeScriptEvents(g) => self.subscribe(g, WsTopic::ScriptEvents).await,
+            WsCommand::UnSubscribeScriptEvents(g) => {
+                self.unsubscribe(g, WsTopic::ScriptEvents).await
+            }
 
             WsCommand::Authorize(_) => Err(WsCloseReason::AuthWhenAuthorized),
         }
     }
 
-    async fn subscribe_logs(&mut self, guild_id: GuildId) -> WsResult {
+    async fn subscribe(&mut self, guild_id: GuildId, topic: WsTopic) -> WsResult {
         if self
-            .active_log_streams
+            .active_streams
             .iter()
-            .any(|s| s.guild_id == guild_id)
+            .any(|s| s.guild_id == guild_id && s.topic == topic)
         {
             // already subscribed
             return Ok(());
@@ -266,28 +299,58 @@ impl<ST: SessionStore + Clone + 'static> WsConn<ST> {
 
         self.check_guild_acces(guild_id).await?;
 
-        let stream = self
-            .bot_rpc
-            .guild_log_stream(guild_id)
+        let inner = open_stream(&self.bot_rpc, guild_id, topic)
             .await
             .map_err(|_| WsCloseReason::BotRpcError)?;
 
-        self.active_log_streams.push(GuildLogStream {
+        self.active_streams.push(GuildStream {
             guild_id,
-            inner: Box::pin(stream),
+            topic,
+            inner: reconnecting_stream(self.bot_rpc.clone(), guild_id, topic, inner),
         });
 
-        self.emit_subscriptions().await
+        self.emit_subscriptions(topic).await
     }
 
-    async fn emit_subscriptions(&mut self) -> WsResult {
+    async fn unsubscribe(&mut self, guild_id: GuildId, topic: WsTopic) -> WsResult {
+        if !self
+            .active_streams
+            .iter()
+            .any(|s| s.guild_id == guild_id && s.topic == topic)
+        {
+            // not subscribed
+            return Ok(());
+        }
+
+        self.remove_stream(guild_id, topic);
+        self.emit_subscriptions(topic).await
+    }
+
+    fn remove_stream(&mut self, guild_id: GuildId, topic: WsTopic) {
+        // SelectAll has no way of removing a single stream so we rebuild it,
+        // dropping the stream closes the rpc stream on the bot side
+        let streams = std::mem::take(&mut self.active_streams);
+        self.active_streams = streams
+            .into_iter()
+            .filter(|s| s.guild_id != guild_id || s.topic != topic)
+            .collect();
+    }
+
+    async fn emit_subscriptions(&mut self, topic: WsTopic) -> WsResult {
         let ids = self
-            .active_log_streams
+            .active_streams
             .iter()
+            .filter(|item| item.topic == topic)
             .map(|item| item.guild_id)
             .collect::<Vec<_>>();
 
-        self.send_event(WsEvent::SubscriptionsUpdated(ids)).await
+        let evt = match topic {
+            WsTopic::Logs => WsEvent::SubscriptionsUpdated(ids),
+            WsTopic::GuildStatus => WsEvent::GuildStatusSubscriptionsUpdated(ids),
+            WsTopic::ScriptEvents => WsEvent::ScriptEventSubscriptionsUpdated(ids),
+        };
+
+        self.send_event(evt).await
     }
 
     async fn check_guild_acces(&mut self, guild_id: GuildId) -> WsResult {
@@ -296,6 +359,12 @@ impl<ST: SessionStore + Clone + 'static> WsConn<ST> {
             _ => panic!("can't check guild access when not authorized"),
         };
 
+        if let Some(scope) = &session.session.session.scope {
+            if !scope.allows_guild(guild_id) || !scope.allows(ApiKeyPermission::ReadLogs) {
+                return Err(WsCloseReason::MissingScope);
+            }
+        }
+
         let user_guilds = session
             .session
             .api_client
@@ -304,14 +373,16 @@ impl<ST: SessionStore + Clone + 'static> WsConn<ST> {
             .map_err(|_| WsCloseReason::InternalError)?;
 
         if let Some(ug) = user_guilds.into_iter().find(|e| e.id == guild_id) {
-            if ug
-                .permissions
-                .intersects(Permissions::ADMINISTRATOR | Permissions::MANAGE_GUILD)
-            {
-                return Ok(());
-            }
+            let access = fetch_guild_access(
+                &self.config_store,
+                &self.bot_rpc,
+                &ug,
+                session.session.session.user.id,
+            )
+            .await
+            .map_err(|_| WsCloseReason::InternalError)?;
 
-            if ug.owner {
+            if access.has(GuildAccessPermission::View) {
                 return Ok(());
             }
 
@@ -346,6 +417,105 @@ impl<ST: SessionStore + Clone + 'static> WsConn<ST> {
     }
 }
 
+/// Opens the bot rpc stream for the topic, mapping its items to the events we send to the client
+async fn open_stream(
+    bot_rpc: &botrpc::Client,
+    guild_id: GuildId,
+    topic: WsTopic,
+) -> Result<WsEventStream, tonic::Status> {
+    Ok(match topic {
+        WsTopic::Logs => Box::pin(
+            bot_rpc
+                .guild_log_stream(guild_id)
+                .await?
+                .map(|item| item.map(WsEvent::ScriptLogMessage)),
+        ),
+        WsTopic::GuildStatus => Box::pin(
+            bot_rpc
+                .guild_status_stream(guild_id)
+                .await?
+                .map(|item| item.map(WsEvent::GuildStatus)),
+        ),
+        WsTopic::ScriptEvents => Box::pin(
+            bot_rpc
+                .script_event_stream(guild_id)
+                .await?
+                .map(|item| item.map(WsEvent::ScriptEvent)),
+        ),
+    })
+}
+
+/// How many times in a row a broken topic stream is re-opened before the subscription is dropped
+const STREAM_RECONNECT_ATTEMPTS: u32 = 6;
+
+enum TopicStreamState {
+    /// The number of failed attempts is reset once the stream yields an item
+    Connected(WsEventStream, u32),
+    Reconnecting(u32),
+    Failed,
+}
+
+/// Wraps the topic stream, re-opening it if it breaks or ends
+///
+/// This happens when the bot node running the guild restarts or the guild's shard moves to another
+/// node, re-opening the stream picks the node currently running the guild.
+/// Yields an error and ends if the stream couldn't be re-opened after [STREAM_RECONNECT_ATTEMPTS] attempts.
+fn reconnecting_stream(
+    bot_rpc: botrpc::Client,
+    guild_id: GuildId,
+    topic: WsTopic,
+    inner: WsEventStream,
+) -> WsEventStream {
+    Box::pin(futures::stream::unfold(
+        TopicStreamState::Connected(inner, 0),
+        move |mut state| {
+            let bot_rpc = bot_rpc.clone();
+            async move {
+                loop {
+                    state = match state {
+                        TopicStreamState::Connected(mut inner, failed_attempts) => {
+                            match inner.next().await {
+                                Some(Ok(evt)) => {
+                                    return Some((Ok(evt), TopicStreamState::Connected(inner, 0)))
+                                }
+                                Some(Err(err)) => {
+                                    warn!(%err, %guild_id, "bot rpc stream broke, re-opening it");
+                                    TopicStreamState::Reconnecting(failed_attempts)
+                                }
+                                None => TopicStreamState::Reconnecting(failed_attempts),
+                            }
+                        }
+                        TopicStreamState::Reconnecting(failed_attempts)
+                            if failed_attempts >= STREAM_RECONNECT_ATTEMPTS =>
+                        {
+                            let err = tonic::Status::unavailable("failed re-opening the stream");
+                            return Some((Err(err), TopicStreamState::Failed));
+                        }
+                        TopicStreamState::Reconnecting(failed_attempts) => {
+                            tokio::time::sleep(reconnect_backoff(failed_attempts)).await;
+
+                            match open_stream(&bot_rpc, guild_id, topic).await {
+                                Ok(inner) => {
+                                    TopicStreamState::Connected(inner, failed_attempts + 1)
+                                }
+                                Err(err) => {
+                                    warn!(%err, %guild_id, "failed re-opening bot rpc stream");
+                                    TopicStreamState::Reconnecting(failed_attempts + 1)
+                                }
+                            }
+                        }
+                        TopicStreamState::Failed => return None,
+                    }
+                }
+            }
+        },
+    ))
+}
+
+fn reconnect_backoff(failed_attempts: u32) -> Duration {
+    Duration::from_secs(1 << failed_attempts.min(5))
+}
+
 #[allow(clippy::large_enum_variant)]
 enum WsState<ST> {
     UnAuth,
@@ -361,8 +531,15 @@ struct AuthorizedWsState<ST> {
 #[serde(tag = "t", content = "d")]
 enum WsEvent {
     AuthSuccess(CurrentUser),
+    /// The guilds we're streaming logs from
     SubscriptionsUpdated(Vec<GuildId>),
+    GuildStatusSubscriptionsUpdated(Vec<GuildId>),
+    ScriptEventSubscriptionsUpdated(Vec<GuildId>),
     ScriptLogMessage(LogEntry),
+    /// Vm lifecycle, script contribs and command sync status changes
+    GuildStatus(GuildStatusEvent),
+    /// A custom event emitted by a script through `script.emitEvent`
+    ScriptEvent(ScriptEvent),
     // GeneralLogMEssage(String)
 }
 
@@ -375,6 +552,17 @@ enum WsCommand {
     // below commands requires authorization
     SubscribeLogs(GuildId),
     UnSubscribeLogs(GuildId),
+    SubscribeGuildStatus(GuildId),
+    UnSubscribeGuildStatus(GuildId),
+    SubscribeScriptEvents(GuildId),
+    UnSubscribeScriptEvents(GuildId),
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum WsTopic {
+    Logs,
+    GuildStatus,
+    ScriptEvents,
 }
 
 #[derive(Serialize)]
@@ -408,6 +596,9 @@ enum WsCloseReason {
 
     // an error occured cummincating with the bot
     BotRpcError,
+
+    // the api key used isn't scoped for this
+    MissingScope,
 }
 
 impl WsCloseReason {
@@ -423,6 +614,7 @@ impl WsCloseReason {
             WsCloseReason::UnknownGuild => 4003,
             WsCloseReason::GuildMissingAccess => 4004,
             WsCloseReason::BotRpcError => 4006,
+            WsCloseReason::MissingScope => 4007,
         }
     }
 
@@ -438,22 +630,30 @@ impl WsCloseReason {
             WsCloseReason::UnknownGuild => "unknown guild",
             WsCloseReason::GuildMissingAccess => "missing access to guild",
             WsCloseReason::BotRpcError => "error on communication with bot",
+            WsCloseReason::MissingScope => "api key is missing the scope for this",
         }
     }
 }
 
-struct GuildLogStream {
+type WsEventStream = Pin<Box<dyn Stream<Item = Result<WsEvent, tonic::Status>> + Send>>;
+
+struct GuildStream {
     guild_id: GuildId,
-    inner: Pin<Box<dyn Stream<Item = Result<LogEntry, tonic::Status>> + Send>>,
+    topic: WsTopic,
+    inner: WsEventStream,
 }
 
-impl Stream for GuildLogStream {
-    type Item = Result<LogEntry, tonic::Status>;
+impl Stream for GuildStream {
+    /// Errors are mapped to the subscription that failed, so it can be dropped
+    type Item = Result<WsEvent, (GuildId, WsTopic)>;
 
     fn poll_next(
         mut self: Pin<&mut Self>,
         cx: &mut std::task::Context<'_>,
     ) -> Poll<Option<Self::Item>> {
-        self.inner.poll_next_unpin(cx)
+        let (guild_id, topic) = (self.guild_id, self.topic);
+        self.inner
+            .poll_next_unpin(cx)
+            .map(|item| item.map(|item| item.map_err(|_| (guild_id, topic))))
     }
 }
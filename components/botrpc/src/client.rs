@@ -1,29 +1,137 @@
+use std::{
+    convert::TryInto,
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+
 use futures::{Stream, StreamExt};
 use guild_logger::LogEntry;
-use twilight_model::id::GuildId;
+use runtime::guild_events::{GuildStatusEvent, ScriptEvent};
+use stores::nodes::{guild_shard, BotNodeStore, ShardRange};
+use tonic::transport::Endpoint;
+use tracing::{error, info};
+use twilight_model::id::{ChannelId, GuildId, RoleId, UserId};
 
 use crate::proto;
 
 type ClientConn = proto::bot_service_client::BotServiceClient<tonic::transport::Channel>;
 
+/// How often the bot nodes are re-fetched when discovering them through the database
+const NODE_REFRESH_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Nodes that haven't sent a heartbeat for this long are considered down
+pub const NODE_MAX_AGE: Duration = Duration::from_secs(30);
+
+/// Client for the bot rpc, requests are sent to the bot node running the guild's shard
 #[derive(Clone)]
 pub struct Client {
-    inner: ClientConn,
+    nodes: Arc<RwLock<Vec<NodeConn>>>,
+}
+
+#[derive(Clone)]
+struct NodeConn {
+    rpc_addr: String,
+    /// `None` if the node runs all the shards
+    shards: Option<ShardRange>,
+    conn: ClientConn,
 }
 
 impl Client {
+    /// Connects to a single bot node running all the shards
     pub async fn new(addr: String) -> Result<Client, tonic::transport::Error> {
-        let client = proto::bot_service_client::BotServiceClient::connect(addr).await?;
+        let client = proto::bot_service_client::BotServiceClient::connect(addr.clone()).await?;
+
+        Ok(Client {
+            nodes: Arc::new(RwLock::new(vec![NodeConn {
+                rpc_addr: addr,
+                shards: None,
+                conn: client,
+            }])),
+        })
+    }
+
+    /// Discovers the bot nodes through the store, the nodes are kept up to date in the background
+    ///
+    /// Connections are made lazily and re-established by tonic if they break, so nodes coming
+    /// and going is transparent to the users of the client.
+    pub async fn new_discovered<ST>(store: ST) -> Result<Client, ST::Error>
+    where
+        ST: BotNodeStore + Send + Sync + 'static,
+    {
+        let client = Client {
+            nodes: Arc::new(RwLock::new(Vec::new())),
+        };
+
+        client.refresh_nodes(&store).await?;
+
+        let cloned = client.clone();
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(NODE_REFRESH_INTERVAL);
+            loop {
+                ticker.tick().await;
+                if let Err(err) = cloned.refresh_nodes(&store).await {
+                    error!(%err, "failed refreshing bot nodes");
+                }
+            }
+        });
 
-        Ok(Client { inner: client })
+        Ok(client)
     }
 
-    pub fn get_conn(&self) -> ClientConn {
-        self.inner.clone()
+    async fn refresh_nodes<ST: BotNodeStore>(&self, store: &ST) -> Result<(), ST::Error> {
+        let nodes = store.get_active_bot_nodes(NODE_MAX_AGE).await?;
+
+        let mut current = self.nodes.write().unwrap();
+        let mut updated = Vec::with_capacity(nodes.len());
+        for node in nodes {
+            // reuse the existing connection if we're already connected to the node
+            let conn = match current.iter().find(|c| c.rpc_addr == node.rpc_addr) {
+                Some(existing) => existing.conn.clone(),
+                None => match Endpoint::from_shared(node.rpc_addr.clone()) {
+                    Ok(endpoint) => match endpoint.connect_lazy() {
+                        Ok(channel) => {
+                            info!(addr = node.rpc_addr.as_str(), "discovered bot node");
+                            ClientConn::new(channel)
+                        }
+                        Err(err) => {
+                            error!(%err, addr = node.rpc_addr.as_str(), "failed connecting to bot node");
+                            continue;
+                        }
+                    },
+                    Err(err) => {
+                        error!(%err, addr = node.rpc_addr.as_str(), "invalid bot node address");
+                        continue;
+                    }
+                },
+            };
+
+            updated.push(NodeConn {
+                rpc_addr: node.rpc_addr,
+                shards: Some(node.shards),
+                conn,
+            });
+        }
+
+        *current = updated;
+        Ok(())
+    }
+
+    /// Returns a connection to the node running the guild
+    pub fn get_conn(&self, guild_id: GuildId) -> Result<ClientConn, tonic::Status> {
+        let nodes = self.nodes.read().unwrap();
+
+        nodes
+            .iter()
+            .find(|node| match &node.shards {
+                Some(shards) => shards.contains(guild_shard(guild_id, shards.total)),
+                None => true,
+            })
+            .map(|node| node.conn.clone())
+            .ok_or_else(|| tonic::Status::unavailable("no bot node is running the guild's shard"))
     }
 
     pub async fn restart_guild_vm(&self, guild_id: GuildId) -> Result<(), tonic::Status> {
-        let mut conn = self.get_conn();
+        let mut conn = self.get_conn(guild_id)?;
 
         conn.reload_vm(proto::GuildScriptSpecifier {
             guild_id: guild_id.get(),
@@ -34,11 +142,119 @@ impl Client {
         Ok(())
     }
 
+    /// Shuts down the guild's vm, it stays unloaded until it's reloaded or the bot rejoins the guild
+    pub async fn unload_guild_vm(&self, guild_id: GuildId) -> Result<(), tonic::Status> {
+        let mut conn = self.get_conn(guild_id)?;
+
+        conn.unload_vm(proto::GuildSpecifier {
+            guild_id: guild_id.get(),
+        })
+        .await?;
+
+        Ok(())
+    }
+
+    /// Loads the current version of the script from the database into the guild's vm
+    pub async fn reload_script(
+        &self,
+        guild_id: GuildId,
+        script_id: u64,
+    ) -> Result<(), tonic::Status> {
+        let mut conn = self.get_conn(guild_id)?;
+
+        conn.reload_script(proto::GuildScriptId {
+            guild_id: guild_id.get(),
+            script_id,
+        })
+        .await?;
+
+        Ok(())
+    }
+
+    /// Dry runs a script in the bot, `script_id` is the script being updated if any
+    pub async fn validate_script(
+        &self,
+        guild_id: GuildId,
+        script_id: Option<u64>,
+        name: String,
+        source: String,
+    ) -> Result<proto::ValidateScriptResponse, tonic::Status> {
+        let mut conn = self.get_conn(guild_id)?;
+
+        let resp = conn
+            .validate_script(proto::ValidateScriptRequest {
+                guild_id: guild_id.get(),
+                script_id: script_id.unwrap_or_default(),
+                name,
+                source,
+            })
+            .await?;
+
+        Ok(resp.into_inner())
+    }
+
+    /// Returns the roles of the member, `None` if the user isn't a member of the guild
+    pub async fn get_member_roles(
+        &self,
+        guild_id: GuildId,
+        user_id: UserId,
+    ) -> Result<Option<Vec<RoleId>>, tonic::Status> {
+        let mut conn = self.get_conn(guild_id)?;
+
+        let resp = conn
+            .get_member_roles(proto::GuildMemberSpecifier {
+                guild_id: guild_id.get(),
+                user_id: user_id.get(),
+            })
+            .await?
+            .into_inner();
+
+        if !resp.is_member {
+            return Ok(None);
+        }
+
+        Ok(Some(
+            resp.role_ids.into_iter().filter_map(RoleId::new).collect(),
+        ))
+    }
+
+    /// Returns whether the channel is in the guild and if the bot can send messages in it
+    pub async fn check_channel_access(
+        &self,
+        guild_id: GuildId,
+        channel_id: ChannelId,
+    ) -> Result<proto::ChannelAccess, tonic::Status> {
+        let mut conn = self.get_conn(guild_id)?;
+
+        let resp = conn
+            .check_channel_access(proto::GuildChannelSpecifier {
+                guild_id: guild_id.get(),
+                channel_id: channel_id.get(),
+            })
+            .await?
+            .into_inner();
+
+        Ok(resp)
+    }
+
+    /// Forwards a request to a script's webhook, the bot enforces the response timeout
+    pub async fn dispatch_webhook(
+        &self,
+        req: proto::WebhookRequest,
+    ) -> Result<proto::WebhookResponse, tonic::Status> {
+        let guild_id = GuildId::new(req.guild_id)
+            .ok_or_else(|| tonic::Status::invalid_argument("invalid guild id"))?;
+
+        let mut conn = self.get_conn(guild_id)?;
+        let resp = conn.dispatch_webhook(req).await?.into_inner();
+        Ok(resp)
+    }
+
     pub async fn guild_log_stream(
         &self,
         guild_id: GuildId,
     ) -> Result<impl Stream<Item = Result<LogEntry, tonic::Status>>, tonic::Status> {
-        let mut conn = self.get_conn();
+        let mut conn = self.get_conn(guild_id)?;
 
         let stream = conn
             .stream_guild_logs(proto::GuildSpecifier {
@@ -49,4 +265,36 @@ impl Client {
 
         Ok(stream.map(|item| item.map(Into::into)))
     }
+
+    pub async fn guild_status_stream(
+        &self,
+        guild_id: GuildId,
+    ) -> Result<impl Stream<Item = Result<GuildStatusEvent, tonic::Status>>, tonic::Status> {
+        let mut conn = self.get_conn(guild_id)?;
+
+        let stream = conn
+            .stream_guild_status(proto::GuildSpecifier {
+                guild_id: guild_id.get(),
+            })
+            .await?
+            .into_inner();
+
+        Ok(stream.map(|item| item.and_then(TryInto::try_into)))
+    }
+
+    pub async fn script_event_stream(
+        &self,
+        guild_id: GuildId,
+    ) -> Result<impl Stream<Item = Result<ScriptEvent, tonic::Status>>, tonic::Status> {
+        let mut conn = self.get_conn(guild_id)?;
+
+        let stream = conn
+            .stream_script_events(proto::GuildSpecifier {
+                guild_id: guild_id.get(),
+            })
+            .await?
+            .into_inner();
+
+        Ok(stream.map(|item| item.and_then(TryInto::try_into)))
+    }
 }
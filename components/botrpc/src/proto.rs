@@ -1,3 +1,6 @@
+use std::convert::TryFrom;
+
+use runtime::guild_events::{GuildStatus, GuildStatusEvent, ScriptEvent};
 use twilight_model::id::GuildId;
 
 tonic::include_proto!("botrpc");
@@ -9,6 +12,7 @@ impl From<guild_logger::LogEntry> for GuildLogItem {
             level: LogLevel::from(entry.level).into(),
             message: entry.message,
             script_context: entry.script_context.map(Into::into),
+            stack: entry.stack.into_iter().map(Into::into).collect(),
         }
     }
 }
@@ -27,6 +31,7 @@ impl From<GuildLogItem> for guild_logger::LogEntry {
             },
             message: entry.message,
             script_context: entry.script_context.map(Into::into),
+            stack: entry.stack.into_iter().map(Into::into).collect(),
         }
     }
 }
@@ -49,6 +54,30 @@ impl From<ScriptContext> for guild_logger::ScriptContext {
     }
 }
 
+impl From<guild_logger::StackFrame> for StackFrame {
+    fn from(frame: guild_logger::StackFrame) -> Self {
+        Self {
+            function: frame.function.unwrap_or_default(),
+            filename: frame.filename,
+            line_col: frame.line_col.map(Into::into),
+        }
+    }
+}
+
+impl From<StackFrame> for guild_logger::StackFrame {
+    fn from(frame: StackFrame) -> Self {
+        Self {
+            function: if frame.function.is_empty() {
+                None
+            } else {
+                Some(frame.function)
+            },
+            filename: frame.filename,
+            line_col: frame.line_col.map(Into::into),
+        }
+    }
+}
+
 impl From<LineCol> for (u32, u32) {
     fn from(l: LineCol) -> Self {
         (l.line, l.column)
@@ -87,3 +116,102 @@ impl From<LogLevel> for guild_logger::LogLevel {
         }
     }
 }
+
+impl From<GuildStatusEvent> for GuildStatusItem {
+    fn from(evt: GuildStatusEvent) -> Self {
+        let status = match evt.status {
+            GuildStatus::Vm(status) => guild_status_item::Status::Vm(VmStatus::from(status).into()),
+            GuildStatus::ScriptContribsUpdated {
+                script_id,
+                script_name,
+            } => guild_status_item::Status::ScriptContribsUpdated(ScriptContribsUpdated {
+                script_id,
+                script_name,
+            }),
+            GuildStatus::CommandSync(status) => {
+                guild_status_item::Status::CommandSyncJson(serde_json::to_string(&status).unwrap())
+            }
+        };
+
+        Self {
+            guild_id: evt.guild_id.get(),
+            status: Some(status),
+        }
+    }
+}
+
+impl TryFrom<GuildStatusItem> for GuildStatusEvent {
+    type Error = tonic::Status;
+
+    fn try_from(item: GuildStatusItem) -> Result<Self, Self::Error> {
+        let invalid = || tonic::Status::internal("invalid guild status item");
+
+        let status = match item.status.ok_or_else(invalid)? {
+            guild_status_item::Status::Vm(status) => {
+                GuildStatus::Vm(VmStatus::from_i32(status).ok_or_else(invalid)?.into())
+            }
+            guild_status_item::Status::ScriptContribsUpdated(updated) => {
+                GuildStatus::ScriptContribsUpdated {
+                    script_id: updated.script_id,
+                    script_name: updated.script_name,
+                }
+            }
+            guild_status_item::Status::CommandSyncJson(encoded) => {
+                GuildStatus::CommandSync(serde_json::from_str(&encoded).map_err(|_| invalid())?)
+            }
+        };
+
+        Ok(Self {
+            guild_id: GuildId::new(item.guild_id).ok_or_else(invalid)?,
+            status,
+        })
+    }
+}
+
+impl From<runtime::guild_events::VmStatus> for VmStatus {
+    fn from(status: runtime::guild_events::VmStatus) -> Self {
+        match status {
+            runtime::guild_events::VmStatus::Started => Self::Started,
+            runtime::guild_events::VmStatus::Restarting => Self::Restarting,
+            runtime::guild_events::VmStatus::Stopped => Self::Stopped,
+            runtime::guild_events::VmStatus::Runaway => Self::Runaway,
+        }
+    }
+}
+
+impl From<VmStatus> for runtime::guild_events::VmStatus {
+    fn from(status: VmStatus) -> Self {
+        match status {
+            VmStatus::Started => Self::Started,
+            VmStatus::Restarting => Self::Restarting,
+            VmStatus::Stopped => Self::Stopped,
+            VmStatus::Runaway => Self::Runaway,
+        }
+    }
+}
+
+impl From<ScriptEvent> for ScriptEventItem {
+    fn from(evt: ScriptEvent) -> Self {
+        Self {
+            guild_id: evt.guild_id.get(),
+            script_id: evt.script_id,
+            name: evt.name,
+            data_json: serde_json::to_string(&evt.data).unwrap(),
+        }
+    }
+}
+
+impl TryFrom<ScriptEventItem> for ScriptEvent {
+    type Error = tonic::Status;
+
+    fn try_from(item: ScriptEventItem) -> Result<Self, Self::Error> {
+        let invalid = || tonic::Status::internal("invalid script event item");
+
+        Ok(Self {
+            guild_id: GuildId::new(item.guild_id).ok_or_else(invalid)?,
+            script_id: item.script_id,
+            name: item.name,
+            data: serde_json::from_str(&item.data_json).map_err(|_| invalid())?,
+        })
+    }
+}
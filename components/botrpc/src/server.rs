@@ -2,9 +2,20 @@ use std::{pin::Pin, sync::Arc};
 
 use futures::Stream;
 use guild_logger::guild_subscriber_backend::GuildSubscriberBackend;
+use runtime::{
+    extensions::webhooks::{WebhookCall, WebhookError},
+    guild_events::GuildEvent,
+};
 use stores::{bucketstore::BucketStore, config::ConfigStore, timers::TimerStore};
+use tokio::sync::broadcast::error::RecvError;
 use tonic::{Response, Status};
-use twilight_model::id::GuildId;
+use twilight_cache_inmemory::InMemoryCache;
+use twilight_http::error::ErrorType;
+use twilight_model::{
+    channel::GuildChannel,
+    guild::Permissions,
+    id::{ChannelId, GuildId, UserId},
+};
 
 use crate::proto;
 
@@ -12,18 +23,24 @@ pub struct Server<CT> {
     addr: String,
     log_subscriber: Arc<GuildSubscriberBackend>,
     vm_manager: vm_manager::Manager<CT>,
+    discord_client: Arc<twilight_http::Client>,
+    bot_state: Arc<InMemoryCache>,
 }
 
 impl<CT: ConfigStore + BucketStore + TimerStore + Send + Sync + 'static> Server<CT> {
     pub fn new(
         log_subscriber: Arc<GuildSubscriberBackend>,
         vm_manager: vm_manager::Manager<CT>,
+        discord_client: Arc<twilight_http::Client>,
+        bot_state: Arc<InMemoryCache>,
         addr: String,
     ) -> Self {
         Self {
             log_subscriber,
             addr,
             vm_manager,
+            discord_client,
+            bot_state,
         }
     }
 
@@ -39,6 +56,10 @@ impl<CT: ConfigStore + BucketStore + TimerStore + Send + Sync + 'static> Server<
 
 type ResponseStream =
     Pin<Box<dyn Stream<Item = Result<proto::GuildLogItem, Status>> + Send + Sync>>;
+type GuildStatusStream =
+    Pin<Box<dyn Stream<Item = Result<proto::GuildStatusItem, Status>> + Send + Sync>>;
+type ScriptEventStream =
+    Pin<Box<dyn Stream<Item = Result<proto::ScriptEventItem, Status>> + Send + Sync>>;
 
 #[tonic::async_trait]
 impl<CT: ConfigStore + BucketStore + TimerStore + Send + Sync + 'static>
@@ -56,6 +77,183 @@ impl<CT: ConfigStore + BucketStore + TimerStore + Send + Sync + 'static>
         }
     }
 
+    async fn unload_vm(
+        &self,
+        request: tonic::Request<proto::GuildSpecifier>,
+    ) -> Result<Response<proto::Empty>, Status> {
+        let guild_id = GuildId::new(request.into_inner().guild_id).unwrap();
+
+        self.vm_manager.remove_guild(guild_id).await;
+        Ok(Response::new(proto::Empty {}))
+    }
+
+    async fn reload_script(
+        &self,
+        request: tonic::Request<proto::GuildScriptId>,
+    ) -> Result<Response<proto::Empty>, Status> {
+        let inner = request.into_inner();
+        let guild_id = GuildId::new(inner.guild_id).unwrap();
+
+        match self
+            .vm_manager
+            .reload_script(guild_id, inner.script_id)
+            .await
+        {
+            Ok(()) => Ok(Response::new(proto::Empty {})),
+            Err(err) => Err(Status::internal(err)),
+        }
+    }
+
+    async fn validate_script(
+        &self,
+        request: tonic::Request<proto::ValidateScriptRequest>,
+    ) -> Result<Response<proto::ValidateScriptResponse>, Status> {
+        let inner = request.into_inner();
+        let guild_id = GuildId::new(inner.guild_id).unwrap();
+        let script_id = if inner.script_id == 0 {
+            None
+        } else {
+            Some(inner.script_id)
+        };
+
+        match self
+            .vm_manager
+            .validate_script(guild_id, script_id, inner.name, inner.source)
+            .await
+        {
+            Ok(validation) => Ok(Response::new(proto::ValidateScriptResponse {
+                error: String::new(),
+                script_meta_json: serde_json::to_string(&validation.meta)
+                    .map_err(|err| Status::internal(err.to_string()))?,
+                conflicts: validation
+                    .conflicts
+                    .into_iter()
+                    .map(|c| proto::CommandConflict {
+                        command: c.command,
+                        other_script_id: c.other_script_id,
+                        other_script_name: c.other_script_name,
+                    })
+                    .collect(),
+            })),
+            Err(err) => Ok(Response::new(proto::ValidateScriptResponse {
+                error: err,
+                script_meta_json: String::new(),
+                conflicts: Vec::new(),
+            })),
+        }
+    }
+
+    async fn get_member_roles(
+        &self,
+        request: tonic::Request<proto::GuildMemberSpecifier>,
+    ) -> Result<Response<proto::MemberRoles>, Status> {
+        let inner = request.into_inner();
+        let guild_id = GuildId::new(inner.guild_id).unwrap();
+        let user_id = UserId::new(inner.user_id).unwrap();
+
+        if let Some(member) = self.bot_state.member(guild_id, user_id) {
+            return Ok(Response::new(proto::MemberRoles {
+                is_member: true,
+                role_ids: member.roles().iter().map(|r| r.get()).collect(),
+            }));
+        }
+
+        // not cached, the member might not have been seen since the bot started
+        let resp = match self
+            .discord_client
+            .guild_member(guild_id, user_id)
+            .exec()
+            .await
+        {
+            Ok(resp) => resp,
+            Err(err) => {
+                if matches!(err.kind(), ErrorType::Response { status, .. } if status.raw() == 404) {
+                    return Ok(Response::new(proto::MemberRoles {
+                        is_member: false,
+                        role_ids: Vec::new(),
+                    }));
+                }
+
+                return Err(Status::internal(err.to_string()));
+            }
+        };
+
+        let member = resp
+            .model()
+            .await
+            .map_err(|err| Status::internal(err.to_string()))?;
+
+        Ok(Response::new(proto::MemberRoles {
+            is_member: true,
+            role_ids: member.roles.into_iter().map(|r| r.get()).collect(),
+        }))
+    }
+
+    async fn check_channel_access(
+        &self,
+        request: tonic::Request<proto::GuildChannelSpecifier>,
+    ) -> Result<Response<proto::ChannelAccess>, Status> {
+        let inner = request.into_inner();
+        let guild_id = GuildId::new(inner.guild_id).unwrap();
+        let channel_id = ChannelId::new(inner.channel_id).unwrap();
+
+        let is_text = match self.bot_state.guild_channel(channel_id) {
+            Some(channel) if channel.guild_id() == guild_id => {
+                matches!(channel.resource(), GuildChannel::Text(_))
+            }
+            _ => {
+                return Ok(Response::new(proto::ChannelAccess {
+                    exists: false,
+                    can_send: false,
+                }))
+            }
+        };
+
+        let current_user = self
+            .bot_state
+            .current_user()
+            .ok_or_else(|| Status::unavailable("bot is not ready"))?;
+
+        let permissions = self
+            .bot_state
+            .permissions()
+            .in_channel(current_user.id, channel_id)
+            .map_err(|err| Status::internal(err.to_string()))?;
+
+        Ok(Response::new(proto::ChannelAccess {
+            exists: true,
+            can_send: is_text
+                && permissions.contains(Permissions::VIEW_CHANNEL | Permissions::SEND_MESSAGES),
+        }))
+    }
+
+    async fn dispatch_webhook(
+        &self,
+        request: tonic::Request<proto::WebhookRequest>,
+    ) -> Result<Response<proto::WebhookResponse>, Status> {
+        let inner = request.into_inner();
+        let guild_id = GuildId::new(inner.guild_id).unwrap();
+
+        let call = WebhookCall {
+            script_id: inner.script_id,
+            name: inner.name,
+            method: inner.method,
+            headers: inner.headers,
+            body: inner.body,
+        };
+
+        match self.vm_manager.dispatch_webhook(guild_id, call).await {
+            Ok(resp) => Ok(Response::new(proto::WebhookResponse {
+                status: resp.status as u32,
+                headers: resp.headers,
+                body: resp.body,
+            })),
+            Err(err @ WebhookError::Timeout) => Err(Status::deadline_exceeded(err.to_string())),
+            Err(err @ WebhookError::VmNotRunning(_)) => Err(Status::unavailable(err.to_string())),
+            Err(err @ WebhookError::Dropped) => Err(Status::aborted(err.to_string())),
+        }
+    }
+
     type StreamGuildLogsStream = ResponseStream;
 
     async fn stream_guild_logs(
@@ -73,4 +271,48 @@ impl<CT: ConfigStore + BucketStore + TimerStore + Send + Sync + 'static>
 
         Ok(Response::new(Box::pin(out)))
     }
+
+    type StreamGuildStatusStream = GuildStatusStream;
+
+    async fn stream_guild_status(
+        &self,
+        request: tonic::Request<proto::GuildSpecifier>,
+    ) -> Result<Response<Self::StreamGuildStatusStream>, Status> {
+        let guild_id = GuildId::new(request.into_inner().guild_id).unwrap();
+
+        let mut rx = self.vm_manager.guild_events().subscribe(guild_id);
+        let out = async_stream::try_stream! {
+            loop {
+                match rx.recv().await {
+                    Ok(GuildEvent::Status(evt)) => yield proto::GuildStatusItem::from(evt),
+                    Ok(_) | Err(RecvError::Lagged(_)) => {}
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        };
+
+        Ok(Response::new(Box::pin(out)))
+    }
+
+    type StreamScriptEventsStream = ScriptEventStream;
+
+    async fn stream_script_events(
+        &self,
+        request: tonic::Request<proto::GuildSpecifier>,
+    ) -> Result<Response<Self::StreamScriptEventsStream>, Status> {
+        let guild_id = GuildId::new(request.into_inner().guild_id).unwrap();
+
+        let mut rx = self.vm_manager.guild_events().subscribe(guild_id);
+        let out = async_stream::try_stream! {
+            loop {
+                match rx.recv().await {
+                    Ok(GuildEvent::Script(evt)) => yield proto::ScriptEventItem::from(evt),
+                    Ok(_) | Err(RecvError::Lagged(_)) => {}
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        };
+
+        Ok(Response::new(Box::pin(out)))
+    }
 }
@@ -1,5 +1,8 @@
+use std::str::FromStr;
+
 use oauth2::{basic::BasicClient, AuthUrl, ClientId, ClientSecret, RedirectUrl, TokenUrl};
 use structopt::StructOpt;
+use tscompiler::typecheck::TypeChecker;
 
 #[derive(Clone, StructOpt)]
 pub struct RunConfig {
@@ -38,9 +41,78 @@ pub struct RunConfig {
 
     #[structopt(long, env = "BOT_RPC_LISTEN_ADDR", default_value = "127.0.0.1:7448")]
     pub bot_rpc_listen_addr: String,
+
+    /// address the other services reach this bot node's rpc server on, defaults to http://{bot_rpc_listen_addr}
+    #[structopt(long, env = "BOT_RPC_ADVERTISE_ADDR")]
+    pub bot_rpc_advertise_addr: Option<String>,
+
+    /// find the bot nodes through the database and route requests to the node running the guild,
+    /// instead of connecting to bot_rpc_connect_addr
+    #[structopt(
+        long,
+        env = "DISCOVER_BOT_NODES",
+        parse(try_from_str),
+        default_value = "false"
+    )]
+    pub discover_bot_nodes: bool,
+
+    /// the shards this bot node runs, in the form of "first-last", all of them if not set
+    #[structopt(long, env = "SHARD_RANGE", requires = "total-shards")]
+    pub shard_range: Option<ShardRangeConfig>,
+
+    /// total number of shards across all the bot nodes, required when running a shard range
+    #[structopt(long, env = "TOTAL_SHARDS")]
+    pub total_shards: Option<u64>,
+
+    #[structopt(
+        long,
+        env = "BOT_METRICS_LISTEN_ADDR",
+        default_value = "127.0.0.1:7449"
+    )]
+    pub bot_metrics_listen_addr: String,
+
+    #[structopt(
+        long,
+        env = "WEBAPI_METRICS_LISTEN_ADDR",
+        default_value = "127.0.0.1:7450"
+    )]
+    pub webapi_metrics_listen_addr: String,
+
+    /// comma separated discord user ids allowed to use the admin api
+    #[structopt(long, env = "SUPERUSERS", use_delimiter = true)]
+    pub superusers: Vec<u64>,
+
+    /// tsc compatible command used to type check scripts when they're saved
+    #[structopt(long, env = "TYPECHECK_COMMAND", default_value = "tsc")]
+    pub typecheck_command: String,
+
+    /// directory with the generated botloader typings, scripts are only type checked if this is set
+    #[structopt(long, env = "TYPECHECK_TYPINGS_DIR")]
+    pub typecheck_typings_dir: Option<String>,
+
+    /// also store compiled scripts in the database, so they don't have to be recompiled after the bot restarts
+    #[structopt(
+        long,
+        env = "PERSIST_COMPILE_CACHE",
+        parse(try_from_str),
+        default_value = "false"
+    )]
+    pub persist_compile_cache: bool,
 }
 
 impl RunConfig {
+    pub fn get_type_checker(&self) -> Option<TypeChecker> {
+        self.typecheck_typings_dir
+            .as_ref()
+            .map(|dir| TypeChecker::new(self.typecheck_command.clone(), dir.into()))
+    }
+
+    pub fn get_bot_rpc_advertise_addr(&self) -> String {
+        self.bot_rpc_advertise_addr
+            .clone()
+            .unwrap_or_else(|| format!("http://{}", self.bot_rpc_listen_addr))
+    }
+
     pub fn get_discord_oauth2_client(&self) -> BasicClient {
         BasicClient::new(
             ClientId::new(self.client_id.clone()),
@@ -54,3 +126,44 @@ impl RunConfig {
         )
     }
 }
+
+/// A range of shards, both ends inclusive
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ShardRangeConfig {
+    pub first: u64,
+    pub last: u64,
+}
+
+impl FromStr for ShardRangeConfig {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (first, last) = s
+            .split_once('-')
+            .ok_or_else(|| "shard range has to be in the form of \"first-last\"".to_string())?;
+
+        let first = first.trim().parse().map_err(|_| "invalid first shard")?;
+        let last = last.trim().parse().map_err(|_| "invalid last shard")?;
+
+        if first > last {
+            return Err("first shard can't be after the last one".to_string());
+        }
+
+        Ok(Self { first, last })
+    }
+}
+
+#[test]
+fn parse_shard_range() {
+    assert_eq!(
+        "0-3".parse::<ShardRangeConfig>(),
+        Ok(ShardRangeConfig { first: 0, last: 3 })
+    );
+    assert_eq!(
+        "5-5".parse::<ShardRangeConfig>(),
+        Ok(ShardRangeConfig { first: 5, last: 5 })
+    );
+    assert!("3-1".parse::<ShardRangeConfig>().is_err());
+    assert!("3".parse::<ShardRangeConfig>().is_err());
+    assert!("a-b".parse::<ShardRangeConfig>().is_err());
+}
@@ -0,0 +1,46 @@
+//! Prometheus metrics are registered in the default registry by the components recording them,
+//! this serves them on `/metrics` on a seperate address so they're not exposed publicly
+
+use std::{convert::Infallible, net::SocketAddr};
+
+use hyper::{
+    header,
+    service::{make_service_fn, service_fn},
+    Body, Method, Request, Response, Server, StatusCode,
+};
+use prometheus::{Encoder, TextEncoder};
+use tracing::{error, info};
+
+pub async fn run_metrics_server(addr: String) {
+    let addr: SocketAddr = addr.parse().expect("invalid metrics listen address");
+
+    let make_service =
+        make_service_fn(|_| async { Ok::<_, Infallible>(service_fn(handle_request)) });
+
+    info!("serving metrics on {}", addr);
+    if let Err(err) = Server::bind(&addr).serve(make_service).await {
+        error!(%err, "metrics server failed");
+    }
+}
+
+async fn handle_request(req: Request<Body>) -> Result<Response<Body>, Infallible> {
+    if req.method() != Method::GET || req.uri().path() != "/metrics" {
+        let mut resp = Response::new(Body::empty());
+        *resp.status_mut() = StatusCode::NOT_FOUND;
+        return Ok(resp);
+    }
+
+    let encoder = TextEncoder::new();
+    let mut buf = Vec::new();
+    if let Err(err) = encoder.encode(&prometheus::gather(), &mut buf) {
+        error!(%err, "failed encoding metrics");
+        let mut resp = Response::new(Body::empty());
+        *resp.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+        return Ok(resp);
+    }
+
+    let mut resp = Response::new(Body::from(buf));
+    resp.headers_mut()
+        .insert(header::CONTENT_TYPE, encoder.format_type().parse().unwrap());
+    Ok(resp)
+}
@@ -0,0 +1,254 @@
+use std::collections::HashSet;
+
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use stores::{
+    bucketstore::{BucketStore, StoreValue, GUILD_STORAGE_LIMIT_BYTES},
+    config::{ConfigStore, CreateScript, GuildMetaConfig},
+    import::{GuildImport, ImportIntervalTimer, ImportScript, ImportStorageEntry, ImportStore},
+    timers::TimerStore,
+};
+use twilight_model::id::GuildId;
+use validation::{validate, ValidationError};
+
+use crate::{fetch_all_bucket_entries, ArchiveError, GuildArchive};
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ImportOptions {
+    /// Only check for conflicts, don't write anything
+    #[serde(default)]
+    pub dry_run: bool,
+
+    /// Overwrite existing scripts and storage entries with the same name/key
+    #[serde(default)]
+    pub overwrite: bool,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "kind")]
+pub enum ImportConflict {
+    /// A script with the same name already exists in the guild
+    ScriptExists { name: String },
+
+    /// The script in the archive is not valid (bad name or similar)
+    InvalidScript {
+        name: String,
+        errors: Vec<ValidationError>,
+    },
+
+    /// A storage entry with the same bucket and key already exists in the guild
+    StorageEntryExists { bucket: String, key: String },
+
+    /// Importing the storage entries would put the guild above its storage limit
+    StorageLimitExceeded {
+        usage_bytes: u64,
+        import_bytes: u64,
+        limit_bytes: u64,
+    },
+
+    /// The archive was made from another guild, the guild config is skipped as it refers to
+    /// channels in that guild
+    OtherGuild { archive_guild_id: GuildId },
+}
+
+impl ImportConflict {
+    /// Returns true if this conflict prevents the import from going through
+    pub fn is_blocking(&self, options: &ImportOptions) -> bool {
+        match self {
+            Self::ScriptExists { .. } | Self::StorageEntryExists { .. } => !options.overwrite,
+            Self::InvalidScript { .. } | Self::StorageLimitExceeded { .. } => true,
+            Self::OtherGuild { .. } => false,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct ImportReport {
+    pub conflicts: Vec<ImportConflict>,
+
+    /// Whether the archive was actually written to the guild,
+    /// this is false for dry runs and imports that had blocking conflicts
+    pub applied: bool,
+
+    pub num_scripts: usize,
+    pub num_storage_entries: usize,
+    pub num_interval_timers: usize,
+}
+
+/// Imports the archive into the provided guild
+///
+/// This checks for conflicts first, and will not write anything if there are any blocking
+/// conflicts or if it's a dry run. Everything is written in a single transaction.
+///
+/// Note that the guild vm needs to be reloaded afterwards for the changes to take effect.
+pub async fn import_guild<ST>(
+    store: &ST,
+    guild_id: GuildId,
+    archive: &GuildArchive,
+    options: &ImportOptions,
+) -> Result<ImportReport, ArchiveError>
+where
+    ST: ConfigStore + BucketStore + TimerStore + ImportStore + Send + Sync,
+    <ST as ConfigStore>::Error: 'static,
+    <ST as TimerStore>::Error: 'static,
+    <ST as ImportStore>::Error: 'static,
+{
+    archive.check_version()?;
+
+    let mut conflicts = Vec::new();
+
+    if archive.guild_id != guild_id {
+        conflicts.push(ImportConflict::OtherGuild {
+            archive_guild_id: archive.guild_id,
+        });
+    }
+
+    // scripts
+    let existing_scripts = store
+        .list_scripts(guild_id)
+        .await
+        .map_err(anyhow::Error::new)?;
+
+    for script in &archive.scripts {
+        let create = CreateScript {
+            name: script.name.clone(),
+            original_source: script.original_source.clone(),
+            enabled: script.enabled,
+            author_id: None,
+        };
+
+        if let Err(errors) = validate(&create) {
+            conflicts.push(ImportConflict::InvalidScript {
+                name: script.name.clone(),
+                errors,
+            });
+        }
+
+        if existing_scripts.iter().any(|s| s.name == script.name) {
+            conflicts.push(ImportConflict::ScriptExists {
+                name: script.name.clone(),
+            });
+        }
+    }
+
+    // storage, entries that have expired since the export are skipped
+    let now = Utc::now();
+    let entries = archive
+        .storage
+        .iter()
+        .filter(|e| e.expires_at.map(|t| t > now).unwrap_or(true))
+        .collect::<Vec<_>>();
+
+    let import_keys = entries
+        .iter()
+        .map(|e| (e.bucket.as_str(), e.key.as_str()))
+        .collect::<HashSet<_>>();
+
+    let buckets = entries
+        .iter()
+        .map(|e| e.bucket.clone())
+        .collect::<HashSet<_>>();
+
+    for bucket in buckets {
+        let existing = fetch_all_bucket_entries(store, guild_id, bucket).await?;
+        for existing_entry in existing {
+            if import_keys.contains(&(existing_entry.bucket.as_str(), existing_entry.key.as_str()))
+            {
+                conflicts.push(ImportConflict::StorageEntryExists {
+                    bucket: existing_entry.bucket,
+                    key: existing_entry.key,
+                });
+            }
+        }
+    }
+
+    let usage_bytes = store
+        .guild_storage_usage_bytes(guild_id)
+        .await
+        .map_err(anyhow::Error::new)?;
+
+    let import_bytes = entries
+        .iter()
+        .map(|e| {
+            let value_size = match &e.value {
+                StoreValue::Json(v) => serde_json::to_string(v).map(|s| s.len()).unwrap_or(0),
+                StoreValue::Float(_) => 8,
+            };
+            (e.bucket.len() + e.key.len() + value_size) as u64
+        })
+        .sum::<u64>();
+
+    if usage_bytes + import_bytes > GUILD_STORAGE_LIMIT_BYTES {
+        conflicts.push(ImportConflict::StorageLimitExceeded {
+            usage_bytes,
+            import_bytes,
+            limit_bytes: GUILD_STORAGE_LIMIT_BYTES,
+        });
+    }
+
+    // timers for scripts not included in the archive has nothing to attach to
+    let timers = archive
+        .interval_timers
+        .iter()
+        .filter(|t| archive.scripts.iter().any(|s| s.name == t.script_name))
+        .collect::<Vec<_>>();
+
+    let blocked = conflicts.iter().any(|c| c.is_blocking(options));
+    let mut report = ImportReport {
+        applied: false,
+        num_scripts: archive.scripts.len(),
+        num_storage_entries: entries.len(),
+        num_interval_timers: timers.len(),
+        conflicts,
+    };
+
+    if blocked || options.dry_run {
+        return Ok(report);
+    }
+
+    // everything checks out, write it all at once
+    let import = GuildImport {
+        scripts: archive
+            .scripts
+            .iter()
+            .map(|script| ImportScript {
+                name: script.name.clone(),
+                original_source: script.original_source.clone(),
+                enabled: script.enabled,
+                contributes: script.contributes.clone(),
+            })
+            .collect(),
+        interval_timers: timers
+            .into_iter()
+            .map(|timer| ImportIntervalTimer {
+                script_name: timer.script_name.clone(),
+                name: timer.name.clone(),
+                interval: timer.interval.clone(),
+                last_run: timer.last_run,
+            })
+            .collect(),
+        storage_entries: entries
+            .into_iter()
+            .map(|entry| ImportStorageEntry {
+                bucket: entry.bucket.clone(),
+                key: entry.key.clone(),
+                value: entry.value.clone(),
+                expires_at: entry.expires_at,
+            })
+            .collect(),
+        meta_config: (archive.guild_id == guild_id).then(|| GuildMetaConfig {
+            guild_id,
+            error_channel_id: archive.meta_config.error_channel_id,
+            command_conflict_policy: archive.meta_config.command_conflict_policy,
+            fetch_allowed_domains: archive.meta_config.fetch_allowed_domains.clone(),
+        }),
+    };
+
+    store
+        .apply_guild_import(guild_id, import)
+        .await
+        .map_err(anyhow::Error::new)?;
+
+    report.applied = true;
+    Ok(report)
+}
@@ -0,0 +1,208 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use stores::{
+    bucketstore::{BucketStore, Entry, StoreValue},
+    config::{CommandConflictPolicy, ConfigStore, GuildMetaConfig, ScriptContributes},
+    timers::{IntervalType, TimerStore},
+};
+use thiserror::Error;
+use twilight_model::id::{ChannelId, GuildId};
+
+mod import;
+
+pub use import::{import_guild, ImportConflict, ImportOptions, ImportReport};
+
+/// The current archive format version, bump this whenever the format changes in a
+/// non backwards compatible way
+pub const ARCHIVE_VERSION: u32 = 1;
+
+/// How many storage entries we fetch at a time when paginating through a bucket
+const ENTRIES_PAGE_SIZE: u32 = 500;
+
+#[derive(Debug, Error)]
+pub enum ArchiveError {
+    #[error("unsupported archive version: {0} (supported: {})", ARCHIVE_VERSION)]
+    UnsupportedVersion(u32),
+
+    #[error("store error: {0}")]
+    Store(#[from] anyhow::Error),
+}
+
+/// A full snapshot of a guild's botloader setup
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GuildArchive {
+    pub version: u32,
+    pub guild_id: GuildId,
+    pub created_at: DateTime<Utc>,
+    pub meta_config: ArchivedMetaConfig,
+    pub scripts: Vec<ArchivedScript>,
+    pub storage: Vec<ArchivedStorageEntry>,
+    pub interval_timers: Vec<ArchivedIntervalTimer>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArchivedMetaConfig {
+    pub error_channel_id: Option<ChannelId>,
+    #[serde(default)]
+    pub command_conflict_policy: CommandConflictPolicy,
+    #[serde(default)]
+    pub fetch_allowed_domains: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArchivedScript {
+    pub name: String,
+    pub original_source: String,
+    pub enabled: bool,
+    pub contributes: ScriptContributes,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArchivedStorageEntry {
+    pub bucket: String,
+    pub key: String,
+    pub value: StoreValue,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+/// Interval timers are tied to scripts by id in the store, since the id's change
+/// when importing we refer to the script by name instead
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArchivedIntervalTimer {
+    pub script_name: String,
+    pub name: String,
+    pub interval: IntervalType,
+    pub last_run: DateTime<Utc>,
+}
+
+impl GuildArchive {
+    pub fn from_json(data: &[u8]) -> Result<Self, anyhow::Error> {
+        Ok(serde_json::from_slice(data)?)
+    }
+
+    pub fn to_json(&self) -> Result<String, anyhow::Error> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    fn check_version(&self) -> Result<(), ArchiveError> {
+        if self.version != ARCHIVE_VERSION {
+            return Err(ArchiveError::UnsupportedVersion(self.version));
+        }
+
+        Ok(())
+    }
+}
+
+/// Creates an archive of all the scripts, storage entries, interval timers and the config
+/// of the provided guild
+pub async fn export_guild<ST>(store: &ST, guild_id: GuildId) -> Result<GuildArchive, ArchiveError>
+where
+    ST: ConfigStore + BucketStore + TimerStore + Send + Sync,
+    <ST as ConfigStore>::Error: 'static,
+    <ST as TimerStore>::Error: 'static,
+{
+    let meta_config = store
+        .get_guild_meta_config_or_default(guild_id)
+        .await
+        .map_err(anyhow::Error::new)?;
+
+    let scripts = store
+        .list_scripts(guild_id)
+        .await
+        .map_err(anyhow::Error::new)?;
+
+    let timers = store
+        .get_all_interval_timers(guild_id)
+        .await
+        .map_err(anyhow::Error::new)?;
+
+    let interval_timers = timers
+        .into_iter()
+        .filter_map(|timer| {
+            // timers belonging to scripts that no longer exist are left behind
+            let script = scripts.iter().find(|s| s.id == timer.script_id)?;
+            Some(ArchivedIntervalTimer {
+                script_name: script.name.clone(),
+                name: timer.name,
+                interval: timer.interval,
+                last_run: timer.last_run,
+            })
+        })
+        .collect();
+
+    let mut storage = Vec::new();
+    let buckets = store
+        .list_buckets(guild_id)
+        .await
+        .map_err(anyhow::Error::new)?;
+
+    for bucket in buckets {
+        let entries = fetch_all_bucket_entries(store, guild_id, bucket.name).await?;
+        storage.extend(entries.into_iter().map(|entry| ArchivedStorageEntry {
+            bucket: entry.bucket,
+            key: entry.key,
+            value: entry.value,
+            expires_at: entry.expires_at,
+        }));
+    }
+
+    Ok(GuildArchive {
+        version: ARCHIVE_VERSION,
+        guild_id,
+        created_at: Utc::now(),
+        meta_config: meta_config.into(),
+        scripts: scripts
+            .into_iter()
+            .map(|script| ArchivedScript {
+                name: script.name,
+                original_source: script.original_source,
+                enabled: script.enabled,
+                contributes: script.contributes,
+            })
+            .collect(),
+        storage,
+        interval_timers,
+    })
+}
+
+async fn fetch_all_bucket_entries<ST: BucketStore + Sync>(
+    store: &ST,
+    guild_id: GuildId,
+    bucket: String,
+) -> Result<Vec<Entry>, ArchiveError> {
+    let mut result = Vec::new();
+    loop {
+        let after = result
+            .last()
+            .map(|e: &Entry| e.key.clone())
+            .unwrap_or_default();
+
+        let entries = store
+            .get_many(
+                guild_id,
+                bucket.clone(),
+                "%".to_string(),
+                after,
+                ENTRIES_PAGE_SIZE,
+            )
+            .await
+            .map_err(anyhow::Error::new)?;
+
+        let done = entries.len() < ENTRIES_PAGE_SIZE as usize;
+        result.extend(entries);
+
+        if done {
+            return Ok(result);
+        }
+    }
+}
+
+impl From<GuildMetaConfig> for ArchivedMetaConfig {
+    fn from(v: GuildMetaConfig) -> Self {
+        Self {
+            error_channel_id: v.error_channel_id,
+            command_conflict_policy: v.command_conflict_policy,
+            fetch_allowed_domains: v.fetch_allowed_domains,
+        }
+    }
+}
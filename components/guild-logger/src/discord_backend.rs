@@ -62,7 +62,12 @@ fn format_entry(entry: LogEntry) -> Option<String> {
         } else {
             format!("[{}]", entry.level)
         };
-        Some(format!("{}: {}", prefix, entry.message))
+        let mut message = format!("{}: {}", prefix, entry.message);
+        // keep the message short, discord has a length limit
+        for frame in entry.stack.iter().take(10) {
+            message.push_str(&format!("\n    {}", frame));
+        }
+        Some(message)
     } else {
         None
     }
@@ -9,6 +9,9 @@ pub struct LogEntry {
     pub message: String,
     pub script_context: Option<ScriptContext>,
     pub level: LogLevel,
+    /// Stack trace of the error, with positions mapped back to the typescript source
+    #[serde(default)]
+    pub stack: Vec<StackFrame>,
 }
 
 impl LogEntry {
@@ -18,6 +21,7 @@ impl LogEntry {
             message: msg,
             level: LogLevel::Critical,
             script_context: None,
+            stack: Vec::new(),
         }
     }
 
@@ -27,6 +31,7 @@ impl LogEntry {
             message: msg,
             level: LogLevel::Error,
             script_context: None,
+            stack: Vec::new(),
         }
     }
 
@@ -36,6 +41,7 @@ impl LogEntry {
             message: msg,
             level: LogLevel::Info,
             script_context: None,
+            stack: Vec::new(),
         }
     }
 
@@ -50,6 +56,20 @@ impl LogEntry {
             script_context: Some(ScriptContext { filename, line_col }),
             message: msg,
             level: LogLevel::Error,
+            stack: Vec::new(),
+        }
+    }
+    /// An uncaught error, the script context is set to the top most frame of the stack
+    pub fn script_exception(guild_id: GuildId, msg: String, stack: Vec<StackFrame>) -> Self {
+        Self {
+            guild_id,
+            script_context: stack.first().map(|frame| ScriptContext {
+                filename: frame.filename.clone(),
+                line_col: frame.line_col,
+            }),
+            message: msg,
+            level: LogLevel::Error,
+            stack,
         }
     }
     pub fn script_warning(
@@ -63,6 +83,7 @@ impl LogEntry {
             script_context: Some(ScriptContext { filename, line_col }),
             message: msg,
             level: LogLevel::Warn,
+            stack: Vec::new(),
         }
     }
     pub fn script_console(
@@ -76,6 +97,7 @@ impl LogEntry {
             script_context: Some(ScriptContext { filename, line_col }),
             message: msg,
             level: LogLevel::ConsoleLog,
+            stack: Vec::new(),
         }
     }
     pub fn script_info(
@@ -89,6 +111,7 @@ impl LogEntry {
             script_context: Some(ScriptContext { filename, line_col }),
             message: msg,
             level: LogLevel::Info,
+            stack: Vec::new(),
         }
     }
 }
@@ -112,6 +135,33 @@ impl Display for ScriptContext {
     }
 }
 
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct StackFrame {
+    pub function: Option<String>,
+    pub filename: String,
+    pub line_col: Option<LineCol>,
+}
+
+impl Display for StackFrame {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "at ")?;
+        if let Some(function) = &self.function {
+            write!(f, "{} (", function)?;
+        }
+
+        write!(f, "{}", self.filename)?;
+        if let Some((line, col)) = self.line_col {
+            write!(f, ":{}:{}", line, col)?;
+        }
+
+        if self.function.is_some() {
+            write!(f, ")")?;
+        }
+
+        Ok(())
+    }
+}
+
 #[derive(Clone, Serialize, Deserialize)]
 pub enum LogLevel {
     Critical,
@@ -5,7 +5,7 @@ pub mod discord_backend;
 pub mod entry;
 pub mod guild_subscriber_backend;
 
-pub use entry::{LogEntry, LogLevel, ScriptContext};
+pub use entry::{LogEntry, LogLevel, ScriptContext, StackFrame};
 
 #[async_trait::async_trait]
 pub trait GuildLoggerBackend {
@@ -3,3 +3,4 @@ pub mod member_remove;
 pub mod message_delete;
 pub mod message_update;
 pub mod timers;
+pub mod webhook;
@@ -0,0 +1,21 @@
+use std::collections::HashMap;
+
+use serde::Serialize;
+use ts_rs::TS;
+
+use crate::util::NotBigU64;
+
+/// A http request made to one of a script's webhooks
+#[derive(Clone, Debug, Serialize, TS)]
+#[ts(export)]
+#[ts(export_to = "bindings/events/WebhookRequest.ts")]
+#[serde(rename_all = "camelCase")]
+pub struct WebhookRequest {
+    pub request_id: NotBigU64,
+    pub script_id: NotBigU64,
+    pub name: String,
+    pub method: String,
+    /// Header names are lowercase, repeated headers are joined with ", "
+    pub headers: HashMap<String, String>,
+    pub body: String,
+}
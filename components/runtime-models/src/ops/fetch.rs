@@ -0,0 +1,30 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+use crate::util::NotBigU64;
+
+#[derive(Clone, Debug, Serialize, Deserialize, TS)]
+#[ts(export)]
+#[ts(export_to = "bindings/ops/FetchRequest.ts")]
+#[serde(rename_all = "camelCase")]
+pub struct OpFetchRequest {
+    pub script_id: NotBigU64,
+    pub url: String,
+    pub method: String,
+    pub headers: HashMap<String, String>,
+    #[serde(default)]
+    #[ts(optional)]
+    pub body: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, TS)]
+#[ts(export)]
+#[ts(export_to = "bindings/ops/FetchResponse.ts")]
+#[serde(rename_all = "camelCase")]
+pub struct OpFetchResponse {
+    pub status: u16,
+    pub headers: HashMap<String, String>,
+    pub body: String,
+}
@@ -1,4 +1,7 @@
 pub mod console;
+pub mod fetch;
 pub mod messages;
 pub mod script;
+pub mod script_events;
 pub mod storage;
+pub mod webhook;
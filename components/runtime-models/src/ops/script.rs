@@ -2,7 +2,7 @@ use serde::{Deserialize, Serialize};
 use ts_rs::TS;
 use twilight_model::application::command::NumberCommandOptionData;
 
-use crate::util::NotBigU64;
+use crate::{ops::storage::OpStorageBucket, util::NotBigU64};
 
 #[derive(Clone, Debug, Deserialize, Serialize, TS)]
 #[ts(export)]
@@ -12,9 +12,37 @@ pub struct ScriptMeta {
     pub description: String,
     #[ts(type = "number")]
     pub script_id: NotBigU64,
+    pub name: String,
     pub commands: Vec<Command>,
     pub command_groups: Vec<CommandGroup>,
     pub interval_timers: Vec<IntervalTimer>,
+    pub storage_buckets: Vec<OpStorageBucket>,
+}
+
+/// Asks whether a script handles a received command interaction
+#[derive(Clone, Debug, Deserialize, Serialize, TS)]
+#[ts(export)]
+#[serde(rename_all = "camelCase")]
+#[ts(export_to = "bindings/ops/CommandOwnerQuery.ts")]
+pub struct OpCommandOwnerQuery {
+    #[ts(type = "number")]
+    pub script_id: NotBigU64,
+    pub name: String,
+    pub parent_name: Option<String>,
+    pub parent_parent_name: Option<String>,
+}
+
+impl OpCommandOwnerQuery {
+    /// The names of the command, separated by spaces like the paths of [stores::config::command_paths]
+    pub fn path(&self) -> String {
+        [&self.parent_parent_name, &self.parent_name]
+            .into_iter()
+            .flatten()
+            .chain(std::iter::once(&self.name))
+            .map(String::as_str)
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize, TS)]
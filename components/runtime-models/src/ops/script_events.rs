@@ -0,0 +1,15 @@
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+use crate::util::NotBigU64;
+
+#[derive(Clone, Debug, Serialize, Deserialize, TS)]
+#[ts(export)]
+#[ts(export_to = "bindings/ops/ScriptEvent.ts")]
+#[serde(rename_all = "camelCase")]
+pub struct OpScriptEvent {
+    pub script_id: NotBigU64,
+    pub name: String,
+    #[ts(type = "any")]
+    pub data: serde_json::Value,
+}
@@ -1,5 +1,8 @@
 use serde::{Deserialize, Serialize};
-use stores::bucketstore::{self, SetCondition};
+use stores::{
+    bucketstore::{self, SetCondition},
+    config::StorageBucketContrib,
+};
 use ts_rs::TS;
 
 use crate::util::NotBigU64;
@@ -10,6 +13,21 @@ use crate::util::NotBigU64;
 #[serde(rename_all = "camelCase")]
 pub struct OpStorageBucket {
     pub name: String,
+    #[serde(default)]
+    #[ts(optional)]
+    pub quota_bytes: Option<NotBigU64>,
+    #[serde(default)]
+    pub shared: bool,
+}
+
+impl From<&OpStorageBucket> for StorageBucketContrib {
+    fn from(v: &OpStorageBucket) -> Self {
+        Self {
+            name: v.name.clone(),
+            quota_bytes: v.quota_bytes.map(|q| q.0),
+            shared: v.shared,
+        }
+    }
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize, TS)]
@@ -44,6 +62,7 @@ impl From<OpStorageBucketValue> for bucketstore::StoreValue {
 #[ts(export_to = "bindings/ops/StorageBucketSetValue.ts")]
 #[serde(rename_all = "camelCase")]
 pub struct OpStorageBucketSetValue {
+    pub bucket_handle: String,
     pub bucket_name: String,
     pub key: String,
     pub value: OpStorageBucketValue,
@@ -57,6 +76,7 @@ pub struct OpStorageBucketSetValue {
 #[ts(export_to = "bindings/ops/StorageBucketSetIf.ts")]
 #[serde(rename_all = "camelCase")]
 pub struct OpStorageBucketSetIf {
+    pub bucket_handle: String,
     pub bucket_name: String,
     pub key: String,
     pub value: OpStorageBucketValue,
@@ -71,6 +91,7 @@ pub struct OpStorageBucketSetIf {
 #[ts(export_to = "bindings/ops/StorageBucketEntryId.ts")]
 #[serde(rename_all = "camelCase")]
 pub struct OpStorageBucketEntryId {
+    pub bucket_handle: String,
     pub bucket_name: String,
     pub key: String,
 }
@@ -80,6 +101,7 @@ pub struct OpStorageBucketEntryId {
 #[ts(export_to = "bindings/ops/StorageBucketList.ts")]
 #[serde(rename_all = "camelCase")]
 pub struct OpStorageBucketList {
+    pub bucket_handle: String,
     pub bucket_name: String,
     #[serde(default)]
     #[ts(optional)]
@@ -114,6 +136,7 @@ impl From<OpStorageBucketListOrder> for bucketstore::SortedOrder {
 #[ts(export_to = "bindings/ops/StorageBucketSortedList.ts")]
 #[serde(rename_all = "camelCase")]
 pub struct OpStorageBucketSortedList {
+    pub bucket_handle: String,
     pub bucket_name: String,
 
     #[serde(default)]
@@ -132,11 +155,78 @@ pub struct OpStorageBucketSortedList {
 #[ts(export_to = "bindings/ops/StorageBucketIncr.ts")]
 #[serde(rename_all = "camelCase")]
 pub struct OpStorageBucketIncr {
+    pub bucket_handle: String,
     pub bucket_name: String,
     pub key: String,
     pub amount: f64,
 }
 
+#[derive(Clone, Debug, Serialize, Deserialize, TS)]
+#[ts(export)]
+#[ts(export_to = "bindings/ops/StorageBucketJsonQuery.ts")]
+#[serde(rename_all = "camelCase")]
+pub struct OpStorageBucketJsonQuery {
+    pub bucket_handle: String,
+    pub bucket_name: String,
+    pub filters: Vec<OpStorageJsonFilter>,
+
+    #[serde(default)]
+    #[ts(optional)]
+    pub order_by: Option<OpStorageJsonOrderBy>,
+
+    #[serde(default)]
+    #[ts(optional)]
+    pub offset: Option<u32>,
+
+    #[serde(default)]
+    #[ts(optional)]
+    pub limit: Option<u32>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, TS)]
+#[ts(export)]
+#[ts(export_to = "bindings/ops/StorageJsonFilter.ts")]
+#[serde(rename_all = "camelCase")]
+pub struct OpStorageJsonFilter {
+    pub path: String,
+    pub op: OpStorageJsonFilterOp,
+    #[ts(type = "any")]
+    pub value: serde_json::Value,
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, TS)]
+#[ts(export)]
+#[ts(export_to = "bindings/ops/StorageJsonFilterOp.ts")]
+#[serde(rename_all = "camelCase")]
+pub enum OpStorageJsonFilterOp {
+    Eq,
+    Lt,
+    Lte,
+    Gt,
+    Gte,
+}
+
+impl From<OpStorageJsonFilterOp> for bucketstore::JsonFilterOp {
+    fn from(v: OpStorageJsonFilterOp) -> Self {
+        match v {
+            OpStorageJsonFilterOp::Eq => Self::Eq,
+            OpStorageJsonFilterOp::Lt => Self::Lt,
+            OpStorageJsonFilterOp::Lte => Self::Lte,
+            OpStorageJsonFilterOp::Gt => Self::Gt,
+            OpStorageJsonFilterOp::Gte => Self::Gte,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, TS)]
+#[ts(export)]
+#[ts(export_to = "bindings/ops/StorageJsonOrderBy.ts")]
+#[serde(rename_all = "camelCase")]
+pub struct OpStorageJsonOrderBy {
+    pub path: String,
+    pub order: OpStorageBucketListOrder,
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize, TS)]
 #[ts(export)]
 #[ts(export_to = "bindings/ops/StorageBucketEntry.ts")]
@@ -175,3 +265,24 @@ impl From<OpStorageBucketSetCondition> for SetCondition {
         }
     }
 }
+
+#[derive(Clone, Debug, Serialize, Deserialize, TS)]
+#[ts(export)]
+#[ts(export_to = "bindings/ops/StorageUsage.ts")]
+#[serde(rename_all = "camelCase")]
+pub struct OpStorageUsage {
+    pub usage_bytes: NotBigU64,
+    pub limit_bytes: NotBigU64,
+    pub buckets: Vec<OpStorageBucketUsage>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, TS)]
+#[ts(export)]
+#[ts(export_to = "bindings/ops/StorageBucketUsage.ts")]
+#[serde(rename_all = "camelCase")]
+pub struct OpStorageBucketUsage {
+    pub name: String,
+    pub entries: NotBigU64,
+    pub size_bytes: NotBigU64,
+    pub quota_bytes: Option<NotBigU64>,
+}
@@ -0,0 +1,17 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+use crate::util::NotBigU64;
+
+#[derive(Clone, Debug, Serialize, Deserialize, TS)]
+#[ts(export)]
+#[ts(export_to = "bindings/ops/WebhookResponse.ts")]
+#[serde(rename_all = "camelCase")]
+pub struct OpWebhookResponse {
+    pub request_id: NotBigU64,
+    pub status: u16,
+    pub headers: HashMap<String, String>,
+    pub body: String,
+}
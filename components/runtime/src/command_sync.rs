@@ -0,0 +1,277 @@
+use std::time::Duration;
+
+use serde::Deserialize;
+use twilight_http::{error::ErrorType, request::application::InteractionError};
+use twilight_model::{
+    application::command::Command,
+    id::{CommandId, GuildId},
+};
+
+/// The changes made to a guild's commands by [sync_guild_commands]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SyncSummary {
+    pub created: usize,
+    pub updated: usize,
+    pub deleted: usize,
+}
+
+#[derive(Debug)]
+pub enum SyncError {
+    /// Temporary failure, e.g a server error or a ratelimit, `retry_after` is set if discord told us when to retry
+    Retryable {
+        msg: String,
+        retry_after: Option<Duration>,
+    },
+    /// Discord rejected the commands, retrying won't help until they're changed
+    Permanent(String),
+}
+
+impl std::fmt::Display for SyncError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Retryable { msg, .. } => f.write_str(msg),
+            Self::Permanent(msg) => f.write_str(msg),
+        }
+    }
+}
+
+/// Brings the guild's commands on discord in line with `desired`
+///
+/// Only the commands that changed are created, updated or deleted, the requests go through the
+/// http client's ratelimiter so they wait for the route's bucket if needed.
+pub async fn sync_guild_commands(
+    client: &twilight_http::Client,
+    guild_id: GuildId,
+    desired: &[Command],
+) -> Result<SyncSummary, SyncError> {
+    let current = client
+        .get_guild_commands(guild_id)
+        .map_err(interaction_error)?
+        .exec()
+        .await
+        .map_err(http_error)?
+        .models()
+        .await
+        .map_err(|err| SyncError::Retryable {
+            msg: format!("failed decoding guild commands: {}", err),
+            retry_after: None,
+        })?;
+
+    let plan = plan_sync(&current, desired);
+    let mut summary = SyncSummary::default();
+
+    // delete first so removed commands don't count against the command limit
+    for id in plan.delete {
+        client
+            .delete_guild_command(guild_id, id)
+            .map_err(interaction_error)?
+            .exec()
+            .await
+            .map_err(http_error)?;
+        summary.deleted += 1;
+    }
+
+    for (id, cmd) in plan.update {
+        client
+            .update_guild_command(guild_id, id)
+            .map_err(interaction_error)?
+            .description(&cmd.description)
+            .command_options(&cmd.options)
+            .exec()
+            .await
+            .map_err(http_error)?;
+        summary.updated += 1;
+    }
+
+    for cmd in plan.create {
+        client
+            .create_guild_command(guild_id, &cmd.name)
+            .map_err(interaction_error)?
+            .chat_input(&cmd.description)
+            .map_err(interaction_error)?
+            .command_options(&cmd.options)
+            .map_err(interaction_error)?
+            .exec()
+            .await
+            .map_err(http_error)?;
+        summary.created += 1;
+    }
+
+    Ok(summary)
+}
+
+/// The requests needed to bring the guild's commands in line with the desired ones
+#[derive(Debug, Default)]
+struct SyncPlan<'a> {
+    delete: Vec<CommandId>,
+    update: Vec<(CommandId, &'a Command)>,
+    create: Vec<&'a Command>,
+}
+
+fn plan_sync<'a>(current: &[Command], desired: &'a [Command]) -> SyncPlan<'a> {
+    let mut plan = SyncPlan::default();
+
+    for existing in current {
+        if desired.iter().any(|cmd| same_command(cmd, existing)) {
+            continue;
+        }
+
+        if let Some(id) = existing.id {
+            plan.delete.push(id);
+        }
+    }
+
+    for cmd in desired {
+        match current.iter().find(|existing| same_command(cmd, existing)) {
+            Some(existing) => {
+                if let Some(id) = existing.id {
+                    if command_changed(existing, cmd) {
+                        plan.update.push((id, cmd));
+                    }
+                }
+            }
+            None => plan.create.push(cmd),
+        }
+    }
+
+    plan
+}
+
+fn same_command(a: &Command, b: &Command) -> bool {
+    a.name == b.name && a.kind == b.kind
+}
+
+fn command_changed(existing: &Command, cmd: &Command) -> bool {
+    existing.description != cmd.description || existing.options != cmd.options
+}
+
+fn interaction_error(err: InteractionError) -> SyncError {
+    SyncError::Permanent(format!("invalid command: {}", err))
+}
+
+fn http_error(err: twilight_http::Error) -> SyncError {
+    let msg = err.to_string();
+    match err.kind() {
+        ErrorType::Response { status, body, .. } => response_error(status.raw(), body, msg),
+        ErrorType::Unauthorized => SyncError::Permanent(msg),
+        _ => SyncError::Retryable {
+            msg,
+            retry_after: None,
+        },
+    }
+}
+
+#[derive(Deserialize)]
+struct RatelimitedBody {
+    retry_after: f64,
+}
+
+/// Ratelimits and server errors are retryable, other client errors are not
+fn response_error(status: u16, body: &[u8], msg: String) -> SyncError {
+    match status {
+        429 => SyncError::Retryable {
+            msg,
+            // read from the body ourselves as twilight doesn't always parse ratelimit errors as such
+            retry_after: serde_json::from_slice::<RatelimitedBody>(body)
+                .ok()
+                .map(|body| Duration::from_secs_f64(body.retry_after.max(0.0))),
+        },
+        400..=499 => SyncError::Permanent(msg),
+        _ => SyncError::Retryable {
+            msg,
+            retry_after: None,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use twilight_model::{
+        application::command::{Command, CommandType},
+        id::{CommandId, CommandVersionId},
+    };
+
+    use super::{plan_sync, response_error, SyncError};
+
+    fn command(id: Option<u64>, name: &str, description: &str) -> Command {
+        Command {
+            application_id: None,
+            default_permission: None,
+            description: description.to_string(),
+            guild_id: None,
+            id: id.map(|id| CommandId::new(id).unwrap()),
+            kind: CommandType::ChatInput,
+            name: name.to_string(),
+            options: Vec::new(),
+            version: CommandVersionId::new(1).unwrap(),
+        }
+    }
+
+    #[test]
+    fn plan_sync_diffs_commands() {
+        let current = vec![
+            command(Some(1), "same", "unchanged"),
+            command(Some(2), "changed", "old description"),
+            command(Some(3), "removed", "gone"),
+        ];
+        let desired = vec![
+            command(None, "same", "unchanged"),
+            command(None, "changed", "new description"),
+            command(None, "added", "new"),
+        ];
+
+        let plan = plan_sync(&current, &desired);
+        assert_eq!(plan.delete, vec![CommandId::new(3).unwrap()]);
+        assert_eq!(plan.update.len(), 1);
+        assert_eq!(plan.update[0].0, CommandId::new(2).unwrap());
+        assert_eq!(plan.update[0].1.description, "new description");
+        assert_eq!(plan.create.len(), 1);
+        assert_eq!(plan.create[0].name, "added");
+
+        let plan = plan_sync(&current, &current);
+        assert!(plan.delete.is_empty() && plan.update.is_empty() && plan.create.is_empty());
+
+        let plan = plan_sync(&current, &[]);
+        assert_eq!(plan.delete.len(), 3);
+    }
+
+    #[test]
+    fn classify_response_errors() {
+        let ratelimited =
+            br#"{"global":false,"message":"You are being rate limited.","retry_after":1.5}"#;
+        match response_error(429, ratelimited, String::new()) {
+            SyncError::Retryable { retry_after, .. } => {
+                assert_eq!(retry_after, Some(Duration::from_millis(1500)))
+            }
+            other => panic!("expected retryable error, got {:?}", other),
+        }
+
+        assert!(matches!(
+            response_error(429, b"", String::new()),
+            SyncError::Retryable {
+                retry_after: None,
+                ..
+            }
+        ));
+
+        let invalid = br#"{"code":50035,"message":"Invalid Form Body"}"#;
+        for status in [400, 403, 404] {
+            assert!(matches!(
+                response_error(status, invalid, String::new()),
+                SyncError::Permanent(_)
+            ));
+        }
+
+        for status in [500, 502, 503] {
+            assert!(matches!(
+                response_error(status, b"", String::new()),
+                SyncError::Retryable {
+                    retry_after: None,
+                    ..
+                }
+            ));
+        }
+    }
+}
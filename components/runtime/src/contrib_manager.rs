@@ -1,7 +1,15 @@
-use std::sync::Arc;
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
 use std::time::{Duration, Instant};
 
-use stores::config::{ConfigStore, IntervalTimerContrib, Script, ScriptContributes};
+use guild_logger::{GuildLogger, LogEntry};
+use lazy_static::lazy_static;
+use prometheus::{register_int_counter_vec, register_int_gauge, IntCounterVec, IntGauge};
+use stores::config::{
+    command_option_name, command_paths, find_command_conflicts, CommandConflict,
+    CommandConflictPolicy, CommandSyncState, ConfigStore, GuildCommandSyncStatus,
+    IntervalTimerContrib, Script, ScriptContributes, StorageBucketContrib,
+};
 use stores::timers::TimerStore;
 use tokio::sync::mpsc;
 use tracing::{error, info};
@@ -11,9 +19,25 @@ use twilight_model::application::command::{
 };
 use twilight_model::id::GuildId;
 
+use crate::command_sync::{self, SyncError};
+use crate::guild_events::{GuildEvents, GuildStatus};
 use runtime_models::ops::script::{Command, CommandGroup, ScriptMeta};
 use vm::vm::VmCommand;
 
+lazy_static! {
+    static ref SYNC_QUEUE_LENGTH: IntGauge = register_int_gauge!(
+        "botloader_command_sync_queue_length",
+        "Number of guilds waiting for their commands to be synced"
+    )
+    .unwrap();
+    static ref SYNC_FAILURES: IntCounterVec = register_int_counter_vec!(
+        "botloader_contrib_sync_failures_total",
+        "Number of failed attempts at storing script contributions and syncing commands",
+        &["kind"]
+    )
+    .unwrap();
+}
+
 #[derive(Clone, Debug)]
 pub struct ContribManagerHandle {
     send_loaded_script: mpsc::UnboundedSender<LoadedScript>,
@@ -25,17 +49,55 @@ impl ContribManagerHandle {
     }
 }
 
+/// The script handling each of the guild's registered commands, shared between all the vms
+///
+/// Set by the contrib manager when it resolves the guild's command conflicts, so that scripts
+/// that lost a conflict don't also run the command.
+#[derive(Clone, Default)]
+pub struct CommandRoutes {
+    inner: Arc<RwLock<HashMap<GuildId, HashMap<String, u64>>>>,
+}
+
+impl CommandRoutes {
+    pub fn set(&self, guild_id: GuildId, routes: HashMap<String, u64>) {
+        self.inner.write().unwrap().insert(guild_id, routes);
+    }
+
+    pub fn remove(&self, guild_id: GuildId) {
+        self.inner.write().unwrap().remove(&guild_id);
+    }
+
+    /// Whether the script handles the command at `path`
+    ///
+    /// Every script is allowed to handle its commands until the guild's conflicts have been resolved.
+    pub fn is_owner(&self, guild_id: GuildId, path: &str, script_id: u64) -> bool {
+        match self.inner.read().unwrap().get(&guild_id) {
+            Some(routes) => routes.get(path) == Some(&script_id),
+            None => true,
+        }
+    }
+}
+
+/// Max number of attempts at syncing a guild's commands before giving up until they change again
+const MAX_SYNC_ATTEMPTS: u32 = 8;
+
 pub struct ContribManager<CT> {
     config_store: CT,
     discord_client: Arc<twilight_http::Client>,
     rcv_loaded_script: mpsc::UnboundedReceiver<LoadedScript>,
     pending_checks: Vec<PendingCheckGroup>,
     timers_scheduler_tx: mpsc::UnboundedSender<timers::Command>,
+    guild_logger: GuildLogger,
+    guild_events: GuildEvents,
+    command_routes: CommandRoutes,
 }
 
 pub fn create_manager_pair<CT: ConfigStore + TimerStore + Clone + Send + Sync + 'static>(
     config_store: CT,
     discord_client: Arc<twilight_http::Client>,
+    guild_logger: GuildLogger,
+    guild_events: GuildEvents,
+    command_routes: CommandRoutes,
 ) -> (ContribManager<CT>, ContribManagerHandle) {
     let timer_tx = timers::Scheduler::create(config_store.clone());
     let (send, rcv) = mpsc::unbounded_channel();
@@ -47,6 +109,9 @@ pub fn create_manager_pair<CT: ConfigStore + TimerStore + Clone + Send + Sync +
             rcv_loaded_script: rcv,
             pending_checks: Vec::new(),
             timers_scheduler_tx: timer_tx,
+            guild_logger,
+            guild_events,
+            command_routes,
         },
         ContribManagerHandle {
             send_loaded_script: send,
@@ -97,6 +162,13 @@ where
 
         self.update_db_contribs(&evt, interval_contribs.clone())
             .await;
+        self.guild_events.send_status(
+            evt.guild_id,
+            GuildStatus::ScriptContribsUpdated {
+                script_id: evt.meta.script_id.0,
+                script_name: evt.meta.name.clone(),
+            },
+        );
 
         let wrapped_timers = interval_contribs
             .iter()
@@ -133,11 +205,16 @@ where
             }
         } else {
             // creata a new guild queue
+            self.set_sync_status(evt.guild_id, CommandSyncState::Pending, 0, None)
+                .await;
             self.pending_checks.push(PendingCheckGroup {
                 guild_id: evt.guild_id,
                 items: vec![evt],
                 started: Instant::now(),
-            })
+                failed_attempts: 0,
+                retry_at: None,
+            });
+            SYNC_QUEUE_LENGTH.set(self.pending_checks.len() as i64);
         }
     }
 
@@ -158,76 +235,322 @@ where
                 ScriptContributes {
                     commands: twilight_commands,
                     interval_timers: interval_contribs,
+                    storage_buckets: evt
+                        .meta
+                        .storage_buckets
+                        .iter()
+                        .map(StorageBucketContrib::from)
+                        .collect(),
                 },
             )
             .await
         {
             error!(%err, "failed updating db contribs");
+            SYNC_FAILURES.with_label_values(&["contribs"]).inc();
         }
     }
 
     async fn handle_tick(&mut self) {
         let old_list = std::mem::take(&mut self.pending_checks);
 
-        for item in old_list {
-            if item.started.elapsed() > Duration::from_secs(10) {
-                if self.process_item(&item).await.is_err() {
-                    // add back to queue if processing failed
-                    self.pending_checks.push(item);
+        for mut item in old_list {
+            let ready = match item.retry_at {
+                Some(retry_at) => Instant::now() >= retry_at,
+                None => item.started.elapsed() > Duration::from_secs(10),
+            };
+
+            if !ready {
+                self.pending_checks.push(item);
+                continue;
+            }
+
+            if let Err(err) = self.process_item(&item).await {
+                SYNC_FAILURES.with_label_values(&["commands"]).inc();
+                item.failed_attempts += 1;
+                if item.failed_attempts >= MAX_SYNC_ATTEMPTS {
+                    error!(%err, guild_id = item.guild_id.0.get(), "giving up syncing guild commands");
+                    self.guild_logger.log(LogEntry::error(
+                        item.guild_id,
+                        format!(
+                            "failed updating commands after {} attempts: {}",
+                            item.failed_attempts, err
+                        ),
+                    ));
+                    self.set_sync_status(
+                        item.guild_id,
+                        CommandSyncState::Failed,
+                        item.failed_attempts,
+                        Some(err.to_string()),
+                    )
+                    .await;
+                    continue;
                 }
-            } else {
+
+                let retry_after = match err {
+                    SyncError::Retryable {
+                        retry_after: Some(retry_after),
+                        ..
+                    } => retry_after.max(sync_backoff(item.failed_attempts)),
+                    _ => sync_backoff(item.failed_attempts),
+                };
+
+                info!(%err, guild_id = item.guild_id.0.get(), "retrying guild command sync in {:?}", retry_after);
+                self.set_sync_status(
+                    item.guild_id,
+                    CommandSyncState::Pending,
+                    item.failed_attempts,
+                    Some(err.to_string()),
+                )
+                .await;
+
+                // add back to queue if processing failed
+                item.retry_at = Some(Instant::now() + retry_after);
                 self.pending_checks.push(item);
             }
         }
+
+        SYNC_QUEUE_LENGTH.set(self.pending_checks.len() as i64);
     }
 
-    async fn process_item(&mut self, item: &PendingCheckGroup) -> Result<(), ()> {
-        // TODO: only do this when they have actually changed
-        // this will be more important when we need to scale it
-        // but needs to be done before before we go beyond the 100 server mark
-        self.update_guild_commands(item.guild_id).await?;
-        Ok(())
+    async fn process_item(&mut self, item: &PendingCheckGroup) -> Result<(), SyncError> {
+        self.update_guild_commands(item.guild_id).await
     }
 
-    async fn update_guild_commands(&mut self, guild_id: GuildId) -> Result<(), ()> {
+    /// Syncs the guild's commands with discord, only retryable errors are returned
+    async fn update_guild_commands(&mut self, guild_id: GuildId) -> Result<(), SyncError> {
         let all_guild_scripts = self
             .config_store
             .list_scripts(guild_id)
             .await
-            .map_err(|err| {
-                error!(%err, "failed retrieving guild scripts");
-            })?;
+            .map_err(|err| store_error("failed retrieving guild scripts", err))?;
 
-        let merged = merge_script_commands(all_guild_scripts);
+        let meta_config = self
+            .config_store
+            .get_guild_meta_config_or_default(guild_id)
+            .await
+            .map_err(|err| store_error("failed retrieving guild meta config", err))?;
+
+        let mut scripts = all_guild_scripts
+            .into_iter()
+            .filter(|s| s.enabled)
+            .collect::<Vec<_>>();
+        scripts.sort_by_key(|s| s.id);
+
+        let policy = meta_config.command_conflict_policy;
+        let n_conflicts = self.resolve_command_conflicts(guild_id, policy, &mut scripts);
+        self.command_routes
+            .set(guild_id, resolved_command_routes(&scripts));
+
+        if n_conflicts > 0 && policy == CommandConflictPolicy::Error {
+            let msg = format!(
+                "not updating commands, {} command conflict(s) need to be resolved first",
+                n_conflicts
+            );
+            self.guild_logger
+                .log(LogEntry::error(guild_id, msg.clone()));
+            self.set_sync_status(guild_id, CommandSyncState::Failed, 0, Some(msg))
+                .await;
+            return Ok(());
+        }
+
+        let merged = merge_script_commands(scripts);
         info!(
             "updating guild commands for {}, n commands: {}",
             guild_id,
             merged.len()
         );
 
+        match command_sync::sync_guild_commands(&self.discord_client, guild_id, &merged).await {
+            Ok(summary) => {
+                info!(
+                    "synced guild commands for {}: {} created, {} updated, {} deleted",
+                    guild_id, summary.created, summary.updated, summary.deleted
+                );
+                self.set_sync_status(guild_id, CommandSyncState::Synced, 0, None)
+                    .await;
+                Ok(())
+            }
+            Err(SyncError::Permanent(msg)) => {
+                error!(%msg, "failed updating guild commands");
+                self.guild_logger.log(LogEntry::error(
+                    guild_id,
+                    format!("failed updating commands: {}", msg),
+                ));
+                self.set_sync_status(guild_id, CommandSyncState::Failed, 0, Some(msg))
+                    .await;
+                Ok(())
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    async fn set_sync_status(
+        &self,
+        guild_id: GuildId,
+        state: CommandSyncState,
+        failed_attempts: u32,
+        last_error: Option<String>,
+    ) {
+        let now = chrono::Utc::now();
+        let status = GuildCommandSyncStatus {
+            guild_id,
+            state,
+            failed_attempts,
+            last_error,
+            last_synced_at: (state == CommandSyncState::Synced).then_some(now),
+            updated_at: now,
+        };
+
         if let Err(err) = self
-            .discord_client
-            .set_guild_commands(guild_id, &merged)
-            .unwrap()
-            .exec()
+            .config_store
+            .set_guild_command_sync_status(&status)
             .await
         {
-            error!(%err, "failed updating guild commands")
-            // TODO: for now this returns an ok, in the future once we have
-            // more validation we could reutrn an err here and have it retry
-            // (but not for client errors)
+            error!(%err, "failed storing guild command sync status");
+            return;
+        }
+
+        // the stored status keeps the time of the last successful sync
+        let stored = match self
+            .config_store
+            .get_guild_command_sync_status(guild_id)
+            .await
+        {
+            Ok(Some(stored)) => stored,
+            _ => status,
+        };
+        self.guild_events
+            .send_status(guild_id, GuildStatus::CommandSync(stored));
+    }
+}
+
+fn store_error<E: std::fmt::Display>(msg: &str, err: E) -> SyncError {
+    error!(%err, "{}", msg);
+    SyncError::Retryable {
+        msg: format!("{}: {}", msg, err),
+        retry_after: None,
+    }
+}
+
+/// Delay before the next attempt at syncing a guild's commands, doubles with every failed attempt
+fn sync_backoff(failed_attempts: u32) -> Duration {
+    Duration::from_secs(10 * 2u64.pow(failed_attempts.min(6)))
+}
+
+impl<CT> ContribManager<CT> {
+    /// Reports commands that are registered by more than one script, applying the policy
+    ///
+    /// Scripts are checked in order against the ones before them, so `scripts` should be sorted by id.
+    /// Returns the number of conflicts found.
+    fn resolve_command_conflicts(
+        &self,
+        guild_id: GuildId,
+        policy: CommandConflictPolicy,
+        scripts: &mut [Script],
+    ) -> usize {
+        let mut total = 0;
+
+        for i in 0..scripts.len() {
+            let (earlier, rest) = scripts.split_at_mut(i);
+            let script = &mut rest[0];
+
+            let conflicts = find_command_conflicts(&script.contributes.commands, earlier);
+            for conflict in &conflicts {
+                let resolution = match policy {
+                    CommandConflictPolicy::FirstWins => {
+                        format!("using the one from {}", conflict.other_script_name)
+                    }
+                    CommandConflictPolicy::Error => "commands will not be updated".to_string(),
+                    CommandConflictPolicy::NamespaceByScript => format!(
+                        "prefixing it with the script name ({})",
+                        namespaced_command_name(&script.name, top_level_name(conflict))
+                    ),
+                };
+
+                self.guild_logger.log(LogEntry::script_warning(
+                    guild_id,
+                    format!(
+                        "command `{}` conflicts with the one in {}, {}",
+                        conflict.command, conflict.other_script_name, resolution
+                    ),
+                    script.name.clone(),
+                    None,
+                ));
+            }
+
+            if policy == CommandConflictPolicy::NamespaceByScript && !conflicts.is_empty() {
+                self.namespace_commands(guild_id, script, &conflicts);
+            }
+
+            total += conflicts.len();
         }
 
-        Ok(())
+        total
+    }
+
+    /// Renames the conflicting top level commands of the script to `script-command`
+    fn namespace_commands(
+        &self,
+        guild_id: GuildId,
+        script: &mut Script,
+        conflicts: &[CommandConflict],
+    ) {
+        let script_name = script.name.clone();
+        script.contributes.commands.retain_mut(|cmd| {
+            if !conflicts.iter().any(|c| top_level_name(c) == cmd.name) {
+                return true;
+            }
+
+            let name = namespaced_command_name(&script_name, &cmd.name);
+            if name.chars().count() > 32 {
+                self.guild_logger.log(LogEntry::script_error(
+                    guild_id,
+                    format!(
+                        "command `{}` can't be registered, `{}` is longer than 32 characters",
+                        cmd.name, name
+                    ),
+                    script_name.clone(),
+                    None,
+                ));
+                return false;
+            }
+
+            cmd.name = name;
+            true
+        });
     }
 }
 
+/// Maps the paths of the resolved commands to the script handling them, the first script wins
+/// for commands that are still in conflict
+fn resolved_command_routes(scripts: &[Script]) -> HashMap<String, u64> {
+    let mut routes = HashMap::new();
+    for script in scripts {
+        for path in script.contributes.commands.iter().flat_map(command_paths) {
+            routes.entry(path).or_insert(script.id);
+        }
+    }
+
+    routes
+}
+
+fn top_level_name(conflict: &CommandConflict) -> &str {
+    conflict.command.split(' ').next().unwrap_or_default()
+}
+
+/// The name the command is registered under with [CommandConflictPolicy::NamespaceByScript]
+pub fn namespaced_command_name(script_name: &str, command_name: &str) -> String {
+    format!("{}-{}", script_name.to_lowercase(), command_name)
+}
+
 static GROUP_DESC_PLACEHOLDER: &str = "no description";
 
 struct PendingCheckGroup {
     guild_id: GuildId,
     started: Instant,
     items: Vec<LoadedScript>,
+    failed_attempts: u32,
+    retry_at: Option<Instant>,
 }
 
 pub fn to_twilight_commands(
@@ -439,21 +762,6 @@ fn merge_subgroups(dst: &mut OptionsCommandOptionData, src: OptionsCommandOption
     }
 }
 
-fn command_option_name(opt: &TwilightCommandOption) -> String {
-    match opt {
-        TwilightCommandOption::SubCommand(v) => v.name.clone(),
-        TwilightCommandOption::SubCommandGroup(v) => v.name.clone(),
-        TwilightCommandOption::String(v) => v.name.clone(),
-        TwilightCommandOption::Integer(v) => v.name.clone(),
-        TwilightCommandOption::Boolean(v) => v.name.clone(),
-        TwilightCommandOption::User(v) => v.name.clone(),
-        TwilightCommandOption::Channel(v) => v.name.clone(),
-        TwilightCommandOption::Role(v) => v.name.clone(),
-        TwilightCommandOption::Mentionable(v) => v.name.clone(),
-        TwilightCommandOption::Number(v) => v.name.clone(),
-    }
-}
-
 pub struct LoadedScript {
     pub guild_id: GuildId,
     pub meta: ScriptMeta,
@@ -0,0 +1,531 @@
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    rc::Rc,
+    sync::{Arc, RwLock},
+    time::{Duration, Instant},
+};
+
+use deno_core::{op_async, Extension, OpState};
+use guild_logger::LogEntry;
+use reqwest::{
+    header::{HeaderMap, HeaderName, HeaderValue},
+    redirect, Method,
+};
+use runtime_models::ops::fetch::{OpFetchRequest, OpFetchResponse};
+use tokio::sync::Semaphore;
+use twilight_model::id::GuildId;
+use url::{Host, Url};
+use vm::AnyError;
+
+use crate::RuntimeContext;
+
+/// Max requests a vm can have in flight at once, further requests fail until one completes
+const MAX_CONCURRENT_REQUESTS: usize = 5;
+
+pub fn extension() -> Extension {
+    Extension::builder()
+        .ops(vec![("op_botloader_fetch", op_async(op_fetch))])
+        .state(move |state| {
+            state.put(FetchState {
+                in_flight: Arc::new(Semaphore::new(MAX_CONCURRENT_REQUESTS)),
+            });
+            Ok(())
+        })
+        .build()
+}
+
+struct FetchState {
+    in_flight: Arc<Semaphore>,
+}
+
+/// The domains each guild's scripts are allowed to make requests to, shared between all the vms
+///
+/// Kept up to date by the vm manager from the guild's settings when its vm is (re)started.
+#[derive(Clone, Default)]
+pub struct FetchAllowlists {
+    inner: Arc<RwLock<HashMap<GuildId, Vec<String>>>>,
+}
+
+impl FetchAllowlists {
+    pub fn set(&self, guild_id: GuildId, domains: Vec<String>) {
+        self.inner.write().unwrap().insert(guild_id, domains);
+    }
+
+    pub fn remove(&self, guild_id: GuildId) {
+        self.inner.write().unwrap().remove(&guild_id);
+    }
+
+    pub fn get(&self, guild_id: GuildId) -> Vec<String> {
+        self.inner
+            .read()
+            .unwrap()
+            .get(&guild_id)
+            .cloned()
+            .unwrap_or_default()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct FetchLimits {
+    /// For the whole request, including reading the response body
+    pub timeout: Duration,
+    pub max_url_len: usize,
+    pub max_request_headers: usize,
+    pub max_request_body_bytes: usize,
+    pub max_response_body_bytes: usize,
+    /// Only turned off in tests, to make requests to a local server
+    pub block_private_ips: bool,
+}
+
+impl Default for FetchLimits {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(10),
+            max_url_len: 2048,
+            max_request_headers: 50,
+            max_request_body_bytes: 1024 * 1024,
+            max_response_body_bytes: 2 * 1024 * 1024,
+            block_private_ips: true,
+        }
+    }
+}
+
+pub async fn op_fetch(
+    state: Rc<RefCell<OpState>>,
+    args: OpFetchRequest,
+    _: (),
+) -> Result<OpFetchResponse, AnyError> {
+    let (rt_ctx, in_flight) = {
+        let state = state.borrow();
+        (
+            state.borrow::<RuntimeContext>().clone(),
+            state.borrow::<FetchState>().in_flight.clone(),
+        )
+    };
+
+    let _permit = in_flight.try_acquire_owned().map_err(|_| {
+        anyhow::anyhow!(
+            "too many requests in flight, max {} at a time",
+            MAX_CONCURRENT_REQUESTS
+        )
+    })?;
+
+    let script_id = args.script_id.0;
+    let method = args.method.to_uppercase();
+    // the query string could contain secrets, leave it out of the logs
+    let log_url = Url::parse(&args.url)
+        .map(|mut url| {
+            url.set_query(None);
+            url.set_fragment(None);
+            url.to_string()
+        })
+        .unwrap_or_default();
+
+    let allowlist = rt_ctx.fetch_allowlists.get(rt_ctx.guild_id);
+    let started = Instant::now();
+
+    match fetch(&FetchLimits::default(), &allowlist, args).await {
+        Ok(resp) => {
+            rt_ctx.guild_logger.log(LogEntry::script_info(
+                rt_ctx.guild_id,
+                format!(
+                    "fetch {} {}: {} ({}ms)",
+                    method,
+                    log_url,
+                    resp.status,
+                    started.elapsed().as_millis()
+                ),
+                script_id.to_string(),
+                None,
+            ));
+            Ok(resp)
+        }
+        Err(err) => {
+            rt_ctx.guild_logger.log(LogEntry::script_warning(
+                rt_ctx.guild_id,
+                format!("fetch {} {} failed: {}", method, log_url, err),
+                script_id.to_string(),
+                None,
+            ));
+            Err(err)
+        }
+    }
+}
+
+/// Makes the request if the url is allowed, redirects are not followed
+pub async fn fetch(
+    limits: &FetchLimits,
+    allowlist: &[String],
+    req: OpFetchRequest,
+) -> Result<OpFetchResponse, AnyError> {
+    if req.url.len() > limits.max_url_len {
+        return Err(anyhow::anyhow!("url too long, max {}", limits.max_url_len));
+    }
+
+    let url = Url::parse(&req.url)?;
+    if url.scheme() != "http" && url.scheme() != "https" {
+        return Err(anyhow::anyhow!("only http and https urls are supported"));
+    }
+
+    let host = url
+        .host()
+        .ok_or_else(|| anyhow::anyhow!("url has no host"))?;
+    let host_name = url.host_str().unwrap_or_default().to_string();
+    if !domain_allowed(allowlist, &host_name) {
+        return Err(anyhow::anyhow!(
+            "{} is not in the guild's list of allowed domains",
+            host_name
+        ));
+    }
+
+    let port = url.port_or_known_default().unwrap_or(80);
+    let addr = match host {
+        Host::Ipv4(ip) => SocketAddr::new(IpAddr::V4(ip), port),
+        Host::Ipv6(ip) => SocketAddr::new(IpAddr::V6(ip), port),
+        Host::Domain(domain) => resolve(limits, domain, port).await?,
+    };
+
+    if limits.block_private_ips && !is_public_ip(addr.ip()) {
+        return Err(anyhow::anyhow!("{} is not a public address", host_name));
+    }
+
+    let method = Method::from_bytes(req.method.to_uppercase().as_bytes())
+        .map_err(|_| anyhow::anyhow!("invalid method: {}", req.method))?;
+
+    if req.headers.len() > limits.max_request_headers {
+        return Err(anyhow::anyhow!(
+            "too many headers, max {}",
+            limits.max_request_headers
+        ));
+    }
+
+    let mut headers = HeaderMap::new();
+    for (name, value) in req.headers {
+        headers.insert(
+            HeaderName::from_bytes(name.as_bytes())?,
+            HeaderValue::from_str(&value)?,
+        );
+    }
+
+    let body = req.body.unwrap_or_default();
+    if body.len() > limits.max_request_body_bytes {
+        return Err(anyhow::anyhow!(
+            "request body too big, max {} bytes",
+            limits.max_request_body_bytes
+        ));
+    }
+
+    let mut client_builder = reqwest::Client::builder()
+        .redirect(redirect::Policy::none())
+        .no_proxy()
+        .timeout(limits.timeout);
+
+    // pin the connection to the address we checked, so it can't resolve to something else later
+    if let Host::Domain(domain) = host {
+        client_builder = client_builder.resolve(domain, addr);
+    }
+
+    let client = client_builder.build()?;
+    let mut resp = client
+        .request(method, url.clone())
+        .headers(headers)
+        .body(body)
+        .send()
+        .await?;
+
+    let status = resp.status().as_u16();
+
+    let mut resp_headers: HashMap<String, String> = HashMap::new();
+    for (name, value) in resp.headers() {
+        let value = match value.to_str() {
+            Ok(v) => v,
+            Err(_) => continue,
+        };
+
+        resp_headers
+            .entry(name.as_str().to_string())
+            .and_modify(|existing| {
+                existing.push_str(", ");
+                existing.push_str(value);
+            })
+            .or_insert_with(|| value.to_string());
+    }
+
+    let mut body = Vec::new();
+    while let Some(chunk) = resp.chunk().await? {
+        if body.len() + chunk.len() > limits.max_response_body_bytes {
+            return Err(anyhow::anyhow!(
+                "response body too big, max {} bytes",
+                limits.max_response_body_bytes
+            ));
+        }
+        body.extend_from_slice(&chunk);
+    }
+
+    Ok(OpFetchResponse {
+        status,
+        headers: resp_headers,
+        body: String::from_utf8_lossy(&body).into_owned(),
+    })
+}
+
+/// Resolves the domain, failing if any of its addresses are private
+///
+/// Checking all of them means a domain can't mix in a private address and hope it gets picked.
+async fn resolve(limits: &FetchLimits, domain: &str, port: u16) -> Result<SocketAddr, AnyError> {
+    let addrs = tokio::time::timeout(limits.timeout, tokio::net::lookup_host((domain, port)))
+        .await
+        .map_err(|_| anyhow::anyhow!("timed out resolving {}", domain))??
+        .collect::<Vec<_>>();
+
+    if limits.block_private_ips && addrs.iter().any(|addr| !is_public_ip(addr.ip())) {
+        return Err(anyhow::anyhow!("{} resolves to a private address", domain));
+    }
+
+    addrs
+        .into_iter()
+        .next()
+        .ok_or_else(|| anyhow::anyhow!("{} did not resolve to any addresses", domain))
+}
+
+/// Whether the host is in the allowlist, entries starting with `*.` also match all subdomains
+pub fn domain_allowed(allowlist: &[String], host: &str) -> bool {
+    let host = host.trim_end_matches('.').to_ascii_lowercase();
+
+    allowlist.iter().any(|entry| {
+        let entry = entry.to_ascii_lowercase();
+        match entry.strip_prefix("*.") {
+            Some(parent) => host == parent || host.ends_with(&format!(".{}", parent)),
+            None => host == entry,
+        }
+    })
+}
+
+pub fn is_public_ip(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_public_ipv4(ip),
+        IpAddr::V6(ip) => match embedded_ipv4(ip) {
+            Some(embedded) => is_public_ipv4(embedded),
+            None => is_public_ipv6(ip),
+        },
+    }
+}
+
+/// The ipv4 address carried by ipv4-mapped, ipv4-compatible, NAT64 and 6to4 addresses
+fn embedded_ipv4(ip: Ipv6Addr) -> Option<Ipv4Addr> {
+    let seg = ip.segments();
+    let from_segments = |hi: u16, lo: u16| {
+        let [a, b] = hi.to_be_bytes();
+        let [c, d] = lo.to_be_bytes();
+        Ipv4Addr::new(a, b, c, d)
+    };
+
+    match seg {
+        // ::ffff:a.b.c.d and ::a.b.c.d
+        [0, 0, 0, 0, 0, 0xffff | 0, hi, lo] => Some(from_segments(hi, lo)),
+        // NAT64 well-known prefix, 64:ff9b::a.b.c.d
+        [0x64, 0xff9b, 0, 0, 0, 0, hi, lo] => Some(from_segments(hi, lo)),
+        // 6to4, 2002:aabb:ccdd::/48
+        [0x2002, hi, lo, ..] => Some(from_segments(hi, lo)),
+        _ => None,
+    }
+}
+
+fn is_public_ipv4(ip: Ipv4Addr) -> bool {
+    let [a, b, c, _] = ip.octets();
+
+    !(ip.is_unspecified()
+        || ip.is_loopback()
+        || ip.is_private()
+        || ip.is_link_local()
+        || ip.is_broadcast()
+        || ip.is_documentation()
+        || ip.is_multicast()
+        // "this network"
+        || a == 0
+        // carrier grade nat
+        || (a == 100 && (64..128).contains(&b))
+        // ietf protocol assignments
+        || (a == 192 && b == 0 && c == 0)
+        // benchmarking
+        || (a == 198 && (b == 18 || b == 19))
+        // reserved
+        || a >= 240)
+}
+
+fn is_public_ipv6(ip: Ipv6Addr) -> bool {
+    let first = ip.segments()[0];
+
+    !(ip.is_unspecified()
+        || ip.is_loopback()
+        || ip.is_multicast()
+        // unique local
+        || (first & 0xfe00) == 0xfc00
+        // link local
+        || (first & 0xffc0) == 0xfe80
+        // documentation
+        || (first == 0x2001 && ip.segments()[1] == 0x0db8))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use runtime_models::{ops::fetch::OpFetchRequest, util::NotBigU64};
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    use super::{domain_allowed, fetch, is_public_ip, FetchLimits};
+
+    /// Responds to a single request with the given body
+    async fn stand_in_server(body: &'static str) -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        tokio::spawn(async move {
+            let (mut conn, _) = listener.accept().await.unwrap();
+            let mut buf = vec![0u8; 4096];
+            let _ = conn.read(&mut buf).await.unwrap();
+
+            let resp = format!(
+                "HTTP/1.1 201 Created\r\nx-test: yes\r\ncontent-length: {}\r\nconnection: \
+                 close\r\n\r\n{}",
+                body.len(),
+                body
+            );
+            conn.write_all(resp.as_bytes()).await.unwrap();
+        });
+
+        port
+    }
+
+    fn get(url: String) -> OpFetchRequest {
+        OpFetchRequest {
+            script_id: NotBigU64(1),
+            url,
+            method: "get".to_string(),
+            headers: HashMap::new(),
+            body: None,
+        }
+    }
+
+    fn local_limits() -> FetchLimits {
+        FetchLimits {
+            block_private_ips: false,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn allowed_domains() {
+        let allowlist = vec!["example.com".to_string(), "*.botloader.io".to_string()];
+
+        assert!(domain_allowed(&allowlist, "example.com"));
+        assert!(domain_allowed(&allowlist, "EXAMPLE.com."));
+        assert!(!domain_allowed(&allowlist, "api.example.com"));
+        assert!(!domain_allowed(&allowlist, "notexample.com"));
+        assert!(domain_allowed(&allowlist, "botloader.io"));
+        assert!(domain_allowed(&allowlist, "api.botloader.io"));
+        assert!(!domain_allowed(&allowlist, "evilbotloader.io"));
+    }
+
+    #[test]
+    fn public_ips() {
+        for ip in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "0.0.0.0",
+            "::1",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:127.0.0.1",
+            "::10.0.0.1",
+            "64:ff9b::127.0.0.1",
+            "64:ff9b::a9fe:a9fe",
+            "2002:c0a8:0101::1",
+            "2002:7f00:1::",
+        ] {
+            assert!(!is_public_ip(ip.parse().unwrap()), "{}", ip);
+        }
+
+        for ip in [
+            "1.1.1.1",
+            "8.8.8.8",
+            "2606:4700:4700::1111",
+            "64:ff9b::8.8.8.8",
+            "2002:0808:0808::1",
+        ] {
+            assert!(is_public_ip(ip.parse().unwrap()), "{}", ip);
+        }
+    }
+
+    #[tokio::test]
+    async fn fetch_local_server() {
+        let port = stand_in_server("hello").await;
+        let allowlist = vec!["127.0.0.1".to_string()];
+
+        let resp = fetch(
+            &local_limits(),
+            &allowlist,
+            get(format!("http://127.0.0.1:{}/path", port)),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(resp.status, 201);
+        assert_eq!(resp.body, "hello");
+        assert_eq!(resp.headers.get("x-test").map(String::as_str), Some("yes"));
+    }
+
+    #[tokio::test]
+    async fn fetch_blocks_private_ips() {
+        let port = stand_in_server("hello").await;
+        let allowlist = vec!["127.0.0.1".to_string()];
+
+        let res = fetch(
+            &FetchLimits::default(),
+            &allowlist,
+            get(format!("http://127.0.0.1:{}/path", port)),
+        )
+        .await;
+        assert!(res.is_err());
+    }
+
+    #[tokio::test]
+    async fn fetch_not_allowed_domain() {
+        let port = stand_in_server("hello").await;
+
+        let res = fetch(
+            &local_limits(),
+            &["example.com".to_string()],
+            get(format!("http://127.0.0.1:{}/path", port)),
+        )
+        .await;
+        assert!(res.is_err());
+    }
+
+    #[tokio::test]
+    async fn fetch_response_too_big() {
+        let port = stand_in_server("hello world").await;
+        let limits = FetchLimits {
+            max_response_body_bytes: 5,
+            ..local_limits()
+        };
+
+        let res = fetch(
+            &limits,
+            &["127.0.0.1".to_string()],
+            get(format!("http://127.0.0.1:{}/path", port)),
+        )
+        .await;
+        assert!(res.is_err());
+    }
+}
@@ -8,7 +8,10 @@ use crate::RuntimeContext;
 
 pub mod console;
 pub mod discord;
+pub mod fetch;
+pub mod script_events;
 pub mod storage;
+pub mod webhooks;
 
 // ensures the provided channel is in the guild, also checking the api as fallback
 pub(crate) async fn get_guild_channel(
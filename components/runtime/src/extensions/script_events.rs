@@ -0,0 +1,45 @@
+use deno_core::{op_sync, Extension, OpState};
+use runtime_models::ops::script_events::OpScriptEvent;
+use vm::AnyError;
+
+use crate::{
+    guild_events::{GuildEvent, ScriptEvent},
+    RuntimeContext,
+};
+
+const MAX_EVENT_NAME_LEN: usize = 64;
+const MAX_EVENT_DATA_BYTES: usize = 10_000;
+
+pub fn extension() -> Extension {
+    Extension::builder()
+        .ops(vec![("op_botloader_emit_event", op_sync(op_emit_event))])
+        .build()
+}
+
+/// Sends a custom event to the website, only received by those currently watching the guild
+pub fn op_emit_event(state: &mut OpState, args: OpScriptEvent, _: ()) -> Result<(), AnyError> {
+    if args.name.is_empty() || args.name.chars().count() > MAX_EVENT_NAME_LEN {
+        return Err(anyhow::anyhow!(
+            "event name has to be 1-{} characters",
+            MAX_EVENT_NAME_LEN
+        ));
+    }
+
+    let encoded_len = serde_json::to_string(&args.data)?.len();
+    if encoded_len > MAX_EVENT_DATA_BYTES {
+        return Err(anyhow::anyhow!(
+            "event data too big, max {} bytes when encoded as json",
+            MAX_EVENT_DATA_BYTES
+        ));
+    }
+
+    let ctx = state.borrow::<RuntimeContext>();
+    ctx.guild_events.send(GuildEvent::Script(ScriptEvent {
+        guild_id: ctx.guild_id,
+        script_id: args.script_id.0,
+        name: args.name,
+        data: args.data,
+    }));
+
+    Ok(())
+}
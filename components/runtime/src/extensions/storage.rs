@@ -1,17 +1,52 @@
-use std::{cell::RefCell, rc::Rc, time::Duration};
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    rc::Rc,
+    time::{Duration, Instant},
+};
 
 use anyhow::anyhow;
 use deno_core::{op_async, Extension, OpState};
-use runtime_models::ops::storage::{
-    OpStorageBucketEntry, OpStorageBucketEntryId, OpStorageBucketIncr, OpStorageBucketList,
-    OpStorageBucketSetIf, OpStorageBucketSetValue, OpStorageBucketSortedList, OpStorageBucketValue,
+use lazy_static::lazy_static;
+use prometheus::{register_histogram, register_int_counter_vec, Histogram, IntCounterVec};
+use runtime_models::{
+    ops::script::ScriptMeta,
+    ops::storage::{
+        OpStorageBucketEntry, OpStorageBucketEntryId, OpStorageBucketIncr,
+        OpStorageBucketJsonQuery, OpStorageBucketList, OpStorageBucketSetIf,
+        OpStorageBucketSetValue, OpStorageBucketSortedList, OpStorageBucketUsage,
+        OpStorageBucketValue, OpStorageUsage,
+    },
+    util::NotBigU64,
+};
+use stores::{
+    bucketstore::{
+        JsonFilter, JsonPath, JsonQuery, StoreError, GUILD_STORAGE_LIMIT_BYTES,
+        MAX_JSON_VALUE_LEN_BYTES, MAX_KEY_LEN_BYTES,
+    },
+    config::StorageBucketContrib,
+    web::gen_token,
 };
 use tracing::{info, instrument};
-use twilight_model::id::GuildId;
 use vm::AnyError;
 
 use crate::RuntimeContext;
 
+lazy_static! {
+    static ref GUILD_STORAGE_USAGE: Histogram = register_histogram!(
+        "botloader_storage_guild_usage_bytes",
+        "Storage used by the guild, observed on every write",
+        prometheus::exponential_buckets(1024.0, 4.0, 10).unwrap()
+    )
+    .unwrap();
+    static ref STORAGE_LIMIT_HITS: IntCounterVec = register_int_counter_vec!(
+        "botloader_storage_limit_hits_total",
+        "Number of writes that went over the guild storage limit or a bucket quota",
+        &["limit"]
+    )
+    .unwrap();
+}
+
 pub fn extension() -> Extension {
     Extension::builder()
         .ops(vec![
@@ -35,12 +70,16 @@ pub fn extension() -> Extension {
                 "op_botloader_bucket_storage_sorted_list",
                 op_async(op_storage_sorted_list),
             ),
+            (
+                "op_botloader_bucket_storage_json_query",
+                op_async(op_storage_json_query),
+            ),
+            ("op_botloader_storage_usage", op_async(op_storage_usage)),
         ])
         .state(move |state| {
             state.put(StorageState {
-                doing_limit_check: false,
-                hit_limit: false,
-                requests_until_limit_check: 0,
+                buckets: HashMap::new(),
+                last_expired_cleanup: None,
             });
             Ok(())
         })
@@ -48,9 +87,152 @@ pub fn extension() -> Extension {
 }
 
 struct StorageState {
-    requests_until_limit_check: u32,
-    doing_limit_check: bool,
-    hit_limit: bool,
+    /// the buckets registered by the scripts that has started in this vm, keyed by the handle
+    /// the script was given for the bucket
+    buckets: HashMap<String, RegisteredBucket>,
+    last_expired_cleanup: Option<Instant>,
+}
+
+struct RegisteredBucket {
+    script_id: u64,
+    name: String,
+    storage_name: String,
+    quota_bytes: Option<u64>,
+    /// Whether the entries stored under the plain bucket name, from before buckets were namespaced
+    /// by script, has been moved to this bucket (see [migrate_legacy_bucket])
+    legacy_migrated: bool,
+}
+
+impl StorageState {
+    /// The quota of the bucket stored under `storage_name`, if multiple scripts declares a quota
+    /// for the same bucket the lowest one is used
+    fn bucket_quota(&self, storage_name: &str) -> Option<u64> {
+        self.buckets
+            .values()
+            .filter(|b| b.storage_name == storage_name)
+            .filter_map(|b| b.quota_bytes)
+            .min()
+    }
+}
+
+/// Registers the storage buckets a script declared, returning the handles the script uses to
+/// access them in the same order as they were declared
+///
+/// This replaces the buckets and quotas the script registered before, if it was reloaded.
+///
+/// Note that since all the scripts in a guild run in the same vm this is not a hard security boundary,
+/// but a script can only access another script's private buckets if that script hands out the handles.
+pub(crate) fn register_script_buckets(state: &mut OpState, meta: &ScriptMeta) -> Vec<String> {
+    unregister_script_buckets(state, meta.script_id.0);
+    let storage_state = state.borrow_mut::<StorageState>();
+
+    let mut handles = Vec::with_capacity(meta.storage_buckets.len());
+    for bucket in &meta.storage_buckets {
+        let storage_name = StorageBucketContrib::from(bucket).storage_name(&meta.name);
+
+        let handle = gen_token();
+        storage_state.buckets.insert(
+            handle.clone(),
+            RegisteredBucket {
+                script_id: meta.script_id.0,
+                name: bucket.name.clone(),
+                storage_name,
+                quota_bytes: bucket.quota_bytes.map(|q| q.0),
+                // shared buckets are stored under their plain name, so there's nothing to move
+                legacy_migrated: bucket.shared,
+            },
+        );
+        handles.push(handle);
+    }
+
+    handles
+}
+
+/// Removes the buckets and quotas registered by the script, their handles stop working
+pub(crate) fn unregister_script_buckets(state: &mut OpState, script_id: u64) {
+    state
+        .borrow_mut::<StorageState>()
+        .buckets
+        .retain(|_, b| b.script_id != script_id);
+}
+
+/// Resolves the name the bucket is stored under
+///
+/// Buckets are only accessible through the handle their script got when it started, so scripts
+/// can only access the private buckets of their own and the shared buckets they declared.
+async fn resolve_bucket(
+    state: &Rc<RefCell<OpState>>,
+    rt_ctx: &RuntimeContext,
+    handle: &str,
+    bucket: &str,
+) -> Result<String, AnyError> {
+    let (storage_name, legacy_migrated) = {
+        let state = state.borrow();
+        state
+            .borrow::<StorageState>()
+            .buckets
+            .get(handle)
+            .filter(|b| b.name == bucket)
+            .map(|b| (b.storage_name.clone(), b.legacy_migrated))
+            .ok_or_else(|| {
+                anyhow!(
+                    "storage bucket {} is not registered by a running script",
+                    bucket
+                )
+            })?
+    };
+
+    if !legacy_migrated {
+        migrate_legacy_bucket(state, rt_ctx, handle, bucket, &storage_name).await?;
+    }
+
+    Ok(storage_name)
+}
+
+/// Moves the entries stored under the plain bucket name to the script's namespace the first time
+/// a private bucket is used, so scripts keep their data from before buckets were namespaced by script
+///
+/// Nothing is moved if the script's bucket already has entries, or if a running script declared
+/// the plain name as a shared bucket. If multiple scripts declare a private bucket with the same
+/// name, the first one to use it gets the entries.
+async fn migrate_legacy_bucket(
+    state: &Rc<RefCell<OpState>>,
+    rt_ctx: &RuntimeContext,
+    handle: &str,
+    bucket: &str,
+    storage_name: &str,
+) -> Result<(), AnyError> {
+    let declared_shared = {
+        let state = state.borrow();
+        state
+            .borrow::<StorageState>()
+            .buckets
+            .values()
+            .any(|b| b.storage_name == bucket)
+    };
+
+    if !declared_shared {
+        match rt_ctx
+            .bucket_store
+            .rename_bucket(
+                rt_ctx.guild_id,
+                bucket.to_string(),
+                storage_name.to_string(),
+            )
+            .await
+        {
+            Ok(0) | Err(StoreError::BucketNotEmpty) => {}
+            Ok(moved) => info!(moved, bucket, storage_name, "moved legacy bucket entries"),
+            Err(err) => return Err(err.into()),
+        }
+    }
+
+    let mut state = state.borrow_mut();
+    if let Some(registered) = state.borrow_mut::<StorageState>().buckets.get_mut(handle) {
+        registered.legacy_migrated = true;
+    }
+
+    Ok(())
 }
 
 pub async fn op_storage_set(
@@ -63,15 +245,17 @@ pub async fn op_storage_set(
         state.borrow::<RuntimeContext>().clone()
     };
 
+    let bucket = resolve_bucket(&state, &rt_ctx, &args.bucket_handle, &args.bucket_name).await?;
+
     check_validate_value_len(&args.value)?;
     check_validate_key_len(&args.key)?;
-    check_validate_storage_usage(rt_ctx.guild_id, &rt_ctx, state.clone()).await?;
+    check_validate_storage_usage(&rt_ctx, state.clone(), &bucket).await?;
 
     let entry = rt_ctx
         .bucket_store
         .set(
             rt_ctx.guild_id,
-            args.bucket_name,
+            bucket,
             args.key,
             args.value.into(),
             args.ttl.map(|ttl| Duration::from_secs(ttl as u64)),
@@ -91,15 +275,17 @@ pub async fn op_storage_set_if(
         state.borrow::<RuntimeContext>().clone()
     };
 
+    let bucket = resolve_bucket(&state, &rt_ctx, &args.bucket_handle, &args.bucket_name).await?;
+
     check_validate_value_len(&args.value)?;
     check_validate_key_len(&args.key)?;
-    check_validate_storage_usage(rt_ctx.guild_id, &rt_ctx, state.clone()).await?;
+    check_validate_storage_usage(&rt_ctx, state.clone(), &bucket).await?;
 
     let entry = rt_ctx
         .bucket_store
         .set_if(
             rt_ctx.guild_id,
-            args.bucket_name,
+            bucket,
             args.key,
             args.value.into(),
             args.ttl.map(|ttl| Duration::from_secs(ttl as u64)),
@@ -120,9 +306,11 @@ pub async fn op_storage_get(
         state.borrow::<RuntimeContext>().clone()
     };
 
+    let bucket = resolve_bucket(&state, &rt_ctx, &args.bucket_handle, &args.bucket_name).await?;
+
     let entry = rt_ctx
         .bucket_store
-        .get(rt_ctx.guild_id, args.bucket_name, args.key)
+        .get(rt_ctx.guild_id, bucket, args.key)
         .await?;
 
     Ok(entry.map(Into::into))
@@ -138,19 +326,13 @@ pub async fn op_storage_del(
         state.borrow::<RuntimeContext>().clone()
     };
 
+    let bucket = resolve_bucket(&state, &rt_ctx, &args.bucket_handle, &args.bucket_name).await?;
+
     let entry = rt_ctx
         .bucket_store
-        .del(rt_ctx.guild_id, args.bucket_name, args.key)
+        .del(rt_ctx.guild_id, bucket, args.key)
         .await?;
 
-    if entry.is_some() {
-        let mut state = state.borrow_mut();
-        let storage_ctx = state.borrow_mut::<StorageState>();
-
-        // re-check in case were at the limti
-        storage_ctx.hit_limit = false;
-    }
-
     Ok(entry.map(Into::into))
 }
 
@@ -164,6 +346,8 @@ pub async fn op_storage_list(
         state.borrow::<RuntimeContext>().clone()
     };
 
+    let bucket = resolve_bucket(&state, &rt_ctx, &args.bucket_handle, &args.bucket_name).await?;
+
     let limit = if let Some(limit) = args.limit {
         if limit < 100 {
             limit
@@ -178,7 +362,7 @@ pub async fn op_storage_list(
         .bucket_store
         .get_many(
             rt_ctx.guild_id,
-            args.bucket_name,
+            bucket,
             args.key_pattern.unwrap_or_else(|| "%".to_string()),
             args.after.unwrap_or_else(String::new),
             limit,
@@ -198,12 +382,14 @@ pub async fn op_storage_incr(
         state.borrow::<RuntimeContext>().clone()
     };
 
+    let bucket = resolve_bucket(&state, &rt_ctx, &args.bucket_handle, &args.bucket_name).await?;
+
     check_validate_key_len(&args.key)?;
-    check_validate_storage_usage(rt_ctx.guild_id, &rt_ctx, state.clone()).await?;
+    check_validate_storage_usage(&rt_ctx, state.clone(), &bucket).await?;
 
     let entry = rt_ctx
         .bucket_store
-        .incr(rt_ctx.guild_id, args.bucket_name, args.key, args.amount)
+        .incr(rt_ctx.guild_id, bucket, args.key, args.amount)
         .await?;
 
     Ok(entry.into())
@@ -219,6 +405,8 @@ pub async fn op_storage_sorted_list(
         state.borrow::<RuntimeContext>().clone()
     };
 
+    let bucket = resolve_bucket(&state, &rt_ctx, &args.bucket_handle, &args.bucket_name).await?;
+
     let limit = if let Some(limit) = args.limit {
         if limit < 100 {
             limit
@@ -233,7 +421,7 @@ pub async fn op_storage_sorted_list(
         .bucket_store
         .sorted_entries(
             rt_ctx.guild_id,
-            args.bucket_name,
+            bucket,
             args.order.into(),
             args.offset.unwrap_or_default(),
             limit,
@@ -243,12 +431,109 @@ pub async fn op_storage_sorted_list(
     Ok(entries.into_iter().map(Into::into).collect())
 }
 
+/// Max number of filters in a single json query
+const JSON_QUERY_MAX_FILTERS: usize = 5;
+
+/// Max offset for json queries, use filters on the ordered path to paginate beyond this
+const JSON_QUERY_MAX_OFFSET: u32 = 10_000;
+
+/// Buckets with more entries than this can only be queried using filters that can use the json index
+const JSON_QUERY_UNINDEXED_MAX_ENTRIES: u64 = 1_000;
+
+pub async fn op_storage_json_query(
+    state: Rc<RefCell<OpState>>,
+    args: OpStorageBucketJsonQuery,
+    _: (),
+) -> Result<Vec<OpStorageBucketEntry>, AnyError> {
+    let rt_ctx = {
+        let state = state.borrow();
+        state.borrow::<RuntimeContext>().clone()
+    };
+
+    let bucket = resolve_bucket(&state, &rt_ctx, &args.bucket_handle, &args.bucket_name).await?;
+
+    if args.filters.len() > JSON_QUERY_MAX_FILTERS {
+        return Err(anyhow!(
+            "too many filters in query (max {})",
+            JSON_QUERY_MAX_FILTERS
+        ));
+    }
+
+    let offset = args.offset.unwrap_or_default();
+    if offset > JSON_QUERY_MAX_OFFSET {
+        return Err(anyhow!("offset too big (max {})", JSON_QUERY_MAX_OFFSET));
+    }
+
+    let limit = if let Some(limit) = args.limit {
+        if limit < 100 {
+            limit
+        } else {
+            100
+        }
+    } else {
+        25
+    };
+
+    let filters = args
+        .filters
+        .into_iter()
+        .map(|f| {
+            Ok(JsonFilter {
+                path: JsonPath::parse(&f.path)?,
+                op: f.op.into(),
+                value: f.value,
+            })
+        })
+        .collect::<Result<Vec<_>, AnyError>>()?;
+
+    let order_by = match args.order_by {
+        Some(order_by) => Some((JsonPath::parse(&order_by.path)?, order_by.order.into())),
+        None => None,
+    };
+
+    // queries on large buckets has to use the index on json values
+    let uses_index = filters.iter().any(|f| f.containment().is_some());
+    if (!filters.is_empty() || order_by.is_some()) && !uses_index {
+        let usage = rt_ctx
+            .bucket_store
+            .bucket_usage(rt_ctx.guild_id, bucket.clone())
+            .await?;
+
+        if usage.entries > JSON_QUERY_UNINDEXED_MAX_ENTRIES {
+            return Err(anyhow!(
+                "buckets with more than {} entries can only be queried with atleast one eq filter \
+                 on a path without array indexes",
+                JSON_QUERY_UNINDEXED_MAX_ENTRIES
+            ));
+        }
+    }
+
+    let entries = rt_ctx
+        .bucket_store
+        .json_query(
+            rt_ctx.guild_id,
+            bucket,
+            JsonQuery {
+                filters,
+                order_by,
+                offset,
+                limit,
+            },
+        )
+        .await?;
+
+    Ok(entries.into_iter().map(Into::into).collect())
+}
+
 fn check_validate_value_len(val: &OpStorageBucketValue) -> Result<(), AnyError> {
     match val {
         OpStorageBucketValue::Json(json) => {
             let serialized = serde_json::to_string(json).unwrap();
-            if serialized.len() > 1_000_000 {
-                Err(anyhow::anyhow!("value too big, max value size is 1MB"))
+            if serialized.len() > MAX_JSON_VALUE_LEN_BYTES {
+                Err(anyhow::anyhow!(
+                    "value too big, max value size is {} bytes",
+                    MAX_JSON_VALUE_LEN_BYTES
+                ))
             } else {
                 Ok(())
             }
@@ -258,77 +543,123 @@ fn check_validate_value_len(val: &OpStorageBucketValue) -> Result<(), AnyError>
 }
 
 fn check_validate_key_len(key: &str) -> Result<(), AnyError> {
-    if key.len() > 256 {
-        Err(anyhow!("key too long (max 256 bytes)"))
+    if key.len() > MAX_KEY_LEN_BYTES {
+        Err(anyhow!("key too long (max {} bytes)", MAX_KEY_LEN_BYTES))
     } else {
         Ok(())
     }
 }
 
+pub async fn op_storage_usage(
+    state: Rc<RefCell<OpState>>,
+    _: (),
+    _: (),
+) -> Result<OpStorageUsage, AnyError> {
+    let rt_ctx = {
+        let state = state.borrow();
+        state.borrow::<RuntimeContext>().clone()
+    };
+
+    let buckets = rt_ctx.bucket_store.list_buckets(rt_ctx.guild_id).await?;
+
+    let state = state.borrow();
+    let storage_state = state.borrow::<StorageState>();
+
+    Ok(OpStorageUsage {
+        usage_bytes: NotBigU64(buckets.iter().map(|b| b.size_bytes).sum()),
+        limit_bytes: NotBigU64(GUILD_STORAGE_LIMIT_BYTES),
+        buckets: buckets
+            .into_iter()
+            .map(|b| OpStorageBucketUsage {
+                quota_bytes: storage_state.bucket_quota(&b.name).map(NotBigU64),
+                name: b.name,
+                entries: NotBigU64(b.entries),
+                size_bytes: NotBigU64(b.size_bytes),
+            })
+            .collect(),
+    })
+}
+
 #[instrument(skip(ctx, state_rc))]
 async fn check_validate_storage_usage(
-    guild_id: GuildId,
     ctx: &RuntimeContext,
     state_rc: Rc<RefCell<OpState>>,
+    bucket: &str,
 ) -> Result<(), AnyError> {
-    let do_check = {
-        // fast path
-        let mut state = state_rc.borrow_mut();
-        let storage_ctx = state.borrow_mut::<StorageState>();
-
-        if !storage_ctx.doing_limit_check {
-            if storage_ctx.hit_limit {
-                return Err(anyhow!("hit storage limit, delete some entries"));
-            } else if storage_ctx.requests_until_limit_check >= 1 {
-                // we have more requests until we need to do a check
-                storage_ctx.requests_until_limit_check -= 1;
-                return Ok(());
-            }
-
-            // need to do check
-            storage_ctx.doing_limit_check = true;
-            true
-        } else {
-            false
-        }
+    let quota = {
+        let state = state_rc.borrow();
+        state.borrow::<StorageState>().bucket_quota(bucket)
     };
 
-    if do_check {
-        info!("doing a storage check");
-        let used_storage = ctx.bucket_store.guild_storage_usage_bytes(guild_id).await;
+    let violation = match check_limits(ctx, bucket, quota).await? {
+        Some(violation) => violation,
+        None => return Ok(()),
+    };
 
+    // the usage counters include expired entries that has not been cleaned up yet,
+    // so clean those up and check again before giving up (but not too often)
+    let do_cleanup = {
         let mut state = state_rc.borrow_mut();
         let storage_ctx = state.borrow_mut::<StorageState>();
-        storage_ctx.doing_limit_check = false;
 
-        match used_storage {
-            Err(e) => Err(e.into()),
-            Ok(0...10_000_000) => {
-                storage_ctx.requests_until_limit_check = 10;
-                Ok(())
-            }
-            // hit the limit
+        match storage_ctx.last_expired_cleanup {
+            Some(last) if last.elapsed() < Duration::from_secs(60) => false,
             _ => {
-                storage_ctx.hit_limit = true;
-                Err(anyhow!("hit storage limit, delete some entries"))
+                storage_ctx.last_expired_cleanup = Some(Instant::now());
+                true
             }
         }
-    } else {
-        info!("waiting for result of storage check");
-        loop {
-            tokio::time::sleep(Duration::from_millis(100)).await;
-
-            let mut state = state_rc.borrow_mut();
-            let storage_ctx = state.borrow_mut::<StorageState>();
-
-            if !storage_ctx.doing_limit_check {
-                // done
-                if storage_ctx.hit_limit {
-                    return Err(anyhow!("hit storage limit, delete some entries"));
-                } else {
-                    return Ok(());
-                }
-            }
+    };
+
+    if !do_cleanup {
+        return Err(violation);
+    }
+
+    info!("cleaning up expired entries");
+    ctx.bucket_store
+        .delete_expired_entries(ctx.guild_id)
+        .await?;
+
+    match check_limits(ctx, bucket, quota).await? {
+        Some(violation) => Err(violation),
+        None => Ok(()),
+    }
+}
+
+async fn check_limits(
+    ctx: &RuntimeContext,
+    bucket: &str,
+    quota: Option<u64>,
+) -> Result<Option<AnyError>, AnyError> {
+    let used = ctx
+        .bucket_store
+        .guild_storage_usage_bytes(ctx.guild_id)
+        .await?;
+
+    GUILD_STORAGE_USAGE.observe(used as f64);
+
+    if used > GUILD_STORAGE_LIMIT_BYTES {
+        STORAGE_LIMIT_HITS.with_label_values(&["guild"]).inc();
+        return Ok(Some(anyhow!("hit storage limit, delete some entries")));
+    }
+
+    if let Some(quota) = quota {
+        let usage = ctx
+            .bucket_store
+            .bucket_usage(ctx.guild_id, bucket.to_string())
+            .await?;
+
+        if usage.size_bytes > quota {
+            STORAGE_LIMIT_HITS
+                .with_label_values(&["bucket_quota"])
+                .inc();
+            return Ok(Some(anyhow!(
+                "hit the storage quota for the bucket {} ({} bytes), delete some entries",
+                bucket,
+                quota
+            )));
         }
     }
+
+    Ok(None)
 }
@@ -0,0 +1,158 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use deno_core::{op_sync, Extension, OpState};
+use runtime_models::{
+    events::webhook::WebhookRequest, ops::webhook::OpWebhookResponse, util::NotBigU64,
+};
+use tokio::sync::oneshot;
+use twilight_model::id::GuildId;
+use vm::AnyError;
+
+use crate::RuntimeContext;
+
+/// Name of the event webhook requests are dispatched to the vm as
+pub const WEBHOOK_REQUEST_EVENT: &str = "BOTLOADER_WEBHOOK_REQUEST";
+
+const MAX_RESPONSE_BODY_BYTES: usize = 1024 * 1024;
+const MAX_RESPONSE_HEADERS: usize = 50;
+
+pub fn extension() -> Extension {
+    Extension::builder()
+        .ops(vec![(
+            "op_botloader_webhook_respond",
+            op_sync(op_webhook_respond),
+        )])
+        .build()
+}
+
+/// A request to a script's webhook, forwarded from the webapi
+#[derive(Debug, Clone)]
+pub struct WebhookCall {
+    pub script_id: u64,
+    pub name: String,
+    pub method: String,
+    pub headers: HashMap<String, String>,
+    pub body: String,
+}
+
+impl WebhookCall {
+    pub fn into_event(self, request_id: u64) -> serde_json::Value {
+        serde_json::to_value(&WebhookRequest {
+            request_id: NotBigU64(request_id),
+            script_id: NotBigU64(self.script_id),
+            name: self.name,
+            method: self.method,
+            headers: self.headers,
+            body: self.body,
+        })
+        .unwrap()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct WebhookResponse {
+    pub status: u16,
+    pub headers: HashMap<String, String>,
+    pub body: String,
+}
+
+#[derive(Debug)]
+pub enum WebhookError {
+    VmNotRunning(String),
+    /// The script didn't respond in time
+    Timeout,
+    /// The vm was restarted or shut down before the script responded
+    Dropped,
+}
+
+impl std::fmt::Display for WebhookError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::VmNotRunning(err) => write!(f, "vm not running: {}", err),
+            Self::Timeout => f.write_str("script did not respond in time"),
+            Self::Dropped => f.write_str("vm stopped before the script responded"),
+        }
+    }
+}
+
+/// Webhook requests waiting on a response from a script, shared between all the vms
+#[derive(Clone, Default)]
+pub struct PendingWebhooks {
+    inner: Arc<Mutex<PendingWebhooksInner>>,
+}
+
+#[derive(Default)]
+struct PendingWebhooksInner {
+    next_id: u64,
+    pending: HashMap<u64, (GuildId, oneshot::Sender<WebhookResponse>)>,
+}
+
+impl PendingWebhooks {
+    /// Returns the id to dispatch the request with and the receiver for the response
+    pub fn add(&self, guild_id: GuildId) -> (u64, oneshot::Receiver<WebhookResponse>) {
+        let (tx, rx) = oneshot::channel();
+
+        let mut inner = self.inner.lock().unwrap();
+        inner.next_id += 1;
+        let id = inner.next_id;
+        inner.pending.insert(id, (guild_id, tx));
+
+        (id, rx)
+    }
+
+    pub fn remove(&self, request_id: u64) {
+        self.inner.lock().unwrap().pending.remove(&request_id);
+    }
+
+    /// Completes the request, returns false if it's unknown, timed out or belongs to another guild
+    fn respond(&self, guild_id: GuildId, request_id: u64, response: WebhookResponse) -> bool {
+        let mut inner = self.inner.lock().unwrap();
+        match inner.pending.get(&request_id) {
+            Some((pending_guild_id, _)) if *pending_guild_id == guild_id => {}
+            _ => return false,
+        }
+
+        let (_, tx) = inner.pending.remove(&request_id).unwrap();
+        tx.send(response).is_ok()
+    }
+}
+
+pub fn op_webhook_respond(
+    state: &mut OpState,
+    args: OpWebhookResponse,
+    _: (),
+) -> Result<(), AnyError> {
+    if !(100..=599).contains(&args.status) {
+        return Err(anyhow::anyhow!("invalid status code: {}", args.status));
+    }
+
+    if args.body.len() > MAX_RESPONSE_BODY_BYTES {
+        return Err(anyhow::anyhow!(
+            "response body too big, max {} bytes",
+            MAX_RESPONSE_BODY_BYTES
+        ));
+    }
+
+    if args.headers.len() > MAX_RESPONSE_HEADERS {
+        return Err(anyhow::anyhow!(
+            "too many response headers, max {}",
+            MAX_RESPONSE_HEADERS
+        ));
+    }
+
+    let ctx = state.borrow::<RuntimeContext>();
+    ctx.pending_webhooks.respond(
+        ctx.guild_id,
+        args.request_id.0,
+        WebhookResponse {
+            status: args.status,
+            headers: args.headers,
+            body: args.body,
+        },
+    );
+
+    Ok(())
+}
@@ -0,0 +1,110 @@
+//! Live events about a guild's vm and scripts, streamed to the website over botrpc
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+};
+
+use serde::{Deserialize, Serialize};
+use stores::config::GuildCommandSyncStatus;
+use tokio::sync::broadcast::{self, Receiver};
+use twilight_model::id::GuildId;
+
+#[derive(Debug, Clone)]
+pub enum GuildEvent {
+    Status(GuildStatusEvent),
+    Script(ScriptEvent),
+}
+
+impl GuildEvent {
+    pub fn guild_id(&self) -> GuildId {
+        match self {
+            Self::Status(evt) => evt.guild_id,
+            Self::Script(evt) => evt.guild_id,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GuildStatusEvent {
+    pub guild_id: GuildId,
+    pub status: GuildStatus,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", content = "data", rename_all = "snake_case")]
+pub enum GuildStatus {
+    Vm(VmStatus),
+    /// A script started and its commands, timers and storage buckets were updated
+    ScriptContribsUpdated {
+        script_id: u64,
+        script_name: String,
+    },
+    CommandSync(GuildCommandSyncStatus),
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum VmStatus {
+    Started,
+    Restarting,
+    Stopped,
+    /// Stopped because a script ran for too long without yielding
+    Runaway,
+}
+
+/// A custom event emitted by a script
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScriptEvent {
+    pub guild_id: GuildId,
+    pub script_id: u64,
+    pub name: String,
+    pub data: serde_json::Value,
+}
+
+/// Broadcasts events to the subscribers of a guild, events for guilds without subscribers are dropped
+#[derive(Clone, Default)]
+pub struct GuildEvents {
+    subscriptions: Arc<RwLock<HashMap<GuildId, broadcast::Sender<GuildEvent>>>>,
+}
+
+impl GuildEvents {
+    pub fn subscribe(&self, guild_id: GuildId) -> Receiver<GuildEvent> {
+        let mut subs = self.subscriptions.write().unwrap();
+        if let Some(entry) = subs.get(&guild_id) {
+            entry.subscribe()
+        } else {
+            let (sender, receiver) = broadcast::channel(1000);
+            subs.insert(guild_id, sender);
+            receiver
+        }
+    }
+
+    pub fn send(&self, evt: GuildEvent) {
+        let guild_id = evt.guild_id();
+        {
+            // fast path with read lock
+            let read = self.subscriptions.read().unwrap();
+            let sender = match read.get(&guild_id) {
+                Some(v) => v,
+                None => return,
+            };
+
+            if sender.send(evt.clone()).is_ok() {
+                return;
+            }
+        }
+
+        // no receivers left, remove the subscription unless someone subscribed in the meantime
+        let mut write = self.subscriptions.write().unwrap();
+        if let Some(sender) = write.get(&guild_id) {
+            if sender.send(evt).is_err() {
+                write.remove(&guild_id);
+            }
+        }
+    }
+
+    pub fn send_status(&self, guild_id: GuildId, status: GuildStatus) {
+        self.send(GuildEvent::Status(GuildStatusEvent { guild_id, status }));
+    }
+}
@@ -3,7 +3,10 @@ use std::sync::Arc;
 use contrib_manager::LoadedScript;
 use deno_core::{op_sync, Extension, OpState};
 use guild_logger::{GuildLogger, LogEntry};
-use runtime_models::ops::script::ScriptMeta;
+use runtime_models::{
+    ops::script::{OpCommandOwnerQuery, ScriptMeta},
+    util::NotBigU64,
+};
 use stores::bucketstore::BucketStore;
 use tokio::sync::mpsc;
 use tracing::info;
@@ -14,10 +17,13 @@ use vm::{
     AnyError, JsValue,
 };
 
+pub mod command_sync;
 pub mod contrib_manager;
 pub mod dispatchevents;
 pub mod extensions;
+pub mod guild_events;
 pub mod jsmodules;
+mod op_metrics;
 pub mod validator;
 
 pub use validator::validate_script;
@@ -27,6 +33,8 @@ pub fn create_extensions(ctx: RuntimeContext) -> Vec<Extension> {
         .ops(vec![
             // botloader stuff
             ("op_botloader_script_start", op_sync(op_script_start)),
+            ("op_botloader_script_stop", op_sync(op_script_stop)),
+            ("op_botloader_command_owner", op_sync(op_command_owner)),
             // discord stuff
         ])
         .state(move |state| {
@@ -36,7 +44,7 @@ pub fn create_extensions(ctx: RuntimeContext) -> Vec<Extension> {
         .middleware(Box::new(|name, b| match name {
             // we have our own custom print function
             "op_print" => op_sync(disabled_op),
-            _ => b,
+            _ => op_metrics::record_op_calls(name, b),
         }))
         .build();
 
@@ -45,6 +53,9 @@ pub fn create_extensions(ctx: RuntimeContext) -> Vec<Extension> {
         extensions::storage::extension(),
         extensions::discord::extension(),
         extensions::console::extension(),
+        extensions::webhooks::extension(),
+        extensions::fetch::extension(),
+        extensions::script_events::extension(),
     ]
 }
 
@@ -72,9 +83,14 @@ pub struct RuntimeContext {
     pub guild_logger: GuildLogger,
     pub vm_cmd_dispatch_tx: mpsc::UnboundedSender<VmCommand>,
     pub bucket_store: Arc<dyn BucketStore + Send + Sync + 'static>,
+    pub pending_webhooks: extensions::webhooks::PendingWebhooks,
+    pub fetch_allowlists: extensions::fetch::FetchAllowlists,
+    pub guild_events: guild_events::GuildEvents,
+    pub command_routes: contrib_manager::CommandRoutes,
 }
 
-pub fn op_script_start(state: &mut OpState, args: JsValue, _: ()) -> Result<(), AnyError> {
+/// Registers the started script, returning the handles for its storage buckets
+pub fn op_script_start(state: &mut OpState, args: JsValue, _: ()) -> Result<Vec<String>, AnyError> {
     let des: ScriptMeta = serde_json::from_value(args)?;
 
     info!(
@@ -96,15 +112,37 @@ pub fn op_script_start(state: &mut OpState, args: JsValue, _: ()) -> Result<(),
         return Err(err);
     }
 
+    let bucket_handles = extensions::storage::register_script_buckets(state, &des);
+
+    let ctx = state.borrow::<RuntimeContext>();
     ctx.contrib_manager_handle.send(LoadedScript {
         guild_id: ctx.guild_id,
         meta: des,
         vm_cmd_dispath_tx: ctx.vm_cmd_dispatch_tx.clone(),
     });
 
+    Ok(bucket_handles)
+}
+
+/// Clears the state kept for a script that is being unloaded, before it's removed or replaced
+/// by a new version
+pub fn op_script_stop(state: &mut OpState, script_id: NotBigU64, _: ()) -> Result<(), AnyError> {
+    extensions::storage::unregister_script_buckets(state, script_id.0);
     Ok(())
 }
 
+/// Whether the script handles the command interaction, see [contrib_manager::CommandRoutes]
+pub fn op_command_owner(
+    state: &mut OpState,
+    args: OpCommandOwnerQuery,
+    _: (),
+) -> Result<bool, AnyError> {
+    let ctx = state.borrow::<RuntimeContext>();
+    Ok(ctx
+        .command_routes
+        .is_owner(ctx.guild_id, &args.path(), args.script_id.0))
+}
+
 pub(crate) fn validate_script_meta(meta: &ScriptMeta) -> Result<(), anyhow::Error> {
     let mut outbuf = String::new();
 
@@ -124,6 +162,14 @@ pub(crate) fn validate_script_meta(meta: &ScriptMeta) -> Result<(), anyhow::Erro
         }
     }
 
+    for bucket in &meta.storage_buckets {
+        if let Err(verrs) = validation::validate(bucket) {
+            for verr in verrs {
+                outbuf.push_str(format!("\nstorage bucket {}: {}", bucket.name, verr).as_str());
+            }
+        }
+    }
+
     if outbuf.is_empty() {
         Ok(())
     } else {
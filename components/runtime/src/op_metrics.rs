@@ -0,0 +1,47 @@
+use deno_core::{Op, OpCall, OpFn, OpResult};
+use lazy_static::lazy_static;
+use prometheus::{register_int_counter_vec, IntCounterVec};
+
+lazy_static! {
+    static ref OP_CALLS: IntCounterVec = register_int_counter_vec!(
+        "botloader_op_calls_total",
+        "Number of ops called by scripts",
+        &["op"]
+    )
+    .unwrap();
+    static ref OP_ERRORS: IntCounterVec = register_int_counter_vec!(
+        "botloader_op_errors_total",
+        "Number of op calls that returned an error",
+        &["op"]
+    )
+    .unwrap();
+}
+
+/// Op middleware that counts the calls and errors of the op
+pub(crate) fn record_op_calls(name: &'static str, opfn: Box<OpFn>) -> Box<OpFn> {
+    Box::new(move |state, payload| {
+        OP_CALLS.with_label_values(&[name]).inc();
+
+        match opfn(state, payload) {
+            Op::Sync(OpResult::Err(err)) => {
+                OP_ERRORS.with_label_values(&[name]).inc();
+                Op::Sync(OpResult::Err(err))
+            }
+            Op::Async(fut) => Op::Async(OpCall::eager(record_async_errors(name, fut))),
+            Op::AsyncUnref(fut) => Op::AsyncUnref(OpCall::eager(record_async_errors(name, fut))),
+            op => op,
+        }
+    })
+}
+
+async fn record_async_errors<T>(
+    name: &'static str,
+    fut: impl std::future::Future<Output = (T, usize, OpResult)>,
+) -> (T, usize, OpResult) {
+    let (promise_id, op_id, result) = fut.await;
+    if matches!(result, OpResult::Err(_)) {
+        OP_ERRORS.with_label_values(&[name]).inc();
+    }
+
+    (promise_id, op_id, result)
+}
@@ -94,12 +94,38 @@ export namespace Commands {
     export class System {
         commands: CommandDef<OptionsMap>[] = [];
 
+        /**
+         * Prefix the top level commands may be registered under if they conflict with other scripts, see the guild command conflict policy
+         */
+        namespace?: string;
+
+        /**
+         * Id of the script the commands belong to, interactions for commands registered by other scripts are ignored
+         */
+        scriptId?: number;
+
         addEventListeners(muxer: EventMuxer) {
             muxer.on("BOTLOADER_COMMAND_INTERACTION_CREATE", this.handleInteractionCreate.bind(this));
         }
 
         handleInteractionCreate(interaction: Events.CommandInteraction) {
+            if (this.scriptId !== undefined && !OpWrappers.commandOwner({
+                scriptId: this.scriptId,
+                name: interaction.name,
+                parentName: interaction.parentName,
+                parentParentName: interaction.parentParentName,
+            })) {
+                return;
+            }
+
             let command = this.commands.find(cmd => matchesCommand(cmd, interaction));
+            if (!command && this.namespace) {
+                const stripped = stripNamespace(interaction, this.namespace, this.commands);
+                if (stripped) {
+                    command = this.commands.find(cmd => matchesCommand(cmd, stripped));
+                }
+            }
+
             if (!command) {
                 return;
             }
@@ -178,6 +204,36 @@ export namespace Commands {
         }
     }
 
+    /**
+     * Returns the interaction with its top level name changed back to the name of one of the commands,
+     * if it was registered under `namespace-name`
+     */
+    function stripNamespace(interaction: Events.CommandInteraction, namespace: string, commands: CommandDef<OptionsMap>[]): Events.CommandInteraction | undefined {
+        const prefix = `${namespace.toLowerCase()}-`;
+        const strip = (name: string) => commands
+            .map(cmd => topLevelName(cmd))
+            .find(cmdName => prefix + cmdName === name);
+
+        if (interaction.parentParentName) {
+            const parentParentName = strip(interaction.parentParentName);
+            return parentParentName ? { ...interaction, parentParentName } : undefined;
+        } else if (interaction.parentName) {
+            const parentName = strip(interaction.parentName);
+            return parentName ? { ...interaction, parentName } : undefined;
+        } else {
+            const name = strip(interaction.name);
+            return name ? { ...interaction, name } : undefined;
+        }
+    }
+
+    function topLevelName(cmd: CommandDef<any>) {
+        if (cmd.group) {
+            return cmd.group.parent ? cmd.group.parent.name : cmd.group.name;
+        }
+
+        return cmd.name;
+    }
+
     function matchesCommand(cmd: CommandDef<any>, interaction: Events.CommandInteraction) {
         if (interaction.parentParentName) {
             if (cmd.group && cmd.group.parent) {
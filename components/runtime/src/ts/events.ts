@@ -9,6 +9,10 @@ export interface EventTypes {
      * @internal
      */
     BOTLOADER_INTERVAL_TIMER_FIRED: Events.IntervalTimerEvent,
+    /**
+     * @internal
+     */
+    BOTLOADER_WEBHOOK_REQUEST: Events.WebhookRequest,
 
     MESSAGE_CREATE: Discord.Message,
     MESSAGE_UPDATE: Events.MessageUpdate,
@@ -30,6 +34,13 @@ export namespace InternalEventSystem {
         eventMuxers.push(muxer)
     }
 
+    export function unregisterEventMuxer(muxer: EventMuxer) {
+        const index = eventMuxers.indexOf(muxer);
+        if (index !== -1) {
+            eventMuxers.splice(index, 1);
+        }
+    }
+
     export function dispatchEvent(evt: DispatchEvent) {
         for (let muxer of eventMuxers) {
             muxer.handleEvent(evt);
@@ -80,5 +91,12 @@ export class EventMuxer {
         }
     }
 
+    /**
+     * @internal
+     */
+    removeAllListeners() {
+        this.listeners = {};
+    }
+
 }
 
@@ -1,3 +1,4 @@
 declare let $jackGlobal: {
     runEventLoop: (cb: (evt: { name: string, data: any }) => void) => void;
+    scriptTeardowns: Map<number, () => void>;
 };
\ No newline at end of file
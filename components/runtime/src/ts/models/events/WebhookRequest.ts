@@ -0,0 +1,8 @@
+export interface WebhookRequest {
+  requestId: number;
+  scriptId: number;
+  name: string;
+  method: string;
+  headers: Record<string, string>;
+  body: string;
+}
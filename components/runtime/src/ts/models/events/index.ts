@@ -6,3 +6,4 @@ export * from './IntervalTimerEvent'
 export * from './MemberRemove'
 export * from './MessageDelete'
 export * from './MessageUpdate'
+export * from './WebhookRequest'
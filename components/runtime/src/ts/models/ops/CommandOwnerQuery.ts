@@ -0,0 +1,6 @@
+export interface OpCommandOwnerQuery {
+  scriptId: number;
+  name: string;
+  parentName: string | null;
+  parentParentName: string | null;
+}
@@ -0,0 +1,7 @@
+export interface OpFetchRequest {
+  scriptId: number;
+  url: string;
+  method: string;
+  headers: Record<string, string>;
+  body?: string;
+}
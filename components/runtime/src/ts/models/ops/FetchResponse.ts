@@ -0,0 +1,5 @@
+export interface OpFetchResponse {
+  status: number;
+  headers: Record<string, string>;
+  body: string;
+}
@@ -0,0 +1,5 @@
+export interface OpScriptEvent {
+  scriptId: number;
+  name: string;
+  data: any;
+}
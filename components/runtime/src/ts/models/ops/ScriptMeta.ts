@@ -1,11 +1,14 @@
 import type { IntervalTimer } from "./IntervalTimer";
 import type { CommandGroup } from "./CommandGroup";
 import type { Command } from "./Command";
+import type { OpStorageBucket } from "./StorageBucket";
 
 export interface ScriptMeta {
   description: string;
   scriptId: number;
+  name: string;
   commands: Array<Command>;
   commandGroups: Array<CommandGroup>;
   intervalTimers: Array<IntervalTimer>;
+  storageBuckets: Array<OpStorageBucket>;
 }
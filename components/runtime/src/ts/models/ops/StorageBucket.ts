@@ -1,3 +1,5 @@
 export interface OpStorageBucket {
   name: string;
+  quotaBytes?: number;
+  shared: boolean;
 }
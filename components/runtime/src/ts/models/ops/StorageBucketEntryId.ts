@@ -1,4 +1,5 @@
 export interface OpStorageBucketEntryId {
+  bucketHandle: string;
   bucketName: string;
   key: string;
 }
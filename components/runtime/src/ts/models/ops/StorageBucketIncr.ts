@@ -1,4 +1,5 @@
 export interface OpStorageBucketIncr {
+  bucketHandle: string;
   bucketName: string;
   key: string;
   amount: number;
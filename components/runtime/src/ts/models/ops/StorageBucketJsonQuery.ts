@@ -0,0 +1,11 @@
+import type { OpStorageJsonOrderBy } from "./StorageJsonOrderBy";
+import type { OpStorageJsonFilter } from "./StorageJsonFilter";
+
+export interface OpStorageBucketJsonQuery {
+  bucketHandle: string;
+  bucketName: string;
+  filters: Array<OpStorageJsonFilter>;
+  orderBy?: OpStorageJsonOrderBy;
+  offset?: number;
+  limit?: number;
+}
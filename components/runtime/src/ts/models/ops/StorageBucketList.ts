@@ -1,4 +1,5 @@
 export interface OpStorageBucketList {
+  bucketHandle: string;
   bucketName: string;
   keyPattern?: string;
   after?: string;
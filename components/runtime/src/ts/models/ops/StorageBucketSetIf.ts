@@ -1,7 +1,8 @@
-import type { OpStorageBucketValue } from "./StorageBucketValue";
 import type { OpStorageBucketSetCondition } from "./StorageBucketSetCondition";
+import type { OpStorageBucketValue } from "./StorageBucketValue";
 
 export interface OpStorageBucketSetIf {
+  bucketHandle: string;
   bucketName: string;
   key: string;
   value: OpStorageBucketValue;
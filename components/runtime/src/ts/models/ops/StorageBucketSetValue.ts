@@ -1,6 +1,7 @@
 import type { OpStorageBucketValue } from "./StorageBucketValue";
 
 export interface OpStorageBucketSetValue {
+  bucketHandle: string;
   bucketName: string;
   key: string;
   value: OpStorageBucketValue;
@@ -1,6 +1,7 @@
 import type { OpStorageBucketListOrder } from "./StorageBucketListOrder";
 
 export interface OpStorageBucketSortedList {
+  bucketHandle: string;
   bucketName: string;
   offset?: number;
   limit?: number;
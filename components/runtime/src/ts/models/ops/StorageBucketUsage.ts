@@ -0,0 +1,6 @@
+export interface OpStorageBucketUsage {
+  name: string;
+  entries: number;
+  sizeBytes: number;
+  quotaBytes: number | null;
+}
@@ -0,0 +1,7 @@
+import type { OpStorageJsonFilterOp } from "./StorageJsonFilterOp";
+
+export interface OpStorageJsonFilter {
+  path: string;
+  op: OpStorageJsonFilterOp;
+  value: any;
+}
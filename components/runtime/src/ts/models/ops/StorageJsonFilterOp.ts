@@ -0,0 +1 @@
+export type OpStorageJsonFilterOp = "eq" | "lt" | "lte" | "gt" | "gte";
@@ -0,0 +1,6 @@
+import type { OpStorageBucketListOrder } from "./StorageBucketListOrder";
+
+export interface OpStorageJsonOrderBy {
+  path: string;
+  order: OpStorageBucketListOrder;
+}
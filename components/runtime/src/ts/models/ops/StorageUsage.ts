@@ -0,0 +1,7 @@
+import type { OpStorageBucketUsage } from "./StorageBucketUsage";
+
+export interface OpStorageUsage {
+  usageBytes: number;
+  limitBytes: number;
+  buckets: Array<OpStorageBucketUsage>;
+}
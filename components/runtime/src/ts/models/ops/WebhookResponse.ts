@@ -0,0 +1,6 @@
+export interface OpWebhookResponse {
+  requestId: number;
+  status: number;
+  headers: Record<string, string>;
+  body: string;
+}
@@ -3,6 +3,7 @@ export * from './AllowedMentions'
 export * from './CommandGroup'
 export * from './CommandOption'
 export * from './CommandOptionType'
+export * from './CommandOwnerQuery'
 export * from './CommandSubGroup'
 export * from './Command'
 export * from './ConsoleLogMessage'
@@ -13,15 +14,19 @@ export * from './DeleteMessagesBulk'
 export * from './DeleteMessage'
 export * from './EditChannelMessage'
 export * from './EditMessageFields'
+export * from './FetchRequest'
+export * from './FetchResponse'
 export * from './GetMessages'
 export * from './GetMessage'
 export * from './IntervalTimer'
 export * from './IntervalType'
 export * from './MentionParseTypes'
+export * from './ScriptEvent'
 export * from './ScriptMeta'
 export * from './StorageBucketEntryId'
 export * from './StorageBucketEntry'
 export * from './StorageBucketIncr'
+export * from './StorageBucketJsonQuery'
 export * from './StorageBucketListOrder'
 export * from './StorageBucketList'
 export * from './StorageBucketSetCondition'
@@ -29,4 +34,10 @@ export * from './StorageBucketSetIf'
 export * from './StorageBucketSetValue'
 export * from './StorageBucketSortedList'
 export * from './StorageBucket'
+export * from './StorageBucketUsage'
 export * from './StorageBucketValue'
+export * from './StorageJsonFilter'
+export * from './StorageJsonFilterOp'
+export * from './StorageJsonOrderBy'
+export * from './StorageUsage'
+export * from './WebhookResponse'
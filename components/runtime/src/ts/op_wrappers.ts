@@ -8,13 +8,27 @@ import {
 // May be removed from the publid API at some point.
 
 export namespace OpWrappers {
-    export function scriptStarted(meta: Ops.ScriptMeta) {
-        Deno.core.opSync(
+    export function scriptStarted(meta: Ops.ScriptMeta): string[] {
+        return Deno.core.opSync(
             "op_botloader_script_start",
             meta
         );
     }
 
+    export function scriptStopped(scriptId: number) {
+        Deno.core.opSync(
+            "op_botloader_script_stop",
+            scriptId
+        );
+    }
+
+    export function commandOwner(args: Ops.OpCommandOwnerQuery): boolean {
+        return Deno.core.opSync(
+            "op_botloader_command_owner",
+            args
+        );
+    }
+
     export function consoleLog(args: Ops.LogMessage) {
         Deno.core.opSync(
             "op_botloader_log",
@@ -22,6 +36,20 @@ export namespace OpWrappers {
         );
     }
 
+    export function respondWebhook(args: Ops.OpWebhookResponse) {
+        Deno.core.opSync(
+            "op_botloader_webhook_respond",
+            args
+        );
+    }
+
+    export function emitEvent(args: Ops.OpScriptEvent) {
+        Deno.core.opSync(
+            "op_botloader_emit_event",
+            args
+        );
+    }
+
     export function getGuild(): Discord.Guild {
         return Deno.core.opSync("discord_get_guild");
     }
@@ -136,4 +164,16 @@ export namespace OpWrappers {
         return await Deno.core.opAsync("op_botloader_bucket_storage_sorted_list", opts);
     }
 
+    export async function bucketStorageJsonQuery(opts: Ops.OpStorageBucketJsonQuery): Promise<Ops.OpStorageBucketEntry[]> {
+        return await Deno.core.opAsync("op_botloader_bucket_storage_json_query", opts);
+    }
+
+    export async function storageUsage(): Promise<Ops.OpStorageUsage> {
+        return await Deno.core.opAsync("op_botloader_storage_usage");
+    }
+
+    export async function fetch(args: Ops.OpFetchRequest): Promise<Ops.OpFetchResponse> {
+        return await Deno.core.opAsync("op_botloader_fetch", args);
+    }
+
 }
\ No newline at end of file
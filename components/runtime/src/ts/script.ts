@@ -13,26 +13,34 @@ export class Script {
         return this._scriptId;
     }
 
+    get name() {
+        return this._name;
+    }
+
     get description() {
         return this._description;
     }
 
     private _scriptId: number;
+    private _name: string;
     private _description: string;
 
     private events = new EventMuxer();
     private commandSystem = new Commands.System();
     private intervalTimers: IntervalTimerListener[] = [];
     private storageBuckets: Storage.Bucket<unknown>[] = [];
+    private webhooks = new Map<string, WebhookHandler>();
+    private teardownHooks: (() => void)[] = [];
 
     private runCalled = false;
 
     /**
      * @internal
      */
-    constructor(id: number) {
+    constructor(id: number, name: string) {
         this._description = `script id ${id}`;
         this._scriptId = id;
+        this._name = name;
     }
 
 
@@ -105,9 +113,10 @@ export class Script {
     }
 
     /**
-     * Register a storage bucket to the script
+     * Register a storage bucket to the script, registered buckets can be used once the script has started.
      * 
-     * Note that the same storage bucket can be registered in multiple scripts, and you can use this to share data betweem scripts.
+     * Buckets are private to the script by default, to share data between scripts register the same bucket
+     * in multiple scripts with the `shared` option.
      *
      * @param bucket The bucket itself
      * @returns The registered bucket
@@ -117,13 +126,88 @@ export class Script {
      *     key: string,
      * }
      * script.registerStorageBucket(new Storage.JsonBucket<Data>("fun-data"));
+     * 
+     * // limit the bucket to 100KB
+     * script.registerStorageBucket(new Storage.JsonBucket<Data>("limited-data", { quotaBytes: 100_000 }));
+     * 
+     * // accessible from all scripts registering a shared bucket named "settings"
+     * script.registerStorageBucket(new Storage.JsonBucket<Data>("settings", { shared: true }));
      * ```
      */
     registerStorageBucket<T extends Storage.Bucket<U>, U>(bucket: T): T {
+        bucket.registered = true;
         this.storageBuckets.push(bucket);
         return bucket;
     }
 
+    /**
+     * Register a handler for incoming webhook requests.
+     * 
+     * The webhook has to be created in the web interface, which gives you its url and secret,
+     * requests without a valid signature or secret never reach the script.
+     * 
+     * Handlers have 10 seconds to respond, requests to a webhook without a handler get a 404 response.
+     * 
+     * @param name The name of the webhook, as created in the web interface
+     * @param callback Callback to run for every request, returning the response to send back
+     * 
+     * @example ```ts
+     * script.registerWebhook("deploys", async (req) => {
+     *     const payload = JSON.parse(req.body);
+     *     await script.createMessage("531120790318350338", { content: `Deployed ${payload.version}` });
+     *     return { status: 200 };
+     * });
+     * ```
+     */
+    registerWebhook(name: string, callback: WebhookHandler) {
+        this.webhooks.set(name, callback);
+    }
+
+    /**
+     * Send a custom event to the website, for example to show live stats in the dashboard.
+     * 
+     * Events are only received by those currently watching the guild, and are not stored anywhere.
+     * 
+     * @param name The name of the event, max 64 characters
+     * @param data Anything that can be encoded as json, max 10KB when encoded
+     * 
+     * @example ```ts
+     * script.on("MESSAGE_CREATE", (msg) => {
+     *     script.emitEvent("message-seen", { channelId: msg.channelId });
+     * });
+     * ```
+     */
+    emitEvent(name: string, data?: unknown) {
+        OpWrappers.emitEvent({
+            scriptId: this.scriptId,
+            name,
+            data: data ?? null,
+        });
+    }
+
+    /**
+     * Register a function to run when this script is unloaded or updated.
+     * 
+     * Updating a script only reloads that script, so use this to clean up things that would otherwise
+     * keep running, the script's event handlers, commands and interval timers are removed automatically.
+     * 
+     * Hooks run in the order they were registered and can't be async.
+     * 
+     * @example ```ts
+     * let running = true;
+     * script.onTeardown(() => { running = false });
+     * 
+     * async function poll() {
+     *     while (running) {
+     *         // do stuff here
+     *     }
+     * }
+     * ```
+     */
+    onTeardown(cb: () => void) {
+        this.teardownHooks.push(cb);
+    }
+
     /**
      * @internal
      */
@@ -134,20 +218,61 @@ export class Script {
 
         this.runCalled = true;
 
+        this.commandSystem.namespace = this.name;
+        this.commandSystem.scriptId = this.scriptId;
         const [cmds, groups] = this.commandSystem.genOpBinding();
 
-        OpWrappers.scriptStarted({
+        const bucketHandles = OpWrappers.scriptStarted({
             description: this.description,
             commands: cmds,
             commandGroups: groups,
             scriptId: this.scriptId,
+            name: this.name,
             intervalTimers: this.intervalTimers.map(inner => inner.timer),
+            storageBuckets: this.storageBuckets.map(bucket => ({
+                name: bucket.name,
+                quotaBytes: bucket.quotaBytes,
+                shared: bucket.shared,
+            })),
         });
+        this.storageBuckets.forEach((bucket, i) => bucket.handle = bucketHandles[i]);
 
         this.commandSystem.addEventListeners(this.events);
         InternalEventSystem.registerEventMuxer(this.events);
 
         this.events.on("BOTLOADER_INTERVAL_TIMER_FIRED", this.onInterval.bind(this));
+        this.events.on("BOTLOADER_WEBHOOK_REQUEST", this.onWebhookRequest.bind(this));
+
+        if ((typeof $jackGlobal) !== "undefined") {
+            $jackGlobal.scriptTeardowns.set(this.scriptId, this.teardown.bind(this));
+        }
+    }
+
+    /**
+     * Removes this script's event handlers, runs its teardown hooks and clears the state kept for it on the rust side,
+     * errors in the hooks are rethrown after all of them have run.
+     */
+    private teardown() {
+        InternalEventSystem.unregisterEventMuxer(this.events);
+        this.events.removeAllListeners();
+
+        let firstError: unknown = undefined;
+        for (const hook of this.teardownHooks) {
+            try {
+                hook();
+            } catch (e) {
+                if (firstError === undefined) {
+                    firstError = e;
+                }
+            }
+        }
+
+        // the hooks may still use the storage buckets, so they're only unregistered afterwards
+        OpWrappers.scriptStopped(this.scriptId);
+
+        if (firstError !== undefined) {
+            throw firstError;
+        }
     }
 
     private onInterval(evt: Events.IntervalTimerEvent) {
@@ -157,6 +282,33 @@ export class Script {
         }
     }
 
+    private async onWebhookRequest(evt: Events.WebhookRequest) {
+        if (evt.scriptId !== this.scriptId) {
+            return;
+        }
+
+        const handler = this.webhooks.get(evt.name);
+        if (!handler) {
+            OpWrappers.respondWebhook({ requestId: evt.requestId, status: 404, headers: {}, body: "" });
+            return;
+        }
+
+        let resp: WebhookResponse;
+        try {
+            resp = await handler(evt);
+        } catch (e) {
+            OpWrappers.respondWebhook({ requestId: evt.requestId, status: 500, headers: {}, body: "" });
+            throw e;
+        }
+
+        OpWrappers.respondWebhook({
+            requestId: evt.requestId,
+            status: resp.status ?? 200,
+            headers: resp.headers ?? {},
+            body: resp.body ?? "",
+        });
+    }
+
     // Guild functions
     getGuild(): Discord.Guild {
         return OpWrappers.getGuild()
@@ -253,6 +405,29 @@ export class Script {
     // editSticker() { }
     // deleteSticker() { }
 
+    /**
+     * Make a http request, the domain has to be in the guild's list of allowed domains in the settings.
+     * 
+     * Redirects are not followed, requests time out after 10 seconds and responses over 2MB fail,
+     * only 5 requests can be in flight at a time.
+     * 
+     * @example ```ts
+     * const resp = await script.fetch("https://api.example.com/status");
+     * if (resp.status === 200) {
+     *     const status = JSON.parse(resp.body);
+     * }
+     * ```
+     */
+    fetch(url: string, options?: FetchOptions): Promise<Ops.OpFetchResponse> {
+        return OpWrappers.fetch({
+            scriptId: this.scriptId,
+            url,
+            method: options?.method ?? "GET",
+            headers: options?.headers ?? {},
+            body: options?.body,
+        });
+    }
+
     async getMember(id: string): Promise<Discord.Member | undefined> {
         return (await OpWrappers.getMembers([id]))[0] || undefined;
     }
@@ -267,6 +442,33 @@ interface IntervalTimerListener {
     callback: () => any,
 }
 
+export type WebhookHandler = (req: Events.WebhookRequest) => WebhookResponse | Promise<WebhookResponse>;
+
+export interface WebhookResponse {
+    /**
+     * Http status code, default 200
+     */
+    status?: number,
+
+    /**
+     * The content type can only be `text/plain` (the default) or `application/json`
+     */
+    headers?: Record<string, string>,
+
+    body?: string,
+}
+
+export interface FetchOptions {
+    /**
+     * Http method, default GET
+     */
+    method?: string,
+
+    headers?: Record<string, string>,
+
+    body?: string,
+}
+
 export interface GetMessagesOptions {
     /**
      * Limit max results, max 100, default 50
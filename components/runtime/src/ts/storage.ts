@@ -43,6 +43,69 @@ export namespace Storage {
         keyPattern?: string
     }
 
+    export interface BucketOptions {
+        /**
+         * Max amount of storage in bytes this bucket can use, writes to the bucket fails after this is reached.
+         * 
+         * This does not raise the guild wide limit, it lets you make sure one bucket doesn't use up all of it.
+         * 
+         * If multiple scripts register the same bucket with different quotas, the lowest one is used.
+         */
+        quotaBytes?: number,
+
+        /**
+         * Buckets are private to the script that registers them by default, shared buckets can be accessed
+         * by all scripts that register a shared bucket with the same name.
+         * 
+         * Entries stored before buckets were namespaced by script are moved to the script's bucket the first time
+         * a private bucket with the same name is used, unless a script registers that name as a shared bucket.
+         */
+        shared?: boolean,
+    }
+
+    export interface JsonQueryFilter {
+        /**
+         * Path to the field in the value, e.g `user.score` or `items.0`
+         */
+        path: string,
+
+        /**
+         * The range operators only match values of the same type as the provided value
+         */
+        op: Ops.OpStorageJsonFilterOp,
+
+        value: any,
+    }
+
+    export interface JsonQueryOptions {
+        /**
+         * Entries have to match all of the filters.
+         * 
+         * Max 5 filters.
+         */
+        filters?: JsonQueryFilter[],
+
+        /**
+         * Path to order the results by, entries are ordered by key if not provided.
+         */
+        orderBy?: {
+            path: string,
+            order: "Ascending" | "Descending",
+        },
+
+        /**
+         * How many entries to skip, max 10000
+         */
+        offset?: number,
+
+        /**
+         * Number of entries to return, max 100.
+         * 
+         * Defaults to 25 as of writing.
+         */
+        limit?: number,
+    }
+
     export interface SortedListOptions {
         /**
          * How many entries to skip, useful for paginating through the list
@@ -83,23 +146,59 @@ export namespace Storage {
      * 
      * Buckets are namespaces, A Bucket with the name `a` holds different values from another Bucket with the name `b` even though the keys might be the same.
      * 
-     * @remark this bucket should be registered with your script or plugin (example: `script.registerStorageBucket(...)`).
+     * Buckets are also namespaced by script, a bucket named `a` in one script holds different values from a bucket named `a` in another script,
+     * unless both are registered as shared (see {@link BucketOptions.shared}).
+     * 
+     * @remark this bucket has to be registered with your script or plugin (example: `script.registerStorageBucket(...)`),
+     * buckets that are not registered can't be used.
      * 
      * @typeParam T - The type of values stored in this bucket
      */
     export abstract class Bucket<T>{
         name: string;
+        quotaBytes?: number;
+        shared: boolean;
+
+        /**
+         * Set when the bucket is registered with a script
+         * 
+         * @internal
+         */
+        registered = false;
+
+        /**
+         * The handle for this bucket, given to the script it's registered with when the script starts
+         * 
+         * @internal
+         */
+        handle?: string;
 
         /**
          * Create a new storage bucket.
          * 
-         * @remark this bucket should be registered with your script or plugin (example: `script.registerStorageBucket(...)`),.
+         * @remark this bucket has to be registered with your script or plugin (example: `script.registerStorageBucket(...)`),
+         * and can only be used after the script has started.
          * 
-         * @param name The name of the bucket. Note that this is not unique across your scripts and
-         * the same name in one script will have the same values as in another and is perfectly safe.
+         * @param name The name of the bucket. Unless the bucket is shared this is unique to your script,
+         * the same name in another script will hold different values.
+         * @param options Optional options
          */
-        constructor(name: string) {
+        constructor(name: string, options?: BucketOptions) {
             this.name = name;
+            this.quotaBytes = options?.quotaBytes;
+            this.shared = options?.shared ?? false;
+        }
+
+        protected get bucketHandle(): string {
+            if (!this.registered) {
+                throw new Error(`storage bucket ${this.name} has to be registered with the script (script.registerStorageBucket)`);
+            }
+
+            if (this.handle === undefined) {
+                throw new Error(`storage bucket ${this.name} can only be used after the script has started`);
+            }
+
+            return this.handle;
         }
 
 
@@ -138,6 +237,7 @@ export namespace Storage {
          */
         async set(key: string, value: T, options?: SetValueOptions) {
             return this.entryFromInternal(await OpWrappers.bucketStorageSet({
+                bucketHandle: this.bucketHandle,
                 bucketName: this.name,
                 key: key,
                 value: this.intoInternalValue(value),
@@ -158,6 +258,7 @@ export namespace Storage {
          */
         async setIf(key: string, value: T, cond: "IfExists" | "IfNotExists", options?: SetValueOptions) {
             return this.entryFromInternalOptional(await OpWrappers.bucketStorageSetIf({
+                bucketHandle: this.bucketHandle,
                 bucketName: this.name,
                 key,
                 value: this.intoInternalValue(value),
@@ -174,6 +275,7 @@ export namespace Storage {
          */
         async get(key: string) {
             return this.entryFromInternalOptional(await OpWrappers.bucketStorageGet({
+                bucketHandle: this.bucketHandle,
                 bucketName: this.name,
                 key: key,
             }));
@@ -187,6 +289,7 @@ export namespace Storage {
          */
         async del(key: string) {
             return this.entryFromInternalOptional(await OpWrappers.bucketStorageDel({
+                bucketHandle: this.bucketHandle,
                 bucketName: this.name,
                 key: key,
             }));
@@ -200,6 +303,7 @@ export namespace Storage {
          */
         async list(options: ListOptions) {
             const res = await OpWrappers.bucketStorageList({
+                bucketHandle: this.bucketHandle,
                 bucketName: this.name,
                 after: options.after,
                 keyPattern: options.keyPattern,
@@ -210,6 +314,15 @@ export namespace Storage {
         }
     }
 
+    /**
+     * Returns the storage usage of this guild, broken down by bucket.
+     * 
+     * Note that this includes all the buckets in the guild, not just the ones registered by this script.
+     */
+    export async function getUsage(): Promise<Ops.OpStorageUsage> {
+        return await OpWrappers.storageUsage();
+    }
+
     /**
      * A Bucket holding number values
      * 
@@ -241,6 +354,7 @@ export namespace Storage {
          */
         async incr(key: string, amount: number) {
             return this.entryFromInternal(await OpWrappers.bucketStorageIncr({
+                bucketHandle: this.bucketHandle,
                 bucketName: this.name,
                 key: key,
                 amount: amount,
@@ -255,6 +369,7 @@ export namespace Storage {
          */
         async sortedList(order: "Ascending" | "Descending", options?: SortedListOptions) {
             const res = await OpWrappers.bucketStorageSortedList({
+                bucketHandle: this.bucketHandle,
                 bucketName: this.name,
                 limit: options?.limit,
                 offset: options?.offset,
@@ -286,5 +401,42 @@ export namespace Storage {
 
             return undefined
         }
+
+        /**
+         * Returns the entries whose values match the provided filters
+         * 
+         * Queries on buckets with more than 1000 entries need atleast one `eq` filter on a path without array indexes
+         * (e.g `team` but not `teams.0`), those are the only filters that can use the index on json values.
+         * Range filters and `orderBy` are not indexed, they're applied to the entries matched by the `eq` filters.
+         * 
+         * @example ```ts
+         * interface Player {
+         *     name: string,
+         *     team: string,
+         *     score: number,
+         * }
+         * 
+         * const players = script.registerStorageBucket(new Storage.JsonBucket<Player>("players"));
+         * 
+         * // top 10 players in the red team
+         * const top = await players.query({
+         *     filters: [{ path: "team", op: "eq", value: "red" }],
+         *     orderBy: { path: "score", order: "Descending" },
+         *     limit: 10,
+         * });
+         * ```
+         */
+        async query(options: JsonQueryOptions) {
+            const res = await OpWrappers.bucketStorageJsonQuery({
+                bucketHandle: this.bucketHandle,
+                bucketName: this.name,
+                filters: options.filters ?? [],
+                orderBy: options.orderBy,
+                offset: options.offset,
+                limit: options.limit,
+            });
+
+            return res.map(v => this.entryFromInternal(v));
+        }
     }
 }
\ No newline at end of file
@@ -6,18 +6,36 @@ use tracing::info;
 use url::Url;
 use v8::{CreateParams, IsolateHandle};
 
-use vm::{
-    moduleloader::{ModuleEntry, ModuleManager},
-    prepend_script_source_header, AnyError, JsValue,
-};
+use vm::{moduleloader::ModuleManager, prepend_script_source_header, AnyError, JsValue};
 
 use runtime_models::ops::script::ScriptMeta;
 
+/// Modules the validated script can import, in addition to the builtin ones
+#[derive(Debug, Default)]
+pub struct ValidationImports {
+    /// Other guild scripts, these should not run the script (see [vm::prepend_library_source_header])
+    pub guild_scripts: Vec<(Url, String)>,
+    pub libraries: Vec<(Url, String)>,
+}
+
 /// Validates a script, making sure it parses correctly and runs the ScriptMeta function to retrieve essential information about this script
-pub async fn validate_script(source: String) -> Result<ScriptMeta, AnyError> {
+///
+/// `source` is the compiled script, it's evaluated as the guild script with the provided name so relative imports resolve to other guild scripts.
+pub async fn validate_script(
+    name: String,
+    source: String,
+    imports: ValidationImports,
+) -> Result<ScriptMeta, AnyError> {
     info!("validating script");
 
     let module_map = crate::jsmodules::create_module_map();
+    let module_manager = ModuleManager::new(module_map);
+    for (specifier, source) in imports.guild_scripts {
+        module_manager.add_guild_module(specifier, source, &[]);
+    }
+    for (specifier, source) in imports.libraries {
+        module_manager.add_library_module(specifier, source);
+    }
 
     let (iso_tx, iso_rx) = oneshot::channel();
     let (result_tx, result_rx) = oneshot::channel();
@@ -26,7 +44,7 @@ pub async fn validate_script(source: String) -> Result<ScriptMeta, AnyError> {
     let current_tokio = tokio::runtime::Handle::current();
     std::thread::spawn(move || {
         current_tokio.block_on(async move {
-            let result = validator_thread(source, module_map, iso_tx, term_rx).await;
+            let result = validator_thread(name, source, module_manager, iso_tx, term_rx).await;
             result_tx.send(result).unwrap();
         })
     });
@@ -46,8 +64,9 @@ pub async fn validate_script(source: String) -> Result<ScriptMeta, AnyError> {
 }
 
 async fn validator_thread(
+    name: String,
     source: String,
-    module_map: Vec<ModuleEntry>,
+    module_manager: ModuleManager,
     iso_handle_back: oneshot::Sender<IsolateHandle>,
     term_rx: oneshot::Receiver<bool>,
 ) -> Result<ScriptMeta, AnyError> {
@@ -58,7 +77,7 @@ async fn validator_thread(
                 op_sync(op_script_start),
             )])
             .build()],
-        module_loader: Some(Rc::new(ModuleManager { module_map })),
+        module_loader: Some(Rc::new(module_manager)),
         create_params: Some(CreateParams::default().heap_limits(512_000, 10_240_000)),
         startup_snapshot: Some(Snapshot::Static(vm::BOTLOADER_CORE_SNAPSHOT)),
         ..Default::default()
@@ -71,14 +90,18 @@ async fn validator_thread(
 
     let module_id = rt
         .load_main_module(
-            &Url::parse("file:///user/validating").unwrap(),
+            &ModuleManager::guild_script_specifier(&name),
             Some(prepend_script_source_header(&source, None)),
         )
         .await?;
     let eval_complete = rt.mod_evaluate(module_id);
 
     tokio::select! {
-        _ = eval_complete => {},
+        res = eval_complete => {
+            if let Ok(Err(err)) = res {
+                return Err(err);
+            }
+        },
         _ = rt.run_event_loop(false) =>{},
         _ = term_rx => {
             return Err(anyhow::anyhow!(
@@ -99,14 +122,17 @@ async fn validator_thread(
             Err(anyhow::anyhow!("failed borrowing op_state"))
         }
     };
-    r
+    let meta = r?;
+    crate::validate_script_meta(&meta)?;
+    Ok(meta)
 }
 
-pub fn op_script_start(state: &mut OpState, args: JsValue, _: ()) -> Result<(), AnyError> {
+pub fn op_script_start(state: &mut OpState, args: JsValue, _: ()) -> Result<Vec<String>, AnyError> {
     let des: ScriptMeta = serde_json::from_value(args)?;
     info!("Set script meta: {:?}", des);
     state.put(des);
-    Ok(())
+    // storage can't be used during validation
+    Ok(Vec::new())
 }
 
 // pub struct EmptyModuleLoader {}
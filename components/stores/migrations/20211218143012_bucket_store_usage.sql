@@ -0,0 +1,45 @@
+-- Add migration script here
+CREATE TABLE IF NOT EXISTS bucket_store_usage (
+    guild_id bigint NOT NULL,
+    bucket text NOT NULL,
+    entries bigint NOT NULL,
+    size_bytes bigint NOT NULL,
+    PRIMARY KEY (guild_id, bucket)
+);
+
+-- keeps bucket_store_usage up to date so we don't have to scan bucket_store to figure out the usage
+CREATE OR REPLACE FUNCTION bucket_store_usage_update() RETURNS trigger AS $$
+BEGIN
+    IF (TG_OP = 'DELETE' OR TG_OP = 'UPDATE') THEN
+        UPDATE bucket_store_usage SET
+            entries = entries - 1,
+            size_bytes = size_bytes - pg_column_size(OLD.*)
+        WHERE guild_id = OLD.guild_id AND bucket = OLD.bucket;
+    END IF;
+
+    IF (TG_OP = 'INSERT' OR TG_OP = 'UPDATE') THEN
+        INSERT INTO bucket_store_usage (guild_id, bucket, entries, size_bytes)
+        VALUES (NEW.guild_id, NEW.bucket, 1, pg_column_size(NEW.*))
+        ON CONFLICT (guild_id, bucket) DO UPDATE SET
+            entries = bucket_store_usage.entries + 1,
+            size_bytes = bucket_store_usage.size_bytes + excluded.size_bytes;
+    END IF;
+
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+-- make sure nothing is written between the backfill and the trigger being created
+LOCK TABLE bucket_store IN SHARE ROW EXCLUSIVE MODE;
+
+INSERT INTO bucket_store_usage (guild_id, bucket, entries, size_bytes)
+SELECT guild_id, bucket, count(*), sum(pg_column_size(t.*)) FROM bucket_store t
+GROUP BY guild_id, bucket
+ON CONFLICT DO NOTHING;
+
+DROP TRIGGER IF EXISTS bucket_store_usage_trigger ON bucket_store;
+CREATE TRIGGER bucket_store_usage_trigger AFTER INSERT OR UPDATE OR DELETE ON bucket_store
+    FOR EACH ROW EXECUTE FUNCTION bucket_store_usage_update();
+
+ALTER TABLE guild_scripts
+    ADD COLUMN IF NOT EXISTS contributes_storage_buckets jsonb NOT NULL DEFAULT '[]';
@@ -0,0 +1,3 @@
+-- Add migration script here
+-- json queries use a single index on all json values, only filters that can be expressed as containment (eq) can use it
+CREATE INDEX IF NOT EXISTS bucket_store_value_json_idx ON bucket_store USING gin (value_json jsonb_path_ops);
@@ -0,0 +1,22 @@
+-- Add migration script here
+CREATE TABLE IF NOT EXISTS guild_script_revisions (
+    script_id bigint NOT NULL REFERENCES guild_scripts (id) ON DELETE CASCADE,
+    revision integer NOT NULL,
+    guild_id bigint NOT NULL,
+    author_id bigint,
+    created_at timestamp with time zone NOT NULL,
+    original_source text NOT NULL,
+    enabled boolean NOT NULL,
+    -- snapshot of the script's contributes (ScriptContributes), kept up to date with the script for the latest revision
+    contributes jsonb NOT NULL,
+    PRIMARY KEY (script_id, revision)
+);
+
+-- the current version of existing scripts becomes their first revision
+INSERT INTO guild_script_revisions (script_id, revision, guild_id, author_id, created_at, original_source, enabled, contributes)
+SELECT id, 1, guild_id, NULL, now(), original_source, enabled, jsonb_build_object(
+    'commands', contributes_commands,
+    'interval_timers', contributes_interval_timers,
+    'storage_buckets', contributes_storage_buckets
+) FROM guild_scripts
+ON CONFLICT DO NOTHING;
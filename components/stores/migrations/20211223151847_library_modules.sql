@@ -0,0 +1,11 @@
+-- Add migration script here
+CREATE TABLE IF NOT EXISTS library_modules (
+    name text NOT NULL,
+    version text NOT NULL,
+    author_id bigint,
+    created_at timestamp with time zone NOT NULL,
+    original_source text NOT NULL,
+    -- published versions are immutable, so the compiled output is cached here and never recompiled
+    compiled_source text NOT NULL,
+    PRIMARY KEY (name, version)
+);
@@ -0,0 +1,4 @@
+-- Add migration script here
+-- compiled output of the script source, only used if the hash matches the current source
+ALTER TABLE guild_scripts ADD COLUMN IF NOT EXISTS compiled_cache_hash text;
+ALTER TABLE guild_scripts ADD COLUMN IF NOT EXISTS compiled_cache jsonb;
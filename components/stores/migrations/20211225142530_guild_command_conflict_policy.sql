@@ -0,0 +1,3 @@
+-- Add migration script here
+-- how conflicting commands between scripts are handled, see CommandConflictPolicy
+ALTER TABLE guild_meta_configs ADD COLUMN IF NOT EXISTS command_conflict_policy text NOT NULL DEFAULT 'first_wins';
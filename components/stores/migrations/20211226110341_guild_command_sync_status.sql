@@ -0,0 +1,11 @@
+-- Add migration script here
+-- state of the last sync of a guild's commands with discord
+CREATE TABLE IF NOT EXISTS guild_command_sync_status (
+    guild_id bigint PRIMARY KEY NOT NULL,
+    state text NOT NULL,
+    -- failed attempts since the last successful sync
+    failed_attempts integer NOT NULL DEFAULT 0,
+    last_error text,
+    last_synced_at timestamp with time zone,
+    updated_at timestamp with time zone NOT NULL DEFAULT now()
+);
@@ -0,0 +1,5 @@
+-- Add migration script here
+-- api keys can be restricted to guilds and permissions (ApiKeyScope), null means unrestricted
+ALTER TABLE web_sessions ADD COLUMN IF NOT EXISTS scope jsonb;
+ALTER TABLE web_sessions ADD COLUMN IF NOT EXISTS expires_at timestamp with time zone;
+ALTER TABLE web_sessions ADD COLUMN IF NOT EXISTS last_used_at timestamp with time zone;
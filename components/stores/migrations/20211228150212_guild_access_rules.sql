@@ -0,0 +1,10 @@
+-- Add migration script here
+-- grants botloader permissions (GuildAccessPermission) to roles and users in a guild
+CREATE TABLE IF NOT EXISTS guild_access_rules (
+    guild_id bigint NOT NULL,
+    -- 1 for roles, 2 for users
+    subject_kind smallint NOT NULL,
+    subject_id bigint NOT NULL,
+    permissions text[] NOT NULL,
+    PRIMARY KEY (guild_id, subject_kind, subject_id)
+);
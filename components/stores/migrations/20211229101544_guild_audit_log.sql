@@ -0,0 +1,27 @@
+-- Add migration script here
+-- record of administrative actions taken on a guild, entries are never changed or removed
+CREATE TABLE IF NOT EXISTS guild_audit_log (
+    id bigserial PRIMARY KEY NOT NULL,
+    guild_id bigint NOT NULL,
+    actor_id bigint NOT NULL,
+    -- where the action came from: web, api_key or chat
+    source text NOT NULL,
+    action text NOT NULL,
+    -- what the action was taken on, e.g the name of a script
+    target text,
+    before jsonb,
+    after jsonb,
+    created_at timestamp with time zone NOT NULL DEFAULT now()
+);
+
+CREATE INDEX IF NOT EXISTS guild_audit_log_guild_id_idx ON guild_audit_log (guild_id, id DESC);
+
+CREATE OR REPLACE FUNCTION guild_audit_log_append_only() RETURNS trigger AS $$
+BEGIN
+    RAISE EXCEPTION 'guild_audit_log is append only';
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER guild_audit_log_append_only
+    BEFORE UPDATE OR DELETE ON guild_audit_log
+    FOR EACH ROW EXECUTE FUNCTION guild_audit_log_append_only();
@@ -0,0 +1,10 @@
+-- Add migration script here
+-- webhooks scripts can receive http requests on, requests are verified with the secret
+CREATE TABLE IF NOT EXISTS guild_webhooks (
+    guild_id bigint NOT NULL,
+    script_id bigint NOT NULL REFERENCES guild_scripts(id) ON DELETE CASCADE,
+    name text NOT NULL,
+    secret text NOT NULL,
+    created_at timestamp with time zone NOT NULL DEFAULT now(),
+    PRIMARY KEY (guild_id, script_id, name)
+);
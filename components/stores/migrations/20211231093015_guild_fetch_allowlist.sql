@@ -0,0 +1,3 @@
+-- Add migration script here
+-- domains scripts are allowed to make http requests to, see GuildMetaConfig::fetch_allowed_domains
+ALTER TABLE guild_meta_configs ADD COLUMN IF NOT EXISTS fetch_allowed_domains text[] NOT NULL DEFAULT '{}';
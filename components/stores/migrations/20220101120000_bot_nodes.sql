@@ -0,0 +1,10 @@
+-- Add migration script here
+-- bot nodes currently running, each one owns a range of the bot's shards
+CREATE TABLE IF NOT EXISTS bot_nodes (
+    rpc_addr text PRIMARY KEY,
+    shard_range_start bigint NOT NULL,
+    shard_range_end bigint NOT NULL,
+    total_shards bigint NOT NULL,
+    started_at timestamp with time zone NOT NULL DEFAULT now(),
+    last_heartbeat_at timestamp with time zone NOT NULL DEFAULT now()
+);
@@ -0,0 +1,14 @@
+-- Add migration script here
+-- the user that published the first version of a library, only they can publish new versions
+CREATE TABLE IF NOT EXISTS library_module_owners (
+    name text PRIMARY KEY NOT NULL,
+    owner_id bigint NOT NULL,
+    created_at timestamp with time zone NOT NULL DEFAULT now()
+);
+
+-- libraries published before owners were recorded are owned by the author of their first version
+INSERT INTO library_module_owners (name, owner_id, created_at)
+SELECT DISTINCT ON (name) name, author_id, created_at FROM library_modules
+WHERE author_id IS NOT NULL
+ORDER BY name, created_at ASC
+ON CONFLICT DO NOTHING;
@@ -1,6 +1,7 @@
-use std::time::Duration;
+use std::{convert::TryFrom, time::Duration};
 
 use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
 use twilight_model::id::GuildId;
 
@@ -9,12 +10,32 @@ pub enum StoreError {
     #[error("guild storage capacity reached")]
     GuildStorageLimitReached,
 
+    #[error("bucket is not empty")]
+    BucketNotEmpty,
+
     #[error("inner error occured: {0}")]
     Other(#[from] anyhow::Error),
 }
 
 pub type StoreResult<T> = Result<T, StoreError>;
 
+/// Max amount of storage a single guild can use, in bytes
+pub const GUILD_STORAGE_LIMIT_BYTES: u64 = 10_000_000;
+
+/// Max length of an entry's key, in bytes
+pub const MAX_KEY_LEN_BYTES: usize = 256;
+
+/// Max size of a json value, in bytes when serialized
+pub const MAX_JSON_VALUE_LEN_BYTES: usize = 1_000_000;
+
+/// Returns the name a bucket private to the provided script is stored under
+///
+/// Buckets are namespaced by script unless they're declared as shared,
+/// script names can't contain slashes so these can't collide across scripts.
+pub fn script_bucket_name(script_name: &str, bucket: &str) -> String {
+    format!("{}/{}", script_name, bucket)
+}
+
 #[async_trait]
 pub trait BucketStore {
     async fn get(
@@ -59,8 +80,34 @@ pub trait BucketStore {
         limit: u32,
     ) -> StoreResult<Vec<Entry>>;
 
+    /// Returns the total storage used by this guild
+    ///
+    /// Note that this also includes expired entries that has not been cleaned up yet,
+    /// see [BucketStore::delete_expired_entries]
     async fn guild_storage_usage_bytes(&self, guild_id: GuildId) -> StoreResult<u64>;
 
+    /// Returns the usage of a single bucket, includes expired entries that has not been cleaned up yet
+    async fn bucket_usage(&self, guild_id: GuildId, bucket: String) -> StoreResult<BucketSummary>;
+
+    /// Returns all the buckets in this guild that has atleast 1 entry
+    async fn list_buckets(&self, guild_id: GuildId) -> StoreResult<Vec<BucketSummary>>;
+
+    /// Deletes all the expired entries in this guild, returning the number of deleted entries
+    async fn delete_expired_entries(&self, guild_id: GuildId) -> StoreResult<u64>;
+
+    /// Moves all the entries in a bucket to another one, returning the number of moved entries
+    ///
+    /// Fails with [StoreError::BucketNotEmpty] if the destination bucket has entries in it.
+    async fn rename_bucket(&self, guild_id: GuildId, from: String, to: String) -> StoreResult<u64>;
+
+    /// Returns the json entries in the bucket matching all the filters in the query
+    async fn json_query(
+        &self,
+        guild_id: GuildId,
+        bucket: String,
+        query: JsonQuery,
+    ) -> StoreResult<Vec<Entry>>;
+
     // the below should only be used for float values
     async fn incr(
         &self,
@@ -85,11 +132,13 @@ pub enum SetCondition {
     IfExists,
 }
 
+#[derive(Debug, Clone, Copy)]
 pub enum SortedOrder {
     Ascending,
     Descending,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Entry {
     pub bucket: String,
     pub key: String,
@@ -97,7 +146,183 @@ pub struct Entry {
     pub expires_at: Option<chrono::DateTime<chrono::Utc>>,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum StoreValue {
     Json(serde_json::Value),
     Float(f64),
 }
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BucketSummary {
+    pub name: String,
+    pub entries: u64,
+    pub size_bytes: u64,
+}
+
+/// A path to a field in a json value, e.g `user.score` or `items.0`
+///
+/// Paths are restricted to ascii alphanumeric and underscore segments so they can be safely
+/// embedded in queries.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct JsonPath(Vec<String>);
+
+#[derive(Debug, Error)]
+#[error("invalid json path: {0}")]
+pub struct InvalidJsonPath(&'static str);
+
+impl JsonPath {
+    pub const MAX_DEPTH: usize = 8;
+    pub const MAX_SEGMENT_LEN: usize = 64;
+
+    pub fn parse(path: &str) -> Result<Self, InvalidJsonPath> {
+        let segments = path.split('.').map(ToString::to_string).collect::<Vec<_>>();
+
+        if segments.len() > Self::MAX_DEPTH {
+            return Err(InvalidJsonPath("too many segments (max 8)"));
+        }
+
+        for segment in &segments {
+            if segment.is_empty() {
+                return Err(InvalidJsonPath("empty segment"));
+            }
+
+            if segment.len() > Self::MAX_SEGMENT_LEN {
+                return Err(InvalidJsonPath("segment too long (max 64 characters)"));
+            }
+
+            if !segment
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_')
+            {
+                return Err(InvalidJsonPath(
+                    "segments can only contain ascii letters, numbers and underscores",
+                ));
+            }
+        }
+
+        Ok(Self(segments))
+    }
+
+    pub fn segments(&self) -> &[String] {
+        &self.0
+    }
+}
+
+impl std::fmt::Display for JsonPath {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0.join("."))
+    }
+}
+
+impl TryFrom<String> for JsonPath {
+    type Error = InvalidJsonPath;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        Self::parse(&value)
+    }
+}
+
+impl From<JsonPath> for String {
+    fn from(v: JsonPath) -> Self {
+        v.to_string()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct JsonQuery {
+    pub filters: Vec<JsonFilter>,
+    pub order_by: Option<(JsonPath, SortedOrder)>,
+    pub offset: u32,
+    pub limit: u32,
+}
+
+#[derive(Debug, Clone)]
+pub struct JsonFilter {
+    pub path: JsonPath,
+    pub op: JsonFilterOp,
+    pub value: serde_json::Value,
+}
+
+impl JsonFilter {
+    /// Returns a json value containing the filter value at the filter path, for equality filters
+    /// that can use the index on json values
+    ///
+    /// Paths with numeric segments are left out since those can also refer to array elements,
+    /// which containment can't express.
+    pub fn containment(&self) -> Option<serde_json::Value> {
+        if !matches!(self.op, JsonFilterOp::Eq) {
+            return None;
+        }
+
+        let segments = self.path.segments();
+        if segments
+            .iter()
+            .any(|s| s.chars().all(|c| c.is_ascii_digit()))
+        {
+            return None;
+        }
+
+        let mut value = self.value.clone();
+        for segment in segments.iter().rev() {
+            let mut object = serde_json::Map::new();
+            object.insert(segment.clone(), value);
+            value = serde_json::Value::Object(object);
+        }
+
+        Some(value)
+    }
+}
+
+/// Range operators only match values of the same json type as the filter value
+#[derive(Debug, Clone, Copy)]
+pub enum JsonFilterOp {
+    Eq,
+    Lt,
+    Lte,
+    Gt,
+    Gte,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{JsonFilter, JsonFilterOp, JsonPath};
+
+    #[test]
+    fn json_path_parse() {
+        let path = JsonPath::parse("user.scores.0").unwrap();
+        assert_eq!(path.segments(), &["user", "scores", "0"]);
+        assert_eq!(path.to_string(), "user.scores.0");
+
+        assert!(JsonPath::parse("").is_err());
+        assert!(JsonPath::parse("a..b").is_err());
+        assert!(JsonPath::parse("a.b c").is_err());
+        assert!(JsonPath::parse("a}'").is_err());
+        assert!(JsonPath::parse("a.b.c.d.e.f.g.h.i").is_err());
+    }
+
+    #[test]
+    fn json_filter_containment() {
+        let filter = JsonFilter {
+            path: JsonPath::parse("user.team").unwrap(),
+            op: JsonFilterOp::Eq,
+            value: serde_json::json!("red"),
+        };
+        assert_eq!(
+            filter.containment(),
+            Some(serde_json::json!({"user": {"team": "red"}}))
+        );
+
+        let range = JsonFilter {
+            op: JsonFilterOp::Gt,
+            ..filter.clone()
+        };
+        assert_eq!(range.containment(), None);
+
+        let array_index = JsonFilter {
+            path: JsonPath::parse("scores.0").unwrap(),
+            ..filter
+        };
+        assert_eq!(array_index.containment(), None);
+    }
+}
@@ -3,7 +3,10 @@ use std::error::Error;
 use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
-use twilight_model::id::{ChannelId, GuildId, UserId};
+use twilight_model::{
+    application::command::{Command, CommandOption},
+    id::{ChannelId, GuildId, RoleId, UserId},
+};
 
 #[derive(Debug, Error)]
 pub enum ConfigStoreError<T: std::fmt::Debug + Error + 'static> {
@@ -13,6 +16,18 @@ pub enum ConfigStoreError<T: std::fmt::Debug + Error + 'static> {
     #[error("script link not found")]
     LinkNotFound,
 
+    #[error("script revision not found")]
+    RevisionNotFound,
+
+    #[error("library module not found")]
+    LibraryNotFound,
+
+    #[error("library module version already exists")]
+    LibraryVersionExists,
+
+    #[error("library module is owned by another user")]
+    LibraryNotOwned,
+
     #[error("reached limit of guild scripts: {0} (limit {1})")]
     GuildScriptLimitReached(u64, u64),
 
@@ -59,6 +74,82 @@ pub trait ConfigStore: Clone + Sync {
     ) -> StoreResult<(), Self::Error>;
     async fn list_scripts(&self, guild_id: GuildId) -> StoreResult<Vec<Script>, Self::Error>;
 
+    /// Returns the revisions of a script, newest first
+    async fn list_script_revisions(
+        &self,
+        guild_id: GuildId,
+        script_id: u64,
+    ) -> StoreResult<Vec<ScriptRevisionSummary>, Self::Error>;
+    async fn get_script_revision(
+        &self,
+        guild_id: GuildId,
+        script_id: u64,
+        revision: u32,
+    ) -> StoreResult<ScriptRevision, Self::Error>;
+
+    /// Restores the source of a script to the one in the provided revision
+    ///
+    /// This is a normal update, so it creates a new revision and the script keeps its current enabled state.
+    async fn rollback_script(
+        &self,
+        guild_id: GuildId,
+        script_id: u64,
+        revision: u32,
+        author_id: Option<UserId>,
+    ) -> StoreResult<Script, Self::Error> {
+        let current = self.get_script_by_id(guild_id, script_id).await?;
+        let target = self
+            .get_script_revision(guild_id, script_id, revision)
+            .await?;
+
+        self.update_script(
+            guild_id,
+            UpdateScript {
+                id: current.id,
+                name: current.name,
+                original_source: target.original_source,
+                enabled: current.enabled,
+                contributes: None,
+                author_id,
+            },
+        )
+        .await
+    }
+
+    /// Publishes a new version of a library module, published versions can't be changed
+    ///
+    /// The author of the first version becomes the owner of the library,
+    /// after that only they can publish new versions.
+    async fn publish_library_module(
+        &self,
+        module: CreateLibraryModule,
+    ) -> StoreResult<LibraryModule, Self::Error>;
+    async fn get_library_module(
+        &self,
+        name: &str,
+        version: &str,
+    ) -> StoreResult<LibraryModule, Self::Error>;
+    /// Returns the published versions of a library module, newest first
+    async fn list_library_module_versions(
+        &self,
+        name: &str,
+    ) -> StoreResult<Vec<LibraryModuleSummary>, Self::Error>;
+
+    /// Returns the persisted compiled output of a script, if it was compiled from a source with this hash
+    async fn get_script_compile_cache(
+        &self,
+        guild_id: GuildId,
+        script_id: u64,
+        source_hash: &str,
+    ) -> StoreResult<Option<serde_json::Value>, Self::Error>;
+    async fn set_script_compile_cache(
+        &self,
+        guild_id: GuildId,
+        script_id: u64,
+        source_hash: &str,
+        compiled: serde_json::Value,
+    ) -> StoreResult<(), Self::Error>;
+
     async fn get_guild_meta_config(
         &self,
         guild_id: GuildId,
@@ -79,6 +170,66 @@ pub trait ConfigStore: Clone + Sync {
         }
     }
 
+    async fn get_guild_command_sync_status(
+        &self,
+        guild_id: GuildId,
+    ) -> StoreResult<Option<GuildCommandSyncStatus>, Self::Error>;
+    /// Stores the sync status, `last_synced_at` is only updated if it's set
+    async fn set_guild_command_sync_status(
+        &self,
+        status: &GuildCommandSyncStatus,
+    ) -> StoreResult<(), Self::Error>;
+
+    async fn get_guild_access_rules(
+        &self,
+        guild_id: GuildId,
+    ) -> StoreResult<Vec<GuildAccessRule>, Self::Error>;
+    /// Replaces all the access rules of the guild
+    async fn set_guild_access_rules(
+        &self,
+        guild_id: GuildId,
+        rules: Vec<GuildAccessRule>,
+    ) -> StoreResult<Vec<GuildAccessRule>, Self::Error>;
+
+    async fn list_guild_webhooks(
+        &self,
+        guild_id: GuildId,
+    ) -> StoreResult<Vec<GuildWebhook>, Self::Error>;
+    async fn get_guild_webhook(
+        &self,
+        guild_id: GuildId,
+        script_id: u64,
+        name: &str,
+    ) -> StoreResult<Option<GuildWebhook>, Self::Error>;
+    /// Creates the webhook, or replaces its secret if it already exists
+    async fn set_guild_webhook(
+        &self,
+        guild_id: GuildId,
+        script_id: u64,
+        name: &str,
+        secret: &str,
+    ) -> StoreResult<GuildWebhook, Self::Error>;
+    async fn del_guild_webhook(
+        &self,
+        guild_id: GuildId,
+        script_id: u64,
+        name: &str,
+    ) -> StoreResult<bool, Self::Error>;
+
+    /// Appends an entry to the guild's audit log, entries can't be changed after this
+    async fn add_audit_log_entry(
+        &self,
+        guild_id: GuildId,
+        entry: CreateAuditLogEntry,
+    ) -> StoreResult<AuditLogEntry, Self::Error>;
+    /// Returns up to `limit` audit log entries older than `before`, newest first
+    async fn list_audit_log_entries(
+        &self,
+        guild_id: GuildId,
+        before: Option<u64>,
+        limit: u32,
+    ) -> StoreResult<Vec<AuditLogEntry>, Self::Error>;
+
     async fn add_update_joined_guild(
         &self,
         guild: JoinedGuild,
@@ -91,7 +242,21 @@ pub trait ConfigStore: Clone + Sync {
         ids: &[GuildId],
     ) -> StoreResult<Vec<JoinedGuild>, Self::Error>;
 
+    /// Returns up to `limit` joined guilds with an id above `after`, ordered by id
+    async fn list_joined_guilds(
+        &self,
+        after: Option<GuildId>,
+        limit: u32,
+    ) -> StoreResult<Vec<JoinedGuild>, Self::Error>;
+
     async fn is_guild_whitelisted(&self, id: GuildId) -> StoreResult<bool, Self::Error>;
+    async fn list_whitelisted_guilds(&self) -> StoreResult<Vec<WhitelistedGuild>, Self::Error>;
+    /// Returns the existing entry if the guild was already whitelisted
+    async fn add_guild_whitelist(
+        &self,
+        guild_id: GuildId,
+    ) -> StoreResult<WhitelistedGuild, Self::Error>;
+    async fn del_guild_whitelist(&self, guild_id: GuildId) -> StoreResult<bool, Self::Error>;
 }
 
 /// Struct you get back from the store
@@ -112,6 +277,8 @@ pub struct UpdateScript {
     pub original_source: String,
     pub enabled: bool,
     pub contributes: Option<ScriptContributes>,
+    /// The user that made this change, recorded in the revision history
+    pub author_id: Option<UserId>,
 }
 
 /// Struct used when creating a script
@@ -120,13 +287,70 @@ pub struct CreateScript {
     pub name: String,
     pub original_source: String,
     pub enabled: bool,
+    /// The user that created the script, recorded in the revision history
+    pub author_id: Option<UserId>,
 }
 
-/// Contribution points for a scripts, e.g triggers, commands etc
+/// A version of a script, a new revision is made every time a script is created or updated
 #[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScriptRevision {
+    pub script_id: u64,
+    pub revision: u32,
+    pub author_id: Option<UserId>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub original_source: String,
+    pub enabled: bool,
+    pub contributes: ScriptContributes,
+}
+
+/// Same as [ScriptRevision] but without the source and contributes
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScriptRevisionSummary {
+    pub script_id: u64,
+    pub revision: u32,
+    pub author_id: Option<UserId>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub enabled: bool,
+}
+
+/// A published version of a shared library module, imported by guild scripts as `lib:name@version`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LibraryModule {
+    pub name: String,
+    pub version: String,
+    pub author_id: Option<UserId>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub original_source: String,
+    pub compiled_source: String,
+}
+
+/// Struct used when publishing a library module
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CreateLibraryModule {
+    pub name: String,
+    pub version: String,
+    pub author_id: Option<UserId>,
+    pub original_source: String,
+    /// The module compiled to js, the vm uses this directly
+    pub compiled_source: String,
+}
+
+/// Same as [LibraryModule] but without the sources
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LibraryModuleSummary {
+    pub name: String,
+    pub version: String,
+    pub author_id: Option<UserId>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// Contribution points for a scripts, e.g triggers, commands etc
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct ScriptContributes {
-    pub commands: Vec<twilight_model::application::command::Command>,
+    pub commands: Vec<Command>,
     pub interval_timers: Vec<IntervalTimerContrib>,
+    #[serde(default)]
+    pub storage_buckets: Vec<StorageBucketContrib>,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -135,11 +359,161 @@ pub struct IntervalTimerContrib {
     pub interval: crate::timers::IntervalType,
 }
 
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct StorageBucketContrib {
+    pub name: String,
+    /// Max amount of storage this bucket can use, in bytes
+    pub quota_bytes: Option<u64>,
+    /// Shared buckets are not namespaced by script and can be accessed by all scripts declaring them
+    #[serde(default)]
+    pub shared: bool,
+}
+
+impl StorageBucketContrib {
+    /// Returns the name this bucket is stored under
+    pub fn storage_name(&self, script_name: &str) -> String {
+        if self.shared {
+            self.name.clone()
+        } else {
+            crate::bucketstore::script_bucket_name(script_name, &self.name)
+        }
+    }
+}
+
+/// A command that would be registered by more than one script
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct CommandConflict {
+    /// Full name of the command, including the group and sub group (e.g `math add`)
+    pub command: String,
+    pub other_script_id: u64,
+    pub other_script_name: String,
+}
+
+/// Returns the commands that would conflict with the commands of other scripts
+///
+/// Groups from different scripts are merged so they only conflict when they have the same
+/// sub command, top level commands conflict on name alone.
+pub fn find_command_conflicts(
+    commands: &[Command],
+    other_scripts: &[Script],
+) -> Vec<CommandConflict> {
+    let mut result = Vec::new();
+
+    for other in other_scripts {
+        for other_cmd in &other.contributes.commands {
+            for cmd in commands.iter().filter(|c| c.name == other_cmd.name) {
+                for path in conflicting_command_paths(cmd, other_cmd) {
+                    result.push(CommandConflict {
+                        command: path,
+                        other_script_id: other.id,
+                        other_script_name: other.name.clone(),
+                    });
+                }
+            }
+        }
+    }
+
+    result
+}
+
+fn conflicting_command_paths(a: &Command, b: &Command) -> Vec<String> {
+    if !is_command_group(a) || !is_command_group(b) {
+        return vec![a.name.clone()];
+    }
+
+    let mut result = Vec::new();
+    for a_opt in &a.options {
+        let name = command_option_name(a_opt);
+        let b_opt = match b.options.iter().find(|v| command_option_name(v) == name) {
+            Some(v) => v,
+            None => continue,
+        };
+
+        match (a_opt, b_opt) {
+            (CommandOption::SubCommandGroup(a_sg), CommandOption::SubCommandGroup(b_sg)) => {
+                for a_sub in &a_sg.options {
+                    let sub_name = command_option_name(a_sub);
+                    if b_sg
+                        .options
+                        .iter()
+                        .any(|v| command_option_name(v) == sub_name)
+                    {
+                        result.push(format!("{} {} {}", a.name, name, sub_name));
+                    }
+                }
+            }
+            _ => result.push(format!("{} {}", a.name, name)),
+        }
+    }
+
+    result
+}
+
+/// Returns the invokable paths of a command, e.g. `group sub` for every sub command of a group
+///
+/// These are the names, separated by spaces, that interactions for the command are received with.
+pub fn command_paths(cmd: &Command) -> Vec<String> {
+    if !is_command_group(cmd) {
+        return vec![cmd.name.clone()];
+    }
+
+    let mut result = Vec::new();
+    for opt in &cmd.options {
+        match opt {
+            CommandOption::SubCommandGroup(sg) => {
+                for sub in &sg.options {
+                    result.push(format!(
+                        "{} {} {}",
+                        cmd.name,
+                        sg.name,
+                        command_option_name(sub)
+                    ));
+                }
+            }
+            _ => result.push(format!("{} {}", cmd.name, command_option_name(opt))),
+        }
+    }
+
+    result
+}
+
+/// Groups are commands with only sub commands and sub groups, these can be merged across scripts
+fn is_command_group(cmd: &Command) -> bool {
+    !cmd.options.is_empty()
+        && cmd.options.iter().all(|opt| {
+            matches!(
+                opt,
+                CommandOption::SubCommand(_) | CommandOption::SubCommandGroup(_)
+            )
+        })
+}
+
+/// Returns the name of a command option, sub commands and groups are options as well
+pub fn command_option_name(opt: &CommandOption) -> String {
+    match opt {
+        CommandOption::SubCommand(v) => v.name.clone(),
+        CommandOption::SubCommandGroup(v) => v.name.clone(),
+        CommandOption::String(v) => v.name.clone(),
+        CommandOption::Integer(v) => v.name.clone(),
+        CommandOption::Boolean(v) => v.name.clone(),
+        CommandOption::User(v) => v.name.clone(),
+        CommandOption::Channel(v) => v.name.clone(),
+        CommandOption::Role(v) => v.name.clone(),
+        CommandOption::Mentionable(v) => v.name.clone(),
+        CommandOption::Number(v) => v.name.clone(),
+    }
+}
+
 /// A guilds config, for storing core botloader settings
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct GuildMetaConfig {
     pub guild_id: GuildId,
     pub error_channel_id: Option<ChannelId>,
+    #[serde(default)]
+    pub command_conflict_policy: CommandConflictPolicy,
+    /// Domains scripts can make http requests to, `*.example.com` also allows all subdomains
+    #[serde(default)]
+    pub fetch_allowed_domains: Vec<String>,
 }
 
 impl GuildMetaConfig {
@@ -147,6 +521,400 @@ impl GuildMetaConfig {
         Self {
             guild_id,
             error_channel_id: None,
+            command_conflict_policy: CommandConflictPolicy::default(),
+            fetch_allowed_domains: Vec::new(),
+        }
+    }
+}
+
+/// A partial update of a [GuildMetaConfig], fields that aren't set are left as is
+///
+/// New settings go in both this and [GuildMetaConfig] so they can be changed through the settings api.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct GuildMetaConfigPatch {
+    /// `Some(None)` unsets the error channel
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        deserialize_with = "deserialize_some"
+    )]
+    pub error_channel_id: Option<Option<ChannelId>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub command_conflict_policy: Option<CommandConflictPolicy>,
+    /// Replaces the whole list
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fetch_allowed_domains: Option<Vec<String>>,
+}
+
+impl GuildMetaConfigPatch {
+    pub fn apply(&self, config: &mut GuildMetaConfig) {
+        if let Some(error_channel_id) = self.error_channel_id {
+            config.error_channel_id = error_channel_id;
+        }
+
+        if let Some(policy) = self.command_conflict_policy {
+            config.command_conflict_policy = policy;
+        }
+
+        if let Some(domains) = &self.fetch_allowed_domains {
+            config.fetch_allowed_domains = domains.clone();
+        }
+    }
+}
+
+// tells apart a field set to null from a missing one, used with #[serde(default)]
+fn deserialize_some<'de, T, D>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    T: Deserialize<'de>,
+    D: serde::Deserializer<'de>,
+{
+    T::deserialize(deserializer).map(Some)
+}
+
+/// Botloader permissions that can be granted to roles and users in a guild
+///
+/// Members with the ADMINISTRATOR or MANAGE_GUILD permission, as well as the owner, have all of them.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum GuildAccessPermission {
+    /// View scripts, their logs and settings
+    View,
+    EditScripts,
+    ManageStorage,
+    ManageSettings,
+}
+
+impl GuildAccessPermission {
+    pub const ALL: [GuildAccessPermission; 4] = [
+        Self::View,
+        Self::EditScripts,
+        Self::ManageStorage,
+        Self::ManageSettings,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::View => "view",
+            Self::EditScripts => "edit_scripts",
+            Self::ManageStorage => "manage_storage",
+            Self::ManageSettings => "manage_settings",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|p| p.as_str() == name)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(tag = "kind", content = "id", rename_all = "snake_case")]
+pub enum GuildAccessSubject {
+    Role(RoleId),
+    User(UserId),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct GuildAccessRule {
+    pub subject: GuildAccessSubject,
+    pub permissions: Vec<GuildAccessPermission>,
+}
+
+/// The botloader permissions a member has in a guild
+#[derive(Debug, Clone, Default, Serialize, PartialEq)]
+pub struct GuildAccess {
+    pub permissions: Vec<GuildAccessPermission>,
+    /// The owner or a member with ADMINISTRATOR or MANAGE_GUILD, only they can change the access rules
+    pub manager: bool,
+}
+
+impl GuildAccess {
+    /// Access for the owner and members with ADMINISTRATOR or MANAGE_GUILD
+    pub fn full() -> Self {
+        Self {
+            permissions: GuildAccessPermission::ALL.to_vec(),
+            manager: true,
+        }
+    }
+
+    /// Combines the permissions of the rules that apply to the user or one of their roles
+    ///
+    /// Any permission also grants [GuildAccessPermission::View].
+    pub fn from_rules(rules: &[GuildAccessRule], user_id: UserId, roles: &[RoleId]) -> Self {
+        let mut permissions = Vec::new();
+        for rule in rules {
+            let applies = match rule.subject {
+                GuildAccessSubject::Role(role_id) => roles.contains(&role_id),
+                GuildAccessSubject::User(id) => id == user_id,
+            };
+
+            if applies {
+                permissions.extend(rule.permissions.iter().copied());
+            }
+        }
+
+        if !permissions.is_empty() {
+            permissions.push(GuildAccessPermission::View);
+        }
+
+        Self {
+            permissions: GuildAccessPermission::ALL
+                .iter()
+                .copied()
+                .filter(|p| permissions.contains(p))
+                .collect(),
+            manager: false,
+        }
+    }
+
+    pub fn has(&self, permission: GuildAccessPermission) -> bool {
+        self.permissions.contains(&permission)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.permissions.is_empty()
+    }
+}
+
+/// A webhook a script can receive http requests on, at `/hooks/:guild/:script/:name`
+#[derive(Debug, Clone, Serialize)]
+pub struct GuildWebhook {
+    pub guild_id: GuildId,
+    pub script_id: u64,
+    pub name: String,
+    /// Used to verify requests, only shown when the webhook is created
+    #[serde(skip_serializing)]
+    pub secret: String,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// Where an administrative action came from
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditLogSource {
+    /// The website, using a normal user session
+    Web,
+    /// The api, using an api key
+    ApiKey,
+    /// The text commands
+    Chat,
+    /// The admin api, used by superusers on guilds they don't manage
+    Admin,
+}
+
+impl AuditLogSource {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Web => "web",
+            Self::ApiKey => "api_key",
+            Self::Chat => "chat",
+            Self::Admin => "admin",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        [Self::Web, Self::ApiKey, Self::Chat, Self::Admin]
+            .iter()
+            .copied()
+            .find(|s| s.as_str() == name)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditLogAction {
+    CreateScript,
+    UpdateScript,
+    EnableScript,
+    DisableScript,
+    DeleteScript,
+    RollbackScript,
+    SetErrorChannel,
+    SetCommandConflictPolicy,
+    SetAccessRules,
+    UpdateSettings,
+    SetWebhook,
+    DeleteWebhook,
+    ReloadVm,
+    UnloadVm,
+    WhitelistGuild,
+    UnwhitelistGuild,
+    ImportArchive,
+}
+
+impl AuditLogAction {
+    pub const ALL: [AuditLogAction; 17] = [
+        Self::CreateScript,
+        Self::UpdateScript,
+        Self::EnableScript,
+        Self::DisableScript,
+        Self::DeleteScript,
+        Self::RollbackScript,
+        Self::SetErrorChannel,
+        Self::SetCommandConflictPolicy,
+        Self::SetAccessRules,
+        Self::UpdateSettings,
+        Self::SetWebhook,
+        Self::DeleteWebhook,
+        Self::ReloadVm,
+        Self::UnloadVm,
+        Self::WhitelistGuild,
+        Self::UnwhitelistGuild,
+        Self::ImportArchive,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::CreateScript => "create_script",
+            Self::UpdateScript => "update_script",
+            Self::EnableScript => "enable_script",
+            Self::DisableScript => "disable_script",
+            Self::DeleteScript => "delete_script",
+            Self::RollbackScript => "rollback_script",
+            Self::SetErrorChannel => "set_error_channel",
+            Self::SetCommandConflictPolicy => "set_command_conflict_policy",
+            Self::SetAccessRules => "set_access_rules",
+            Self::UpdateSettings => "update_settings",
+            Self::SetWebhook => "set_webhook",
+            Self::DeleteWebhook => "delete_webhook",
+            Self::ReloadVm => "reload_vm",
+            Self::UnloadVm => "unload_vm",
+            Self::WhitelistGuild => "whitelist_guild",
+            Self::UnwhitelistGuild => "unwhitelist_guild",
+            Self::ImportArchive => "import_archive",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|a| a.as_str() == name)
+    }
+
+    /// The actions for the changes between two versions of a script
+    ///
+    /// Enabling or disabling a script is recorded separately from changes to its source.
+    pub fn for_script_update(before: &Script, after: &Script) -> Vec<Self> {
+        let mut actions = Vec::new();
+        if before.name != after.name || before.original_source != after.original_source {
+            actions.push(Self::UpdateScript);
+        }
+
+        if before.enabled != after.enabled {
+            actions.push(if after.enabled {
+                Self::EnableScript
+            } else {
+                Self::DisableScript
+            });
+        }
+
+        actions
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditLogEntry {
+    pub id: u64,
+    pub guild_id: GuildId,
+    pub actor_id: UserId,
+    pub source: AuditLogSource,
+    pub action: AuditLogAction,
+    pub target: Option<String>,
+    /// Summary of the state before the action, if there was any
+    pub before: Option<serde_json::Value>,
+    /// Summary of the state after the action, if there is any
+    pub after: Option<serde_json::Value>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// Struct used when adding an audit log entry
+#[derive(Debug, Clone)]
+pub struct CreateAuditLogEntry {
+    pub actor_id: UserId,
+    pub source: AuditLogSource,
+    pub action: AuditLogAction,
+    pub target: Option<String>,
+    pub before: Option<serde_json::Value>,
+    pub after: Option<serde_json::Value>,
+}
+
+impl Script {
+    /// Summary of the script used in the audit log, the source is left out as it's in the revision history
+    pub fn audit_summary(&self) -> serde_json::Value {
+        serde_json::json!({
+            "id": self.id,
+            "name": self.name,
+            "enabled": self.enabled,
+            "source_len": self.original_source.len(),
+        })
+    }
+}
+
+/// State of the last sync of a guild's commands with discord
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct GuildCommandSyncStatus {
+    pub guild_id: GuildId,
+    pub state: CommandSyncState,
+    /// Failed attempts since the last successful sync
+    pub failed_attempts: u32,
+    pub last_error: Option<String>,
+    pub last_synced_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub updated_at: chrono::DateTime<chrono::Utc>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CommandSyncState {
+    /// The commands changed and are waiting to be synced, or a failed sync will be retried
+    Pending,
+    /// The commands on discord match the ones registered by the scripts
+    Synced,
+    /// The sync failed and won't be retried until the commands change again
+    Failed,
+}
+
+impl CommandSyncState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Pending => "pending",
+            Self::Synced => "synced",
+            Self::Failed => "failed",
+        }
+    }
+
+    pub fn from_str_or_default(s: &str) -> Self {
+        match s {
+            "synced" => Self::Synced,
+            "failed" => Self::Failed,
+            _ => Self::Pending,
+        }
+    }
+}
+
+/// How commands registered by more than one script are handled
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CommandConflictPolicy {
+    /// The command of the script with the lowest id is used
+    #[default]
+    FirstWins,
+    /// None of the guild's commands are updated until the conflict is resolved
+    Error,
+    /// The conflicting commands are prefixed with the name of the script, e.g `script-command`
+    NamespaceByScript,
+}
+
+impl CommandConflictPolicy {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::FirstWins => "first_wins",
+            Self::Error => "error",
+            Self::NamespaceByScript => "namespace_by_script",
+        }
+    }
+
+    pub fn from_str_or_default(s: &str) -> Self {
+        match s {
+            "error" => Self::Error,
+            "namespace_by_script" => Self::NamespaceByScript,
+            _ => Self::FirstWins,
         }
     }
 }
@@ -159,3 +927,158 @@ pub struct JoinedGuild {
     pub icon: String,
     pub owner_id: UserId,
 }
+
+/// A guild the bot is allowed to stay in
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WhitelistedGuild {
+    pub guild_id: GuildId,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        command_paths, AuditLogAction, CommandConflictPolicy, GuildAccess, GuildAccessPermission,
+        GuildAccessRule, GuildAccessSubject, GuildMetaConfig, GuildMetaConfigPatch, Script,
+    };
+    use twilight_model::{
+        application::command::{
+            BaseCommandOptionData, Command, CommandOption, CommandType, OptionsCommandOptionData,
+        },
+        id::{ChannelId, CommandVersionId, GuildId, RoleId, UserId},
+    };
+
+    #[test]
+    fn guild_access_from_rules() {
+        let rules = vec![
+            GuildAccessRule {
+                subject: GuildAccessSubject::Role(RoleId::new(1).unwrap()),
+                permissions: vec![GuildAccessPermission::EditScripts],
+            },
+            GuildAccessRule {
+                subject: GuildAccessSubject::User(UserId::new(2).unwrap()),
+                permissions: vec![GuildAccessPermission::ManageStorage],
+            },
+        ];
+
+        let access =
+            GuildAccess::from_rules(&rules, UserId::new(2).unwrap(), &[RoleId::new(1).unwrap()]);
+        assert_eq!(
+            access.permissions,
+            vec![
+                GuildAccessPermission::View,
+                GuildAccessPermission::EditScripts,
+                GuildAccessPermission::ManageStorage,
+            ]
+        );
+
+        let access =
+            GuildAccess::from_rules(&rules, UserId::new(3).unwrap(), &[RoleId::new(4).unwrap()]);
+        assert!(access.is_empty());
+    }
+
+    #[test]
+    fn audit_actions_for_script_update() {
+        let before = Script {
+            id: 1,
+            name: "a".to_string(),
+            original_source: "1".to_string(),
+            enabled: true,
+            contributes: Default::default(),
+        };
+
+        let mut after = before.clone();
+        after.enabled = false;
+        assert_eq!(
+            AuditLogAction::for_script_update(&before, &after),
+            vec![AuditLogAction::DisableScript]
+        );
+
+        after.original_source = "2".to_string();
+        assert_eq!(
+            AuditLogAction::for_script_update(&before, &after),
+            vec![AuditLogAction::UpdateScript, AuditLogAction::DisableScript]
+        );
+
+        assert!(AuditLogAction::for_script_update(&before, &before).is_empty());
+    }
+
+    #[test]
+    fn meta_config_patch() {
+        let guild_id = GuildId::new(1).unwrap();
+        let mut config = GuildMetaConfig::guild_default(guild_id);
+        config.error_channel_id = ChannelId::new(2);
+
+        let patch: GuildMetaConfigPatch =
+            serde_json::from_str(r#"{"command_conflict_policy": "error"}"#).unwrap();
+        patch.apply(&mut config);
+        assert_eq!(config.error_channel_id, ChannelId::new(2));
+        assert_eq!(config.command_conflict_policy, CommandConflictPolicy::Error);
+
+        let patch: GuildMetaConfigPatch =
+            serde_json::from_str(r#"{"error_channel_id": null}"#).unwrap();
+        patch.apply(&mut config);
+        assert_eq!(config.error_channel_id, None);
+
+        let patch: GuildMetaConfigPatch =
+            serde_json::from_str(r#"{"fetch_allowed_domains": ["example.com"]}"#).unwrap();
+        patch.apply(&mut config);
+        assert_eq!(
+            config.fetch_allowed_domains,
+            vec!["example.com".to_string()]
+        );
+        assert_eq!(config.command_conflict_policy, CommandConflictPolicy::Error);
+    }
+
+    #[test]
+    fn paths_of_commands() {
+        let sub = |name: &str| {
+            CommandOption::SubCommand(OptionsCommandOptionData {
+                name: name.to_string(),
+                description: "d".to_string(),
+                options: Vec::new(),
+            })
+        };
+        let command = |name: &str, options: Vec<CommandOption>| Command {
+            application_id: None,
+            default_permission: None,
+            description: "d".to_string(),
+            guild_id: None,
+            id: None,
+            kind: CommandType::ChatInput,
+            name: name.to_string(),
+            options,
+            version: CommandVersionId::new(1).unwrap(),
+        };
+
+        let plain = command(
+            "ping",
+            vec![CommandOption::Boolean(BaseCommandOptionData {
+                name: "loud".to_string(),
+                description: "d".to_string(),
+                required: false,
+            })],
+        );
+        assert_eq!(command_paths(&plain), vec!["ping".to_string()]);
+
+        let group = command(
+            "admin",
+            vec![
+                sub("ban"),
+                CommandOption::SubCommandGroup(OptionsCommandOptionData {
+                    name: "roles".to_string(),
+                    description: "d".to_string(),
+                    options: vec![sub("add"), sub("remove")],
+                }),
+            ],
+        );
+        assert_eq!(
+            command_paths(&group),
+            vec![
+                "admin ban".to_string(),
+                "admin roles add".to_string(),
+                "admin roles remove".to_string(),
+            ]
+        );
+    }
+}
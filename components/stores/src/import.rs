@@ -0,0 +1,59 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use twilight_model::id::GuildId;
+
+use crate::{
+    bucketstore::StoreValue,
+    config::{GuildMetaConfig, ScriptContributes, StoreResult},
+    timers::IntervalType,
+};
+
+/// Everything written to a guild when importing an archive
+#[derive(Debug, Clone)]
+pub struct GuildImport {
+    pub scripts: Vec<ImportScript>,
+    pub interval_timers: Vec<ImportIntervalTimer>,
+    pub storage_entries: Vec<ImportStorageEntry>,
+
+    /// Left alone if this is none
+    pub meta_config: Option<GuildMetaConfig>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ImportScript {
+    pub name: String,
+    pub original_source: String,
+    pub enabled: bool,
+    pub contributes: ScriptContributes,
+}
+
+/// Timers refer to the script by name, as the id's of the imported scripts are not known up front
+#[derive(Debug, Clone)]
+pub struct ImportIntervalTimer {
+    pub script_name: String,
+    pub name: String,
+    pub interval: IntervalType,
+    pub last_run: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ImportStorageEntry {
+    pub bucket: String,
+    pub key: String,
+    pub value: StoreValue,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+#[async_trait]
+pub trait ImportStore {
+    type Error: std::error::Error + Send + Sync;
+
+    /// Writes the import to the guild in a single transaction, so either all of it is written or none of it
+    ///
+    /// Existing scripts with the same name and storage entries with the same key are overwritten.
+    async fn apply_guild_import(
+        &self,
+        guild_id: GuildId,
+        import: GuildImport,
+    ) -> StoreResult<(), Self::Error>;
+}
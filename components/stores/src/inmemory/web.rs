@@ -5,7 +5,7 @@ use dashmap::{mapref::entry::Entry, DashMap};
 use oauth2::CsrfToken;
 use twilight_model::{id::UserId, user::CurrentUser};
 
-use crate::web::{gen_token, CsrfStore, DiscordOauthToken, Session, SessionType};
+use crate::web::{gen_token, ApiKeyScope, CsrfStore, DiscordOauthToken, Session, SessionType};
 
 #[derive(Default, Clone)]
 pub struct InMemorySessionStore {
@@ -17,6 +17,66 @@ pub struct BareSession {
     pub user: CurrentUser,
     pub kind: SessionType,
     pub created_at: chrono::DateTime<chrono::Utc>,
+    pub scope: Option<ApiKeyScope>,
+    pub expires_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub last_used_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+impl BareSession {
+    fn to_session(&self, oauth_token: DiscordOauthToken) -> Session {
+        Session {
+            oauth_token,
+            token: self.token.clone(),
+            kind: self.kind,
+            user: self.user.clone(),
+            created_at: self.created_at,
+            scope: self.scope.clone(),
+            expires_at: self.expires_at,
+            last_used_at: self.last_used_at,
+        }
+    }
+
+    fn is_expired(&self) -> bool {
+        matches!(self.expires_at, Some(t) if t <= chrono::Utc::now())
+    }
+}
+
+impl InMemorySessionStore {
+    fn insert_session(
+        &self,
+        user: CurrentUser,
+        kind: SessionType,
+        scope: Option<ApiKeyScope>,
+        expires_at: Option<chrono::DateTime<chrono::Utc>>,
+    ) -> Result<Session, Error> {
+        let oauth_token = match self.tokens.get(&user.id) {
+            Some(t) => t,
+            None => return Err(Error::OauthTokenNotFound),
+        };
+
+        loop {
+            let token = gen_token();
+
+            match self.sessions.entry(token.clone()) {
+                Entry::Occupied(_) => continue,
+                Entry::Vacant(e) => {
+                    let bare_session = BareSession {
+                        token,
+                        user,
+                        created_at: chrono::Utc::now(),
+                        kind,
+                        scope,
+                        expires_at,
+                        last_used_at: None,
+                    };
+
+                    let session = bare_session.to_session(oauth_token.clone());
+                    e.insert(bare_session);
+                    return Ok(session);
+                }
+            }
+        }
+    }
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -53,37 +113,16 @@ impl crate::web::SessionStore for InMemorySessionStore {
         user: CurrentUser,
         kind: SessionType,
     ) -> Result<Session, Self::Error> {
-        let oauth_token = match self.tokens.get(&user.id) {
-            Some(t) => t,
-            None => return Err(Error::OauthTokenNotFound),
-        };
-
-        loop {
-            let token = gen_token();
-
-            match self.sessions.entry(token.clone()) {
-                Entry::Occupied(_) => continue,
-                Entry::Vacant(e) => {
-                    let bare_session = BareSession {
-                        token: token.clone(),
-                        user: user.clone(),
-                        created_at: chrono::Utc::now(),
-                        kind,
-                    };
-
-                    let session = Session {
-                        oauth_token: oauth_token.clone(),
-                        created_at: bare_session.created_at,
-                        token,
-                        kind,
-                        user,
-                    };
+        self.insert_session(user, kind, None, None)
+    }
 
-                    e.insert(bare_session);
-                    return Ok(session);
-                }
-            }
-        }
+    async fn create_api_key(
+        &self,
+        user: CurrentUser,
+        scope: Option<ApiKeyScope>,
+        expires_at: Option<chrono::DateTime<chrono::Utc>>,
+    ) -> Result<Session, Self::Error> {
+        self.insert_session(user, SessionType::ApiKey, scope, expires_at)
     }
 
     async fn get_oauth_token(&self, user_id: UserId) -> Result<DiscordOauthToken, Self::Error> {
@@ -97,8 +136,8 @@ impl crate::web::SessionStore for InMemorySessionStore {
 
     async fn get_session(&self, token: &str) -> Result<Option<Session>, Self::Error> {
         let bare_session = match self.sessions.get(token) {
-            Some(s) => s,
-            None => return Ok(None),
+            Some(s) if !s.is_expired() => s,
+            _ => return Ok(None),
         };
 
         let token = match self.tokens.get(&bare_session.user.id) {
@@ -106,13 +145,14 @@ impl crate::web::SessionStore for InMemorySessionStore {
             None => return Err(Error::OauthTokenNotFound),
         };
 
-        Ok(Some(Session {
-            oauth_token: token.clone(),
-            token: bare_session.token.clone(),
-            kind: bare_session.kind,
-            user: bare_session.user.clone(),
-            created_at: bare_session.created_at,
-        }))
+        Ok(Some(bare_session.to_session(token.clone())))
+    }
+
+    async fn touch_session(&self, token: &str) -> Result<(), Self::Error> {
+        if let Some(mut session) = self.sessions.get_mut(token) {
+            session.last_used_at = Some(chrono::Utc::now());
+        }
+        Ok(())
     }
 
     async fn get_all_sessions(&self, user_id: UserId) -> Result<Vec<Session>, Self::Error> {
@@ -125,13 +165,7 @@ impl crate::web::SessionStore for InMemorySessionStore {
             .sessions
             .iter()
             .filter(|e| e.user.id == user_id)
-            .map(|e| Session {
-                oauth_token: token.clone(),
-                token: e.token.clone(),
-                kind: e.kind,
-                user: e.user.clone(),
-                created_at: e.created_at,
-            })
+            .map(|e| e.to_session(token.clone()))
             .collect())
     }
 
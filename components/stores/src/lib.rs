@@ -1,6 +1,8 @@
 pub mod bucketstore;
 pub mod config;
+pub mod import;
 pub mod inmemory;
+pub mod nodes;
 pub mod postgres;
 pub mod timers;
 pub mod web;
@@ -0,0 +1,74 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use twilight_model::id::GuildId;
+
+#[async_trait]
+pub trait BotNodeStore {
+    type Error: std::error::Error + Send + Sync;
+
+    /// Registers the node, or refreshes its heartbeat if it's already registered
+    async fn heartbeat_bot_node(&self, node: &BotNode) -> Result<(), Self::Error>;
+    async fn del_bot_node(&self, rpc_addr: &str) -> Result<bool, Self::Error>;
+    /// Returns the nodes that sent a heartbeat within `max_age`
+    async fn get_active_bot_nodes(&self, max_age: Duration) -> Result<Vec<BotNode>, Self::Error>;
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct BotNode {
+    /// Where the node's bot rpc server can be reached
+    pub rpc_addr: String,
+    pub shards: ShardRange,
+}
+
+impl BotNode {
+    pub fn owns_guild(&self, guild_id: GuildId) -> bool {
+        self.shards
+            .contains(guild_shard(guild_id, self.shards.total))
+    }
+}
+
+/// A range of the bot's shards, `start` and `end` are inclusive
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ShardRange {
+    pub start: u64,
+    pub end: u64,
+    pub total: u64,
+}
+
+impl ShardRange {
+    pub fn contains(&self, shard_id: u64) -> bool {
+        shard_id >= self.start && shard_id <= self.end
+    }
+}
+
+/// The shard discord sends the guild's events on
+pub fn guild_shard(guild_id: GuildId, total_shards: u64) -> u64 {
+    (guild_id.get() >> 22) % total_shards.max(1)
+}
+
+#[test]
+fn node_owns_guild() {
+    let node = BotNode {
+        rpc_addr: "http://127.0.0.1:7448".to_string(),
+        shards: ShardRange {
+            start: 2,
+            end: 3,
+            total: 4,
+        },
+    };
+
+    // shards 0 to 3
+    let guilds = [
+        GuildId::new(4 << 22).unwrap(),
+        GuildId::new((5 << 22) + 123).unwrap(),
+        GuildId::new(6 << 22).unwrap(),
+        GuildId::new(7 << 22).unwrap(),
+    ];
+
+    assert!(!node.owns_guild(guilds[0]));
+    assert!(!node.owns_guild(guilds[1]));
+    assert!(node.owns_guild(guilds[2]));
+    assert!(node.owns_guild(guilds[3]));
+}
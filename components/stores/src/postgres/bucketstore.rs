@@ -1,14 +1,67 @@
-use std::time::Duration;
+use std::{fmt::Write, time::Duration};
 
-use crate::bucketstore::{Entry, SetCondition, SortedOrder, StoreResult, StoreValue};
+use crate::bucketstore::{
+    BucketSummary, Entry, JsonFilterOp, JsonPath, JsonQuery, SetCondition, SortedOrder, StoreError,
+    StoreResult, StoreValue,
+};
 
 use super::Postgres;
 use anyhow::Error;
 use async_trait::async_trait;
+use sqlx::Executor;
 use twilight_model::id::GuildId;
 
 use chrono::{DateTime, Utc};
 
+impl Postgres {
+    pub(crate) async fn set_entry<'c, E>(
+        executor: E,
+        guild_id: GuildId,
+        bucket: String,
+        key: String,
+        value: StoreValue,
+        expires_at: Option<DateTime<Utc>>,
+    ) -> Result<DbEntry, sqlx::Error>
+    where
+        E: Executor<'c, Database = sqlx::Postgres>,
+    {
+        let (val_num, val_json) = match value {
+            StoreValue::Json(json) => (None, Some(json)),
+            StoreValue::Float(n) => (Some(n), None),
+        };
+
+        sqlx::query_as!(
+            DbEntry,
+            "INSERT INTO bucket_store 
+                     (guild_id, bucket, key, created_at, updated_at, expires_at, value_json, \
+             value_float)
+                     VALUES 
+                     ($1,         $2,    $3,   now(),      now(),      $4,         $5,         $6) 
+                     ON CONFLICT (guild_id, bucket, key) DO UPDATE SET
+                     created_at = CASE
+                        WHEN bucket_store.expires_at IS NOT NULL AND bucket_store.expires_at < \
+             now() 
+                        THEN now()
+                        ELSE bucket_store.created_at
+                        END,
+                     updated_at = now(),
+                     expires_at = excluded.expires_at,
+                     value_json = excluded.value_json,
+                     value_float = excluded.value_float
+                     RETURNING guild_id, bucket, key, created_at, updated_at, expires_at, \
+             value_json, value_float;",
+            guild_id.get() as i64,
+            bucket,
+            key,
+            expires_at,
+            val_json,
+            val_num,
+        )
+        .fetch_one(executor)
+        .await
+    }
+}
+
 #[async_trait]
 impl crate::bucketstore::BucketStore for Postgres {
     async fn get(
@@ -49,41 +102,9 @@ impl crate::bucketstore::BucketStore for Postgres {
             })
             .flatten();
 
-        let (val_num, val_json) = match value {
-            StoreValue::Json(json) => (None, Some(json)),
-            StoreValue::Float(n) => (Some(n), None),
-        };
-
-        let res = sqlx::query_as!(
-            DbEntry,
-            "INSERT INTO bucket_store 
-                     (guild_id, bucket, key, created_at, updated_at, expires_at, value_json, \
-             value_float)
-                     VALUES 
-                     ($1,         $2,    $3,   now(),      now(),      $4,         $5,         $6) 
-                     ON CONFLICT (guild_id, bucket, key) DO UPDATE SET
-                     created_at = CASE
-                        WHEN bucket_store.expires_at IS NOT NULL AND bucket_store.expires_at < \
-             now() 
-                        THEN now()
-                        ELSE bucket_store.created_at
-                        END,
-                     updated_at = now(),
-                     expires_at = excluded.expires_at,
-                     value_json = excluded.value_json,
-                     value_float = excluded.value_float
-                     RETURNING guild_id, bucket, key, created_at, updated_at, expires_at, \
-             value_json, value_float;",
-            guild_id.get() as i64,
-            bucket,
-            key,
-            expires_at,
-            val_json,
-            val_num,
-        )
-        .fetch_one(&self.pool)
-        .await
-        .map_err(Error::new)?;
+        let res = Self::set_entry(&self.pool, guild_id, bucket, key, value, expires_at)
+            .await
+            .map_err(Error::new)?;
 
         Ok(res.into())
     }
@@ -216,8 +237,7 @@ impl crate::bucketstore::BucketStore for Postgres {
 
     async fn guild_storage_usage_bytes(&self, guild_id: GuildId) -> StoreResult<u64> {
         let res = sqlx::query!(
-            "SELECT sum(pg_column_size(t)) FROM bucket_store t WHERE guild_id=$1 AND (expires_at \
-             IS NULL OR expires_at > now())",
+            "SELECT sum(size_bytes)::bigint FROM bucket_store_usage WHERE guild_id=$1",
             guild_id.get() as i64,
         )
         .fetch_one(&self.pool)
@@ -227,6 +247,180 @@ impl crate::bucketstore::BucketStore for Postgres {
         Ok(res.sum.unwrap_or_default() as u64)
     }
 
+    async fn bucket_usage(&self, guild_id: GuildId, bucket: String) -> StoreResult<BucketSummary> {
+        let res = sqlx::query!(
+            "SELECT entries, size_bytes FROM bucket_store_usage WHERE guild_id = $1 AND bucket = \
+             $2;",
+            guild_id.get() as i64,
+            bucket,
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(Error::new)?;
+
+        Ok(BucketSummary {
+            entries: res.as_ref().map(|r| r.entries as u64).unwrap_or_default(),
+            size_bytes: res
+                .as_ref()
+                .map(|r| r.size_bytes as u64)
+                .unwrap_or_default(),
+            name: bucket,
+        })
+    }
+
+    async fn list_buckets(&self, guild_id: GuildId) -> StoreResult<Vec<BucketSummary>> {
+        let res = sqlx::query!(
+            "SELECT bucket, entries, size_bytes FROM bucket_store_usage WHERE guild_id = $1 AND \
+             entries > 0 ORDER BY bucket;",
+            guild_id.get() as i64,
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(Error::new)?;
+
+        Ok(res
+            .into_iter()
+            .map(|row| BucketSummary {
+                name: row.bucket,
+                entries: row.entries as u64,
+                size_bytes: row.size_bytes as u64,
+            })
+            .collect())
+    }
+
+    async fn delete_expired_entries(&self, guild_id: GuildId) -> StoreResult<u64> {
+        let res = sqlx::query!(
+            "DELETE FROM bucket_store WHERE guild_id = $1 AND expires_at IS NOT NULL AND \
+             expires_at < now();",
+            guild_id.get() as i64,
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(Error::new)?;
+
+        Ok(res.rows_affected())
+    }
+
+    async fn rename_bucket(&self, guild_id: GuildId, from: String, to: String) -> StoreResult<u64> {
+        let mut tx = self.pool.begin().await.map_err(Error::new)?;
+
+        let existing = sqlx::query!(
+            "SELECT count(*) FROM bucket_store WHERE guild_id = $1 AND bucket = $2;",
+            guild_id.get() as i64,
+            to,
+        )
+        .fetch_one(&mut tx)
+        .await
+        .map_err(Error::new)?;
+
+        if existing.count.unwrap_or_default() > 0 {
+            return Err(StoreError::BucketNotEmpty);
+        }
+
+        let res = sqlx::query!(
+            "UPDATE bucket_store SET bucket = $3 WHERE guild_id = $1 AND bucket = $2;",
+            guild_id.get() as i64,
+            from,
+            to,
+        )
+        .execute(&mut tx)
+        .await
+        .map_err(Error::new)?;
+
+        tx.commit().await.map_err(Error::new)?;
+
+        Ok(res.rows_affected())
+    }
+
+    async fn json_query(
+        &self,
+        guild_id: GuildId,
+        bucket: String,
+        query: JsonQuery,
+    ) -> StoreResult<Vec<Entry>> {
+        let mut sql = "SELECT guild_id, bucket, key, created_at, updated_at, expires_at, \
+                       value_json, value_float FROM bucket_store WHERE guild_id = $1 AND bucket = \
+                       $2 AND value_json IS NOT NULL AND (expires_at IS NULL OR expires_at > now())"
+            .to_string();
+
+        let mut params: Vec<serde_json::Value> = Vec::new();
+        for filter in &query.filters {
+            let expr = json_path_expr(&filter.path);
+
+            // containment can use the index on the json values, the exact comparison below
+            // is still needed as containment also matches arrays with more elements
+            if let Some(containment) = filter.containment() {
+                params.push(containment);
+                write!(sql, " AND value_json @> ${}", params.len() + 2).unwrap();
+            }
+
+            params.push(filter.value.clone());
+            let param = params.len() + 2;
+
+            let op = match filter.op {
+                JsonFilterOp::Eq => {
+                    write!(sql, " AND {} = ${}", expr, param).unwrap();
+                    continue;
+                }
+                JsonFilterOp::Lt => "<",
+                JsonFilterOp::Lte => "<=",
+                JsonFilterOp::Gt => ">",
+                JsonFilterOp::Gte => ">=",
+            };
+
+            // jsonb orders values of different types too (e.g strings are always less than numbers)
+            // so we make sure we only match values of the same type
+            write!(
+                sql,
+                " AND jsonb_typeof({expr}) = jsonb_typeof(${param}) AND {expr} {op} ${param}",
+                expr = expr,
+                param = param,
+                op = op
+            )
+            .unwrap();
+        }
+
+        match &query.order_by {
+            Some((path, order)) => write!(
+                sql,
+                " ORDER BY {} {}, key ASC",
+                json_path_expr(path),
+                match order {
+                    SortedOrder::Ascending => "ASC NULLS LAST",
+                    SortedOrder::Descending => "DESC NULLS LAST",
+                }
+            )
+            .unwrap(),
+            None => sql.push_str(" ORDER BY key ASC"),
+        }
+
+        write!(
+            sql,
+            " LIMIT ${} OFFSET ${};",
+            params.len() + 3,
+            params.len() + 4
+        )
+        .unwrap();
+
+        let mut q = sqlx::query_as::<_, DbEntry>(&sql)
+            .bind(guild_id.get() as i64)
+            .bind(bucket);
+        for param in params {
+            q = q.bind(param);
+        }
+        q = q.bind(query.limit as i64).bind(query.offset as i64);
+
+        let mut tx = self.pool.begin().await.map_err(Error::new)?;
+        tx.execute(format!("SET LOCAL statement_timeout = {};", JSON_QUERY_TIMEOUT_MS).as_str())
+            .await
+            .map_err(Error::new)?;
+
+        let res = q.fetch_all(&mut tx).await.map_err(Error::new)?;
+        tx.commit().await.map_err(Error::new)?;
+
+        Ok(res.into_iter().map(Into::into).collect())
+    }
+
     // the below should only be used for float values
     async fn incr(
         &self,
@@ -315,6 +509,7 @@ impl crate::bucketstore::BucketStore for Postgres {
 }
 
 #[allow(dead_code)]
+#[derive(sqlx::FromRow)]
 pub struct DbEntry {
     guild_id: i64,
     bucket: String,
@@ -326,6 +521,14 @@ pub struct DbEntry {
     value_float: Option<f64>,
 }
 
+/// Max time a single json query can run for
+const JSON_QUERY_TIMEOUT_MS: u32 = 2000;
+
+// paths are restricted to safe characters, see [JsonPath::parse]
+fn json_path_expr(path: &JsonPath) -> String {
+    format!("(value_json #> '{{{}}}')", path.segments().join(","))
+}
+
 impl From<DbEntry> for Entry {
     fn from(v: DbEntry) -> Self {
         Self {
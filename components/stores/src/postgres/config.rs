@@ -1,13 +1,20 @@
 use super::Postgres;
 use async_trait::async_trait;
-use twilight_model::id::{ChannelId, GuildId, UserId};
+use twilight_model::id::{ChannelId, GuildId, RoleId, UserId};
 
 use crate::config::{
-    ConfigStoreError, CreateScript, GuildMetaConfig, JoinedGuild, Script, ScriptContributes,
-    StoreResult, UpdateScript,
+    AuditLogAction, AuditLogEntry, AuditLogSource, CommandConflictPolicy, CommandSyncState,
+    ConfigStoreError, CreateAuditLogEntry, CreateLibraryModule, CreateScript,
+    GuildAccessPermission, GuildAccessRule, GuildAccessSubject, GuildCommandSyncStatus,
+    GuildMetaConfig, GuildWebhook, JoinedGuild, LibraryModule, LibraryModuleSummary, Script,
+    ScriptContributes, ScriptRevision, ScriptRevisionSummary, StoreResult, UpdateScript,
+    WhitelistedGuild,
 };
 
-const GUILD_SCRIPT_COUNT_LIMIT: i64 = 100;
+pub(crate) const GUILD_SCRIPT_COUNT_LIMIT: i64 = 100;
+
+/// Max number of revisions we keep per script, older ones are deleted
+const SCRIPT_REVISIONS_LIMIT: i32 = 50;
 
 impl Postgres {
     async fn get_db_script_by_name(
@@ -18,7 +25,8 @@ impl Postgres {
         match sqlx::query_as!(
             DbScript,
             "SELECT id, guild_id, original_source, name, enabled, contributes_commands, \
-             contributes_interval_timers FROM guild_scripts WHERE guild_id = $1 AND name = $2;",
+             contributes_interval_timers, contributes_storage_buckets FROM guild_scripts WHERE \
+             guild_id = $1 AND name = $2;",
             guild_id.get() as i64,
             script_name
         )
@@ -39,7 +47,8 @@ impl Postgres {
         Ok(sqlx::query_as!(
             DbScript,
             "SELECT id, guild_id, name, original_source, enabled, contributes_commands, \
-             contributes_interval_timers FROM guild_scripts WHERE guild_id = $1 AND id = $2;",
+             contributes_interval_timers, contributes_storage_buckets FROM guild_scripts WHERE \
+             guild_id = $1 AND id = $2;",
             guild_id.0.get() as i64,
             id
         )
@@ -47,83 +56,83 @@ impl Postgres {
         .await?)
     }
 
-    async fn get_guild_script_count(&self, guild_id: GuildId) -> StoreResult<i64, sqlx::Error> {
-        let result = sqlx::query!(
-            "SELECT count(*) FROM guild_scripts WHERE guild_id = $1;",
-            guild_id.0.get() as i64,
+    async fn insert_script_revision(
+        tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+        guild_id: GuildId,
+        script: &Script,
+        author_id: Option<UserId>,
+    ) -> Result<(), sqlx::Error> {
+        // lock the script so concurrent updates can't pick the same revision number
+        sqlx::query!(
+            "SELECT id FROM guild_scripts WHERE id = $1 FOR UPDATE;",
+            script.id as i64,
         )
-        .fetch_one(&self.pool)
+        .fetch_one(&mut *tx)
         .await?;
 
-        Ok(result.count.unwrap_or_default())
-    }
-}
-
-#[async_trait]
-impl crate::config::ConfigStore for Postgres {
-    type Error = sqlx::Error;
+        let revision = sqlx::query!(
+            "INSERT INTO guild_script_revisions (script_id, revision, guild_id, author_id, \
+             created_at, original_source, enabled, contributes)
+            VALUES ($1, (SELECT COALESCE(max(revision), 0) + 1 FROM guild_script_revisions WHERE \
+             script_id = $1), $2, $3, now(), $4, $5, $6)
+            RETURNING revision;",
+            script.id as i64,
+            guild_id.get() as i64,
+            author_id.map(|id| id.get() as i64),
+            script.original_source,
+            script.enabled,
+            serde_json::to_value(&script.contributes).unwrap(),
+        )
+        .fetch_one(&mut *tx)
+        .await?;
 
-    async fn get_script(
-        &self,
-        guild_id: GuildId,
-        script_name: String,
-    ) -> StoreResult<Script, Self::Error> {
-        Ok(self
-            .get_db_script_by_name(guild_id, &script_name)
-            .await?
-            .into())
-    }
+        sqlx::query!(
+            "DELETE FROM guild_script_revisions WHERE script_id = $1 AND revision <= $2;",
+            script.id as i64,
+            revision.revision - SCRIPT_REVISIONS_LIMIT,
+        )
+        .execute(&mut *tx)
+        .await?;
 
-    async fn get_script_by_id(
-        &self,
-        guild_id: GuildId,
-        script_id: u64,
-    ) -> StoreResult<Script, Self::Error> {
-        Ok(self
-            .get_db_script_by_id(guild_id, script_id as i64)
-            .await?
-            .into())
+        Ok(())
     }
 
-    async fn create_script(
-        &self,
+    pub(crate) async fn create_script_tx(
+        tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
         guild_id: GuildId,
         script: CreateScript,
-    ) -> StoreResult<Script, Self::Error> {
-        let count = self.get_guild_script_count(guild_id).await?;
-        if count > GUILD_SCRIPT_COUNT_LIMIT {
-            return Err(ConfigStoreError::GuildScriptLimitReached(
-                count as u64,
-                GUILD_SCRIPT_COUNT_LIMIT as u64,
-            ));
-        }
-
+    ) -> Result<Script, sqlx::Error> {
         let res = sqlx::query_as!(
             DbScript,
             "
                 INSERT INTO guild_scripts (guild_id, name, original_source, enabled) 
                 VALUES ($1, $2, $3, $4)
                 RETURNING id, guild_id, name, original_source, enabled, contributes_commands, \
-             contributes_interval_timers;
+             contributes_interval_timers, contributes_storage_buckets;
             ",
             guild_id.0.get() as i64,
             script.name,
             script.original_source,
             script.enabled,
         )
-        .fetch_one(&self.pool)
+        .fetch_one(&mut *tx)
         .await?;
 
-        Ok(res.into())
+        let created: Script = res.into();
+        Self::insert_script_revision(tx, guild_id, &created, script.author_id).await?;
+
+        Ok(created)
     }
 
-    async fn update_script(
-        &self,
+    pub(crate) async fn update_script_tx(
+        tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
         guild_id: GuildId,
         script: UpdateScript,
-    ) -> StoreResult<Script, Self::Error> {
+    ) -> Result<Script, sqlx::Error> {
         let res = if let Some(contribs) = script.contributes {
-            let commands_enc = serde_json::to_value(contribs).unwrap();
+            let commands_enc = serde_json::to_value(contribs.commands).unwrap();
+            let intervals_enc = serde_json::to_value(contribs.interval_timers).unwrap();
+            let buckets_enc = serde_json::to_value(contribs.storage_buckets).unwrap();
 
             sqlx::query_as!(
                 DbScript,
@@ -131,18 +140,22 @@ impl crate::config::ConfigStore for Postgres {
                     UPDATE guild_scripts SET
                     original_source = $3,
                     enabled = $4,
-                    contributes_commands = $5
+                    contributes_commands = $5,
+                    contributes_interval_timers = $6,
+                    contributes_storage_buckets = $7
                     WHERE guild_id = $1 AND id=$2
                     RETURNING id, name, original_source, guild_id, enabled, contributes_commands, \
-                 contributes_interval_timers;
+                 contributes_interval_timers, contributes_storage_buckets;
                 ",
                 guild_id.0.get() as i64,
                 script.id as i64,
                 script.original_source,
                 script.enabled,
                 commands_enc,
+                intervals_enc,
+                buckets_enc,
             )
-            .fetch_one(&self.pool)
+            .fetch_one(&mut *tx)
             .await?
         } else {
             sqlx::query_as!(
@@ -153,48 +166,178 @@ impl crate::config::ConfigStore for Postgres {
                     enabled = $4
                     WHERE guild_id = $1 AND id=$2
                     RETURNING id, name, original_source, guild_id, enabled, contributes_commands, \
-                 contributes_interval_timers;
+                 contributes_interval_timers, contributes_storage_buckets;
                 ",
                 guild_id.0.get() as i64,
                 script.id as i64,
                 script.original_source,
                 script.enabled,
             )
-            .fetch_one(&self.pool)
+            .fetch_one(&mut *tx)
             .await?
         };
 
-        Ok(res.into())
+        let updated: Script = res.into();
+        Self::insert_script_revision(tx, guild_id, &updated, script.author_id).await?;
+
+        Ok(updated)
     }
 
-    async fn update_script_contributes(
-        &self,
+    pub(crate) async fn update_script_contributes_tx(
+        tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
         guild_id: GuildId,
         script_id: u64,
         contribs: ScriptContributes,
-    ) -> StoreResult<Script, Self::Error> {
+    ) -> Result<Script, sqlx::Error> {
         let commands_enc = serde_json::to_value(contribs.commands).unwrap();
         let intervals_enc = serde_json::to_value(contribs.interval_timers).unwrap();
+        let buckets_enc = serde_json::to_value(contribs.storage_buckets).unwrap();
 
         let res = sqlx::query_as!(
             DbScript,
             "
                     UPDATE guild_scripts SET
                     contributes_commands = $3,
-                    contributes_interval_timers = $4
+                    contributes_interval_timers = $4,
+                    contributes_storage_buckets = $5
                     WHERE guild_id = $1 AND id=$2
                     RETURNING id, name, original_source, guild_id, enabled, contributes_commands, \
-             contributes_interval_timers;
+             contributes_interval_timers, contributes_storage_buckets;
                 ",
             guild_id.0.get() as i64,
             script_id as i64,
             commands_enc,
             intervals_enc,
+            buckets_enc,
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+
+        let updated: Script = res.into();
+
+        // contributes are only known after the new source has run, so keep the snapshot in the
+        // latest revision up to date
+        sqlx::query!(
+            "UPDATE guild_script_revisions SET contributes = $2 WHERE script_id = $1 AND \
+             revision = (SELECT max(revision) FROM guild_script_revisions WHERE script_id = $1);",
+            script_id as i64,
+            serde_json::to_value(&updated.contributes).unwrap(),
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        Ok(updated)
+    }
+
+    pub(crate) async fn upsert_guild_meta_config<'c, E>(
+        executor: E,
+        conf: &GuildMetaConfig,
+    ) -> Result<DbGuildMetaConfig, sqlx::Error>
+    where
+        E: sqlx::Executor<'c, Database = sqlx::Postgres>,
+    {
+        sqlx::query_as!(
+            DbGuildMetaConfig,
+            "INSERT INTO guild_meta_configs (guild_id, error_channel_id, command_conflict_policy, \
+             fetch_allowed_domains) VALUES ($1, $2, $3, $4)
+            ON CONFLICT (guild_id) DO UPDATE SET
+            error_channel_id = $2,
+            command_conflict_policy = $3,
+            fetch_allowed_domains = $4
+            RETURNING guild_id, error_channel_id, command_conflict_policy, fetch_allowed_domains;",
+            conf.guild_id.0.get() as i64,
+            conf.error_channel_id
+                .map(|e| e.0.get() as i64)
+                .unwrap_or_default(),
+            conf.command_conflict_policy.as_str(),
+            &conf.fetch_allowed_domains,
+        )
+        .fetch_one(executor)
+        .await
+    }
+
+    async fn get_guild_script_count(&self, guild_id: GuildId) -> StoreResult<i64, sqlx::Error> {
+        let result = sqlx::query!(
+            "SELECT count(*) FROM guild_scripts WHERE guild_id = $1;",
+            guild_id.0.get() as i64,
         )
         .fetch_one(&self.pool)
         .await?;
 
-        Ok(res.into())
+        Ok(result.count.unwrap_or_default())
+    }
+}
+
+#[async_trait]
+impl crate::config::ConfigStore for Postgres {
+    type Error = sqlx::Error;
+
+    async fn get_script(
+        &self,
+        guild_id: GuildId,
+        script_name: String,
+    ) -> StoreResult<Script, Self::Error> {
+        Ok(self
+            .get_db_script_by_name(guild_id, &script_name)
+            .await?
+            .into())
+    }
+
+    async fn get_script_by_id(
+        &self,
+        guild_id: GuildId,
+        script_id: u64,
+    ) -> StoreResult<Script, Self::Error> {
+        Ok(self
+            .get_db_script_by_id(guild_id, script_id as i64)
+            .await?
+            .into())
+    }
+
+    async fn create_script(
+        &self,
+        guild_id: GuildId,
+        script: CreateScript,
+    ) -> StoreResult<Script, Self::Error> {
+        let count = self.get_guild_script_count(guild_id).await?;
+        if count > GUILD_SCRIPT_COUNT_LIMIT {
+            return Err(ConfigStoreError::GuildScriptLimitReached(
+                count as u64,
+                GUILD_SCRIPT_COUNT_LIMIT as u64,
+            ));
+        }
+
+        let mut tx = self.pool.begin().await?;
+        let created = Self::create_script_tx(&mut tx, guild_id, script).await?;
+        tx.commit().await?;
+
+        Ok(created)
+    }
+
+    async fn update_script(
+        &self,
+        guild_id: GuildId,
+        script: UpdateScript,
+    ) -> StoreResult<Script, Self::Error> {
+        let mut tx = self.pool.begin().await?;
+        let updated = Self::update_script_tx(&mut tx, guild_id, script).await?;
+        tx.commit().await?;
+
+        Ok(updated)
+    }
+
+    async fn update_script_contributes(
+        &self,
+        guild_id: GuildId,
+        script_id: u64,
+        contribs: ScriptContributes,
+    ) -> StoreResult<Script, Self::Error> {
+        let mut tx = self.pool.begin().await?;
+        let updated =
+            Self::update_script_contributes_tx(&mut tx, guild_id, script_id, contribs).await?;
+        tx.commit().await?;
+
+        Ok(updated)
     }
 
     async fn del_script(
@@ -221,7 +364,8 @@ impl crate::config::ConfigStore for Postgres {
         let res = sqlx::query_as!(
             DbScript,
             "SELECT id, guild_id, original_source, name, enabled, contributes_commands, \
-             contributes_interval_timers FROM guild_scripts WHERE guild_id = $1",
+             contributes_interval_timers, contributes_storage_buckets FROM guild_scripts WHERE \
+             guild_id = $1",
             guild_id.0.get() as i64,
         )
         .fetch_all(&self.pool)
@@ -230,14 +374,200 @@ impl crate::config::ConfigStore for Postgres {
         Ok(res.into_iter().map(|e| e.into()).collect())
     }
 
+    async fn list_script_revisions(
+        &self,
+        guild_id: GuildId,
+        script_id: u64,
+    ) -> StoreResult<Vec<ScriptRevisionSummary>, Self::Error> {
+        let res = sqlx::query!(
+            "SELECT script_id, revision, author_id, created_at, enabled FROM \
+             guild_script_revisions WHERE guild_id = $1 AND script_id = $2 ORDER BY revision DESC;",
+            guild_id.get() as i64,
+            script_id as i64,
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(res
+            .into_iter()
+            .map(|r| ScriptRevisionSummary {
+                script_id: r.script_id as u64,
+                revision: r.revision as u32,
+                author_id: r.author_id.and_then(|id| UserId::new(id as u64)),
+                created_at: r.created_at,
+                enabled: r.enabled,
+            })
+            .collect())
+    }
+
+    async fn get_script_revision(
+        &self,
+        guild_id: GuildId,
+        script_id: u64,
+        revision: u32,
+    ) -> StoreResult<ScriptRevision, Self::Error> {
+        let res = sqlx::query!(
+            "SELECT script_id, revision, author_id, created_at, original_source, enabled, \
+             contributes FROM guild_script_revisions WHERE guild_id = $1 AND script_id = $2 AND \
+             revision = $3;",
+            guild_id.get() as i64,
+            script_id as i64,
+            revision as i32,
+        )
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or(ConfigStoreError::RevisionNotFound)?;
+
+        Ok(ScriptRevision {
+            script_id: res.script_id as u64,
+            revision: res.revision as u32,
+            author_id: res.author_id.and_then(|id| UserId::new(id as u64)),
+            created_at: res.created_at,
+            original_source: res.original_source,
+            enabled: res.enabled,
+            contributes: serde_json::from_value(res.contributes).unwrap_or_default(),
+        })
+    }
+
+    async fn publish_library_module(
+        &self,
+        module: CreateLibraryModule,
+    ) -> StoreResult<LibraryModule, Self::Error> {
+        let mut tx = self.pool.begin().await?;
+
+        if let Some(author_id) = module.author_id {
+            sqlx::query!(
+                "INSERT INTO library_module_owners (name, owner_id) VALUES ($1, $2) ON CONFLICT \
+                 DO NOTHING;",
+                module.name,
+                author_id.get() as i64,
+            )
+            .execute(&mut tx)
+            .await?;
+        }
+
+        let owner = sqlx::query!(
+            "SELECT owner_id FROM library_module_owners WHERE name = $1 FOR UPDATE;",
+            module.name,
+        )
+        .fetch_optional(&mut tx)
+        .await?;
+
+        if let Some(owner) = owner {
+            if module.author_id.map(|id| id.get() as i64) != Some(owner.owner_id) {
+                return Err(ConfigStoreError::LibraryNotOwned);
+            }
+        }
+
+        let res = sqlx::query_as!(
+            DbLibraryModule,
+            "INSERT INTO library_modules (name, version, author_id, created_at, original_source, \
+             compiled_source) VALUES ($1, $2, $3, now(), $4, $5) ON CONFLICT DO NOTHING \
+             RETURNING name, version, author_id, created_at, original_source, compiled_source;",
+            module.name,
+            module.version,
+            module.author_id.map(|id| id.get() as i64),
+            module.original_source,
+            module.compiled_source,
+        )
+        .fetch_optional(&mut tx)
+        .await?
+        .ok_or(ConfigStoreError::LibraryVersionExists)?;
+
+        tx.commit().await?;
+        Ok(res.into())
+    }
+
+    async fn get_library_module(
+        &self,
+        name: &str,
+        version: &str,
+    ) -> StoreResult<LibraryModule, Self::Error> {
+        let res = sqlx::query_as!(
+            DbLibraryModule,
+            "SELECT name, version, author_id, created_at, original_source, compiled_source FROM \
+             library_modules WHERE name = $1 AND version = $2;",
+            name,
+            version,
+        )
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or(ConfigStoreError::LibraryNotFound)?;
+
+        Ok(res.into())
+    }
+
+    async fn list_library_module_versions(
+        &self,
+        name: &str,
+    ) -> StoreResult<Vec<LibraryModuleSummary>, Self::Error> {
+        let res = sqlx::query!(
+            "SELECT name, version, author_id, created_at FROM library_modules WHERE name = $1 \
+             ORDER BY created_at DESC;",
+            name,
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(res
+            .into_iter()
+            .map(|r| LibraryModuleSummary {
+                name: r.name,
+                version: r.version,
+                author_id: r.author_id.and_then(|id| UserId::new(id as u64)),
+                created_at: r.created_at,
+            })
+            .collect())
+    }
+
+    async fn get_script_compile_cache(
+        &self,
+        guild_id: GuildId,
+        script_id: u64,
+        source_hash: &str,
+    ) -> StoreResult<Option<serde_json::Value>, Self::Error> {
+        let res = sqlx::query!(
+            "SELECT compiled_cache FROM guild_scripts WHERE guild_id = $1 AND id = $2 AND \
+             compiled_cache_hash = $3;",
+            guild_id.0.get() as i64,
+            script_id as i64,
+            source_hash,
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(res.and_then(|r| r.compiled_cache))
+    }
+
+    async fn set_script_compile_cache(
+        &self,
+        guild_id: GuildId,
+        script_id: u64,
+        source_hash: &str,
+        compiled: serde_json::Value,
+    ) -> StoreResult<(), Self::Error> {
+        sqlx::query!(
+            "UPDATE guild_scripts SET compiled_cache_hash = $3, compiled_cache = $4 WHERE \
+             guild_id = $1 AND id = $2;",
+            guild_id.0.get() as i64,
+            script_id as i64,
+            source_hash,
+            compiled,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
     async fn get_guild_meta_config(
         &self,
         guild_id: GuildId,
     ) -> StoreResult<Option<GuildMetaConfig>, Self::Error> {
         match sqlx::query_as!(
             DbGuildMetaConfig,
-            "SELECT guild_id, error_channel_id FROM guild_meta_configs
-        WHERE guild_id = $1;",
+            "SELECT guild_id, error_channel_id, command_conflict_policy, fetch_allowed_domains \
+             FROM guild_meta_configs WHERE guild_id = $1;",
             guild_id.0.get() as i64,
         )
         .fetch_one(&self.pool)
@@ -253,21 +583,239 @@ impl crate::config::ConfigStore for Postgres {
         &self,
         conf: &GuildMetaConfig,
     ) -> StoreResult<GuildMetaConfig, Self::Error> {
-        let db_conf = sqlx::query_as!(
-            DbGuildMetaConfig,
-            "INSERT INTO guild_meta_configs (guild_id, error_channel_id) VALUES ($1, $2)
+        let db_conf = Self::upsert_guild_meta_config(&self.pool, conf).await?;
+
+        Ok(db_conf.into())
+    }
+
+    async fn get_guild_command_sync_status(
+        &self,
+        guild_id: GuildId,
+    ) -> StoreResult<Option<GuildCommandSyncStatus>, Self::Error> {
+        let res = sqlx::query_as!(
+            DbGuildCommandSyncStatus,
+            "SELECT guild_id, state, failed_attempts, last_error, last_synced_at, updated_at \
+             FROM guild_command_sync_status WHERE guild_id = $1;",
+            guild_id.0.get() as i64,
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(res.map(Into::into))
+    }
+
+    async fn set_guild_command_sync_status(
+        &self,
+        status: &GuildCommandSyncStatus,
+    ) -> StoreResult<(), Self::Error> {
+        sqlx::query!(
+            "INSERT INTO guild_command_sync_status (guild_id, state, failed_attempts, \
+             last_error, last_synced_at, updated_at) VALUES ($1, $2, $3, $4, $5, $6)
             ON CONFLICT (guild_id) DO UPDATE SET
-            error_channel_id = $2
-            RETURNING guild_id, error_channel_id;",
-            conf.guild_id.0.get() as i64,
-            conf.error_channel_id
-                .map(|e| e.0.get() as i64)
-                .unwrap_or_default(),
+            state = $2,
+            failed_attempts = $3,
+            last_error = $4,
+            last_synced_at = COALESCE($5, guild_command_sync_status.last_synced_at),
+            updated_at = $6;",
+            status.guild_id.0.get() as i64,
+            status.state.as_str(),
+            status.failed_attempts as i32,
+            status.last_error,
+            status.last_synced_at,
+            status.updated_at,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn get_guild_access_rules(
+        &self,
+        guild_id: GuildId,
+    ) -> StoreResult<Vec<GuildAccessRule>, Self::Error> {
+        let res = sqlx::query_as!(
+            DbGuildAccessRule,
+            "SELECT subject_kind, subject_id, permissions FROM guild_access_rules WHERE guild_id \
+             = $1 ORDER BY subject_kind, subject_id;",
+            guild_id.0.get() as i64,
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(res
+            .into_iter()
+            .filter_map(DbGuildAccessRule::into_rule)
+            .collect())
+    }
+
+    async fn set_guild_access_rules(
+        &self,
+        guild_id: GuildId,
+        rules: Vec<GuildAccessRule>,
+    ) -> StoreResult<Vec<GuildAccessRule>, Self::Error> {
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query!(
+            "DELETE FROM guild_access_rules WHERE guild_id = $1;",
+            guild_id.0.get() as i64,
+        )
+        .execute(&mut tx)
+        .await?;
+
+        for rule in &rules {
+            let (subject_kind, subject_id) = match rule.subject {
+                GuildAccessSubject::Role(id) => (1i16, id.0.get() as i64),
+                GuildAccessSubject::User(id) => (2i16, id.0.get() as i64),
+            };
+
+            sqlx::query!(
+                "INSERT INTO guild_access_rules (guild_id, subject_kind, subject_id, permissions) \
+                 VALUES ($1, $2, $3, $4)
+                ON CONFLICT (guild_id, subject_kind, subject_id) DO UPDATE SET
+                permissions = $4;",
+                guild_id.0.get() as i64,
+                subject_kind,
+                subject_id,
+                &rule
+                    .permissions
+                    .iter()
+                    .map(|p| p.as_str().to_string())
+                    .collect::<Vec<_>>(),
+            )
+            .execute(&mut tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+
+        self.get_guild_access_rules(guild_id).await
+    }
+
+    async fn list_guild_webhooks(
+        &self,
+        guild_id: GuildId,
+    ) -> StoreResult<Vec<GuildWebhook>, Self::Error> {
+        let res = sqlx::query_as!(
+            DbGuildWebhook,
+            "SELECT guild_id, script_id, name, secret, created_at FROM guild_webhooks WHERE \
+             guild_id = $1 ORDER BY script_id, name;",
+            guild_id.0.get() as i64,
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(res.into_iter().map(Into::into).collect())
+    }
+
+    async fn get_guild_webhook(
+        &self,
+        guild_id: GuildId,
+        script_id: u64,
+        name: &str,
+    ) -> StoreResult<Option<GuildWebhook>, Self::Error> {
+        let res = sqlx::query_as!(
+            DbGuildWebhook,
+            "SELECT guild_id, script_id, name, secret, created_at FROM guild_webhooks WHERE \
+             guild_id = $1 AND script_id = $2 AND name = $3;",
+            guild_id.0.get() as i64,
+            script_id as i64,
+            name,
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(res.map(Into::into))
+    }
+
+    async fn set_guild_webhook(
+        &self,
+        guild_id: GuildId,
+        script_id: u64,
+        name: &str,
+        secret: &str,
+    ) -> StoreResult<GuildWebhook, Self::Error> {
+        let res = sqlx::query_as!(
+            DbGuildWebhook,
+            "INSERT INTO guild_webhooks (guild_id, script_id, name, secret) VALUES ($1, $2, $3, \
+             $4)
+            ON CONFLICT (guild_id, script_id, name) DO UPDATE SET
+            secret = $4, created_at = now()
+            RETURNING guild_id, script_id, name, secret, created_at;",
+            guild_id.0.get() as i64,
+            script_id as i64,
+            name,
+            secret,
         )
         .fetch_one(&self.pool)
         .await?;
 
-        Ok(db_conf.into())
+        Ok(res.into())
+    }
+
+    async fn del_guild_webhook(
+        &self,
+        guild_id: GuildId,
+        script_id: u64,
+        name: &str,
+    ) -> StoreResult<bool, Self::Error> {
+        let res = sqlx::query!(
+            "DELETE FROM guild_webhooks WHERE guild_id = $1 AND script_id = $2 AND name = $3;",
+            guild_id.0.get() as i64,
+            script_id as i64,
+            name,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(res.rows_affected() > 0)
+    }
+
+    async fn add_audit_log_entry(
+        &self,
+        guild_id: GuildId,
+        entry: CreateAuditLogEntry,
+    ) -> StoreResult<AuditLogEntry, Self::Error> {
+        let res = sqlx::query_as!(
+            DbAuditLogEntry,
+            "INSERT INTO guild_audit_log (guild_id, actor_id, source, action, target, before, \
+             after) VALUES ($1, $2, $3, $4, $5, $6, $7)
+            RETURNING id, guild_id, actor_id, source, action, target, before, after, created_at;",
+            guild_id.0.get() as i64,
+            entry.actor_id.0.get() as i64,
+            entry.source.as_str(),
+            entry.action.as_str(),
+            entry.target,
+            entry.before,
+            entry.after,
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(res.into_entry().unwrap())
+    }
+
+    async fn list_audit_log_entries(
+        &self,
+        guild_id: GuildId,
+        before: Option<u64>,
+        limit: u32,
+    ) -> StoreResult<Vec<AuditLogEntry>, Self::Error> {
+        let res = sqlx::query_as!(
+            DbAuditLogEntry,
+            "SELECT id, guild_id, actor_id, source, action, target, before, after, created_at \
+             FROM guild_audit_log WHERE guild_id = $1 AND id < $2 ORDER BY id DESC LIMIT $3;",
+            guild_id.0.get() as i64,
+            before.map(|id| id as i64).unwrap_or(i64::MAX),
+            limit as i64,
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(res
+            .into_iter()
+            .filter_map(DbAuditLogEntry::into_entry)
+            .collect())
     }
 
     async fn add_update_joined_guild(
@@ -329,6 +877,63 @@ impl crate::config::ConfigStore for Postgres {
 
         Ok(result.count.unwrap_or_default() > 0)
     }
+
+    async fn list_joined_guilds(
+        &self,
+        after: Option<GuildId>,
+        limit: u32,
+    ) -> StoreResult<Vec<JoinedGuild>, Self::Error> {
+        let guilds = sqlx::query_as!(
+            DbJoinedGuild,
+            "SELECT id, name, icon, owner_id FROM joined_guilds WHERE id > $1 ORDER BY id ASC \
+             LIMIT $2;",
+            after.map(|id| id.get() as i64).unwrap_or(0),
+            limit as i64,
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(guilds.into_iter().map(|e| e.into()).collect())
+    }
+
+    async fn list_whitelisted_guilds(&self) -> StoreResult<Vec<WhitelistedGuild>, Self::Error> {
+        let res = sqlx::query_as!(
+            DbWhitelistedGuild,
+            "SELECT guild_id, created_at FROM guild_whitelist ORDER BY created_at ASC;",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(res.into_iter().map(Into::into).collect())
+    }
+
+    async fn add_guild_whitelist(
+        &self,
+        guild_id: GuildId,
+    ) -> StoreResult<WhitelistedGuild, Self::Error> {
+        let res = sqlx::query_as!(
+            DbWhitelistedGuild,
+            "INSERT INTO guild_whitelist (guild_id) VALUES ($1)
+            ON CONFLICT (guild_id) DO UPDATE SET guild_id = excluded.guild_id
+            RETURNING guild_id, created_at;",
+            guild_id.get() as i64,
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(res.into())
+    }
+
+    async fn del_guild_whitelist(&self, guild_id: GuildId) -> StoreResult<bool, Self::Error> {
+        let res = sqlx::query!(
+            "DELETE FROM guild_whitelist WHERE guild_id = $1;",
+            guild_id.get() as i64,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(res.rows_affected() > 0)
+    }
 }
 
 #[allow(dead_code)]
@@ -340,6 +945,7 @@ struct DbScript {
     enabled: bool,
     contributes_commands: serde_json::Value,
     contributes_interval_timers: serde_json::Value,
+    contributes_storage_buckets: serde_json::Value,
 }
 
 impl From<DbScript> for Script {
@@ -347,6 +953,8 @@ impl From<DbScript> for Script {
         let commands_dec = serde_json::from_value(script.contributes_commands).unwrap_or_default();
         let intervals_dec =
             serde_json::from_value(script.contributes_interval_timers).unwrap_or_default();
+        let buckets_dec =
+            serde_json::from_value(script.contributes_storage_buckets).unwrap_or_default();
 
         Self {
             id: script.id as u64,
@@ -356,14 +964,39 @@ impl From<DbScript> for Script {
             contributes: ScriptContributes {
                 commands: commands_dec,
                 interval_timers: intervals_dec,
+                storage_buckets: buckets_dec,
             },
         }
     }
 }
 
-struct DbGuildMetaConfig {
+struct DbLibraryModule {
+    name: String,
+    version: String,
+    author_id: Option<i64>,
+    created_at: chrono::DateTime<chrono::Utc>,
+    original_source: String,
+    compiled_source: String,
+}
+
+impl From<DbLibraryModule> for LibraryModule {
+    fn from(module: DbLibraryModule) -> Self {
+        Self {
+            name: module.name,
+            version: module.version,
+            author_id: module.author_id.and_then(|id| UserId::new(id as u64)),
+            created_at: module.created_at,
+            original_source: module.original_source,
+            compiled_source: module.compiled_source,
+        }
+    }
+}
+
+pub(crate) struct DbGuildMetaConfig {
     pub guild_id: i64,
     pub error_channel_id: i64,
+    pub command_conflict_policy: String,
+    pub fetch_allowed_domains: Vec<String>,
 }
 
 impl From<DbGuildMetaConfig> for GuildMetaConfig {
@@ -375,6 +1008,120 @@ impl From<DbGuildMetaConfig> for GuildMetaConfig {
             } else {
                 None
             },
+            command_conflict_policy: CommandConflictPolicy::from_str_or_default(
+                &mc.command_conflict_policy,
+            ),
+            fetch_allowed_domains: mc.fetch_allowed_domains,
+        }
+    }
+}
+
+struct DbGuildAccessRule {
+    subject_kind: i16,
+    subject_id: i64,
+    permissions: Vec<String>,
+}
+
+impl DbGuildAccessRule {
+    fn into_rule(self) -> Option<GuildAccessRule> {
+        let subject = match self.subject_kind {
+            1 => GuildAccessSubject::Role(RoleId::new(self.subject_id as u64)?),
+            2 => GuildAccessSubject::User(UserId::new(self.subject_id as u64)?),
+            _ => return None,
+        };
+
+        Some(GuildAccessRule {
+            subject,
+            permissions: self
+                .permissions
+                .iter()
+                .filter_map(|p| GuildAccessPermission::from_name(p))
+                .collect(),
+        })
+    }
+}
+
+struct DbGuildWebhook {
+    guild_id: i64,
+    script_id: i64,
+    name: String,
+    secret: String,
+    created_at: chrono::DateTime<chrono::Utc>,
+}
+
+impl From<DbGuildWebhook> for GuildWebhook {
+    fn from(w: DbGuildWebhook) -> Self {
+        Self {
+            guild_id: GuildId::new(w.guild_id as u64).unwrap(),
+            script_id: w.script_id as u64,
+            name: w.name,
+            secret: w.secret,
+            created_at: w.created_at,
+        }
+    }
+}
+
+struct DbWhitelistedGuild {
+    guild_id: i64,
+    created_at: chrono::DateTime<chrono::Utc>,
+}
+
+impl From<DbWhitelistedGuild> for WhitelistedGuild {
+    fn from(g: DbWhitelistedGuild) -> Self {
+        Self {
+            guild_id: GuildId::new(g.guild_id as u64).unwrap(),
+            created_at: g.created_at,
+        }
+    }
+}
+
+struct DbAuditLogEntry {
+    id: i64,
+    guild_id: i64,
+    actor_id: i64,
+    source: String,
+    action: String,
+    target: Option<String>,
+    before: Option<serde_json::Value>,
+    after: Option<serde_json::Value>,
+    created_at: chrono::DateTime<chrono::Utc>,
+}
+
+impl DbAuditLogEntry {
+    /// Returns None for entries with a source or action this version doesn't know about
+    fn into_entry(self) -> Option<AuditLogEntry> {
+        Some(AuditLogEntry {
+            id: self.id as u64,
+            guild_id: GuildId::new(self.guild_id as u64)?,
+            actor_id: UserId::new(self.actor_id as u64)?,
+            source: AuditLogSource::from_name(&self.source)?,
+            action: AuditLogAction::from_name(&self.action)?,
+            target: self.target,
+            before: self.before,
+            after: self.after,
+            created_at: self.created_at,
+        })
+    }
+}
+
+struct DbGuildCommandSyncStatus {
+    guild_id: i64,
+    state: String,
+    failed_attempts: i32,
+    last_error: Option<String>,
+    last_synced_at: Option<chrono::DateTime<chrono::Utc>>,
+    updated_at: chrono::DateTime<chrono::Utc>,
+}
+
+impl From<DbGuildCommandSyncStatus> for GuildCommandSyncStatus {
+    fn from(s: DbGuildCommandSyncStatus) -> Self {
+        Self {
+            guild_id: GuildId::new(s.guild_id as u64).unwrap(),
+            state: CommandSyncState::from_str_or_default(&s.state),
+            failed_attempts: s.failed_attempts as u32,
+            last_error: s.last_error,
+            last_synced_at: s.last_synced_at,
+            updated_at: s.updated_at,
         }
     }
 }
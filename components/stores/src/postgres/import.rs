@@ -0,0 +1,127 @@
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use twilight_model::id::GuildId;
+
+use super::{config::GUILD_SCRIPT_COUNT_LIMIT, Postgres};
+use crate::{
+    config::{ConfigStoreError, CreateScript, StoreResult, UpdateScript},
+    import::GuildImport,
+    timers::IntervalTimer,
+};
+
+#[async_trait]
+impl crate::import::ImportStore for Postgres {
+    type Error = sqlx::Error;
+
+    async fn apply_guild_import(
+        &self,
+        guild_id: GuildId,
+        import: GuildImport,
+    ) -> StoreResult<(), Self::Error> {
+        let mut tx = self.pool.begin().await?;
+
+        // locked so that scripts created in the meantime can't take us over the limit
+        let existing = sqlx::query!(
+            "SELECT id, name FROM guild_scripts WHERE guild_id = $1 FOR UPDATE;",
+            guild_id.get() as i64,
+        )
+        .fetch_all(&mut tx)
+        .await?
+        .into_iter()
+        .map(|row| (row.name, row.id as u64))
+        .collect::<HashMap<_, _>>();
+
+        let count = existing.len()
+            + import
+                .scripts
+                .iter()
+                .filter(|s| !existing.contains_key(&s.name))
+                .count();
+        if count as i64 > GUILD_SCRIPT_COUNT_LIMIT {
+            return Err(ConfigStoreError::GuildScriptLimitReached(
+                count as u64,
+                GUILD_SCRIPT_COUNT_LIMIT as u64,
+            ));
+        }
+
+        let mut script_ids = HashMap::new();
+        for script in import.scripts {
+            let written = match existing.get(&script.name) {
+                Some(id) => {
+                    Self::update_script_tx(
+                        &mut tx,
+                        guild_id,
+                        UpdateScript {
+                            id: *id,
+                            name: script.name,
+                            original_source: script.original_source,
+                            enabled: script.enabled,
+                            contributes: Some(script.contributes),
+                            author_id: None,
+                        },
+                    )
+                    .await?
+                }
+                None => {
+                    let created = Self::create_script_tx(
+                        &mut tx,
+                        guild_id,
+                        CreateScript {
+                            name: script.name,
+                            original_source: script.original_source,
+                            enabled: script.enabled,
+                            author_id: None,
+                        },
+                    )
+                    .await?;
+
+                    Self::update_script_contributes_tx(
+                        &mut tx,
+                        guild_id,
+                        created.id,
+                        script.contributes,
+                    )
+                    .await?
+                }
+            };
+
+            script_ids.insert(written.name, written.id);
+        }
+
+        for timer in import.interval_timers {
+            if let Some(script_id) = script_ids.get(&timer.script_name) {
+                Self::upsert_interval_timer(
+                    &mut tx,
+                    guild_id,
+                    IntervalTimer {
+                        name: timer.name,
+                        script_id: *script_id,
+                        interval: timer.interval,
+                        last_run: timer.last_run,
+                    },
+                )
+                .await?;
+            }
+        }
+
+        for entry in import.storage_entries {
+            Self::set_entry(
+                &mut tx,
+                guild_id,
+                entry.bucket,
+                entry.key,
+                entry.value,
+                entry.expires_at,
+            )
+            .await?;
+        }
+
+        if let Some(meta_config) = &import.meta_config {
+            Self::upsert_guild_meta_config(&mut tx, meta_config).await?;
+        }
+
+        tx.commit().await?;
+        Ok(())
+    }
+}
@@ -2,6 +2,8 @@ use sqlx::{postgres::PgPoolOptions, PgPool};
 
 pub mod bucketstore;
 pub mod config;
+pub mod import;
+pub mod nodes;
 pub mod timers;
 pub mod web;
 
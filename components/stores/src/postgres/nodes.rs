@@ -0,0 +1,79 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use chrono::Utc;
+
+use crate::nodes::{BotNode, ShardRange};
+
+use super::Postgres;
+
+#[async_trait]
+impl crate::nodes::BotNodeStore for Postgres {
+    type Error = sqlx::Error;
+
+    async fn heartbeat_bot_node(&self, node: &BotNode) -> Result<(), Self::Error> {
+        sqlx::query!(
+            "INSERT INTO bot_nodes (rpc_addr, shard_range_start, shard_range_end, total_shards, \
+             started_at, last_heartbeat_at)
+            VALUES ($1, $2, $3, $4, now(), now())
+            ON CONFLICT (rpc_addr)
+            DO UPDATE SET
+            shard_range_start = $2,
+            shard_range_end = $3,
+            total_shards = $4,
+            last_heartbeat_at = now();",
+            node.rpc_addr,
+            node.shards.start as i64,
+            node.shards.end as i64,
+            node.shards.total as i64,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn del_bot_node(&self, rpc_addr: &str) -> Result<bool, Self::Error> {
+        let res = sqlx::query!("DELETE FROM bot_nodes WHERE rpc_addr = $1;", rpc_addr)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(res.rows_affected() > 0)
+    }
+
+    async fn get_active_bot_nodes(&self, max_age: Duration) -> Result<Vec<BotNode>, Self::Error> {
+        let oldest = Utc::now() - chrono::Duration::from_std(max_age).unwrap();
+
+        let res = sqlx::query_as!(
+            DbBotNode,
+            "SELECT rpc_addr, shard_range_start, shard_range_end, total_shards
+            FROM bot_nodes WHERE last_heartbeat_at > $1
+            ORDER BY shard_range_start;",
+            oldest,
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(res.into_iter().map(Into::into).collect())
+    }
+}
+
+struct DbBotNode {
+    rpc_addr: String,
+    shard_range_start: i64,
+    shard_range_end: i64,
+    total_shards: i64,
+}
+
+impl From<DbBotNode> for BotNode {
+    fn from(node: DbBotNode) -> Self {
+        Self {
+            rpc_addr: node.rpc_addr,
+            shards: ShardRange {
+                start: node.shard_range_start as u64,
+                end: node.shard_range_end as u64,
+                total: node.total_shards as u64,
+            },
+        }
+    }
+}
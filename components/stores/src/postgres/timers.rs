@@ -16,42 +16,21 @@ pub enum Error {
     Sql(#[from] sqlx::Error),
 }
 
-#[async_trait]
-impl crate::timers::TimerStore for Postgres {
-    type Error = Error;
-
-    async fn get_all_interval_timers(
-        &self,
-        guild_id: GuildId,
-    ) -> StoreResult<Vec<IntervalTimer>, Self::Error> {
-        let res = sqlx::query_as!(
-            DbIntervalTimer,
-            "SELECT guild_id, script_id, timer_name, interval_minutes, interval_cron, \
-             last_run_at, created_at, updated_at
-            FROM interval_timers WHERE guild_id=$1;",
-            guild_id.get() as i64,
-        )
-        .fetch_all(&self.pool)
-        .await
-        .map_err(|v| TimerStoreError::Other(v.into()))?;
-
-        Ok(res
-            .into_iter()
-            .filter_map(|v| IntervalTimer::try_from(v).ok())
-            .collect())
-    }
-
-    async fn update_interval_timer(
-        &self,
+impl Postgres {
+    pub(crate) async fn upsert_interval_timer<'c, E>(
+        executor: E,
         guild_id: GuildId,
         timer: IntervalTimer,
-    ) -> StoreResult<IntervalTimer, Self::Error> {
+    ) -> Result<DbIntervalTimer, sqlx::Error>
+    where
+        E: sqlx::Executor<'c, Database = sqlx::Postgres>,
+    {
         let (interval_minutes, interval_cron) = match timer.interval {
             IntervalType::Minutes(m) => (Some(m as i32), None),
             IntervalType::Cron(c) => (None, Some(c)),
         };
 
-        let res = sqlx::query_as!(
+        sqlx::query_as!(
             DbIntervalTimer,
             "
             INSERT INTO interval_timers (guild_id, script_id, timer_name, interval_minutes, \
@@ -73,10 +52,45 @@ impl crate::timers::TimerStore for Postgres {
             interval_cron,
             timer.last_run,
         )
-        .fetch_one(&self.pool)
+        .fetch_one(executor)
+        .await
+    }
+}
+
+#[async_trait]
+impl crate::timers::TimerStore for Postgres {
+    type Error = Error;
+
+    async fn get_all_interval_timers(
+        &self,
+        guild_id: GuildId,
+    ) -> StoreResult<Vec<IntervalTimer>, Self::Error> {
+        let res = sqlx::query_as!(
+            DbIntervalTimer,
+            "SELECT guild_id, script_id, timer_name, interval_minutes, interval_cron, \
+             last_run_at, created_at, updated_at
+            FROM interval_timers WHERE guild_id=$1;",
+            guild_id.get() as i64,
+        )
+        .fetch_all(&self.pool)
         .await
         .map_err(|v| TimerStoreError::Other(v.into()))?;
 
+        Ok(res
+            .into_iter()
+            .filter_map(|v| IntervalTimer::try_from(v).ok())
+            .collect())
+    }
+
+    async fn update_interval_timer(
+        &self,
+        guild_id: GuildId,
+        timer: IntervalTimer,
+    ) -> StoreResult<IntervalTimer, Self::Error> {
+        let res = Self::upsert_interval_timer(&self.pool, guild_id, timer)
+            .await
+            .map_err(|v| TimerStoreError::Other(v.into()))?;
+
         Ok(IntervalTimer::try_from(res)?)
     }
 
@@ -100,7 +114,7 @@ impl crate::timers::TimerStore for Postgres {
     }
 }
 
-struct DbIntervalTimer {
+pub(crate) struct DbIntervalTimer {
     guild_id: i64,
     script_id: i64,
     timer_name: String,
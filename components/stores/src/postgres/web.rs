@@ -1,4 +1,4 @@
-use crate::web::{gen_token, DiscordOauthToken, Session, SessionType};
+use crate::web::{gen_token, ApiKeyScope, DiscordOauthToken, Session, SessionType};
 
 use super::Postgres;
 use async_trait::async_trait;
@@ -30,6 +30,64 @@ impl Postgres {
 
         Ok(result.count.unwrap_or_default())
     }
+
+    async fn insert_session(
+        &self,
+        user: CurrentUser,
+        kind: SessionType,
+        scope: Option<ApiKeyScope>,
+        expires_at: Option<chrono::DateTime<chrono::Utc>>,
+    ) -> Result<Session, Error> {
+        if matches!(kind, SessionType::ApiKey) {
+            let count = self.get_api_key_count(user.id).await?;
+            if count > USER_API_KEY_LIMIT {
+                return Err(Error::ApiKeyLimitReached(
+                    count as u64,
+                    USER_API_KEY_LIMIT as u64,
+                ));
+            }
+        }
+
+        let oauth_token = sqlx::query_as!(
+            DbOauthToken,
+            "SELECT user_id, discord_bearer_token, discord_refresh_token, discord_token_expires_at
+            FROM discord_oauth_tokens WHERE user_id = $1",
+            user.id.get() as i64,
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        let token = gen_token();
+
+        let resp = sqlx::query_as!(
+            DbSession,
+            "INSERT INTO web_sessions (token, kind, user_id, discriminator, username, avatar, \
+             created_at, scope, expires_at) VALUES ($1, $2, $3, $4, $5, $6, now(), $7, $8)
+            RETURNING token, kind, user_id, discriminator, username, avatar, created_at, scope, \
+             expires_at, last_used_at;",
+            &token,
+            i16::from(kind),
+            user.id.get() as i64,
+            user.discriminator as i16,
+            user.name,
+            user.avatar,
+            scope.as_ref().map(|s| serde_json::to_value(s).unwrap()),
+            expires_at,
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(Session {
+            oauth_token: oauth_token.into(),
+            created_at: resp.created_at,
+            token,
+            kind,
+            user,
+            scope,
+            expires_at,
+            last_used_at: None,
+        })
+    }
 }
 
 #[async_trait]
@@ -76,49 +134,17 @@ impl crate::web::SessionStore for Postgres {
         user: CurrentUser,
         kind: SessionType,
     ) -> Result<Session, Self::Error> {
-        if matches!(kind, SessionType::ApiKey) {
-            let count = self.get_api_key_count(user.id).await?;
-            if count > USER_API_KEY_LIMIT {
-                return Err(Error::ApiKeyLimitReached(
-                    count as u64,
-                    USER_API_KEY_LIMIT as u64,
-                ));
-            }
-        }
-
-        let oauth_token = sqlx::query_as!(
-            DbOauthToken,
-            "SELECT user_id, discord_bearer_token, discord_refresh_token, discord_token_expires_at
-            FROM discord_oauth_tokens WHERE user_id = $1",
-            user.id.get() as i64,
-        )
-        .fetch_one(&self.pool)
-        .await?;
-
-        let token = gen_token();
-
-        let resp = sqlx::query_as!(
-            DbSession,
-            "INSERT INTO web_sessions (token, kind, user_id, discriminator, username, avatar, \
-             created_at) VALUES ($1, $2, $3, $4, $5, $6, now())
-            RETURNING token, kind, user_id, discriminator, username, avatar, created_at;",
-            &token,
-            i16::from(kind),
-            user.id.get() as i64,
-            user.discriminator as i16,
-            user.name,
-            user.avatar,
-        )
-        .fetch_one(&self.pool)
-        .await?;
+        self.insert_session(user, kind, None, None).await
+    }
 
-        Ok(Session {
-            oauth_token: oauth_token.into(),
-            created_at: resp.created_at,
-            token,
-            kind,
-            user,
-        })
+    async fn create_api_key(
+        &self,
+        user: CurrentUser,
+        scope: Option<ApiKeyScope>,
+        expires_at: Option<chrono::DateTime<chrono::Utc>>,
+    ) -> Result<Session, Self::Error> {
+        self.insert_session(user, SessionType::ApiKey, scope, expires_at)
+            .await
     }
 
     async fn get_oauth_token(&self, user_id: UserId) -> Result<DiscordOauthToken, Self::Error> {
@@ -136,8 +162,9 @@ impl crate::web::SessionStore for Postgres {
     async fn get_session(&self, token: &str) -> Result<Option<Session>, Self::Error> {
         let session = match sqlx::query_as!(
             DbSession,
-            "SELECT token, kind, user_id, discriminator, username, avatar, created_at FROM \
-             web_sessions WHERE token = $1;",
+            "SELECT token, kind, user_id, discriminator, username, avatar, created_at, scope, \
+             expires_at, last_used_at FROM web_sessions WHERE token = $1 AND (expires_at IS NULL \
+             OR expires_at > now());",
             token
         )
         .fetch_one(&self.pool)
@@ -162,9 +189,23 @@ impl crate::web::SessionStore for Postgres {
             kind: SessionType::from(session.kind),
             oauth_token: oauth_token.into(),
             created_at: session.created_at,
+            scope: session.scope(),
+            expires_at: session.expires_at,
+            last_used_at: session.last_used_at,
             user: session.into(),
         }))
     }
+
+    async fn touch_session(&self, token: &str) -> Result<(), Self::Error> {
+        sqlx::query!(
+            "UPDATE web_sessions SET last_used_at = now() WHERE token = $1;",
+            token
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
     async fn get_all_sessions(&self, user_id: UserId) -> Result<Vec<Session>, Self::Error> {
         let oauth_token: DiscordOauthToken = sqlx::query_as!(
             DbOauthToken,
@@ -178,8 +219,8 @@ impl crate::web::SessionStore for Postgres {
 
         let sessions = sqlx::query_as!(
             DbSession,
-            "SELECT token, kind, user_id, discriminator, username, avatar, created_at FROM \
-             web_sessions WHERE user_id = $1",
+            "SELECT token, kind, user_id, discriminator, username, avatar, created_at, scope, \
+             expires_at, last_used_at FROM web_sessions WHERE user_id = $1",
             user_id.get() as i64,
         )
         .fetch_all(&self.pool)
@@ -192,6 +233,9 @@ impl crate::web::SessionStore for Postgres {
                 kind: e.kind.into(),
                 oauth_token: oauth_token.clone(),
                 created_at: e.created_at,
+                scope: e.scope(),
+                expires_at: e.expires_at,
+                last_used_at: e.last_used_at,
                 user: e.into(),
             })
             .collect())
@@ -243,6 +287,21 @@ struct DbSession {
     username: String,
     avatar: String,
     created_at: chrono::DateTime<chrono::Utc>,
+    scope: Option<serde_json::Value>,
+    expires_at: Option<chrono::DateTime<chrono::Utc>>,
+    last_used_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+impl DbSession {
+    fn scope(&self) -> Option<ApiKeyScope> {
+        parse_scope(self.scope.clone())
+    }
+}
+
+/// A scope that can't be parsed, e.g because of an unknown permission, allows nothing
+/// instead of being treated as unscoped
+fn parse_scope(value: Option<serde_json::Value>) -> Option<ApiKeyScope> {
+    value.map(|v| serde_json::from_value(v).unwrap_or_else(|_| ApiKeyScope::none()))
 }
 
 impl From<DbSession> for CurrentUser {
@@ -288,3 +347,27 @@ impl From<i16> for SessionType {
         }
     }
 }
+
+#[test]
+fn malformed_scope_allows_nothing() {
+    use crate::web::ApiKeyPermission;
+
+    assert_eq!(parse_scope(None), None);
+
+    let scope = parse_scope(Some(serde_json::json!({
+        "guild_ids": null,
+        "permissions": ["read_scripts"],
+    })))
+    .unwrap();
+    assert!(scope.allows(ApiKeyPermission::ReadScripts));
+
+    let unknown = parse_scope(Some(serde_json::json!({
+        "guild_ids": null,
+        "permissions": ["read_scripts", "everything"],
+    })));
+    assert_eq!(unknown, Some(ApiKeyScope::none()));
+
+    let malformed = parse_scope(Some(serde_json::json!("all"))).unwrap();
+    assert!(!malformed.allows_guild(twilight_model::id::GuildId::new(1).unwrap()));
+    assert!(!malformed.allows(ApiKeyPermission::ReadScripts));
+}
@@ -6,7 +6,10 @@ use oauth2::{
 };
 use rand::{thread_rng, Rng};
 use serde::{Deserialize, Serialize};
-use twilight_model::{id::UserId, user::CurrentUser};
+use twilight_model::{
+    id::{GuildId, UserId},
+    user::CurrentUser,
+};
 
 pub type OauthToken = StandardTokenResponse<EmptyExtraTokenFields, BasicTokenType>;
 
@@ -16,6 +19,50 @@ pub enum SessionType {
     ApiKey,
 }
 
+/// The things a scoped api key can be allowed to do
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ApiKeyPermission {
+    ReadScripts,
+    WriteScripts,
+    ReloadVm,
+    ReadLogs,
+    ReadStorage,
+    WriteStorage,
+}
+
+/// Restricts what an api key can be used for
+///
+/// Routes that don't fall under any of the permissions, like managing sessions, can't be used
+/// with a scoped key at all.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct ApiKeyScope {
+    /// The guilds the key can be used for, all the guilds the user can manage if not set
+    pub guild_ids: Option<Vec<GuildId>>,
+    pub permissions: Vec<ApiKeyPermission>,
+}
+
+impl ApiKeyScope {
+    /// A scope that doesn't allow anything
+    pub fn none() -> Self {
+        Self {
+            guild_ids: Some(Vec::new()),
+            permissions: Vec::new(),
+        }
+    }
+
+    pub fn allows_guild(&self, guild_id: GuildId) -> bool {
+        match &self.guild_ids {
+            Some(ids) => ids.contains(&guild_id),
+            None => true,
+        }
+    }
+
+    pub fn allows(&self, permission: ApiKeyPermission) -> bool {
+        self.permissions.contains(&permission)
+    }
+}
+
 #[async_trait]
 pub trait SessionStore {
     type Error: std::error::Error + Send + Sync;
@@ -38,8 +85,19 @@ pub trait SessionStore {
         kind: SessionType,
     ) -> Result<Session, Self::Error>;
 
+    /// Creates an api key session, restricted to `scope` if set
+    async fn create_api_key(
+        &self,
+        user: CurrentUser,
+        scope: Option<ApiKeyScope>,
+        expires_at: Option<chrono::DateTime<chrono::Utc>>,
+    ) -> Result<Session, Self::Error>;
+
     async fn get_oauth_token(&self, user_id: UserId) -> Result<DiscordOauthToken, Self::Error>;
+    /// Returns the session, expired sessions are treated as not found
     async fn get_session(&self, token: &str) -> Result<Option<Session>, Self::Error>;
+    /// Sets the last time the session was used to now
+    async fn touch_session(&self, token: &str) -> Result<(), Self::Error>;
     async fn get_all_sessions(&self, user_id: UserId) -> Result<Vec<Session>, Self::Error>;
     async fn del_session(&self, token: &str) -> Result<bool, Self::Error>;
     async fn del_all_sessions(&self, user_id: UserId) -> Result<(), Self::Error>;
@@ -65,6 +123,10 @@ pub struct Session {
     pub kind: SessionType,
     pub user: CurrentUser,
     pub created_at: chrono::DateTime<chrono::Utc>,
+    /// Only set for scoped api keys, other sessions have the full authority of the user
+    pub scope: Option<ApiKeyScope>,
+    pub expires_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub last_used_at: Option<chrono::DateTime<chrono::Utc>>,
 }
 
 #[derive(Clone, Serialize, Deserialize)]
@@ -1,12 +1,22 @@
 use std::{collections::HashMap, ops::Add, str::FromStr};
 
 use chrono::{DateTime, Duration, Utc};
+use lazy_static::lazy_static;
+use prometheus::{register_histogram, Histogram};
 use runtime_models::util::NotBigU64;
 use stores::timers::{IntervalTimer, IntervalType};
 use tokio::sync::mpsc;
 use twilight_model::id::GuildId;
 use vm::vm::VmCommand;
 
+lazy_static! {
+    static ref TIMER_FIRE_LAG: Histogram = register_histogram!(
+        "botloader_timer_fire_lag_seconds",
+        "Time between when an interval timer was scheduled to run and when it was fired"
+    )
+    .unwrap();
+}
+
 #[derive(Debug)]
 pub enum Error {
     StorageError(anyhow::Error),
@@ -118,6 +128,10 @@ impl<T: stores::timers::TimerStore + Send + Sync + 'static> Scheduler<T> {
 
         let serialized = serde_json::to_value(&evt).unwrap();
 
+        if let Ok(lag) = (t - timer.next_run).to_std() {
+            TIMER_FIRE_LAG.observe(lag.as_secs_f64());
+        }
+
         let delete = if let Some(g) = self.guilds.get(&guild_id) {
             match g.dispath_tx.send(VmCommand::DispatchEvent(
                 "BOTLOADER_INTERVAL_TIMER_FIRED",
@@ -0,0 +1,160 @@
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    convert::TryFrom,
+    sync::Mutex,
+};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{compile_typescript, CompiledItem};
+
+/// Included in the source hash, bump this whenever the compiler output changes
+/// so that previously cached (and persisted) output isn't used anymore
+const CACHE_VERSION: u32 = 1;
+
+/// Returns the key compiled output for this source is cached under
+pub fn source_hash(source: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(CACHE_VERSION.to_le_bytes());
+    hasher.update(source.as_bytes());
+    format!("{:x}", hasher.finalize())
+}
+
+/// In memory cache of compiled output, keyed by [source_hash]
+///
+/// Entries are stored in their serialized form as [CompiledItem] can't be shared between threads,
+/// once full the oldest entries are evicted first.
+pub struct CompileCache {
+    max_entries: usize,
+    inner: Mutex<CompileCacheInner>,
+}
+
+#[derive(Default)]
+struct CompileCacheInner {
+    entries: HashMap<String, SerializedCompiledItem>,
+    order: VecDeque<String>,
+}
+
+impl CompileCache {
+    pub fn new(max_entries: usize) -> Self {
+        Self {
+            max_entries,
+            inner: Mutex::new(Default::default()),
+        }
+    }
+
+    pub fn get(&self, source_hash: &str) -> Option<CompiledItem> {
+        let entry = self
+            .inner
+            .lock()
+            .unwrap()
+            .entries
+            .get(source_hash)
+            .cloned()?;
+        CompiledItem::try_from(entry).ok()
+    }
+
+    pub fn insert(&self, source_hash: String, compiled: &CompiledItem) {
+        if self.max_entries == 0 {
+            return;
+        }
+
+        let mut inner = self.inner.lock().unwrap();
+        if inner
+            .entries
+            .insert(source_hash.clone(), SerializedCompiledItem::from(compiled))
+            .is_some()
+        {
+            return;
+        }
+
+        inner.order.push_back(source_hash);
+        while inner.order.len() > self.max_entries {
+            if let Some(oldest) = inner.order.pop_front() {
+                inner.entries.remove(&oldest);
+            }
+        }
+    }
+
+    /// Returns the cached output for this source, compiling it if it's not in the cache
+    pub fn compile(&self, source: &str) -> Result<CompiledItem, String> {
+        let hash = source_hash(source);
+        if let Some(compiled) = self.get(&hash) {
+            return Ok(compiled);
+        }
+
+        let compiled = compile_typescript(source)?;
+        self.insert(hash, &compiled);
+        Ok(compiled)
+    }
+
+    pub fn len(&self) -> usize {
+        self.inner.lock().unwrap().entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Serializable form of [CompiledItem], used to persist compiled output
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SerializedCompiledItem {
+    pub output: String,
+    pub source_map: String,
+    pub imports: Vec<String>,
+    pub free_identifiers: HashSet<String>,
+}
+
+impl From<&CompiledItem> for SerializedCompiledItem {
+    fn from(compiled: &CompiledItem) -> Self {
+        let mut source_map = Vec::new();
+        compiled
+            .source_map
+            .to_writer(&mut source_map)
+            .expect("writing to a vec");
+
+        Self {
+            output: compiled.output.clone(),
+            source_map: String::from_utf8(source_map).unwrap_or_default(),
+            imports: compiled.imports.clone(),
+            free_identifiers: compiled.free_identifiers.clone(),
+        }
+    }
+}
+
+impl TryFrom<SerializedCompiledItem> for CompiledItem {
+    type Error = sourcemap::Error;
+
+    fn try_from(value: SerializedCompiledItem) -> Result<Self, Self::Error> {
+        Ok(Self {
+            output: value.output,
+            source_map: sourcemap::SourceMap::from_slice(value.source_map.as_bytes())?,
+            imports: value.imports,
+            free_identifiers: value.free_identifiers,
+        })
+    }
+}
+
+#[test]
+fn caches_compiled_output() {
+    let cache = CompileCache::new(1);
+    let a = cache.compile("const a: number = 1;").unwrap();
+    assert_eq!(cache.len(), 1);
+
+    let cached = cache.get(&source_hash("const a: number = 1;")).unwrap();
+    assert_eq!(cached.output, a.output);
+
+    // evicts the oldest entry
+    cache.compile("const b: number = 2;").unwrap();
+    assert_eq!(cache.len(), 1);
+    assert!(cache.get(&source_hash("const a: number = 1;")).is_none());
+
+    let restored = CompiledItem::try_from(SerializedCompiledItem::from(&a)).unwrap();
+    assert_eq!(restored.output, a.output);
+    assert_eq!(
+        restored.source_map.get_token_count(),
+        a.source_map.get_token_count()
+    );
+}
@@ -1,26 +1,35 @@
-use std::sync::{Arc, Mutex};
+use std::{
+    collections::HashSet,
+    sync::{Arc, Mutex},
+};
 
 use swc_common::{
     self, chain,
     errors::{Diagnostic, Emitter, Handler},
     sync::Lrc,
-    BytePos, FileName, Globals, LineCol, Mark, SourceMap,
+    BytePos, FileName, Globals, LineCol, Mark, SourceMap, SyntaxContext, DUMMY_SP,
 };
 // use swc_ecmascript::ast::Module;
 use swc_ecmascript::{
-    ast::EsVersion,
+    ast::{
+        BindingIdent, ClassDecl, EsVersion, Expr, ExprOrSuper, FnDecl, Ident,
+        ImportDefaultSpecifier, ImportNamedSpecifier, ImportStarAsSpecifier, Invalid, MemberExpr,
+        ModuleDecl, ModuleItem, Prop, TsEnumDecl,
+    },
     codegen::{text_writer::JsWriter, Emitter as CodeEmitter},
     parser::TsConfig,
     transforms::{
         compat::{self, es2020::export_namespace_from},
-        fixer, helpers, resolver_with_mark,
+        fixer, helpers,
+        resolver::ts_resolver,
+        resolver_with_mark,
         typescript::strip,
     },
 };
 use swc_ecmascript::{
     codegen::Config as CodeGenConfig,
     parser::{lexer::Lexer, Capturing, Parser, StringInput, Syntax},
-    visit::FoldWith,
+    visit::{FoldWith, Node, Visit, VisitWith},
 };
 
 pub fn compile_typescript(input: &str) -> Result<CompiledItem, String> {
@@ -70,6 +79,14 @@ fn compile_typescript_inner(input: &str) -> Result<CompiledItem, String> {
                 }
             };
 
+            // find the globals on the untransformed module, the passes below leave their own marks on identifiers
+            let top_level_mark = Mark::fresh(Mark::root());
+            let mut free_idents = FreeIdentCollector::new(top_level_mark);
+            module
+                .clone()
+                .fold_with(&mut ts_resolver(top_level_mark))
+                .visit_with(&Invalid { span: DUMMY_SP }, &mut free_idents);
+
             let mut pass = chain!(
                 compat::es2021::es2021(),
                 strip(),
@@ -104,11 +121,131 @@ fn compile_typescript_inner(input: &str) -> Result<CompiledItem, String> {
             Ok(CompiledItem {
                 output: String::from_utf8(result_buf).unwrap(),
                 source_map: proper_source_map,
+                imports: collect_imports(&module),
+                free_identifiers: free_idents.into_free_identifiers(),
             })
         })
     })
 }
 
+/// Returns the specifiers of all the static imports and re-exports in the module
+fn collect_imports(module: &swc_ecmascript::ast::Module) -> Vec<String> {
+    module
+        .body
+        .iter()
+        .filter_map(|item| match item {
+            ModuleItem::ModuleDecl(ModuleDecl::Import(import)) => {
+                Some(import.src.value.to_string())
+            }
+            ModuleItem::ModuleDecl(ModuleDecl::ExportAll(export)) => {
+                Some(export.src.value.to_string())
+            }
+            ModuleItem::ModuleDecl(ModuleDecl::ExportNamed(export)) => {
+                export.src.as_ref().map(|s| s.value.to_string())
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+/// Collects identifiers that are referenced but not declared anywhere in the module
+///
+/// The resolver marks both top level declarations and unresolved references with the top level mark,
+/// so the references that don't have a matching top level declaration are the free ones.
+struct FreeIdentCollector {
+    top_level_ctxt: SyntaxContext,
+    references: HashSet<String>,
+    declarations: HashSet<String>,
+}
+
+impl FreeIdentCollector {
+    fn new(top_level_mark: Mark) -> Self {
+        Self {
+            top_level_ctxt: SyntaxContext::empty().apply_mark(top_level_mark),
+            references: HashSet::new(),
+            declarations: HashSet::new(),
+        }
+    }
+
+    fn add_ref(&mut self, ident: &Ident) {
+        if ident.span.ctxt == self.top_level_ctxt {
+            self.references.insert(ident.sym.to_string());
+        }
+    }
+
+    fn add_decl(&mut self, ident: &Ident) {
+        if ident.span.ctxt == self.top_level_ctxt {
+            self.declarations.insert(ident.sym.to_string());
+        }
+    }
+
+    fn into_free_identifiers(self) -> HashSet<String> {
+        let declarations = self.declarations;
+        self.references
+            .into_iter()
+            .filter(|v| !declarations.contains(v))
+            .collect()
+    }
+}
+
+impl Visit for FreeIdentCollector {
+    fn visit_expr(&mut self, n: &Expr, _parent: &dyn Node) {
+        match n {
+            Expr::Ident(ident) => self.add_ref(ident),
+            _ => n.visit_children_with(self),
+        }
+    }
+
+    fn visit_member_expr(&mut self, n: &MemberExpr, _parent: &dyn Node) {
+        if let ExprOrSuper::Expr(obj) = &n.obj {
+            obj.visit_with(n, self);
+        }
+
+        // non computed properties are not references
+        if n.computed {
+            n.prop.visit_with(n, self);
+        }
+    }
+
+    fn visit_prop(&mut self, n: &Prop, _parent: &dyn Node) {
+        match n {
+            Prop::Shorthand(ident) => self.add_ref(ident),
+            _ => n.visit_children_with(self),
+        }
+    }
+
+    fn visit_binding_ident(&mut self, n: &BindingIdent, _parent: &dyn Node) {
+        self.add_decl(&n.id);
+    }
+
+    fn visit_fn_decl(&mut self, n: &FnDecl, _parent: &dyn Node) {
+        self.add_decl(&n.ident);
+        n.visit_children_with(self);
+    }
+
+    fn visit_class_decl(&mut self, n: &ClassDecl, _parent: &dyn Node) {
+        self.add_decl(&n.ident);
+        n.visit_children_with(self);
+    }
+
+    fn visit_ts_enum_decl(&mut self, n: &TsEnumDecl, _parent: &dyn Node) {
+        self.add_decl(&n.id);
+        n.visit_children_with(self);
+    }
+
+    fn visit_import_default_specifier(&mut self, n: &ImportDefaultSpecifier, _parent: &dyn Node) {
+        self.add_decl(&n.local);
+    }
+
+    fn visit_import_star_as_specifier(&mut self, n: &ImportStarAsSpecifier, _parent: &dyn Node) {
+        self.add_decl(&n.local);
+    }
+
+    fn visit_import_named_specifier(&mut self, n: &ImportNamedSpecifier, _parent: &dyn Node) {
+        self.add_decl(&n.local);
+    }
+}
+
 struct CollectingEmitter {
     messages: Arc<Mutex<Vec<Diagnostic>>>,
 }
@@ -148,4 +285,30 @@ impl std::io::Write for VecLocked {
 pub struct CompiledItem {
     pub output: String,
     pub source_map: sourcemap::SourceMap,
+    /// Specifiers of the modules this module imports from, type only imports are not included
+    pub imports: Vec<String>,
+    /// Globals referenced by this module, e.g `console`
+    pub free_identifiers: HashSet<String>,
+}
+
+#[test]
+fn collects_imports_and_free_identifiers() {
+    let compiled = compile_typescript(
+        r#"
+        import { a } from "./a";
+        import type { B } from "./b";
+        export * from "./c";
+        const local = 1;
+        const obj = { script, other: local };
+        console.log(a, obj.notFree as B);
+        "#,
+    )
+    .unwrap();
+
+    assert_eq!(compiled.imports, vec!["./a".to_string(), "./c".to_string()]);
+    assert!(compiled.free_identifiers.contains("script"));
+    assert!(compiled.free_identifiers.contains("console"));
+    assert!(!compiled.free_identifiers.contains("a"));
+    assert!(!compiled.free_identifiers.contains("local"));
+    assert!(!compiled.free_identifiers.contains("notFree"));
 }
@@ -0,0 +1,270 @@
+//! Type checking of scripts using an external typescript checker
+//!
+//! swc only strips the types, so this is done by spawning a tsc compatible process
+//! on the script together with the botloader typings.
+
+use std::{
+    fmt::Display,
+    io::Read,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, Instant},
+};
+
+static CHECK_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// Error codes for failing to find a module, see [TypeChecker::check]
+const TS_CANNOT_FIND_MODULE: u32 = 2307;
+
+#[derive(Debug, Clone)]
+pub struct TypeChecker {
+    /// The tsc compatible command to run, e.g `tsc`
+    pub command: String,
+
+    /// Directory with the generated botloader typings (see `components/runtime/src/ts/typedecls.sh`)
+    pub typings_dir: PathBuf,
+
+    pub timeout: Duration,
+}
+
+#[derive(Debug, Clone)]
+pub struct TypeDiagnostic {
+    pub line: u32,
+    pub column: u32,
+    pub code: u32,
+    pub message: String,
+}
+
+impl Display for TypeDiagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}:{}: TS{}: {}",
+            self.line, self.column, self.code, self.message
+        )
+    }
+}
+
+#[derive(Debug)]
+pub enum TypeCheckError {
+    Io(std::io::Error),
+    TimedOut,
+}
+
+impl Display for TypeCheckError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(err) => write!(f, "failed running type checker: {}", err),
+            Self::TimedOut => write!(f, "type checker timed out"),
+        }
+    }
+}
+
+impl std::error::Error for TypeCheckError {}
+
+impl From<std::io::Error> for TypeCheckError {
+    fn from(err: std::io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+impl TypeChecker {
+    pub fn new(command: String, typings_dir: PathBuf) -> Self {
+        Self {
+            command,
+            typings_dir,
+            timeout: Duration::from_secs(10),
+        }
+    }
+
+    /// Type checks the source of a script, returning the diagnostics for it
+    ///
+    /// This blocks until the checker process exits, so run it on a blocking thread.
+    ///
+    /// Imports of other guild scripts and libraries are not available to the checker,
+    /// so errors about not finding those modules are ignored.
+    pub fn check(&self, source: &str) -> Result<Vec<TypeDiagnostic>, TypeCheckError> {
+        let dir = std::env::temp_dir().join(format!(
+            "botloader-typecheck-{}-{}",
+            std::process::id(),
+            CHECK_COUNTER.fetch_add(1, Ordering::SeqCst)
+        ));
+
+        std::fs::create_dir_all(&dir)?;
+        let result = self.check_in_dir(&dir, source);
+        std::fs::remove_dir_all(&dir).ok();
+
+        result
+    }
+
+    fn check_in_dir(
+        &self,
+        dir: &Path,
+        source: &str,
+    ) -> Result<Vec<TypeDiagnostic>, TypeCheckError> {
+        std::fs::write(dir.join("script.ts"), source)?;
+        std::fs::write(dir.join("tsconfig.json"), self.gen_tsconfig())?;
+
+        let mut child = Command::new(&self.command)
+            .arg("-p")
+            .arg(dir.join("tsconfig.json"))
+            .arg("--pretty")
+            .arg("false")
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+
+        // read the output while the checker runs, it would otherwise block once the pipe is full
+        let mut stdout_pipe = child.stdout.take().expect("stdout is piped");
+        let stdout_reader = std::thread::spawn(move || {
+            let mut buf = Vec::new();
+            stdout_pipe.read_to_end(&mut buf).ok();
+            buf
+        });
+
+        let started = Instant::now();
+        while child.try_wait()?.is_none() {
+            if started.elapsed() > self.timeout {
+                child.kill().ok();
+                child.wait().ok();
+                return Err(TypeCheckError::TimedOut);
+            }
+
+            std::thread::sleep(Duration::from_millis(25));
+        }
+
+        let stdout = stdout_reader.join().unwrap_or_default();
+        let stdout = String::from_utf8_lossy(&stdout);
+
+        Ok(parse_diagnostics(&stdout)
+            .into_iter()
+            .filter(|diag| !is_unavailable_module_err(diag))
+            .collect())
+    }
+
+    fn gen_tsconfig(&self) -> String {
+        let typing = |name: &str| self.typings_dir.join(name).to_string_lossy().to_string();
+
+        serde_json::json!({
+            "compilerOptions": {
+                "target": "ES2020",
+                "module": "ES2020",
+                "moduleResolution": "node",
+                "lib": ["ES2020"],
+                "types": [],
+                "strict": true,
+                "noEmit": true,
+                "baseUrl": ".",
+                "paths": {
+                    "botloader": [typing("index")],
+                },
+            },
+            "files": [
+                "script.ts",
+                typing("script_globals.d.ts"),
+                typing("lib.botloader_user.core.d.ts"),
+            ],
+        })
+        .to_string()
+    }
+}
+
+fn is_unavailable_module_err(diag: &TypeDiagnostic) -> bool {
+    diag.code == TS_CANNOT_FIND_MODULE
+        && ["'./", "'../", "'lib:"]
+            .iter()
+            .any(|prefix| diag.message.contains(prefix))
+}
+
+/// Parses the diagnostics for script.ts from the non pretty tsc output,
+/// which looks like `script.ts(3,5): error TS2322: message`
+fn parse_diagnostics(output: &str) -> Vec<TypeDiagnostic> {
+    let mut result: Vec<TypeDiagnostic> = Vec::new();
+    let mut in_script_diag = false;
+
+    for line in output.lines() {
+        if line.starts_with(char::is_whitespace) {
+            // continuation of the previous message
+            if in_script_diag {
+                if let Some(last) = result.last_mut() {
+                    last.message.push('\n');
+                    last.message.push_str(line.trim());
+                }
+            }
+            continue;
+        }
+
+        match parse_diagnostic_line(line) {
+            Some(diag) => {
+                result.push(diag);
+                in_script_diag = true;
+            }
+            None => in_script_diag = false,
+        }
+    }
+
+    result
+}
+
+fn parse_diagnostic_line(line: &str) -> Option<TypeDiagnostic> {
+    let rest = line.strip_prefix("script.ts(")?;
+    let (pos, rest) = rest.split_once("): error TS")?;
+    let (line_no, column) = pos.split_once(',')?;
+    let (code, message) = rest.split_once(": ")?;
+
+    Some(TypeDiagnostic {
+        line: line_no.parse().ok()?,
+        column: column.parse().ok()?,
+        code: code.parse().ok()?,
+        message: message.to_string(),
+    })
+}
+
+#[test]
+fn parse_tsc_output() {
+    let output = "script.ts(3,5): error TS2322: Type 'string' is not assignable to type 'number'.\n\
+                  script.ts(1,20): error TS2307: Cannot find module './other' or its corresponding type declarations.\n\
+                  script.ts(7,1): error TS2345: Argument of type 'number' is not assignable to parameter of type 'string'.\n  \
+                  Some more detail.\n\
+                  ../typings/index.d.ts(1,1): error TS1000: not ours\n";
+
+    let diags = parse_diagnostics(output)
+        .into_iter()
+        .filter(|diag| !is_unavailable_module_err(diag))
+        .collect::<Vec<_>>();
+
+    assert_eq!(diags.len(), 2);
+    assert_eq!(
+        (diags[0].line, diags[0].column, diags[0].code),
+        (3, 5, 2322)
+    );
+    assert_eq!(diags[1].code, 2345);
+    assert!(diags[1].message.ends_with("\nSome more detail."));
+}
+
+#[cfg(unix)]
+#[test]
+fn check_large_output() {
+    use std::os::unix::fs::PermissionsExt;
+
+    // more output than fits in a pipe, for a checker that exits right away
+    let dir = std::env::temp_dir().join(format!("botloader-typecheck-test-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let command = dir.join("fake-tsc");
+    std::fs::write(
+        &command,
+        "#!/bin/sh\nhead -c 1000000 /dev/zero | tr '\\0' 'a'\necho\necho \"script.ts(1,1): error TS2322: bad\"\n",
+    )
+    .unwrap();
+    std::fs::set_permissions(&command, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+    let checker = TypeChecker::new(command.to_string_lossy().to_string(), dir.clone());
+    let result = checker.check("");
+    std::fs::remove_dir_all(&dir).ok();
+
+    let diags = result.unwrap();
+    assert_eq!(diags.len(), 1);
+    assert_eq!(diags[0].code, 2322);
+}
@@ -1,6 +1,9 @@
 use lazy_static::lazy_static;
 use regex::Regex;
-use runtime_models::ops::script::{Command, CommandGroup, CommandOption, CommandSubGroup};
+use runtime_models::ops::{
+    script::{Command, CommandGroup, CommandOption, CommandSubGroup},
+    storage::OpStorageBucket,
+};
 
 use crate::{ValidationContext, Validator};
 
@@ -63,6 +66,20 @@ impl Validator for CommandSubGroup {
     }
 }
 
+impl Validator for OpStorageBucket {
+    fn validate(&self, ctx: &mut ValidationContext) {
+        if self.name.is_empty() {
+            ctx.push_error("name", "has to be atleast 1 character".to_string());
+        }
+
+        // private buckets are stored under `script/bucket`, so a shared bucket with a slash in
+        // its name could access another script's private bucket
+        if self.name.contains('/') {
+            ctx.push_error("name", "can't contain slashes".to_string());
+        }
+    }
+}
+
 fn check_name_field(ctx: &mut ValidationContext, field: &str, value: &str) {
     if value.chars().count() < 1 {
         ctx.push_error(field, "has to be atleast 1 character".to_string());
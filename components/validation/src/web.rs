@@ -1,8 +1,18 @@
 use lazy_static::lazy_static;
 use regex::Regex;
-use stores::config::{CreateScript, UpdateScript};
+use stores::config::{CreateLibraryModule, CreateScript, UpdateScript};
+use tscompiler::typecheck::TypeDiagnostic;
 
-use crate::{ValidationContext, Validator};
+use crate::{ValidationContext, ValidationError, Validator};
+
+impl From<TypeDiagnostic> for ValidationError {
+    fn from(diag: TypeDiagnostic) -> Self {
+        Self {
+            field: "original_source".to_string(),
+            msg: diag.to_string(),
+        }
+    }
+}
 
 impl Validator for CreateScript {
     fn validate(&self, ctx: &mut ValidationContext) {
@@ -18,6 +28,27 @@ impl Validator for UpdateScript {
     }
 }
 
+impl Validator for CreateLibraryModule {
+    fn validate(&self, ctx: &mut ValidationContext) {
+        check_script_name(ctx, &self.name);
+        check_library_version(ctx, &self.version);
+        check_script_source(ctx, &self.original_source);
+    }
+}
+
+fn check_library_version(ctx: &mut ValidationContext, version: &str) {
+    lazy_static! {
+        static ref RE: Regex = Regex::new(r#"^\d{1,5}(\.\d{1,5}){0,2}$"#).unwrap();
+    }
+    if !RE.is_match(version) {
+        ctx.push_error(
+            "version",
+            "version has to be in the format 'major', 'major.minor' or 'major.minor.patch'"
+                .to_string(),
+        );
+    }
+}
+
 fn check_script_name(ctx: &mut ValidationContext, name: &str) {
     if name.chars().count() > 32 {
         ctx.push_error("name", "name can be max 32 characters long".to_string());
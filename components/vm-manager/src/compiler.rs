@@ -0,0 +1,111 @@
+use std::{convert::TryFrom, sync::Arc};
+
+use async_trait::async_trait;
+use stores::config::{ConfigStore, Script};
+use tracing::error;
+use tscompiler::{
+    cache::{source_hash, CompileCache, SerializedCompiledItem},
+    CompiledItem,
+};
+use twilight_model::id::GuildId;
+use vm::ScriptCompiler;
+
+/// Max number of compiled scripts kept in memory
+const MAX_CACHED_SCRIPTS: usize = 10_000;
+
+/// Compiles guild scripts, caching the output by source hash
+///
+/// The in memory cache is shared between all the guild vm's, if `persist` is set the output
+/// is also stored alongside the script in the config store so it survives bot restarts.
+pub struct CachedScriptCompiler<CT> {
+    config_store: CT,
+    cache: CompileCache,
+    persist: bool,
+}
+
+impl<CT> CachedScriptCompiler<CT> {
+    pub fn new(config_store: CT, persist: bool) -> Arc<Self> {
+        Arc::new(Self {
+            config_store,
+            cache: CompileCache::new(MAX_CACHED_SCRIPTS),
+            persist,
+        })
+    }
+}
+
+impl<CT> CachedScriptCompiler<CT>
+where
+    CT: ConfigStore + Send + Sync + 'static,
+{
+    async fn get_persisted(
+        &self,
+        guild_id: GuildId,
+        script: &Script,
+        hash: &str,
+    ) -> Option<CompiledItem> {
+        let value = match self
+            .config_store
+            .get_script_compile_cache(guild_id, script.id, hash)
+            .await
+        {
+            Ok(v) => v?,
+            Err(err) => {
+                error!(%err, "failed fetching script compile cache");
+                return None;
+            }
+        };
+
+        serde_json::from_value::<SerializedCompiledItem>(value)
+            .ok()
+            .and_then(|v| CompiledItem::try_from(v).ok())
+    }
+
+    async fn persist(
+        &self,
+        guild_id: GuildId,
+        script: &Script,
+        hash: &str,
+        compiled: &CompiledItem,
+    ) {
+        let value = serde_json::to_value(SerializedCompiledItem::from(compiled)).unwrap();
+        if let Err(err) = self
+            .config_store
+            .set_script_compile_cache(guild_id, script.id, hash, value)
+            .await
+        {
+            error!(%err, "failed storing script compile cache");
+        }
+    }
+}
+
+#[async_trait(?Send)]
+impl<CT> ScriptCompiler for CachedScriptCompiler<CT>
+where
+    CT: ConfigStore + Send + Sync + 'static,
+{
+    async fn compile_script(
+        &self,
+        guild_id: GuildId,
+        script: &Script,
+    ) -> Result<CompiledItem, String> {
+        let hash = source_hash(&script.original_source);
+        if let Some(compiled) = self.cache.get(&hash) {
+            return Ok(compiled);
+        }
+
+        if self.persist {
+            if let Some(compiled) = self.get_persisted(guild_id, script, &hash).await {
+                self.cache.insert(hash, &compiled);
+                return Ok(compiled);
+            }
+        }
+
+        let compiled = tscompiler::compile_typescript(&script.original_source)?;
+        if self.persist {
+            self.persist(guild_id, script, &hash, &compiled).await;
+        }
+
+        self.cache.insert(hash, &compiled);
+        Ok(compiled)
+    }
+}
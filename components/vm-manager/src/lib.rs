@@ -1,9 +1,22 @@
 #![doc = include_str!("../README.md")]
 
-use std::{collections::HashMap, sync::Arc};
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use compiler::CachedScriptCompiler;
+use libraries::CachedLibraryLoader;
 
 use guild_logger::{GuildLogger, LogEntry};
-use runtime::{contrib_manager::ContribManagerHandle, RuntimeContext};
+use runtime::{
+    contrib_manager::{CommandRoutes, ContribManagerHandle},
+    extensions::{
+        fetch::FetchAllowlists,
+        webhooks::{
+            PendingWebhooks, WebhookCall, WebhookError, WebhookResponse, WEBHOOK_REQUEST_EVENT,
+        },
+    },
+    guild_events::{GuildEvents, GuildStatus, VmStatus},
+    RuntimeContext,
+};
 use stores::{
     bucketstore::BucketStore,
     config::{ConfigStore, Script},
@@ -20,6 +33,13 @@ use twilight_model::id::GuildId;
 use vm::vm::{CreateRt, GuildVmEvent, Vm, VmCommand, VmContext, VmEvent, VmRole};
 use vmthread::{ShutdownReason, VmThreadCommand, VmThreadFuture, VmThreadHandle};
 
+pub mod compiler;
+pub mod libraries;
+pub mod validate;
+
+/// How long scripts have to respond to webhook requests
+const WEBHOOK_RESPONSE_TIMEOUT: Duration = Duration::from_secs(10);
+
 type GuildMap = HashMap<GuildId, GuildState>;
 pub struct InnerManager<CT> {
     guilds: RwLock<GuildMap>,
@@ -30,6 +50,12 @@ pub struct InnerManager<CT> {
     rt_evt_tx: UnboundedSender<GuildVmEvent>,
     guild_logger: GuildLogger,
     contrib_manager_handle: ContribManagerHandle,
+    library_loader: Arc<CachedLibraryLoader<CT>>,
+    script_compiler: Arc<CachedScriptCompiler<CT>>,
+    pending_webhooks: PendingWebhooks,
+    fetch_allowlists: FetchAllowlists,
+    guild_events: GuildEvents,
+    command_routes: CommandRoutes,
 }
 
 #[derive(Clone)]
@@ -47,13 +73,19 @@ where
         twilight_http_client: Arc<twilight_http::Client>,
         state: Arc<InMemoryCache>,
         config_store: CT,
+        persist_compile_cache: bool,
     ) -> Self {
         let (tx, rx) = mpsc::unbounded_channel();
+        let guild_events = GuildEvents::default();
+        let command_routes = CommandRoutes::default();
 
         let (mut contrib_manager, contrib_manager_handle) =
             runtime::contrib_manager::create_manager_pair(
                 config_store.clone(),
                 twilight_http_client.clone(),
+                guild_logger.clone(),
+                guild_events.clone(),
+                command_routes.clone(),
             );
 
         tokio::spawn(async move { contrib_manager.run().await });
@@ -65,9 +97,18 @@ where
                 http: twilight_http_client,
                 rt_evt_tx: tx,
                 guild_logger,
+                library_loader: CachedLibraryLoader::new(config_store.clone()),
+                script_compiler: CachedScriptCompiler::new(
+                    config_store.clone(),
+                    persist_compile_cache,
+                ),
                 config_store,
                 state,
                 contrib_manager_handle,
+                pending_webhooks: PendingWebhooks::default(),
+                fetch_allowlists: FetchAllowlists::default(),
+                guild_events,
+                command_routes,
             }),
         };
 
@@ -79,12 +120,19 @@ where
         manager
     }
 
+    /// Live status and script events of the guilds, see [GuildEvents]
+    pub fn guild_events(&self) -> &GuildEvents {
+        &self.inner.guild_events
+    }
+
     pub async fn init_guild(&self, guild_id: GuildId) -> Result<(), String> {
         self.restart_guild_vm(guild_id).await
     }
 
     pub async fn remove_guild(&self, guild_id: GuildId) {
         info!("removing guild {}", guild_id);
+        self.inner.fetch_allowlists.remove(guild_id);
+        self.inner.command_routes.remove(guild_id);
         if let Some(gs) = self.inner.guilds.write().await.remove(&guild_id) {
             gs.worker_thread
                 .send_cmd
@@ -113,6 +161,8 @@ where
     }
 
     pub async fn restart_guild_vm(&self, guild_id: GuildId) -> Result<(), String> {
+        self.load_fetch_allowlist(guild_id).await?;
+
         let mut guilds = self.inner.guilds.write().await;
 
         match guilds.get(&guild_id) {
@@ -172,6 +222,10 @@ where
             guild_logger: self.inner.guild_logger.clone(),
             vm_cmd_dispatch_tx: tx.clone(),
             bucket_store: Arc::new(self.inner.config_store.clone()),
+            pending_webhooks: self.inner.pending_webhooks.clone(),
+            fetch_allowlists: self.inner.fetch_allowlists.clone(),
+            guild_events: self.inner.guild_events.clone(),
+            command_routes: self.inner.command_routes.clone(),
         };
 
         let worker_thread = if let Some(gs) = guilds.get(&guild_id) {
@@ -197,6 +251,8 @@ where
                 load_scripts: to_load,
                 extension_factory: Box::new(move || runtime::create_extensions(rt_ctx.clone())),
                 extension_modules: runtime::jsmodules::create_module_map(),
+                library_loader: self.inner.library_loader.clone(),
+                script_compiler: self.inner.script_compiler.clone(),
             }))
             .map_err(|_| panic!("failed creating vm"))
             .unwrap();
@@ -214,6 +270,21 @@ where
         Ok(())
     }
 
+    /// Settings are only read when the vm is (re)started, so changing them requires a restart
+    async fn load_fetch_allowlist(&self, guild_id: GuildId) -> Result<(), String> {
+        let config = self
+            .inner
+            .config_store
+            .get_guild_meta_config_or_default(guild_id)
+            .await
+            .map_err(|err| err.to_string())?;
+
+        self.inner
+            .fetch_allowlists
+            .set(guild_id, config.fetch_allowed_domains);
+        Ok(())
+    }
+
     fn filter_load_scripts(&self, scripts: Vec<Script>) -> Vec<Script> {
         scripts.into_iter().filter(|e| e.enabled).collect()
     }
@@ -247,6 +318,10 @@ where
                 guild_logger: self.inner.guild_logger.clone(),
                 vm_cmd_dispatch_tx: tx.clone(),
                 bucket_store: Arc::new(self.inner.config_store.clone()),
+                pending_webhooks: self.inner.pending_webhooks.clone(),
+                fetch_allowlists: self.inner.fetch_allowlists.clone(),
+                guild_events: self.inner.guild_events.clone(),
+                command_routes: self.inner.command_routes.clone(),
             };
 
             info!("spawning guild vm for {}", guild_id);
@@ -265,6 +340,8 @@ where
                     load_scripts: to_load,
                     extension_factory: Box::new(move || runtime::create_extensions(rt_ctx.clone())),
                     extension_modules: runtime::jsmodules::create_module_map(),
+                    library_loader: self.inner.library_loader.clone(),
+                    script_compiler: self.inner.script_compiler.clone(),
                 }))
                 .map_err(|_| panic!("failed creating vm"))
                 .unwrap();
@@ -283,8 +360,22 @@ where
         }
     }
 
-    async fn handle_vm_evt(&self, guild_id: GuildId, _vr: VmRole, evt: VmEvent) {
+    async fn handle_vm_evt(&self, guild_id: GuildId, vr: VmRole, evt: VmEvent) {
+        // pack vms are only used for benchmarking
+        if matches!(vr, VmRole::Main) {
+            let status = match &evt {
+                VmEvent::Started => VmStatus::Started,
+                VmEvent::Restarting => VmStatus::Restarting,
+                VmEvent::Shutdown(ShutdownReason::Runaway) => VmStatus::Runaway,
+                VmEvent::Shutdown(_) => VmStatus::Stopped,
+            };
+            self.inner
+                .guild_events
+                .send_status(guild_id, GuildStatus::Vm(status));
+        }
+
         match evt {
+            VmEvent::Started | VmEvent::Restarting => {}
             VmEvent::Shutdown(reason) => {
                 self.with_guild_mut(guild_id, |g| {
                     g.main_vm = VmState::Stopped;
@@ -320,6 +411,22 @@ where
             .await
     }
 
+    /// Fetches the current version of a script from the config store and updates it in the guild's vm
+    pub async fn reload_script(&self, guild_id: GuildId, script_id: u64) -> Result<(), String> {
+        let script = self
+            .inner
+            .config_store
+            .get_script_by_id(guild_id, script_id)
+            .await
+            .map_err(|err| err.to_string())?;
+
+        if script.enabled {
+            self.update_script(guild_id, script).await
+        } else {
+            self.unload_scripts(guild_id, vec![script]).await
+        }
+    }
+
     pub async fn unload_scripts(
         &self,
         guild_id: GuildId,
@@ -346,6 +453,36 @@ where
         }
     }
 
+    /// Dispatches a webhook request to the guild's vm and waits for the script to respond
+    pub async fn dispatch_webhook(
+        &self,
+        guild_id: GuildId,
+        call: WebhookCall,
+    ) -> Result<WebhookResponse, WebhookError> {
+        let (request_id, rx) = self.inner.pending_webhooks.add(guild_id);
+
+        if let Err(err) = self
+            .send_vm_command(
+                guild_id,
+                VmRole::Main,
+                VmCommand::DispatchEvent(WEBHOOK_REQUEST_EVENT, call.into_event(request_id)),
+            )
+            .await
+        {
+            self.inner.pending_webhooks.remove(request_id);
+            return Err(WebhookError::VmNotRunning(err));
+        }
+
+        match tokio::time::timeout(WEBHOOK_RESPONSE_TIMEOUT, rx).await {
+            Ok(Ok(resp)) => Ok(resp),
+            Ok(Err(_)) => Err(WebhookError::Dropped),
+            Err(_) => {
+                self.inner.pending_webhooks.remove(request_id);
+                Err(WebhookError::Timeout)
+            }
+        }
+    }
+
     async fn send_vm_command(
         &self,
         guild_id: GuildId,
@@ -0,0 +1,48 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+};
+
+use async_trait::async_trait;
+use stores::config::{ConfigStore, LibraryModule};
+use vm::{AnyError, LibraryLoader};
+
+/// Loads library modules from the config store
+///
+/// Published versions never change so they're cached for the lifetime of the process
+/// and shared between all the guild vm's.
+pub struct CachedLibraryLoader<CT> {
+    config_store: CT,
+    cache: RwLock<HashMap<(String, String), LibraryModule>>,
+}
+
+impl<CT> CachedLibraryLoader<CT> {
+    pub fn new(config_store: CT) -> Arc<Self> {
+        Arc::new(Self {
+            config_store,
+            cache: RwLock::new(HashMap::new()),
+        })
+    }
+}
+
+#[async_trait]
+impl<CT> LibraryLoader for CachedLibraryLoader<CT>
+where
+    CT: ConfigStore + Send + Sync + 'static,
+{
+    async fn load_library(&self, name: &str, version: &str) -> Result<LibraryModule, AnyError> {
+        let key = (name.to_string(), version.to_string());
+        if let Some(cached) = self.cache.read().unwrap().get(&key) {
+            return Ok(cached.clone());
+        }
+
+        let library = self
+            .config_store
+            .get_library_module(name, version)
+            .await
+            .map_err(|err| AnyError::msg(err.to_string()))?;
+
+        self.cache.write().unwrap().insert(key, library.clone());
+        Ok(library)
+    }
+}
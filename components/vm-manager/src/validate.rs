@@ -0,0 +1,120 @@
+use runtime::{contrib_manager::to_twilight_commands, validator::ValidationImports};
+use runtime_models::ops::script::ScriptMeta;
+use serde::Serialize;
+use stores::{
+    bucketstore::BucketStore,
+    config::{find_command_conflicts, CommandConflict, ConfigStore, Script},
+    timers::TimerStore,
+};
+use twilight_model::id::GuildId;
+use vm::{moduleloader::ModuleManager, LibraryLoader};
+
+use crate::Manager;
+
+/// What a script would register if it was saved, see [Manager::validate_script]
+#[derive(Debug, Clone, Serialize)]
+pub struct ScriptValidation {
+    pub meta: ScriptMeta,
+    pub conflicts: Vec<CommandConflict>,
+}
+
+impl<CT> Manager<CT>
+where
+    CT: ConfigStore + TimerStore + BucketStore + Send + 'static + Sync,
+{
+    /// Compiles and runs the script in a throwaway vm, nothing is persisted
+    ///
+    /// `script_id` is the script being updated, if any, it's excluded from the conflict checks.
+    pub async fn validate_script(
+        &self,
+        guild_id: GuildId,
+        script_id: Option<u64>,
+        name: String,
+        source: String,
+    ) -> Result<ScriptValidation, String> {
+        // the compiled item can't be held across awaits
+        let (output, imports) = tscompiler::compile_typescript(&source)
+            .map(|compiled| (compiled.output, compiled.imports))
+            .map_err(|err| format!("compilation failed: {}", err))?;
+
+        let guild_scripts = self
+            .inner
+            .config_store
+            .list_scripts(guild_id)
+            .await
+            .map_err(|err| err.to_string())?;
+
+        let imports = self
+            .validation_imports(&name, &imports, &guild_scripts)
+            .await;
+
+        let meta = runtime::validate_script(name.clone(), output, imports)
+            .await
+            .map_err(|err| err.to_string())?;
+
+        let other_scripts = guild_scripts
+            .into_iter()
+            .filter(|s| s.enabled && Some(s.id) != script_id && s.name != name)
+            .collect::<Vec<_>>();
+
+        let commands = to_twilight_commands(guild_id, &meta.commands, &meta.command_groups);
+        let conflicts = find_command_conflicts(&commands, &other_scripts);
+
+        Ok(ScriptValidation { meta, conflicts })
+    }
+
+    /// Collects the guild scripts and libraries imported by the script, directly or through other guild scripts
+    ///
+    /// Imports that can't be found are left out, the validator reports them when the script is run.
+    async fn validation_imports(
+        &self,
+        name: &str,
+        imports: &[String],
+        guild_scripts: &[Script],
+    ) -> ValidationImports {
+        let mut result = ValidationImports::default();
+        let mut pending = imports.to_vec();
+        let mut seen = vec![name.to_string()];
+
+        while let Some(import) = pending.pop() {
+            if let Some((lib_name, version)) = ModuleManager::parse_library_specifier(&import) {
+                let specifier = ModuleManager::library_specifier(lib_name, version);
+                if result.libraries.iter().any(|(s, _)| *s == specifier) {
+                    continue;
+                }
+
+                if let Ok(library) = self
+                    .inner
+                    .library_loader
+                    .load_library(lib_name, version)
+                    .await
+                {
+                    result.libraries.push((specifier, library.compiled_source));
+                }
+            } else if let Some(script_name) = import.strip_prefix("./") {
+                if seen.iter().any(|v| v == script_name) {
+                    continue;
+                }
+                seen.push(script_name.to_string());
+
+                let script = match guild_scripts
+                    .iter()
+                    .find(|s| s.enabled && s.name == script_name)
+                {
+                    Some(s) => s,
+                    None => continue,
+                };
+
+                if let Ok(compiled) = tscompiler::compile_typescript(&script.original_source) {
+                    result.guild_scripts.push((
+                        ModuleManager::guild_script_specifier(&script.name),
+                        vm::prepend_library_source_header(&compiled.output, Some(script)),
+                    ));
+                    pending.extend(compiled.imports);
+                }
+            }
+        }
+
+        result
+    }
+}
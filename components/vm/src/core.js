@@ -1,6 +1,21 @@
 (function($window){
     $window.$jackGlobal = {}
 
+    // provides the structured stack frames that are mapped back to the typescript sources
+    Error.prepareStackTrace = Deno.core.createPrepareStackTrace();
+
+    // scripts register their teardown when they're started, it's called when a script
+    // is unloaded or reloaded without restarting the whole vm
+    $window.$jackGlobal.scriptTeardowns = new Map();
+
+    $window.$jackGlobal.teardownScript = function(scriptId){
+        const teardown = $window.$jackGlobal.scriptTeardowns.get(scriptId);
+        if (teardown){
+            $window.$jackGlobal.scriptTeardowns.delete(scriptId);
+            teardown();
+        }
+    }
+
     $window.$jackGlobal.runEventLoop = async function(cb){
         while(true){
             const next = await Deno.core.opAsync("op_botloader_rcv_event");
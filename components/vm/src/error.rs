@@ -1,85 +1,138 @@
-use std::rc::Rc;
+use std::{cell::RefCell, rc::Rc};
 
-use deno_core::error::JsError;
-use regex::Regex;
+use deno_core::error::{JsError, JsStackFrame};
+use guild_logger::StackFrame;
 
-use crate::LoadedScriptsStore;
-use lazy_static::lazy_static;
+use crate::{AnyError, LoadedScriptsStore};
 use tracing::info;
 
+/// A uncaught script error with the stack mapped back to the original sources
+#[derive(Debug, Clone)]
+pub struct ScriptError {
+    pub message: String,
+    pub frames: Vec<StackFrame>,
+
+    // the display output of the error, used to find the original error again after deno has wrapped it
+    display: String,
+}
+
+/// Holds the last error created by the error fn
+///
+/// Deno wraps some of the errors in its own type we can't downcast through,
+/// so the structured version of them is kept here instead.
+#[derive(Clone, Default)]
+pub(crate) struct LastScriptError(Rc<RefCell<Option<ScriptError>>>);
+
+impl LastScriptError {
+    /// Returns the mapped version of the error, if it was created by the error fn
+    pub(crate) fn take_matching(&self, err: &AnyError) -> Option<ScriptError> {
+        let mut last = self.0.borrow_mut();
+        if last.as_ref()?.display == err.to_string() {
+            last.take()
+        } else {
+            None
+        }
+    }
+}
+
 pub(crate) fn create_error_fn(
     loaded_scripts: LoadedScriptsStore,
+    last_error: LastScriptError,
 ) -> Rc<deno_core::JsErrorCreateFn> {
     Rc::new(move |mut err: JsError| {
-        // let scripts = loaded_scripts.borrow_mut();
-
         info!("IN CREATE_ERROR_FN, {:#?}", err);
 
         parse_transform_err_source(&mut err, &loaded_scripts);
 
-        if let Some(stack) = err.stack {
-            err.stack = Some(parse_transform_stack(&loaded_scripts, &stack));
+        let frames = err
+            .frames
+            .iter_mut()
+            .map(|frame| transform_frame(&loaded_scripts, frame))
+            .collect::<Vec<_>>();
+
+        if !frames.is_empty() {
+            err.stack = Some(format_stack(err.stack.as_deref(), &frames));
         }
 
+        let script_error = ScriptError {
+            message: err.message.clone(),
+            frames,
+            display: err.to_string(),
+        };
+        *last_error.0.borrow_mut() = Some(script_error);
+
         err.into()
     })
 }
 
-fn parse_transform_stack(scripts: &LoadedScriptsStore, stack: &str) -> String {
-    let mut output = String::new();
+/// Maps the frame back to the original source, returning the structured version of it
+fn transform_frame(scripts: &LoadedScriptsStore, frame: &mut JsStackFrame) -> StackFrame {
+    let function = frame
+        .function_name
+        .clone()
+        .or_else(|| frame.method_name.clone())
+        .map(|name| {
+            if frame.is_async {
+                format!("async {}", name)
+            } else if frame.is_constructor {
+                format!("new {}", name)
+            } else {
+                name
+            }
+        });
+
+    let filename = frame.file_name.clone().unwrap_or_default();
+
+    // v8 columns are 1 based, while the source map ones are 0 based
+    let line_col = match (frame.line_number, frame.column_number) {
+        (Some(line), Some(col)) => Some((line as u32, (col as u32).saturating_sub(1))),
+        _ => None,
+    };
 
-    for line in stack.split('\n') {
-        if let Some(new_line) = parse_transform_stack_line(scripts, line) {
-            output.push_str(&new_line)
-        } else {
-            output.push_str(line)
+    if let Some((line, col)) = line_col {
+        if let Some((new_file, src_line, src_col)) =
+            scripts.get_original_line_col(&filename, line, col)
+        {
+            frame.file_name = Some(new_file.clone());
+            frame.line_number = Some(src_line as i64);
+            frame.column_number = Some(src_col as i64 + 1);
+
+            return StackFrame {
+                function,
+                filename: new_file,
+                line_col: Some((src_line, src_col)),
+            };
         }
-        output.push('\n');
     }
 
-    output
-}
-
-fn parse_transform_stack_line(scripts: &LoadedScriptsStore, line: &str) -> Option<String> {
-    info!("checking line {}", line);
-    lazy_static! {
-        static ref RE: Regex = Regex::new(r#"\(?(file:///[\w/\.]+):(\d+):(\d+)\)?"#).unwrap();
+    StackFrame {
+        function,
+        filename,
+        line_col,
     }
+}
 
-    let segments = line
-        .split(' ')
-        .filter(|v| !v.is_empty())
-        .collect::<Vec<_>>();
-
-    info!("segments: {:?}", segments);
-
-    let (main_part, func) = if segments.len() == 2 {
-        (segments[1], None)
-    } else if segments.len() == 3 {
-        (segments[2], Some(segments[1]))
-    } else {
-        return None;
-    };
-
-    let cap = RE.captures(main_part)?;
-    info!("Matched regex!");
-
-    let file = cap.get(1)?.as_str();
-    let line: u32 = cap.get(2)?.as_str().parse().ok()?;
-    let col: u32 = cap.get(3)?.as_str().parse().ok()?;
+/// Replaces the frames in the stack with the mapped ones, keeping the message lines
+fn format_stack(original: Option<&str>, frames: &[StackFrame]) -> String {
+    let mut output = String::new();
 
-    info!("file: {}, line: {}, col; {}", file, line, col);
+    if let Some(original) = original {
+        for line in original
+            .split('\n')
+            .take_while(|line| !line.trim_start().starts_with("at "))
+        {
+            output.push_str(line);
+            output.push('\n');
+        }
+    }
 
-    let (new_file, src_line, src_col) = scripts.get_original_line_col(file, line, col)?;
-    let mut output = String::new();
-    output.push_str("    at ");
-    if let Some(f) = func {
-        output.push_str(f);
-        output.push(' ');
+    for frame in frames {
+        output.push_str("    ");
+        output.push_str(&frame.to_string());
+        output.push('\n');
     }
 
-    output.push_str(&format!("({}:{}:{})", new_file, src_line, src_col));
-    Some(output)
+    output
 }
 
 fn parse_transform_err_source(err: &mut JsError, scripts: &LoadedScriptsStore) -> Option<()> {
@@ -1,8 +1,10 @@
 use std::{cell::RefCell, rc::Rc};
 
+use async_trait::async_trait;
 use deno_core::v8_set_flags;
-use stores::config::Script;
+use stores::config::{LibraryModule, Script};
 use tscompiler::CompiledItem;
+use twilight_model::id::GuildId;
 
 pub mod error;
 pub mod moduleloader;
@@ -19,10 +21,22 @@ pub type AnyError = deno_core::error::AnyError;
 pub static BOTLOADER_CORE_SNAPSHOT: &[u8] =
     include_bytes!(concat!(env!("OUT_DIR"), "/BOTLOADER_SNAPSHOT.bin"));
 
+/// Runs the script, the module loader adds this to guild scripts that are not imported by other
+/// guild scripts (see [moduleloader::ModuleManager::guild_module_imported])
+const SCRIPT_RUN_FOOTER: &str = "\nscript.run();";
+
 pub fn prepend_script_source_header(source: &str, script: Option<&Script>) -> String {
+    let mut result = prepend_library_source_header(source, script);
+    result.push_str(SCRIPT_RUN_FOOTER);
+
+    result
+}
+
+/// Same as [prepend_script_source_header] but without running the script,
+/// used for modules that only export things to other scripts
+pub fn prepend_library_source_header(source: &str, script: Option<&Script>) -> String {
     let mut result = gen_script_source_header(script);
     result.push_str(source);
-    result.push_str("\nscript.run();");
 
     result
 }
@@ -39,16 +53,17 @@ fn gen_script_source_header(script: Option<&Script>) -> String {
     match script {
         None => r#"
         import {Script} from "script";
-        const script = new Script(0);
+        const script = new Script(0, "");
         "#
         .to_string(),
         Some(h) => {
             format!(
                 r#"
                 import {{Script}} from "script";
-                const script = new Script({});
+                const script = new Script({}, {});
                 "#,
-                h.id
+                h.id,
+                serde_json::to_string(&h.name).unwrap_or_default()
             )
         }
     }
@@ -80,6 +95,25 @@ pub fn init_v8_flags(v8_flags: &[String]) {
     }
 }
 
+/// Provides the library modules guild scripts import using `lib:name@version`
+#[async_trait]
+pub trait LibraryLoader {
+    async fn load_library(&self, name: &str, version: &str) -> Result<LibraryModule, AnyError>;
+}
+
+/// Compiles guild scripts, implementations can cache the output so that restarting a vm
+/// doesn't have to recompile all of its scripts
+///
+/// Compiled output can't be sent between threads, so the returned future isn't Send either
+#[async_trait(?Send)]
+pub trait ScriptCompiler {
+    async fn compile_script(
+        &self,
+        guild_id: GuildId,
+        script: &Script,
+    ) -> Result<CompiledItem, String>;
+}
+
 #[derive(Clone, Debug)]
 pub struct ScriptLoad {
     pub compiled: CompiledItem,
@@ -87,6 +121,14 @@ pub struct ScriptLoad {
 }
 
 impl ScriptLoad {
+    /// The full source of the module, including the header
+    ///
+    /// This doesn't run the script, that's added by the module loader unless the script is
+    /// imported by another guild script.
+    pub fn module_source(&self) -> String {
+        prepend_library_source_header(&self.compiled.output, Some(&self.inner))
+    }
+
     fn get_original_line_col(&self, line_no: u32, col: u32) -> Option<(u32, u32)> {
         self.compiled
             .source_map
@@ -125,6 +167,8 @@ impl LoadedScriptsStore {
     }
 
     pub fn get_guild_script_name(res: &str) -> Option<&str> {
+        // reloaded scripts have a version in the query
+        let res = res.split_once('?').map(|(path, _)| path).unwrap_or(res);
         if let Some(stripped) = res.strip_prefix("file:///guild_scripts/") {
             if let Some(end_trimmed) = stripped.strip_suffix(".js") {
                 return Some(end_trimmed);
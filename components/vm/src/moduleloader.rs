@@ -1,3 +1,5 @@
+use std::cell::{Cell, RefCell};
+
 use deno_core::{ModuleLoader, ModuleSource};
 use futures::future::ready;
 use url::Url;
@@ -5,6 +7,217 @@ use url::Url;
 pub struct ModuleManager {
     // loaded_modules: Vec<url::Url>,
     pub module_map: Vec<ModuleEntry>,
+
+    /// The guild's own scripts, registered as they're compiled so they can import eachother
+    guild_modules: RefCell<Vec<GuildModuleEntry>>,
+
+    /// Shared library modules imported by the guild scripts, these are immutable so they're kept across restarts
+    library_modules: RefCell<Vec<LibraryModuleEntry>>,
+
+    /// Guild scripts that were removed after being loaded into the isolate, the isolate keeps those
+    /// modules around so new versions of them are registered under a different specifier
+    retired_guild_scripts: RefCell<Vec<String>>,
+    reload_counter: Cell<u32>,
+}
+
+impl ModuleManager {
+    pub fn new(module_map: Vec<ModuleEntry>) -> Self {
+        Self {
+            module_map,
+            guild_modules: RefCell::new(Vec::new()),
+            library_modules: RefCell::new(Vec::new()),
+            retired_guild_scripts: RefCell::new(Vec::new()),
+            reload_counter: Cell::new(0),
+        }
+    }
+
+    /// Parses a library specifier in the form of `lib:name@version`
+    pub fn parse_library_specifier(specifier: &str) -> Option<(&str, &str)> {
+        let stripped = specifier.strip_prefix("lib:")?;
+        let (name, version) = stripped.split_once('@')?;
+        if name.is_empty() || version.is_empty() {
+            return None;
+        }
+
+        Some((name, version))
+    }
+
+    pub fn library_specifier(name: &str, version: &str) -> Url {
+        Url::parse(format!("lib:{}@{}", name, version).as_str()).unwrap()
+    }
+
+    pub fn has_library_module(&self, specifier: &Url) -> bool {
+        self.library_modules
+            .borrow()
+            .iter()
+            .any(|e| e.specifier == *specifier)
+    }
+
+    pub fn add_library_module(&self, specifier: Url, source: String) {
+        let mut library_modules = self.library_modules.borrow_mut();
+        if !library_modules.iter().any(|e| e.specifier == specifier) {
+            library_modules.push(LibraryModuleEntry { specifier, source });
+        }
+    }
+
+    pub fn guild_script_specifier(script_name: &str) -> Url {
+        Url::parse(format!("file:///guild_scripts/{}.js", script_name).as_str()).unwrap()
+    }
+
+    /// Returns the specifier to register a new version of the guild script under
+    ///
+    /// This is the plain specifier unless an earlier version was loaded into the isolate,
+    /// in which case a version is added to it (e.g `file:///guild_scripts/a.js?v=1`)
+    pub fn fresh_guild_script_specifier(&self, script_name: &str) -> Url {
+        let mut specifier = Self::guild_script_specifier(script_name);
+        if self
+            .retired_guild_scripts
+            .borrow()
+            .iter()
+            .any(|v| v == script_name)
+        {
+            let version = self.reload_counter.get() + 1;
+            self.reload_counter.set(version);
+            specifier.set_query(Some(&format!("v={}", version)));
+        }
+
+        specifier
+    }
+
+    /// Returns the specifier the current version of the guild script is registered under
+    pub fn guild_module_specifier(&self, script_name: &str) -> Option<Url> {
+        self.guild_modules
+            .borrow()
+            .iter()
+            .find(|e| {
+                crate::LoadedScriptsStore::get_guild_script_name(e.specifier.as_str())
+                    == Some(script_name)
+            })
+            .map(|e| e.specifier.clone())
+    }
+
+    /// Registers a compiled guild script, `imports` are the specifiers as they appear in the source
+    pub fn add_guild_module(&self, specifier: Url, source: String, imports: &[String]) {
+        let imports = imports
+            .iter()
+            .filter_map(|import| self.resolve(import, specifier.as_str(), false).ok())
+            .collect();
+
+        let mut guild_modules = self.guild_modules.borrow_mut();
+        guild_modules.retain(|e| e.specifier != specifier);
+        guild_modules.push(GuildModuleEntry {
+            specifier,
+            source,
+            imports,
+            fetched: false,
+        });
+    }
+
+    /// Removes a single guild script, the module stays in the isolate if it was loaded
+    pub fn remove_guild_module(&self, specifier: &Url) {
+        let mut guild_modules = self.guild_modules.borrow_mut();
+        let removed = match guild_modules.iter().position(|e| e.specifier == *specifier) {
+            Some(index) => guild_modules.remove(index),
+            None => return,
+        };
+
+        if removed.fetched {
+            if let Some(name) = crate::LoadedScriptsStore::get_guild_script_name(specifier.as_str())
+            {
+                self.retired_guild_scripts
+                    .borrow_mut()
+                    .push(name.to_string());
+            }
+        }
+    }
+
+    /// Returns the loaded guild scripts that import the provided one
+    pub fn guild_module_importers(&self, specifier: &Url) -> Vec<Url> {
+        self.guild_modules
+            .borrow()
+            .iter()
+            .filter(|e| e.fetched && e.imports.contains(specifier))
+            .map(|e| e.specifier.clone())
+            .collect()
+    }
+
+    /// Removes all guild scripts, needs to be called when the modules are loaded into a new isolate
+    pub fn clear_guild_modules(&self) {
+        self.guild_modules.borrow_mut().clear();
+        self.retired_guild_scripts.borrow_mut().clear();
+    }
+
+    /// Returns true if another registered guild script imports the provided one
+    ///
+    /// Imported guild scripts are library modules, they only export things to the scripts
+    /// importing them and don't run `script.run()`, so they can't register commands, timers etc.
+    /// All other guild scripts are run, even if they don't register anything, so whatever they
+    /// registered before is cleared.
+    pub fn guild_module_imported(&self, specifier: &Url) -> bool {
+        let name = match crate::LoadedScriptsStore::get_guild_script_name(specifier.as_str()) {
+            Some(name) => name,
+            None => return false,
+        };
+
+        // compared by name since the importer may have been registered before a reload of this script
+        self.guild_modules
+            .borrow()
+            .iter()
+            .filter(|e| e.specifier != *specifier)
+            .flat_map(|e| e.imports.iter())
+            .any(|import| {
+                crate::LoadedScriptsStore::get_guild_script_name(import.as_str()) == Some(name)
+            })
+    }
+
+    /// Returns true if the guild script has been loaded into the isolate, either directly or as a dependency of another script
+    pub fn guild_module_fetched(&self, specifier: &Url) -> bool {
+        self.guild_modules
+            .borrow()
+            .iter()
+            .any(|e| e.specifier == *specifier && e.fetched)
+    }
+
+    /// Returns the first import cycle between guild scripts reachable from the provided guild script, if any
+    ///
+    /// The returned path starts and ends with the same module
+    pub fn find_guild_import_cycle(&self, root: &Url) -> Option<Vec<Url>> {
+        let guild_modules = self.guild_modules.borrow();
+        let mut path = vec![root.clone()];
+        let mut done = Vec::new();
+
+        Self::find_cycle_inner(&guild_modules, &mut path, &mut done)
+    }
+
+    fn find_cycle_inner(
+        guild_modules: &[GuildModuleEntry],
+        path: &mut Vec<Url>,
+        done: &mut Vec<Url>,
+    ) -> Option<Vec<Url>> {
+        let current = path.last().unwrap().clone();
+        let entry = guild_modules.iter().find(|e| e.specifier == current)?;
+
+        for import in &entry.imports {
+            if let Some(pos) = path.iter().position(|v| v == import) {
+                let mut cycle = path[pos..].to_vec();
+                cycle.push(import.clone());
+                return Some(cycle);
+            }
+
+            if done.contains(import) {
+                continue;
+            }
+
+            path.push(import.clone());
+            if let Some(cycle) = Self::find_cycle_inner(guild_modules, path, done) {
+                return Some(cycle);
+            }
+            path.pop();
+        }
+
+        done.push(current);
+        None
+    }
 }
 
 // TODO: make a formal spec for this behaviour
@@ -45,6 +258,14 @@ impl ModuleLoader for ModuleManager {
                 .join(format!("{}.js", specifier).as_str())
                 .unwrap();
 
+            // imports of reloaded guild scripts resolve to their latest version
+            if let Some(name) = crate::LoadedScriptsStore::get_guild_script_name(resolved.as_str())
+            {
+                if let Some(current) = self.guild_module_specifier(name) {
+                    return Ok(current);
+                }
+            }
+
             Ok(resolved)
         } else {
             let resolved =
@@ -63,20 +284,57 @@ impl ModuleLoader for ModuleManager {
     ) -> std::pin::Pin<Box<deno_core::ModuleSourceFuture>> {
         // info!("loading module: {}", module_specifier.to_string());
 
-        let f = match self
+        let source = match self
             .module_map
             .iter()
             .find(|e| e.specifier == *module_specifier)
         {
-            Some(e) => ready(Ok(ModuleSource {
-                code: e.source.to_string(),
+            Some(e) => Some(e.source.to_string()),
+            None if module_specifier.scheme() == "lib" => self
+                .library_modules
+                .borrow()
+                .iter()
+                .find(|e| e.specifier == *module_specifier)
+                .map(|e| e.source.clone()),
+            None => {
+                let imported = self.guild_module_imported(module_specifier);
+                self.guild_modules
+                    .borrow_mut()
+                    .iter_mut()
+                    .find(|e| e.specifier == *module_specifier)
+                    .map(|e| {
+                        e.fetched = true;
+                        if imported {
+                            e.source.clone()
+                        } else {
+                            format!("{}{}", e.source, crate::SCRIPT_RUN_FOOTER)
+                        }
+                    })
+            }
+        };
+
+        let f = match source {
+            Some(code) => ready(Ok(ModuleSource {
+                code,
                 module_url_found: module_specifier.to_string(),
                 module_url_specified: module_specifier.to_string(),
             })),
-            None => ready(Err(anyhow::anyhow!(
-                "failed finding module {:?}",
+            None if module_specifier.scheme() == "lib" => ready(Err(anyhow::anyhow!(
+                "failed finding library {}, make sure the version is published",
                 module_specifier
             ))),
+            None => match crate::LoadedScriptsStore::get_guild_script_name(
+                module_specifier.as_str(),
+            ) {
+                Some(name) => ready(Err(anyhow::anyhow!(
+                    "failed finding guild script {}.ts, make sure it exists, is enabled and compiles",
+                    name
+                ))),
+                None => ready(Err(anyhow::anyhow!(
+                    "failed finding module {:?}",
+                    module_specifier
+                ))),
+            },
         };
 
         Box::pin(f)
@@ -87,3 +345,76 @@ pub struct ModuleEntry {
     pub specifier: Url,
     pub source: &'static str,
 }
+
+struct GuildModuleEntry {
+    specifier: Url,
+    source: String,
+    imports: Vec<Url>,
+    /// Whether the module has been handed to the isolate
+    fetched: bool,
+}
+
+struct LibraryModuleEntry {
+    specifier: Url,
+    source: String,
+}
+
+#[test]
+fn reloaded_guild_scripts_get_new_specifiers() {
+    let manager = ModuleManager::new(Vec::new());
+
+    let a = manager.fresh_guild_script_specifier("a");
+    assert_eq!(a.as_str(), "file:///guild_scripts/a.js");
+    manager.add_guild_module(a.clone(), String::new(), &[]);
+    manager.add_guild_module(
+        manager.fresh_guild_script_specifier("b"),
+        String::new(),
+        &["./a".to_string()],
+    );
+
+    // only scripts loaded into the isolate count as importers
+    assert!(manager.guild_module_importers(&a).is_empty());
+    for entry in manager.guild_modules.borrow_mut().iter_mut() {
+        entry.fetched = true;
+    }
+    assert_eq!(manager.guild_module_importers(&a).len(), 1);
+
+    manager.remove_guild_module(&a);
+    let a_reloaded = manager.fresh_guild_script_specifier("a");
+    assert_eq!(a_reloaded.as_str(), "file:///guild_scripts/a.js?v=1");
+    manager.add_guild_module(a_reloaded.clone(), String::new(), &[]);
+
+    // new imports resolve to the latest version
+    assert_eq!(
+        manager
+            .resolve("./a", "file:///guild_scripts/c.js", false)
+            .unwrap(),
+        a_reloaded
+    );
+    assert_eq!(manager.guild_module_specifier("a"), Some(a_reloaded));
+}
+
+#[test]
+fn imported_guild_scripts_are_libraries() {
+    let manager = ModuleManager::new(Vec::new());
+
+    let helpers = manager.fresh_guild_script_specifier("helpers");
+    manager.add_guild_module(helpers.clone(), String::new(), &[]);
+    let main = manager.fresh_guild_script_specifier("main");
+    manager.add_guild_module(main.clone(), String::new(), &["./helpers".to_string()]);
+    let standalone = manager.fresh_guild_script_specifier("standalone");
+    manager.add_guild_module(standalone.clone(), String::new(), &[]);
+
+    assert!(manager.guild_module_imported(&helpers));
+    assert!(!manager.guild_module_imported(&main));
+    assert!(!manager.guild_module_imported(&standalone));
+
+    // still a library after being reloaded
+    for entry in manager.guild_modules.borrow_mut().iter_mut() {
+        entry.fetched = true;
+    }
+    manager.remove_guild_module(&helpers);
+    let helpers_reloaded = manager.fresh_guild_script_specifier("helpers");
+    manager.add_guild_module(helpers_reloaded.clone(), String::new(), &[]);
+    assert!(manager.guild_module_imported(&helpers_reloaded));
+}
@@ -1,11 +1,13 @@
-use crate::error::create_error_fn;
+use crate::error::{create_error_fn, LastScriptError};
 use crate::moduleloader::{ModuleEntry, ModuleManager};
-use crate::{prepend_script_source_header, AnyError, JsValue, ScriptLoad};
+use crate::{AnyError, JsValue, LibraryLoader, ScriptCompiler, ScriptLoad};
 use anyhow::anyhow;
 use deno_core::{op_async, Extension, OpState, RuntimeOptions, Snapshot};
 use futures::{future::LocalBoxFuture, FutureExt};
 use guild_logger::{GuildLogger, LogEntry};
 use isolatecell::{IsolateCell, ManagedIsolate};
+use lazy_static::lazy_static;
+use prometheus::{register_histogram_vec, register_int_counter_vec, HistogramVec, IntCounterVec};
 use serde::Serialize;
 use std::pin::Pin;
 use std::{
@@ -14,15 +16,30 @@ use std::{
     rc::Rc,
     sync::{atomic::AtomicBool, Arc, RwLock as StdRwLock},
     task::{Context, Poll, Wake, Waker},
+    time::Instant,
 };
 use stores::config::Script;
 use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
 use tracing::{info, instrument};
 use twilight_model::id::GuildId;
-use url::Url;
 use v8::{CreateParams, HeapStatistics, IsolateHandle};
 use vmthread::{CreateVmSuccess, ShutdownReason, VmInterface};
 
+lazy_static! {
+    static ref EVENTS_DISPATCHED: IntCounterVec = register_int_counter_vec!(
+        "botloader_vm_events_dispatched_total",
+        "Number of events dispatched to vms",
+        &["event"]
+    )
+    .unwrap();
+    static ref EVENT_DISPATCH_LATENCY: HistogramVec = register_histogram_vec!(
+        "botloader_vm_event_dispatch_latency_seconds",
+        "Time from an event being dispatched until the vm picked it up",
+        &["event"]
+    )
+    .unwrap();
+}
+
 #[derive(Debug, Clone)]
 pub enum VmCommand {
     DispatchEvent(&'static str, serde_json::Value),
@@ -38,6 +55,10 @@ pub enum VmCommand {
 
 #[derive(Debug)]
 pub enum VmEvent {
+    /// The vm started running, or finished restarting
+    Started,
+    /// The vm is being restarted, followed by [VmEvent::Started] when it's done
+    Restarting,
     Shutdown(ShutdownReason),
 }
 
@@ -53,6 +74,9 @@ pub type GuildVmEvent = (GuildId, VmRole, VmEvent);
 struct ScriptDispatchData {
     name: String,
     data: serde_json::Value,
+    /// Only set for events from [Vm::dispatch_event]
+    #[serde(skip)]
+    queued_at: Option<Instant>,
 }
 
 pub struct Vm {
@@ -72,9 +96,12 @@ pub struct Vm {
 
     extension_factory: ExtensionFactory,
     module_manager: Rc<ModuleManager>,
+    library_loader: Arc<dyn LibraryLoader + Send + Sync>,
+    script_compiler: Arc<dyn ScriptCompiler + Send + Sync>,
 
     script_dispatch_tx: UnboundedSender<ScriptDispatchData>,
     wakeup_rx: UnboundedReceiver<()>,
+    last_script_error: LastScriptError,
 }
 
 #[derive(Debug, Clone)]
@@ -94,9 +121,7 @@ impl Vm {
         isolate_cell: Rc<IsolateCell>,
         wakeup_rx: UnboundedReceiver<()>,
     ) -> Self {
-        let module_manager = Rc::new(ModuleManager {
-            module_map: create_req.extension_modules,
-        });
+        let module_manager = Rc::new(ModuleManager::new(create_req.extension_modules));
 
         let (script_dispatch_tx, script_dispatch_rx) = mpsc::unbounded_channel();
 
@@ -105,11 +130,13 @@ impl Vm {
             loaded_scripts: loaded_scripts.clone(),
         };
 
+        let last_script_error = LastScriptError::default();
+
         let sandbox = Self::create_isolate(
             &create_req.extension_factory,
             module_manager.clone(),
             script_dispatch_rx,
-            create_error_fn(scripts_store.clone()),
+            create_error_fn(scripts_store.clone(), last_script_error.clone()),
             scripts_store,
         );
 
@@ -127,14 +154,15 @@ impl Vm {
             runtime: sandbox,
             extension_factory: create_req.extension_factory,
             module_manager,
+            library_loader: create_req.library_loader,
+            script_compiler: create_req.script_compiler,
             wakeup_rx,
+            last_script_error,
         };
 
         rt.emit_isolate_handle();
 
-        for script in create_req.load_scripts {
-            rt.load_script(script).await
-        }
+        rt.load_scripts(create_req.load_scripts).await;
 
         rt
     }
@@ -195,6 +223,7 @@ impl Vm {
             self.ctx.guild_id,
             "starting guild vm".to_string(),
         ));
+        self.send_event(VmEvent::Started);
 
         while !self.check_terminated() {
             let fut = TickFuture {
@@ -207,12 +236,7 @@ impl Vm {
             match fut.await {
                 Ok(Some(cmd)) => self.handle_cmd(cmd).await,
                 Ok(None) => {}
-                Err(e) => {
-                    self.guild_logger.log(LogEntry::error(
-                        self.ctx.guild_id,
-                        format!("Script error occured: {}", e),
-                    ));
-                }
+                Err(e) => self.log_guild_err(e),
             }
         }
 
@@ -247,6 +271,10 @@ impl Vm {
             .unwrap();
     }
 
+    fn send_event(&self, evt: VmEvent) {
+        self.tx.send((self.ctx.guild_id, self.ctx.role, evt)).ok();
+    }
+
     fn check_terminated(&mut self) -> bool {
         self.timeout_handle
             .terminated
@@ -260,9 +288,24 @@ impl Vm {
                 self.restart(new_scripts).await;
             }
             VmCommand::DispatchEvent(name, evt) => self.dispatch_event(name, &evt),
-            VmCommand::LoadScript(script) => self.load_script(script).await,
+            VmCommand::LoadScript(script) => self.load_scripts(vec![script]).await,
 
             VmCommand::UpdateScript(script) => {
+                let is_loaded = self
+                    .loaded_scripts
+                    .borrow()
+                    .iter()
+                    .any(|v| v.inner.id == script.id);
+
+                if !is_loaded {
+                    return;
+                }
+
+                if self.unload_script_in_place(script.id) {
+                    self.load_scripts(vec![script]).await;
+                    return;
+                }
+
                 let mut cloned_scripts = self
                     .loaded_scripts
                     .borrow()
@@ -271,19 +314,27 @@ impl Vm {
                     .map(|v| v.inner)
                     .collect::<Vec<_>>();
 
-                let mut need_reset = false;
                 for old in &mut cloned_scripts {
                     if old.id == script.id {
                         *old = script.clone();
-                        need_reset = true;
                     }
                 }
 
-                if need_reset {
-                    self.restart(cloned_scripts).await;
-                }
+                self.restart(cloned_scripts).await;
             }
             VmCommand::UnloadScripts(scripts) => {
+                let mut need_restart = false;
+                for script in &scripts {
+                    if !self.unload_script_in_place(script.id) {
+                        need_restart = true;
+                        break;
+                    }
+                }
+
+                if !need_restart {
+                    return;
+                }
+
                 let new_scripts = self
                     .loaded_scripts
                     .borrow()
@@ -302,9 +353,78 @@ impl Vm {
         }
     }
 
+    /// Tears down a script and removes it from the running vm, leaving the other scripts untouched
+    ///
+    /// Returns false if that wasn't possible and the vm has to be restarted instead, this is the case
+    /// if other scripts import it (they would keep using the old version) or if the teardown failed.
+    fn unload_script_in_place(&mut self, script_id: u64) -> bool {
+        let script = match self
+            .loaded_scripts
+            .borrow()
+            .iter()
+            .find(|v| v.inner.id == script_id)
+        {
+            Some(v) => v.inner.clone(),
+            // not loaded, nothing to do
+            None => return true,
+        };
+
+        let specifier = match self.module_manager.guild_module_specifier(&script.name) {
+            Some(v) => v,
+            None => return false,
+        };
+
+        if !self
+            .module_manager
+            .guild_module_importers(&specifier)
+            .is_empty()
+        {
+            info!(
+                "script: {} is imported by other scripts, restarting vm",
+                script.id
+            );
+            return false;
+        }
+
+        // scripts that failed to load have nothing to tear down
+        if !self.failed_scripts.iter().any(|v| v.inner.id == script.id) {
+            let res = {
+                let mut rt = self.isolate_cell.enter_isolate(&mut self.runtime);
+                rt.execute_script(
+                    "teardown",
+                    &format!("$jackGlobal.teardownScript({});", script.id),
+                )
+            };
+
+            if let Err(err) = res {
+                self.log_guild_err(err);
+                self.guild_logger.log(LogEntry::error(
+                    self.ctx.guild_id,
+                    format!(
+                        "Script {}.ts failed tearing down, restarting the vm",
+                        script.name
+                    ),
+                ));
+                return false;
+            }
+        }
+
+        self.module_manager.remove_guild_module(&specifier);
+        self.loaded_scripts
+            .borrow_mut()
+            .retain(|v| v.inner.id != script.id);
+        self.failed_scripts.retain(|v| v.inner.id != script.id);
+
+        true
+    }
+
     #[instrument(skip(self, script))]
-    fn compile_script(&self, script: Script) -> Option<ScriptLoad> {
-        match tscompiler::compile_typescript(&script.original_source) {
+    async fn compile_script(&self, script: Script) -> Option<ScriptLoad> {
+        match self
+            .script_compiler
+            .compile_script(self.ctx.guild_id, &script)
+            .await
+        {
             Ok(compiled) => Some(ScriptLoad {
                 compiled,
                 inner: script,
@@ -319,40 +439,119 @@ impl Vm {
         }
     }
 
-    #[instrument(skip(self, script))]
-    async fn load_script(&mut self, script: Script) {
-        if self
-            .loaded_scripts
-            .borrow()
-            .iter()
-            .any(|sc| sc.inner.id == script.id)
+    /// Compiles and registers all the scripts with the module loader before evaluating them,
+    /// that way scripts can import eachother regardless of the order they're loaded in
+    #[instrument(skip(self, scripts))]
+    async fn load_scripts(&mut self, scripts: Vec<Script>) {
+        let mut compiled_scripts = Vec::new();
+        for script in scripts {
+            if self
+                .loaded_scripts
+                .borrow()
+                .iter()
+                .any(|sc| sc.inner.id == script.id)
+            {
+                info!("script: {} was already loaded, skipping", script.id);
+                continue;
+            }
+
+            let compiled = if let Some(compiled) = self.compile_script(script).await {
+                compiled
+            } else {
+                continue;
+            };
+
+            self.module_manager.add_guild_module(
+                self.module_manager
+                    .fresh_guild_script_specifier(&compiled.inner.name),
+                compiled.module_source(),
+                &compiled.compiled.imports,
+            );
+
+            self.loaded_scripts.borrow_mut().push(compiled.clone());
+            compiled_scripts.push(compiled);
+        }
+
+        for compiled in &compiled_scripts {
+            self.load_libraries(compiled).await;
+        }
+
+        for compiled in compiled_scripts {
+            self.evaluate_script(compiled).await;
+        }
+    }
+
+    /// Fetches the library modules imported by the script that are not loaded already
+    async fn load_libraries(&self, compiled: &ScriptLoad) {
+        for import in &compiled.compiled.imports {
+            let (name, version) = match ModuleManager::parse_library_specifier(import) {
+                Some(v) => v,
+                None => continue,
+            };
+
+            let specifier = ModuleManager::library_specifier(name, version);
+            if self.module_manager.has_library_module(&specifier) {
+                continue;
+            }
+
+            match self.library_loader.load_library(name, version).await {
+                Ok(library) => self
+                    .module_manager
+                    .add_library_module(specifier, library.compiled_source),
+                Err(err) => {
+                    // the script will fail to load with an error from the module loader
+                    info!(%err, "failed loading library {}", specifier);
+                }
+            }
+        }
+    }
+
+    #[instrument(skip(self, compiled))]
+    async fn evaluate_script(&mut self, compiled: ScriptLoad) {
+        let specifier = match self
+            .module_manager
+            .guild_module_specifier(&compiled.inner.name)
         {
-            info!("script: {} was already loaded, skipping", script.id);
+            Some(v) => v,
+            None => return,
+        };
+
+        if let Some(cycle) = self.module_manager.find_guild_import_cycle(&specifier) {
+            let path = cycle
+                .iter()
+                .map(|v| {
+                    crate::LoadedScriptsStore::get_guild_script_name(v.as_str())
+                        .map(|name| format!("{}.ts", name))
+                        .unwrap_or_else(|| v.to_string())
+                })
+                .collect::<Vec<_>>();
+
+            self.guild_logger.log(LogEntry::error(
+                self.ctx.guild_id,
+                format!(
+                    "Script {}.ts was not loaded, import cycle detected: {}",
+                    compiled.inner.name,
+                    path.join(" -> ")
+                ),
+            ));
+            self.failed_scripts.push(compiled);
             return;
         }
 
-        let compiled = if let Some(compiled) = self.compile_script(script) {
-            compiled
-        } else {
+        // evaluating a module more than once is not allowed
+        if self.module_manager.guild_module_fetched(&specifier) {
+            info!(
+                "script: {} was already evaluated as a dependency, skipping",
+                compiled.inner.id
+            );
             return;
-        };
-
-        self.loaded_scripts.borrow_mut().push(compiled.clone());
+        }
 
         let eval_res = {
             let mut rt = self.isolate_cell.enter_isolate(&mut self.runtime);
 
-            let parsed_uri =
-                Url::parse(format!("file:///guild_scripts/{}.js", compiled.inner.name).as_str())
-                    .unwrap();
-
-            let fut = rt.load_side_module(
-                &parsed_uri,
-                Some(prepend_script_source_header(
-                    &compiled.compiled.output,
-                    Some(&compiled.inner),
-                )),
-            );
+            // the source is provided by the module manager
+            let fut = rt.load_side_module(&specifier, None);
 
             // Yes this is very hacky, we should have a proper solution for this at some point.
             //
@@ -397,11 +596,13 @@ impl Vm {
 
         // self._dump_heap_stats();
         info!("rt {} dispatching event: {}", self.ctx.guild_id, name);
+        EVENTS_DISPATCHED.with_label_values(&[name]).inc();
         let serialized = serde_json::to_value(args).unwrap();
         self.script_dispatch_tx
             .send(ScriptDispatchData {
                 name: name.to_string(),
                 data: serialized,
+                queued_at: Some(Instant::now()),
             })
             .ok();
         // self._dump_heap_stats();
@@ -445,6 +646,7 @@ impl Vm {
             .send(ScriptDispatchData {
                 name: "NOOP".to_string(),
                 data: serde_json::Value::Null,
+                queued_at: None,
             })
             .ok();
 
@@ -486,10 +688,19 @@ impl Vm {
     }
 
     fn log_guild_err(&self, err: AnyError) {
-        self.guild_logger.log(LogEntry::error(
-            self.ctx.guild_id,
-            format!("Script error occured: {}", err),
-        ));
+        match self.last_script_error.take_matching(&err) {
+            Some(script_err) if !script_err.frames.is_empty() => {
+                self.guild_logger.log(LogEntry::script_exception(
+                    self.ctx.guild_id,
+                    format!("Script error occured: {}", script_err.message),
+                    script_err.frames,
+                ))
+            }
+            _ => self.guild_logger.log(LogEntry::error(
+                self.ctx.guild_id,
+                format!("Script error occured: {}", err),
+            )),
+        }
     }
 
     async fn restart(&mut self, new_scripts: Vec<Script>) {
@@ -498,6 +709,7 @@ impl Vm {
             self.ctx.guild_id,
             "restarting guild vm with new scripts".to_string(),
         ));
+        self.send_event(VmEvent::Restarting);
 
         let core_data = self.stop_vm().await;
 
@@ -510,7 +722,7 @@ impl Vm {
             &self.extension_factory,
             self.module_manager.clone(),
             core_data,
-            create_error_fn(scripts_store.clone()),
+            create_error_fn(scripts_store.clone(), self.last_script_error.clone()),
             scripts_store,
         );
 
@@ -519,15 +731,15 @@ impl Vm {
 
         self.loaded_scripts.borrow_mut().clear();
         self.failed_scripts.clear();
+        self.module_manager.clear_guild_modules();
 
-        for script in new_scripts {
-            self.load_script(script).await;
-        }
+        self.load_scripts(new_scripts).await;
 
         self.guild_logger.log(LogEntry::info(
             self.ctx.guild_id,
             "vm restarted".to_string(),
         ));
+        self.send_event(VmEvent::Started);
     }
 }
 
@@ -543,7 +755,14 @@ async fn op_rcv_event(
 
         if let Some(rx) = &mut core_data.rcv_events {
             match rx.poll_recv(ctx) {
-                Poll::Ready(Some(v)) => Poll::Ready(Ok(v)),
+                Poll::Ready(Some(v)) => {
+                    if let Some(queued_at) = v.queued_at {
+                        EVENT_DISPATCH_LATENCY
+                            .with_label_values(&[&v.name])
+                            .observe(queued_at.elapsed().as_secs_f64());
+                    }
+                    Poll::Ready(Ok(v))
+                }
                 Poll::Ready(None) => Poll::Ready(Err(anyhow!("no more events!"))),
                 Poll::Pending => Poll::Pending,
             }
@@ -551,6 +770,7 @@ async fn op_rcv_event(
             Poll::Ready(Ok(ScriptDispatchData {
                 name: "STOP".to_string(),
                 data: JsValue::Null,
+                queued_at: None,
             }))
         }
     })
@@ -726,6 +946,8 @@ pub struct CreateRt {
     pub load_scripts: Vec<Script>,
     pub extension_factory: ExtensionFactory,
     pub extension_modules: Vec<ModuleEntry>,
+    pub library_loader: Arc<dyn LibraryLoader + Send + Sync>,
+    pub script_compiler: Arc<dyn ScriptCompiler + Send + Sync>,
 }
 
 type ExtensionFactory = Box<dyn Fn() -> Vec<Extension> + Send>;
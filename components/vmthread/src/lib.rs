@@ -3,12 +3,17 @@ use std::{
     future::Future,
     pin::Pin,
     rc::Rc,
-    sync::{Arc, RwLock},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, RwLock,
+    },
     task::Poll,
     time::Duration,
 };
 
 use isolatecell::IsolateCell;
+use lazy_static::lazy_static;
+use prometheus::{register_int_gauge_vec, IntGauge, IntGaugeVec};
 use tokio::{
     sync::{
         mpsc::{self, UnboundedReceiver, UnboundedSender},
@@ -18,6 +23,17 @@ use tokio::{
 };
 use tracing::info;
 
+lazy_static! {
+    static ref RUNNING_VMS: IntGaugeVec = register_int_gauge_vec!(
+        "botloader_vmthread_running_vms",
+        "Number of vms running on the vm thread",
+        &["thread"]
+    )
+    .unwrap();
+}
+
+static NEXT_THREAD_ID: AtomicUsize = AtomicUsize::new(0);
+
 pub enum VmThreadCommand<T> {
     StartVM(T),
     Ping(oneshot::Sender<bool>),
@@ -32,6 +48,7 @@ pub struct VmThreadFuture<T: VmInterface> {
     running_vm: RunningVmTimeout<T::VmId, T::ShutdownHandle>,
     isolate_cell: Rc<IsolateCell>,
     shutting_down: bool,
+    running_vms_gauge: IntGauge,
 }
 
 impl<T> VmThreadFuture<T>
@@ -47,6 +64,8 @@ where
         let running = Arc::new(RwLock::new(None));
         let running_clone = running.clone();
 
+        let thread_id = NEXT_THREAD_ID.fetch_add(1, Ordering::SeqCst);
+
         let tokio_current = tokio::runtime::Handle::current();
         std::thread::spawn(move || {
             let t = VmThreadFuture::<T> {
@@ -55,6 +74,7 @@ where
                 vms: Vec::new(),
                 isolate_cell: Rc::new(Default::default()),
                 shutting_down: false,
+                running_vms_gauge: RUNNING_VMS.with_label_values(&[&thread_id.to_string()]),
             };
 
             tokio_current.block_on(t);
@@ -98,6 +118,7 @@ where
                         shutdown_handle,
                     },
                 });
+                self.running_vms_gauge.set(self.vms.len() as i64);
             }
         }
     }
@@ -214,8 +235,11 @@ where
 
         set_running_vm(&*running_handle, None);
 
-        for index in to_remove {
-            self.vms.remove(index);
+        if !to_remove.is_empty() {
+            for index in to_remove {
+                self.vms.remove(index);
+            }
+            self.running_vms_gauge.set(self.vms.len() as i64);
         }
 
         if self.vms.is_empty() && self.shutting_down {